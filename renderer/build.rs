@@ -0,0 +1,96 @@
+//! Compiles the GLSL shaders in `shaders/` to SPIR-V in `OUT_DIR/shaders/<file>.spv`,
+//! the stage is taken from the extension. Files ending in `.glsl` are only included.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use glslang::{
+    include::{IncludeHandler, IncludeResult, IncludeType},
+    Compiler, CompilerOptions, ShaderInput, ShaderSource, ShaderStage, SourceLanguage,
+    SpirvVersion, Target, VulkanVersion,
+};
+
+struct Includer {
+    dir: PathBuf,
+}
+
+impl IncludeHandler for Includer {
+    fn include(
+        &mut self,
+        _ty: IncludeType,
+        header_name: &str,
+        _includer_name: &str,
+        _include_depth: usize,
+    ) -> Option<IncludeResult> {
+        let data = fs::read_to_string(self.dir.join(header_name)).ok()?;
+        Some(IncludeResult {
+            name: header_name.to_owned(),
+            data,
+        })
+    }
+}
+
+fn stage(path: &Path) -> Option<ShaderStage> {
+    match path.extension()?.to_str()? {
+        "vert" => Some(ShaderStage::Vertex),
+        "frag" => Some(ShaderStage::Fragment),
+        "comp" => Some(ShaderStage::Compute),
+        _ => None,
+    }
+}
+
+fn main() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders");
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap()).join("shaders");
+    fs::create_dir_all(&out_dir).unwrap();
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let compiler = Compiler::acquire().expect("glslang is already in use");
+    let options = CompilerOptions {
+        source_language: SourceLanguage::GLSL,
+        target: Target::Vulkan {
+            version: VulkanVersion::Vulkan1_3,
+            spirv_version: SpirvVersion::SPIRV1_6,
+        },
+        ..Default::default()
+    };
+
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        println!("cargo:rerun-if-changed={}", path.display());
+        let Some(stage) = stage(&path) else {
+            continue;
+        };
+
+        let source = ShaderSource::from(fs::read_to_string(&path).unwrap());
+        let mut includer = Includer { dir: dir.clone() };
+        let input = ShaderInput::new(
+            &source,
+            stage,
+            &options,
+            None::<&[(&str, Option<&str>)]>,
+            Some(&mut includer),
+        )
+        .unwrap();
+
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let shader = match compiler.create_shader(input) {
+            Ok(val) => val,
+            Err(err) => panic!("Failed to parse {}: {}", name, err),
+        };
+        let code = match shader.compile() {
+            Ok(val) => val,
+            Err(err) => panic!("Failed to compile {}: {}", name, err),
+        };
+
+        let bytes: Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        fs::write(out_dir.join(format!("{}.spv", name)), bytes).unwrap();
+    }
+}
@@ -0,0 +1,27 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Halves a bloom level with 13 bilinear taps weighted like a wide box, which keeps
+// small bright spots from flickering as they move.
+
+#include "post.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+vec3 tap(float x, float y) {
+    return texture(source, in_uv + vec2(x, y) * texel_size).rgb;
+}
+
+void main() {
+    vec3 center = tap(0.0, 0.0);
+    vec3 inner = tap(-1.0, -1.0) + tap(1.0, -1.0) + tap(-1.0, 1.0) + tap(1.0, 1.0);
+    vec3 edges = tap(-2.0, 0.0) + tap(2.0, 0.0) + tap(0.0, -2.0) + tap(0.0, 2.0);
+    vec3 corners = tap(-2.0, -2.0) + tap(2.0, -2.0) + tap(-2.0, 2.0) + tap(2.0, 2.0);
+
+    vec3 color = inner * 0.125 + center * 0.125 + edges * 0.0625 + corners * 0.03125;
+    out_color = vec4(color, 1.0);
+}
@@ -0,0 +1,25 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// First bloom level: keeps what is brighter than the threshold in the scene at half
+// resolution, with a soft knee so the bloom doesn't pop in.
+
+#include "post.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    // the bilinear tap between four scene texels averages them
+    vec3 color = texture(source, in_uv).rgb * exposure;
+    float peak = max(color.r, max(color.g, color.b));
+
+    float soft = clamp(peak - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-4);
+    float contribution = max(soft, peak - threshold) / max(peak, 1e-4);
+
+    out_color = vec4(color * contribution, 1.0);
+}
@@ -0,0 +1,25 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Upsamples a bloom level with a 3x3 tent filter, added over the level above it.
+
+#include "post.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+vec3 tap(float x, float y) {
+    return texture(source, in_uv + vec2(x, y) * texel_size).rgb;
+}
+
+void main() {
+    vec3 color = tap(0.0, 0.0) * 4.0;
+    color += (tap(-1.0, 0.0) + tap(1.0, 0.0) + tap(0.0, -1.0) + tap(0.0, 1.0)) * 2.0;
+    color += tap(-1.0, -1.0) + tap(1.0, -1.0) + tap(-1.0, 1.0) + tap(1.0, 1.0);
+
+    // blended additively, the alpha is the source factor
+    out_color = vec4(color / 16.0, 1.0);
+}
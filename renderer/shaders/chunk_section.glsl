@@ -0,0 +1,40 @@
+// Chunk section draw parameters shared by the culling shaders
+
+struct Section {
+    vec4 aabb_min;
+    vec4 aabb_max;
+    // corner the packed vertex positions are relative to
+    ivec4 origin;
+    // first index, index count and vertex offset of each render layer
+    uvec4 layers[3];
+};
+
+const uint LAYER_OPAQUE = 0;
+const uint LAYER_CUTOUT = 1;
+const uint LAYER_TRANSLUCENT = 2;
+
+// opaque and cutout, translucent sections are sorted and drawn from the CPU
+const uint CULLED_LAYERS = 2;
+
+bool section_empty(Section section) {
+    return section.layers[0].y == 0 && section.layers[1].y == 0 && section.layers[2].y == 0;
+}
+
+const uint SECTION_VISIBLE = 0;
+const uint SECTION_EMPTY = 1;
+const uint SECTION_OUTSIDE_FRUSTUM = 2;
+const uint SECTION_OCCLUDED = 3;
+
+const uint CULL_OCCLUSION = 1;
+const uint CULL_REVERSE_Z = 2;
+
+layout(std140, set = 0, binding = 3) uniform CullParams {
+    vec4 planes[6];
+    // camera the depth pyramid was rendered with
+    mat4 hiz_view_proj;
+    vec2 hiz_size;
+    uint section_count;
+    uint flags;
+    // draws of each layer start this many draws after those of the previous one
+    uint layer_stride;
+} params;
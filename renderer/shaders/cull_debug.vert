@@ -0,0 +1,45 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Draws the box of every non empty section as lines, colored by how it was culled.
+// One instance per section, 24 vertices per instance.
+
+#include "chunk_section.glsl"
+
+layout(set = 0, binding = 0) readonly buffer Sections {
+    Section sections[];
+};
+
+layout(set = 0, binding = 5) readonly buffer Status {
+    uint status[];
+};
+
+layout(push_constant) uniform Constants {
+    mat4 view_proj;
+};
+
+layout(location = 0) out vec4 out_color;
+
+const int EDGES[24] = int[](
+    0, 1, 2, 3, 4, 5, 6, 7,
+    0, 2, 1, 3, 4, 6, 5, 7,
+    0, 4, 1, 5, 2, 6, 3, 7
+);
+
+void main() {
+    Section section = sections[gl_InstanceIndex];
+    uint state = status[gl_InstanceIndex];
+
+    if (state == SECTION_EMPTY || state == SECTION_OUTSIDE_FRUSTUM) {
+        // outside of the clip volume, the line is discarded
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        out_color = vec4(0.0);
+        return;
+    }
+
+    int corner = EDGES[gl_VertexIndex];
+    vec3 position = mix(section.aabb_min.xyz, section.aabb_max.xyz, bvec3(corner & 1, corner & 2, corner & 4));
+
+    gl_Position = view_proj * vec4(position, 1.0);
+    out_color = state == SECTION_OCCLUDED ? vec4(1.0, 0.1, 0.1, 1.0) : vec4(0.1, 1.0, 0.1, 1.0);
+}
@@ -0,0 +1,38 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+#extension GL_EXT_buffer_reference : require
+
+// Pulls the vertices of the debug lines, keep `DebugVertex` and `DebugConstants` in sync
+// with `src/debug_draw.rs`. Lines drawn without depth test are moved on the near plane,
+// where they pass the test against anything.
+
+#include "output.glsl"
+
+struct Vertex {
+    vec3 position;
+    uint depth_test;
+    // sRGB color and opacity
+    vec4 color;
+};
+
+layout(buffer_reference, std430, buffer_reference_align = 16) readonly buffer Vertices {
+    Vertex vertices[];
+};
+
+layout(push_constant) uniform Constants {
+    mat4 view_proj;
+    Vertices batch;
+    uint reverse_z;
+};
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    Vertex vertex = batch.vertices[gl_VertexIndex];
+
+    gl_Position = view_proj * vec4(vertex.position, 1.0);
+    if (vertex.depth_test == 0) {
+        gl_Position.z = reverse_z != 0 ? gl_Position.w : 0.0;
+    }
+    out_color = vec4(srgb_to_linear(vertex.color.rgb), vertex.color.a);
+}
@@ -0,0 +1,46 @@
+// Uniforms shared by every pass of a frame and the fog applied with them, keep
+// `FrameParams` in sync with `src/uniforms.rs`. Define FRAME_SET before including it when the set isn't the first.
+
+#ifndef FRAME_SET
+#define FRAME_SET 0
+#endif
+
+layout(set = FRAME_SET, binding = 0) uniform FrameUniforms {
+    // towards the sun and the moon
+    vec4 sun_direction;
+    vec4 moon_direction;
+    vec4 zenith_color;
+    vec4 horizon_color;
+    // color of the light reaching sky lit blocks
+    vec4 light_color;
+    // fraction of a day, 0.5 is noon
+    float time_of_day;
+    // how much of the block sky light is left, 1 at noon
+    float sky_light;
+    float star_visibility;
+    vec4 eye;
+    vec4 fog_color;
+    // the fog is linear from start to end, thickened by the exponential density
+    float fog_start;
+    float fog_end;
+    float fog_density;
+    uint medium;
+} frame;
+
+const uint MEDIUM_AIR = 0;
+const uint MEDIUM_WATER = 1;
+const uint MEDIUM_LAVA = 2;
+
+// how much of a surface at `world` is hidden by the fog, keep in sync with `FogState`
+float fog_amount(vec3 world) {
+    float distance = length(world - frame.eye.xyz);
+    float linear = clamp((distance - frame.fog_start) / max(frame.fog_end - frame.fog_start, 1e-5), 0.0, 1.0);
+    float exponential = 1.0 - exp(-frame.fog_density * distance);
+    return max(linear, exponential);
+}
+
+// fogs the color of a surface at `world`, terrain, entities and particles all go
+// through it so they fade out together
+vec3 apply_fog(vec3 color, vec3 world) {
+    return mix(color, frame.fog_color.rgb, fog_amount(world));
+}
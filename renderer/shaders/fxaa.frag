@@ -0,0 +1,142 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Fast approximate anti-aliasing on the tonemapped image: finds the edges from the
+// contrast of the luma, searches along them for their ends and blends across them by
+// how far the pixel is from the nearest end. Based on FXAA 3.11 by Timothy Lottes.
+
+#include "post.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+// contrast under which a pixel is left alone, relative to its brightest neighbour and
+// absolute for the dark ones
+const float EDGE_THRESHOLD = 0.125;
+const float EDGE_THRESHOLD_MIN = 0.0312;
+const float SUBPIXEL_QUALITY = 0.75;
+
+const int SEARCH_STEPS = 10;
+const float SEARCH_STRIDE[SEARCH_STEPS] =
+    float[](1.0, 1.0, 1.0, 1.0, 1.5, 2.0, 2.0, 2.0, 4.0, 8.0);
+
+float luma_at(vec2 uv) {
+    return texture(source, uv).a;
+}
+
+float luma_offset(int x, int y) {
+    return texture(source, in_uv + vec2(x, y) * texel_size).a;
+}
+
+void main() {
+    vec4 center = texture(source, in_uv);
+    float luma_m = center.a;
+    float luma_n = luma_offset(0, -1);
+    float luma_s = luma_offset(0, 1);
+    float luma_w = luma_offset(-1, 0);
+    float luma_e = luma_offset(1, 0);
+
+    float luma_min = min(luma_m, min(min(luma_n, luma_s), min(luma_w, luma_e)));
+    float luma_max = max(luma_m, max(max(luma_n, luma_s), max(luma_w, luma_e)));
+    float range = luma_max - luma_min;
+    if (range < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD)) {
+        out_color = post_output(center.rgb);
+        return;
+    }
+
+    float luma_nw = luma_offset(-1, -1);
+    float luma_ne = luma_offset(1, -1);
+    float luma_sw = luma_offset(-1, 1);
+    float luma_se = luma_offset(1, 1);
+
+    // whether the edge runs along x, from the second derivatives of the 3x3 luma
+    float horizontal = abs(luma_nw + luma_ne - 2.0 * luma_n)
+        + 2.0 * abs(luma_w + luma_e - 2.0 * luma_m)
+        + abs(luma_sw + luma_se - 2.0 * luma_s);
+    float vertical = abs(luma_nw + luma_sw - 2.0 * luma_w)
+        + 2.0 * abs(luma_n + luma_s - 2.0 * luma_m)
+        + abs(luma_ne + luma_se - 2.0 * luma_e);
+    bool is_horizontal = horizontal >= vertical;
+
+    // step across the edge towards the side with the steepest gradient
+    float luma_negative = is_horizontal ? luma_n : luma_w;
+    float luma_positive = is_horizontal ? luma_s : luma_e;
+    float gradient_negative = abs(luma_negative - luma_m);
+    float gradient_positive = abs(luma_positive - luma_m);
+
+    float step_length = is_horizontal ? texel_size.y : texel_size.x;
+    float luma_side;
+    float gradient;
+    if (gradient_negative >= gradient_positive) {
+        step_length = -step_length;
+        luma_side = luma_negative;
+        gradient = gradient_negative;
+    } else {
+        luma_side = luma_positive;
+        gradient = gradient_positive;
+    }
+    float luma_edge = 0.5 * (luma_m + luma_side);
+    float gradient_scaled = 0.25 * gradient;
+
+    // search along the middle of the edge until the luma changes on both sides
+    vec2 edge_uv = in_uv;
+    vec2 along;
+    if (is_horizontal) {
+        edge_uv.y += 0.5 * step_length;
+        along = vec2(texel_size.x, 0.0);
+    } else {
+        edge_uv.x += 0.5 * step_length;
+        along = vec2(0.0, texel_size.y);
+    }
+
+    vec2 uv_negative = edge_uv - along;
+    vec2 uv_positive = edge_uv + along;
+    float end_negative = luma_at(uv_negative) - luma_edge;
+    float end_positive = luma_at(uv_positive) - luma_edge;
+    bool done_negative = abs(end_negative) >= gradient_scaled;
+    bool done_positive = abs(end_positive) >= gradient_scaled;
+
+    for (int i = 1; i < SEARCH_STEPS && !(done_negative && done_positive); i++) {
+        if (!done_negative) {
+            uv_negative -= along * SEARCH_STRIDE[i];
+            end_negative = luma_at(uv_negative) - luma_edge;
+            done_negative = abs(end_negative) >= gradient_scaled;
+        }
+        if (!done_positive) {
+            uv_positive += along * SEARCH_STRIDE[i];
+            end_positive = luma_at(uv_positive) - luma_edge;
+            done_positive = abs(end_positive) >= gradient_scaled;
+        }
+    }
+
+    float distance_negative = is_horizontal ? in_uv.x - uv_negative.x : in_uv.y - uv_negative.y;
+    float distance_positive = is_horizontal ? uv_positive.x - in_uv.x : uv_positive.y - in_uv.y;
+    bool negative_closer = distance_negative < distance_positive;
+    float distance = min(distance_negative, distance_positive);
+    float length = distance_negative + distance_positive;
+
+    // only blend when the closest end goes the other way than the center of the edge
+    bool center_smaller = luma_m < luma_edge;
+    float end = negative_closer ? end_negative : end_positive;
+    float edge_offset = ((end < 0.0) != center_smaller) ? 0.5 - distance / length : 0.0;
+
+    // thin features and single pixels are blended from the average of their neighbours
+    float luma_average = (2.0 * (luma_n + luma_s + luma_w + luma_e)
+        + luma_nw + luma_ne + luma_sw + luma_se) / 12.0;
+    float subpixel = clamp(abs(luma_average - luma_m) / range, 0.0, 1.0);
+    subpixel = (-2.0 * subpixel + 3.0) * subpixel * subpixel;
+    float subpixel_offset = subpixel * subpixel * SUBPIXEL_QUALITY;
+
+    vec2 uv = in_uv;
+    float offset = max(edge_offset, subpixel_offset) * step_length;
+    if (is_horizontal) {
+        uv.y += offset;
+    } else {
+        uv.x += offset;
+    }
+
+    out_color = post_output(texture(source, uv).rgb);
+}
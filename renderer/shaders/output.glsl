@@ -0,0 +1,53 @@
+// Encoding of sRGB display colors for the output image, `OutputEncoding` of the
+// swapchain as numbered by `OutputEncoding::shader_id`.
+
+const uint ENCODING_LINEAR = 0;
+const uint ENCODING_SRGB = 1;
+const uint ENCODING_PQ = 2;
+const uint ENCODING_EXTENDED_LINEAR = 3;
+
+// brightness display white is shown at on HDR outputs
+const float PAPER_WHITE_NITS = 200.0;
+
+vec3 srgb_to_linear(vec3 color) {
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(low, high, step(vec3(0.04045), color));
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    color = max(color, vec3(0.0));
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1.0 / 2.4)) - 0.055;
+    return mix(low, high, step(vec3(0.0031308), color));
+}
+
+vec3 pq(vec3 color) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+
+    vec3 p = pow(max(color, vec3(0.0)), vec3(m1));
+    return pow((c1 + c2 * p) / (1.0 + c3 * p), vec3(m2));
+}
+
+// sRGB encoded display color to what the output image holds
+vec3 encode_display(vec3 display, uint encoding) {
+    vec3 linear = srgb_to_linear(display);
+    switch (encoding) {
+    case ENCODING_SRGB:
+        return display;
+    case ENCODING_PQ:
+        const mat3 BT709_TO_BT2020 = mat3(
+            0.6274, 0.0691, 0.0164,
+            0.3293, 0.9195, 0.0880,
+            0.0433, 0.0114, 0.8956);
+        return pq(BT709_TO_BT2020 * linear * (PAPER_WHITE_NITS / 10000.0));
+    case ENCODING_EXTENDED_LINEAR:
+        return linear * (PAPER_WHITE_NITS / 80.0);
+    default:
+        return linear;
+    }
+}
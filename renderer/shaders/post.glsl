@@ -0,0 +1,47 @@
+// Push constants shared by the post-processing passes and the encoding of the display
+// colors for the output image, see `PostConstants` in `post/mod.rs`.
+
+#include "output.glsl"
+
+layout(push_constant) uniform Constants {
+    // size of a texel of the sampled source
+    vec2 texel_size;
+    float threshold;
+    float knee;
+    float intensity;
+    float exposure;
+    float brightness;
+    float gamma;
+    uint tonemap;
+    uint flags;
+    uint encoding;
+    float lut_size;
+    float sharpness;
+};
+
+const uint TONEMAP_ACES = 0;
+const uint TONEMAP_REINHARD = 1;
+
+const uint POST_BLOOM = 1;
+const uint POST_LUT = 2;
+// the pass writes the output image rather than an LDR image read by the next pass
+const uint POST_ENCODE = 4;
+// the upscale restores the contrast of the edges
+const uint POST_SHARPEN = 8;
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+// sRGB encoded display color to what the output image holds
+vec3 encode_output(vec3 display) {
+    return encode_display(display, encoding);
+}
+
+// what a pass writes for `display`, the output encoding or sRGB with the luma in alpha
+vec4 post_output(vec3 display) {
+    if ((flags & POST_ENCODE) != 0) {
+        return vec4(encode_output(display), 1.0);
+    }
+    return vec4(display, luma(display));
+}
@@ -0,0 +1,26 @@
+#version 460
+
+// Depth only pass of the shadow maps, cutout geometry is alpha tested so leaves cast
+// dappled shadows
+
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) in flat uint in_layer;
+
+layout(set = 1, binding = 0) uniform sampler2DArray block_textures;
+
+layout(push_constant) uniform Constants {
+    layout(offset = 72) uint render_layer;
+};
+
+const uint LAYER_CUTOUT = 1;
+
+const float ALPHA_CUTOFF = 0.5;
+
+void main() {
+    if (render_layer == LAYER_CUTOUT) {
+        float alpha = texture(block_textures, vec3(in_uv, float(in_layer))).a;
+        if (alpha < ALPHA_CUTOFF) {
+            discard;
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Cascaded sun shadows sampled by the terrain, keep `ShadowParams` in sync with
+// `src/chunks/shadow.rs`
+
+const uint MAX_CASCADES = 4;
+
+const uint SHADOW_DEBUG_CASCADES = 1;
+
+layout(set = 2, binding = 0) uniform ShadowParams {
+    mat4 light_view_proj[MAX_CASCADES];
+    // view depth each cascade ends at
+    vec4 splits;
+    // world size of a shadow map texel in each cascade
+    vec4 texel_world;
+    vec4 eye;
+    vec4 forward;
+    // direction the light travels in
+    vec4 light_dir;
+    uint cascade_count;
+    uint flags;
+    // size of a texel in shadow map coordinates
+    float texel_size;
+} shadows;
+
+layout(set = 2, binding = 1) uniform sampler2DArrayShadow shadow_map;
+
+// how far along the normal the lookup moves, in texels, against acne on sloped receivers
+const float NORMAL_OFFSET = 1.5;
+
+const vec3 CASCADE_COLORS[MAX_CASCADES] = vec3[](
+    vec3(1.0, 0.4, 0.4),
+    vec3(0.4, 1.0, 0.4),
+    vec3(0.4, 0.4, 1.0),
+    vec3(1.0, 1.0, 0.4)
+);
+
+// cascade covering `world`, -1 past the shadow distance
+int shadow_cascade(vec3 world) {
+    float depth = dot(world - shadows.eye.xyz, shadows.forward.xyz);
+    for (uint cascade = 0; cascade < shadows.cascade_count; cascade++) {
+        if (depth < shadows.splits[cascade]) {
+            return int(cascade);
+        }
+    }
+    return -1;
+}
+
+// how much of the sun reaches `world` from 0, shadowed, to 1, lit, filtered over 3x3
+// texels on top of the hardware 2x2 comparison
+float shadow_visibility(vec3 world, vec3 normal) {
+    int cascade = shadow_cascade(world);
+    if (cascade < 0) {
+        return 1.0;
+    }
+    if (dot(normal, -shadows.light_dir.xyz) <= 0.0) {
+        return 0.0;
+    }
+
+    vec3 offset = normal * shadows.texel_world[cascade] * NORMAL_OFFSET;
+    vec4 light = shadows.light_view_proj[cascade] * vec4(world + offset, 1.0);
+    vec2 uv = light.xy * 0.5 + 0.5;
+
+    float lit = 0.0;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            vec2 texel = uv + vec2(x, y) * shadows.texel_size;
+            lit += texture(shadow_map, vec4(texel, float(cascade), light.z));
+        }
+    }
+    return lit / 9.0;
+}
+
+// tint of the cascade covering `world` when the debug view is on
+vec3 shadow_debug_tint(vec3 world) {
+    int cascade = shadow_cascade(world);
+    if ((shadows.flags & SHADOW_DEBUG_CASCADES) == 0 || cascade < 0) {
+        return vec3(1.0);
+    }
+    return CASCADE_COLORS[cascade];
+}
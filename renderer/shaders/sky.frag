@@ -0,0 +1,87 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Sky gradient from the horizon to the zenith with a glow around the sun at dusk and
+// dawn, the sun and moon discs and the stars turning with them.
+
+#include "frame.glsl"
+
+layout(location = 0) in vec3 in_ray;
+
+layout(location = 0) out vec4 out_color;
+
+const float PI = 3.14159265;
+
+// cosines of the angular radii of the discs
+const float SUN_SIZE = 0.9994;
+const float MOON_SIZE = 0.9996;
+const float DISC_EDGE = 0.00015;
+
+const vec3 SUN_COLOR = vec3(1.0, 0.95, 0.8);
+const vec3 MOON_COLOR = vec3(0.85, 0.88, 0.95);
+
+// cells per unit of the star grid and the share of cells holding a star
+const float STAR_CELLS = 160.0;
+const float STAR_CHANCE = 0.0025;
+
+float hash(vec3 cell) {
+    cell = fract(cell * vec3(0.1031, 0.1030, 0.0973));
+    cell += dot(cell, cell.yxz + 33.33);
+    return fract((cell.x + cell.y) * cell.z);
+}
+
+// the stars are fixed to the sky, which turns around z with the sun
+vec3 sky_space(vec3 ray) {
+    float angle = (frame.time_of_day - 0.25) * 2.0 * PI;
+    float c = cos(angle);
+    float s = sin(angle);
+    return vec3(c * ray.x + s * ray.y, -s * ray.x + c * ray.y, ray.z);
+}
+
+float stars(vec3 ray) {
+    vec3 position = sky_space(ray) * STAR_CELLS;
+    vec3 cell = floor(position);
+    float chance = hash(cell);
+    if (chance > STAR_CHANCE) {
+        return 0.0;
+    }
+
+    float distance_to_center = length(fract(position) - 0.5);
+    float twinkle = 0.6 + 0.4 * hash(cell + 17.0);
+    return smoothstep(0.3, 0.0, distance_to_center) * twinkle;
+}
+
+float disc(vec3 ray, vec3 direction, float size) {
+    return smoothstep(size - DISC_EDGE, size, dot(ray, direction));
+}
+
+void main() {
+    vec3 ray = normalize(in_ray);
+    vec3 sun = frame.sun_direction.xyz;
+    float height = ray.y;
+
+    vec3 color = mix(frame.horizon_color.rgb, frame.zenith_color.rgb, sqrt(clamp(height, 0.0, 1.0)));
+    // under the horizon the sky fades to a darker tone of the horizon
+    color *= mix(1.0, 0.6, smoothstep(0.0, -0.4, height));
+
+    // the sky glows around the sun while it is close to the horizon
+    float twilight = 1.0 - smoothstep(0.0, 0.35, abs(sun.y));
+    float glow = pow(max(dot(ray, sun), 0.0), 6.0) * twilight;
+    color += frame.light_color.rgb * glow * 0.45;
+
+    float horizon_fade = smoothstep(-0.05, 0.1, height);
+    color += stars(ray) * frame.star_visibility * horizon_fade;
+
+    float sun_disc = disc(ray, sun, SUN_SIZE);
+    float moon_disc = disc(ray, frame.moon_direction.xyz, MOON_SIZE);
+    color = mix(color, SUN_COLOR * 4.0, sun_disc * horizon_fade);
+    color = mix(color, MOON_COLOR, moon_disc * horizon_fade);
+
+    // under water and in lava the sky is hidden, in the air it is left clear as it
+    // already fades into the fog color at the horizon
+    if (frame.medium != MEDIUM_AIR) {
+        color = frame.fog_color.rgb;
+    }
+
+    out_color = vec4(color, 1.0);
+}
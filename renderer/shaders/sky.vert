@@ -0,0 +1,22 @@
+#version 460
+
+// Covers the screen with a single triangle and sends the view ray of each corner to
+// the fragment shader.
+
+layout(push_constant) uniform Constants {
+    mat4 inverse_view_proj;
+    vec4 eye;
+};
+
+layout(location = 0) out vec3 out_ray;
+
+void main() {
+    vec2 uv = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    vec2 ndc = uv * 2.0 - 1.0;
+
+    // any depth inside the clip volume lies on the ray, the far plane may be at infinity
+    vec4 world = inverse_view_proj * vec4(ndc, 0.5, 1.0);
+    out_ray = world.xyz / world.w - eye.xyz;
+
+    gl_Position = vec4(ndc, 0.0, 1.0);
+}
@@ -0,0 +1,95 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Ambient occlusion from the linear depth: samples spread in the hemisphere around the
+// normal rebuilt from the depth are occluded by what is drawn in front of them. Every
+// pixel turns the samples by its own angle, the blur smooths the noise out.
+
+#include "ssao.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D depth;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out float out_occlusion;
+
+const float GOLDEN_ANGLE = 2.39996323;
+const float TAU = 6.28318531;
+
+// occluders closer than this to the surface are ignored, against self occlusion
+const float BIAS = 0.03;
+
+float depth_at(vec2 uv) {
+    return textureLod(depth, uv, 0.0).r;
+}
+
+// Jimenez's interleaved gradient noise
+float noise(vec2 pixel) {
+    return fract(52.9829189 * fract(dot(pixel, vec2(0.06711056, 0.00583715))));
+}
+
+// the neighbour closest in depth on each axis, so edges don't bend the normal
+vec3 rebuild_normal(vec3 center) {
+    vec2 dx = vec2(texel_size.x, 0.0);
+    vec2 dy = vec2(0.0, texel_size.y);
+    vec3 left = view_position(in_uv - dx, depth_at(in_uv - dx));
+    vec3 right = view_position(in_uv + dx, depth_at(in_uv + dx));
+    vec3 up = view_position(in_uv - dy, depth_at(in_uv - dy));
+    vec3 down = view_position(in_uv + dy, depth_at(in_uv + dy));
+
+    vec3 horizontal = abs(right.z - center.z) < abs(center.z - left.z)
+        ? right - center
+        : center - left;
+    vec3 vertical = abs(down.z - center.z) < abs(center.z - up.z)
+        ? down - center
+        : center - up;
+
+    vec3 normal = normalize(cross(horizontal, vertical));
+    return dot(normal, center) > 0.0 ? -normal : normal;
+}
+
+void main() {
+    float center_depth = depth_at(in_uv);
+    if (center_depth == SKY) {
+        out_occlusion = 1.0;
+        return;
+    }
+
+    vec3 center = view_position(in_uv, center_depth);
+    vec3 normal = rebuild_normal(center);
+
+    // basis around the normal, turned by the noise of the pixel
+    float angle = noise(gl_FragCoord.xy) * TAU;
+    vec3 helper = abs(normal.z) < 0.999 ? vec3(0.0, 0.0, 1.0) : vec3(1.0, 0.0, 0.0);
+    vec3 tangent = normalize(cross(helper, normal));
+    vec3 bitangent = cross(normal, tangent);
+    tangent = tangent * cos(angle) + bitangent * sin(angle);
+    bitangent = cross(normal, tangent);
+
+    float occlusion = 0.0;
+    for (uint i = 0; i < samples; i++) {
+        // spiral over the hemisphere, most samples close to the surface
+        float t = (float(i) + 0.5) / float(samples);
+        float cos_theta = sqrt(1.0 - t);
+        float sin_theta = sqrt(t);
+        float phi = float(i) * GOLDEN_ANGLE;
+        vec3 direction = tangent * (cos(phi) * sin_theta)
+            + bitangent * (sin(phi) * sin_theta)
+            + normal * cos_theta;
+        float scale = mix(0.1, 1.0, t * t);
+        vec3 sample_position = center + direction * radius * scale;
+
+        float sample_depth = -sample_position.z;
+        vec2 uv = sample_position.xy / (sample_depth * proj_scale) * 0.5 + 0.5;
+        float scene_depth = depth_at(uv);
+        if (scene_depth == SKY) {
+            continue;
+        }
+
+        // occluders far in front of the surface are other objects, they fade out
+        float range = smoothstep(0.0, 1.0, radius / abs(center_depth - scene_depth));
+        occlusion += (scene_depth < sample_depth - BIAS ? 1.0 : 0.0) * range;
+    }
+
+    out_occlusion = pow(clamp(1.0 - occlusion / float(samples), 0.0, 1.0), intensity);
+}
@@ -0,0 +1,34 @@
+// Push constants shared by the ambient occlusion passes and the reconstruction of view
+// space positions from the linear depth, see `SsaoConstants` in `post/ssao.rs`.
+
+layout(push_constant) uniform Constants {
+    // inverse of the x and y scales of the projection
+    vec2 proj_scale;
+    // linear depth = x / (depth + y)
+    vec2 depth_params;
+    // size of a texel of the sampled image
+    vec2 texel_size;
+    // step of the blur, in texels
+    vec2 direction;
+    float radius;
+    float intensity;
+    // depth the depth buffer is cleared to, nothing was drawn there
+    float sky_depth;
+    uint samples;
+    // texels of the depth buffer per texel of the occlusion
+    uint scale;
+};
+
+// linear depth written where nothing was drawn
+const float SKY = 0.0;
+
+float linearize(float depth) {
+    if (depth == sky_depth) {
+        return SKY;
+    }
+    return depth_params.x / (depth + depth_params.y);
+}
+
+vec3 view_position(vec2 uv, float depth) {
+    return vec3((uv * 2.0 - 1.0) * proj_scale * depth, -depth);
+}
@@ -0,0 +1,17 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Darkens the scene with the occlusion, blended multiplicatively over the vertex
+// occlusion of the blocks already in it.
+
+#include "ssao.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D occlusion;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    out_color = vec4(vec3(textureLod(occlusion, in_uv, 0.0).r), 1.0);
+}
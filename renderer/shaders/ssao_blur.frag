@@ -0,0 +1,40 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// One direction of a bilateral blur of the occlusion: taps weigh less as their depth
+// moves away from the center, so the occlusion doesn't bleed over edges.
+
+#include "ssao.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D occlusion;
+layout(set = 1, binding = 0) uniform sampler2D depth;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out float out_occlusion;
+
+const int TAPS = 4;
+const float WEIGHTS[TAPS + 1] = float[](0.2270, 0.1946, 0.1216, 0.0541, 0.0162);
+
+// relative depth difference at which a tap is mostly ignored
+const float DEPTH_FALLOFF = 0.05;
+
+void main() {
+    float center_depth = textureLod(depth, in_uv, 0.0).r;
+    float sum = textureLod(occlusion, in_uv, 0.0).r * WEIGHTS[0];
+    float total = WEIGHTS[0];
+
+    for (int i = 1; i <= TAPS; i++) {
+        for (int side = -1; side <= 1; side += 2) {
+            vec2 uv = in_uv + direction * texel_size * float(i * side);
+            float tap_depth = textureLod(depth, uv, 0.0).r;
+            float difference = abs(tap_depth - center_depth) / max(center_depth, 1e-4);
+            float weight = WEIGHTS[i] * max(0.0, 1.0 - difference / DEPTH_FALLOFF);
+
+            sum += textureLod(occlusion, uv, 0.0).r * weight;
+            total += weight;
+        }
+    }
+
+    out_occlusion = sum / total;
+}
@@ -0,0 +1,17 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Linear view depth of the depth buffer at the resolution of the occlusion.
+
+#include "ssao.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D depth_buffer;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out float out_depth;
+
+void main() {
+    ivec2 coords = ivec2(gl_FragCoord.xy) * int(scale);
+    out_depth = linearize(texelFetch(depth_buffer, coords, 0).r);
+}
@@ -0,0 +1,19 @@
+// Push constants of the temporal anti-aliasing passes, see `TaaConstants` in
+// `post/taa.rs`.
+
+layout(push_constant) uniform Constants {
+    // NDC of this frame to the clip space of the previous one
+    mat4 reprojection;
+    // NDC offset of the projection of this frame
+    vec2 jitter;
+    vec2 texel_size;
+    // weight of the current frame against the history
+    float feedback;
+    float sharpness;
+    uint flags;
+    uint _pad;
+};
+
+const uint TAA_HISTORY = 1;
+const uint TAA_OBJECT_MOTION = 2;
+const uint TAA_REVERSE_Z = 4;
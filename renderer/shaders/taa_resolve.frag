@@ -0,0 +1,106 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Blends the jittered scene into the history reprojected with the motion of the pixel.
+// Static geometry moves with the camera, found from the depth, moving objects write
+// their own motion. The history is clipped to the colors around the pixel so stale
+// samples don't ghost, and the blend weighs bright samples less against fireflies.
+
+#include "taa.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 1, binding = 0) uniform sampler2D history;
+layout(set = 2, binding = 0) uniform sampler2D depth;
+layout(set = 3, binding = 0) uniform sampler2D object_motion;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+vec3 to_ycocg(vec3 color) {
+    return vec3(
+        0.25 * color.r + 0.5 * color.g + 0.25 * color.b,
+        0.5 * color.r - 0.5 * color.b,
+        -0.25 * color.r + 0.5 * color.g - 0.25 * color.b);
+}
+
+vec3 from_ycocg(vec3 color) {
+    return vec3(
+        color.x + color.y - color.z,
+        color.x + color.z,
+        color.x - color.y - color.z);
+}
+
+// moves `color` towards the center of the box until it is inside
+vec3 clip_to_box(vec3 color, vec3 box_min, vec3 box_max) {
+    vec3 center = 0.5 * (box_max + box_min);
+    vec3 extents = 0.5 * (box_max - box_min) + 1e-5;
+    vec3 offset = color - center;
+    vec3 units = abs(offset / extents);
+    float furthest = max(units.x, max(units.y, units.z));
+    return furthest > 1.0 ? center + offset / furthest : color;
+}
+
+// motion of the closest surface around the pixel, edges keep the motion of what is in
+// front of them
+vec2 motion() {
+    vec2 closest_uv = in_uv;
+    float closest = textureLod(depth, in_uv, 0.0).r;
+    bool reverse_z = (flags & TAA_REVERSE_Z) != 0;
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            vec2 uv = in_uv + vec2(x, y) * texel_size;
+            float d = textureLod(depth, uv, 0.0).r;
+            if (reverse_z ? d > closest : d < closest) {
+                closest = d;
+                closest_uv = uv;
+            }
+        }
+    }
+
+    if ((flags & TAA_OBJECT_MOTION) != 0) {
+        vec4 object = textureLod(object_motion, closest_uv, 0.0);
+        if (object.a > 0.0) {
+            return object.xy;
+        }
+    }
+
+    // the depth was drawn jittered, the motion is between unjittered positions
+    vec4 previous = reprojection * vec4(closest_uv * 2.0 - 1.0, closest, 1.0);
+    vec2 previous_uv = previous.xy / previous.w * 0.5 + 0.5;
+    return closest_uv - jitter * 0.5 - previous_uv;
+}
+
+void main() {
+    vec3 current = textureLod(scene, in_uv, 0.0).rgb;
+
+    vec3 mean = vec3(0.0);
+    vec3 square_mean = vec3(0.0);
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            vec3 color = to_ycocg(textureLod(scene, in_uv + vec2(x, y) * texel_size, 0.0).rgb);
+            mean += color;
+            square_mean += color * color;
+        }
+    }
+    mean /= 9.0;
+    vec3 deviation = sqrt(max(square_mean / 9.0 - mean * mean, 0.0));
+
+    vec2 history_uv = in_uv - motion();
+    bool outside = any(lessThan(history_uv, vec2(0.0))) || any(greaterThan(history_uv, vec2(1.0)));
+    if ((flags & TAA_HISTORY) == 0 || outside) {
+        out_color = vec4(current, 1.0);
+        return;
+    }
+
+    vec3 previous = to_ycocg(textureLod(history, history_uv, 0.0).rgb);
+    previous = from_ycocg(clip_to_box(previous, mean - deviation, mean + deviation));
+    previous = max(previous, vec3(0.0));
+
+    float current_weight = feedback / (1.0 + dot(current, vec3(0.2126, 0.7152, 0.0722)));
+    float history_weight = (1.0 - feedback) / (1.0 + dot(previous, vec3(0.2126, 0.7152, 0.0722)));
+    vec3 color = (current * current_weight + previous * history_weight)
+        / (current_weight + history_weight);
+
+    out_color = vec4(color, 1.0);
+}
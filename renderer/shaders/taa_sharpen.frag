@@ -0,0 +1,31 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Sharpens the accumulated image back into the scene, against the softness of the
+// blending. The result stays within its neighbours so edges don't ring.
+
+#include "taa.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+vec3 tap(float x, float y) {
+    return textureLod(source, in_uv + vec2(x, y) * texel_size, 0.0).rgb;
+}
+
+void main() {
+    vec3 center = tap(0.0, 0.0);
+    vec3 north = tap(0.0, -1.0);
+    vec3 south = tap(0.0, 1.0);
+    vec3 west = tap(-1.0, 0.0);
+    vec3 east = tap(1.0, 0.0);
+
+    vec3 low = min(center, min(min(north, south), min(west, east)));
+    vec3 high = max(center, max(max(north, south), max(west, east)));
+    vec3 sharpened = center + (4.0 * center - north - south - west - east) * sharpness;
+
+    out_color = vec4(clamp(sharpened, low, high), 1.0);
+}
@@ -0,0 +1,19 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Glyph coverage from the atlas, blended over the output in its encoding.
+
+#include "text.glsl"
+#include "output.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D atlas;
+
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) in vec4 in_color;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    float coverage = texture(atlas, in_uv).r;
+    out_color = vec4(encode_display(in_color.rgb, encoding), in_color.a * coverage);
+}
@@ -0,0 +1,21 @@
+// Glyph quads of the text batch and the push constants of the text pass, see
+// `GlyphInstance` in `text/layout.rs` and `TextConstants` in `text/mod.rs`.
+
+#extension GL_EXT_buffer_reference : require
+
+struct Glyph {
+    // covered output pixels, left, top, right and bottom
+    vec4 rect;
+    vec4 uv;
+    vec4 color;
+};
+
+layout(buffer_reference, std430, buffer_reference_align = 16) readonly buffer Glyphs {
+    Glyph glyphs[];
+};
+
+layout(push_constant) uniform Constants {
+    Glyphs batch;
+    vec2 screen_size;
+    uint encoding;
+};
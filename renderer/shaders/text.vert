@@ -0,0 +1,19 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Expands every instance into the quad of its glyph, drawn as a strip of 4 vertices.
+
+#include "text.glsl"
+
+layout(location = 0) out vec2 out_uv;
+layout(location = 1) out vec4 out_color;
+
+void main() {
+    Glyph glyph = batch.glyphs[gl_InstanceIndex];
+    vec2 corner = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+
+    vec2 position = mix(glyph.rect.xy, glyph.rect.zw, corner);
+    out_uv = mix(glyph.uv.xy, glyph.uv.zw, corner);
+    out_color = glyph.color;
+    gl_Position = vec4(position / screen_size * 2.0 - 1.0, 0.0, 1.0);
+}
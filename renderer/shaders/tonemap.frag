@@ -0,0 +1,55 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Exposes the HDR scene, adds the bloom and maps it to the display range, then grades
+// the display colors with the LUT and applies the brightness and gamma. Without FXAA
+// this is the last pass and encodes for the output, otherwise it writes sRGB with the
+// luma in alpha for FXAA.
+
+#include "post.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 1, binding = 0) uniform sampler2D bloom;
+layout(set = 2, binding = 0) uniform sampler3D lut;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+// Narkowicz's fit of the ACES reference rendering and output transforms
+vec3 aces(vec3 color) {
+    color *= 0.6;
+    return clamp(
+        (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14),
+        0.0,
+        1.0);
+}
+
+void main() {
+    vec3 color = texture(scene, in_uv).rgb * exposure;
+    if ((flags & POST_BLOOM) != 0) {
+        color += texture(bloom, in_uv).rgb * intensity;
+    }
+
+    switch (tonemap) {
+    case TONEMAP_ACES:
+        color = aces(color);
+        break;
+    case TONEMAP_REINHARD:
+        color = color / (1.0 + color);
+        break;
+    default:
+        color = clamp(color, 0.0, 1.0);
+        break;
+    }
+
+    vec3 display = linear_to_srgb(color);
+    if ((flags & POST_LUT) != 0) {
+        // texel centers of the first and last entries map to 0 and 1
+        vec3 coords = display * ((lut_size - 1.0) / lut_size) + 0.5 / lut_size;
+        display = texture(lut, coords).rgb;
+    }
+    display = pow(clamp(display * brightness, 0.0, 1.0), vec3(1.0 / gamma));
+
+    out_color = post_output(display);
+}
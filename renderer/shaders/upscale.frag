@@ -0,0 +1,37 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Brings the tonemapped scene drawn at a lower resolution up to the size of the output,
+// bilinear or sharpened: the difference with the 4 neighbours is added back, less where
+// the neighbourhood already has contrast, and clamped to them so edges don't ring.
+
+#include "post.glsl"
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+vec3 source_offset(float x, float y) {
+    return texture(source, in_uv + vec2(x, y) * texel_size).rgb;
+}
+
+void main() {
+    vec3 center = texture(source, in_uv).rgb;
+
+    if ((flags & POST_SHARPEN) != 0) {
+        vec3 n = source_offset(0.0, -1.0);
+        vec3 s = source_offset(0.0, 1.0);
+        vec3 w = source_offset(-1.0, 0.0);
+        vec3 e = source_offset(1.0, 0.0);
+
+        vec3 low = min(center, min(min(n, s), min(w, e)));
+        vec3 high = max(center, max(max(n, s), max(w, e)));
+        vec3 amount = sqrt(clamp(min(low, 1.0 - high) / max(high, 1e-4), 0.0, 1.0));
+        vec3 sharpened = center + (4.0 * center - n - s - w - e) * amount * sharpness * 0.25;
+        center = clamp(sharpened, low, high);
+    }
+
+    out_color = vec4(encode_output(center), 1.0);
+}
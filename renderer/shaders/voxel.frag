@@ -0,0 +1,73 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+// Shades chunk geometry with the block texture array, the sky light of the time of day
+// and the sun shadows, then fogged. Cutout geometry is alpha tested, translucent geometry
+// is blended.
+
+#define FRAME_SET 3
+#include "frame.glsl"
+#include "shadow.glsl"
+
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) in flat uint in_layer;
+layout(location = 2) in float in_shade;
+layout(location = 3) in vec3 in_world;
+layout(location = 4) in flat vec3 in_normal;
+layout(location = 5) in float in_sky;
+layout(location = 6) in float in_block;
+layout(location = 7) in float in_emission;
+
+layout(set = 1, binding = 0) uniform sampler2DArray block_textures;
+
+layout(push_constant) uniform Constants {
+    layout(offset = 72) uint render_layer;
+};
+
+layout(location = 0) out vec4 out_color;
+
+const uint LAYER_OPAQUE = 0;
+const uint LAYER_CUTOUT = 1;
+
+const float ALPHA_CUTOFF = 0.5;
+// averaged mips lose alpha coverage and foliage thins out with distance, sharpen the
+// alpha back a little for every level
+const float MIP_ALPHA_SCALE = 0.25;
+
+// light left in the shade of the sun, where the sky reaches
+const float SHADOW_LIGHT = 0.55;
+// light left in complete darkness
+const float MIN_LIGHT = 0.05;
+// brightness of fully emissive faces, past white so they bloom
+const float EMISSIVE_STRENGTH = 4.0;
+
+void main() {
+    vec3 coord = vec3(in_uv, float(in_layer));
+    vec4 texel = texture(block_textures, coord);
+
+    float alpha = texel.a;
+    if (render_layer == LAYER_OPAQUE) {
+        alpha = 1.0;
+    } else if (render_layer == LAYER_CUTOUT) {
+        float lod = textureQueryLod(block_textures, in_uv).x;
+        alpha *= 1.0 + max(lod, 0.0) * MIP_ALPHA_SCALE;
+        if (alpha < ALPHA_CUTOFF) {
+            discard;
+        }
+        alpha = 1.0;
+    }
+
+    // only sky lit surfaces see the sun
+    float sun = shadow_visibility(in_world, in_normal);
+    float shadow = mix(1.0, mix(SHADOW_LIGHT, 1.0, sun), in_sky);
+
+    // torches don't fade at night, the brightest of both lights wins
+    vec3 sky = frame.light_color.rgb * in_sky * frame.sky_light * shadow;
+    vec3 light = mix(vec3(MIN_LIGHT), vec3(1.0), max(sky, vec3(in_block)));
+
+    vec3 color = texel.rgb * in_shade * light * shadow_debug_tint(in_world);
+    // emissive blocks light themselves evenly, whatever the face
+    color = mix(color, texel.rgb * EMISSIVE_STRENGTH, in_emission);
+    color = apply_fog(color, in_world);
+    out_color = vec4(color, alpha);
+}
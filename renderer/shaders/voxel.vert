@@ -0,0 +1,62 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+#extension GL_EXT_buffer_reference : require
+
+// Pulls the packed vertices of the chunk sections from the geometry arena through their
+// device address. The instance index of each draw is the section it comes from.
+
+#include "chunk_section.glsl"
+#include "voxel_vertex.glsl"
+
+layout(buffer_reference, std430, buffer_reference_align = 8) readonly buffer Vertices {
+    uvec2 vertices[];
+};
+
+layout(set = 0, binding = 0) readonly buffer Sections {
+    Section sections[];
+};
+
+layout(push_constant) uniform Constants {
+    mat4 view_proj;
+    Vertices arena;
+    uint render_layer;
+};
+
+layout(location = 0) out vec2 out_uv;
+layout(location = 1) out flat uint out_layer;
+layout(location = 2) out float out_shade;
+layout(location = 3) out vec3 out_world;
+layout(location = 4) out flat vec3 out_normal;
+layout(location = 5) out float out_sky;
+layout(location = 6) out float out_block;
+layout(location = 7) out float out_emission;
+
+// directional shading so faces of the same block stay apart without lighting
+const float FACE_SHADE[6] = float[](0.8, 0.8, 1.0, 0.5, 0.65, 0.65);
+
+const vec3 FACE_NORMAL[6] = vec3[](
+    vec3(1, 0, 0), vec3(-1, 0, 0),
+    vec3(0, 1, 0), vec3(0, -1, 0),
+    vec3(0, 0, 1), vec3(0, 0, -1)
+);
+
+void main() {
+    VoxelVertex vertex = decode_vertex(arena.vertices[gl_VertexIndex]);
+    Section section = sections[gl_InstanceIndex];
+
+    vec3 position = vec3(section.origin.xyz) + vertex.position;
+    gl_Position = view_proj * vec4(position, 1.0);
+
+    float ao = 1.0 - 0.2 * float(vertex.ao);
+
+    out_uv = vertex.uv;
+    out_layer = vertex.layer;
+    out_shade = FACE_SHADE[vertex.face] * ao;
+    out_world = position;
+    out_normal = FACE_NORMAL[vertex.face];
+    // the sky light depends on the time of day, it is combined with the block light
+    // in the fragment shader
+    out_sky = float(vertex.sky_light) / float(MAX_LIGHT);
+    out_block = float(vertex.block_light) / float(MAX_LIGHT);
+    out_emission = float(vertex.emission) / float(MAX_LIGHT);
+}
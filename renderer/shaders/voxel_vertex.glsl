@@ -0,0 +1,31 @@
+// Decodes the packed chunk vertex, keep in sync with `src/chunks/vertex.rs`
+
+struct VoxelVertex {
+    vec3 position;
+    uint face;
+    vec2 uv;
+    uint ao;
+    uint layer;
+    uint sky_light;
+    uint block_light;
+    uint emission;
+};
+
+const uint MAX_LIGHT = 15;
+
+VoxelVertex decode_vertex(uvec2 packed) {
+    VoxelVertex vertex;
+    vertex.position = vec3(
+        bitfieldExtract(packed.x, 0, 5),
+        bitfieldExtract(packed.x, 5, 5),
+        bitfieldExtract(packed.x, 10, 5)
+    );
+    vertex.face = bitfieldExtract(packed.x, 15, 3);
+    vertex.ao = bitfieldExtract(packed.x, 18, 2);
+    vertex.uv = vec2(bitfieldExtract(packed.x, 20, 5), bitfieldExtract(packed.x, 25, 5));
+    vertex.layer = bitfieldExtract(packed.y, 0, 16);
+    vertex.sky_light = bitfieldExtract(packed.y, 16, 4);
+    vertex.block_light = bitfieldExtract(packed.y, 20, 4);
+    vertex.emission = bitfieldExtract(packed.y, 24, 4);
+    return vertex;
+}
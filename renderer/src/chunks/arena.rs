@@ -0,0 +1,898 @@
+use std::{error::Error, mem::size_of, ops::Range};
+
+use ash::vk;
+
+use crate::{
+    core,
+    graph::{BufferId, BufferUsage, RenderGraph},
+    MemoryLocation, Renderer,
+};
+
+/// Ratio of fragmented to free space over which compaction kicks in
+const DEFAULT_COMPACTION_THRESHOLD: f32 = 0.25;
+
+/// Bytes moved per frame while compacting
+const DEFAULT_COMPACTION_BUDGET: u64 = 4 * 1024 * 1024;
+
+/// First fit allocator over a range of elements, freed blocks are only reused once
+/// the last frame reading them is completed
+#[derive(Debug)]
+struct FreeList {
+    capacity: u64,
+    /// Sorted and coalesced
+    free: Vec<Range<u64>>,
+    /// Freed blocks with the last frame reading them
+    retired: Vec<(Range<u64>, u64)>,
+}
+
+impl FreeList {
+    fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            free: std::iter::once(0..capacity).collect(),
+            retired: vec![],
+        }
+    }
+
+    fn allocate(&mut self, size: u64) -> Option<u64> {
+        let index = self
+            .free
+            .iter()
+            .position(|block| block.end - block.start >= size)?;
+
+        let block = &mut self.free[index];
+        let offset = block.start;
+        block.start += size;
+        if block.is_empty() {
+            self.free.remove(index);
+        }
+        Some(offset)
+    }
+
+    /// Lowest block that fits `size` and ends before `before`
+    fn allocate_below(&mut self, size: u64, before: u64) -> Option<u64> {
+        let index = self
+            .free
+            .iter()
+            .position(|block| block.end - block.start >= size && block.start + size <= before)?;
+
+        let block = &mut self.free[index];
+        let offset = block.start;
+        block.start += size;
+        if block.is_empty() {
+            self.free.remove(index);
+        }
+        Some(offset)
+    }
+
+    fn retire(&mut self, range: Range<u64>, frame: u64) {
+        if !range.is_empty() {
+            self.retired.push((range, frame));
+        }
+    }
+
+    /// Returns the blocks of the frames up to `completed` to the free list, see
+    /// [`Renderer::completed_frame`]
+    fn collect(&mut self, completed: u64) {
+        let (expired, retired): (Vec<_>, Vec<_>) = self
+            .retired
+            .drain(..)
+            .partition(|(_, frame)| *frame <= completed);
+        self.retired = retired;
+
+        for (range, _) in expired {
+            self.release(range);
+        }
+    }
+
+    fn release(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let index = self.free.partition_point(|block| block.start < range.start);
+        self.free.insert(index, range);
+
+        // merge with the next block, then with the previous one
+        if index + 1 < self.free.len() && self.free[index].end == self.free[index + 1].start {
+            self.free[index].end = self.free.remove(index + 1).end;
+        }
+        if index > 0 && self.free[index - 1].end == self.free[index].start {
+            self.free[index - 1].end = self.free.remove(index).end;
+        }
+    }
+
+    fn free_elements(&self) -> u64 {
+        self.free.iter().map(|block| block.end - block.start).sum()
+    }
+
+    fn retired_elements(&self) -> u64 {
+        self.retired
+            .iter()
+            .map(|(block, _)| block.end - block.start)
+            .sum()
+    }
+
+    fn largest_free_block(&self) -> u64 {
+        self.free
+            .iter()
+            .map(|block| block.end - block.start)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Handle to a mesh in a [`GeometryArena`], stays valid across re-meshing and compaction
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshHandle {
+    index: u32,
+    generation: u32,
+}
+
+/// Where a mesh lives in the arena buffers, in vertices and indices
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshRange {
+    pub vertex_offset: u32,
+    pub vertex_count: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+struct Slot {
+    generation: u32,
+    range: Option<MeshRange>,
+}
+
+/// Memory usage of a [`GeometryArena`], in bytes over both buffers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArenaStats {
+    pub used: u64,
+    pub free: u64,
+    /// Free space outside the largest free block of each buffer
+    pub fragmented: u64,
+    /// Freed space waiting for the frames in flight before it can be reused
+    pub retired: u64,
+    pub meshes: u32,
+}
+
+impl ArenaStats {
+    /// Share of the free space that can't hold a large allocation
+    pub fn fragmentation(&self) -> f32 {
+        if self.free == 0 {
+            0.0
+        } else {
+            self.fragmented as f32 / self.free as f32
+        }
+    }
+}
+
+/// Copy between or into the arena buffers recorded by [`GeometryArena::flush`]
+struct Copy {
+    src: Option<vk::Buffer>,
+    index_buffer: bool,
+    region: vk::BufferCopy,
+}
+
+/// Vertex and index buffers shared by every chunk section mesh, sub-allocated with a
+/// free list. Meshes are compacted towards the start of the buffers a bit every frame
+/// once fragmentation grows over a threshold.
+pub struct GeometryArena {
+    vertices: core::AllocatedBuffer,
+    indices: core::AllocatedBuffer,
+    vertex_address: vk::DeviceAddress,
+    vertex_size: u64,
+    vertex_list: FreeList,
+    index_list: FreeList,
+    slots: Vec<Slot>,
+    free_slots: Vec<u32>,
+    /// Uploads waiting for the next flush, data is staged right away
+    pending: Vec<Copy>,
+    /// Meshes whose range changed since the last call to `take_moved`
+    moved: Vec<MeshHandle>,
+    compaction_threshold: f32,
+    compaction_budget: u64,
+}
+
+impl GeometryArena {
+    /// Creates buffers for `vertex_capacity` vertices of `vertex_size` bytes and
+    /// `index_capacity` u32 indices
+    pub fn new(
+        renderer: &Renderer,
+        vertex_size: u32,
+        vertex_capacity: u32,
+        index_capacity: u32,
+    ) -> Result<Self, Box<dyn Error>> {
+        let vertex_size = vertex_size as u64;
+
+        let vertices = renderer.create_buffer(
+            "chunk vertices",
+            vertex_size * vertex_capacity as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::TRANSFER_SRC
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+        let indices = match renderer.create_buffer(
+            "chunk indices",
+            (size_of::<u32>() * index_capacity as usize) as vk::DeviceSize,
+            vk::BufferUsageFlags::INDEX_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_SRC
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        ) {
+            Ok(val) => val,
+            Err(err) => {
+                renderer.destroy_buffer(vertices);
+                return Err(err);
+            }
+        };
+
+        Ok(Self {
+            vertex_address: renderer.buffer_address(&vertices),
+            vertices,
+            indices,
+            vertex_size,
+            vertex_list: FreeList::new(vertex_capacity as u64),
+            index_list: FreeList::new(index_capacity as u64),
+            slots: vec![],
+            free_slots: vec![],
+            pending: vec![],
+            moved: vec![],
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            compaction_budget: DEFAULT_COMPACTION_BUDGET,
+        })
+    }
+
+    pub fn vertex_buffer(&self) -> vk::Buffer {
+        self.vertices.handle
+    }
+
+    /// Size of the vertices in bytes
+    pub fn vertex_size(&self) -> u64 {
+        self.vertex_size
+    }
+
+    /// Device address vertex shaders pull the vertices from
+    pub fn vertex_address(&self) -> vk::DeviceAddress {
+        self.vertex_address
+    }
+
+    pub fn index_buffer(&self) -> vk::Buffer {
+        self.indices.handle
+    }
+
+    /// Fragmentation ratio (see [`ArenaStats::fragmentation`]) over which meshes start
+    /// being moved, and how many bytes can be moved per frame
+    pub fn set_compaction(&mut self, threshold: f32, budget: u64) {
+        self.compaction_threshold = threshold;
+        self.compaction_budget = budget;
+    }
+
+    pub fn range(&self, handle: MeshHandle) -> Option<MeshRange> {
+        let slot = self.slots.get(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        slot.range
+    }
+
+    /// Allocates and uploads a new mesh, `vertices` holds whole vertices. Like every
+    /// upload it must happen between [`Renderer::begin_frame`] and [`Renderer::end_frame`].
+    pub fn insert(
+        &mut self,
+        renderer: &mut Renderer,
+        vertices: &[u8],
+        indices: &[u32],
+    ) -> Result<MeshHandle, Box<dyn Error>> {
+        let index = match self.free_slots.pop() {
+            Some(val) => val,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    range: None,
+                });
+                self.slots.len() as u32 - 1
+            }
+        };
+        let handle = MeshHandle {
+            index,
+            generation: self.slots[index as usize].generation,
+        };
+
+        match self.upload(renderer, vertices, indices) {
+            Ok(range) => {
+                self.place(handle, range);
+                Ok(handle)
+            }
+            Err(err) => {
+                self.free_slots.push(index);
+                Err(err)
+            }
+        }
+    }
+
+    /// Replaces the mesh of `handle` after a chunk was re-meshed, the handle stays the
+    /// same but its range changes
+    pub fn update(
+        &mut self,
+        renderer: &mut Renderer,
+        handle: MeshHandle,
+        vertices: &[u8],
+        indices: &[u32],
+    ) -> Result<MeshRange, Box<dyn Error>> {
+        let old = self.range(handle).ok_or("Stale chunk mesh handle")?;
+
+        // the old mesh stays in place if the new one can't be uploaded
+        let range = self.upload(renderer, vertices, indices)?;
+        self.retire(old, renderer.frame_count() + 1);
+        self.place(handle, range);
+        Ok(range)
+    }
+
+    /// Overwrites the indices of `handle` in place with the same number of indices, for
+    /// translucent meshes sorted again. The range of the mesh doesn't change.
+    pub fn reorder_indices(
+        &mut self,
+        renderer: &mut Renderer,
+        handle: MeshHandle,
+        indices: &[u32],
+    ) -> Result<(), Box<dyn Error>> {
+        let range = self.range(handle).ok_or("Stale chunk mesh handle")?;
+        if indices.len() != range.index_count as usize {
+            return Err("Reordered indices don't match the mesh index count".into());
+        }
+        if indices.is_empty() {
+            return Ok(());
+        }
+
+        let data = bytemuck::cast_slice::<_, u8>(indices);
+        let src = renderer.stage(data)?;
+        self.pending.push(Copy {
+            src: Some(src),
+            index_buffer: true,
+            region: vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: range.first_index as u64 * size_of::<u32>() as u64,
+                size: data.len() as vk::DeviceSize,
+            },
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, renderer: &Renderer, handle: MeshHandle) {
+        if self.range(handle).is_none() {
+            return;
+        }
+
+        let slot = &mut self.slots[handle.index as usize];
+        let range = slot.range.take().unwrap();
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(handle.index);
+        self.retire(range, renderer.frame_count() + 1);
+    }
+
+    fn place(&mut self, handle: MeshHandle, range: MeshRange) {
+        self.slots[handle.index as usize].range = Some(range);
+        self.moved.push(handle);
+    }
+
+    /// Frees a range once `frame` is completed, the frame being recorded and the ones in
+    /// flight may still read it. Frames of other surfaces may be in flight too, so it
+    /// can't be a number of frames after the current one.
+    fn retire(&mut self, range: MeshRange, frame: u64) {
+        let (vertices, indices) = Self::blocks(&range);
+        self.vertex_list.retire(vertices, frame);
+        self.index_list.retire(indices, frame);
+    }
+
+    /// Frees a range nothing was ever recorded for
+    fn release(&mut self, range: MeshRange) {
+        let (vertices, indices) = Self::blocks(&range);
+        self.vertex_list.release(vertices);
+        self.index_list.release(indices);
+    }
+
+    /// Vertex and index blocks of `range`
+    fn blocks(range: &MeshRange) -> (Range<u64>, Range<u64>) {
+        let vertex_start = range.vertex_offset as u64;
+        let index_start = range.first_index as u64;
+        (
+            vertex_start..vertex_start + range.vertex_count as u64,
+            index_start..index_start + range.index_count as u64,
+        )
+    }
+
+    fn allocate(
+        &mut self,
+        vertex_count: u64,
+        index_count: u64,
+    ) -> Result<MeshRange, Box<dyn Error>> {
+        let vertex_offset = self
+            .vertex_list
+            .allocate(vertex_count)
+            .ok_or("Chunk geometry arena is out of vertex space")?;
+        let Some(first_index) = self.index_list.allocate(index_count) else {
+            self.vertex_list
+                .release(vertex_offset..vertex_offset + vertex_count);
+            return Err("Chunk geometry arena is out of index space".into());
+        };
+
+        Ok(MeshRange {
+            vertex_offset: vertex_offset as u32,
+            vertex_count: vertex_count as u32,
+            first_index: first_index as u32,
+            index_count: index_count as u32,
+        })
+    }
+
+    /// Allocates a range for the mesh and stages its data, nothing is left allocated
+    /// or queued on failure
+    fn upload(
+        &mut self,
+        renderer: &mut Renderer,
+        vertices: &[u8],
+        indices: &[u32],
+    ) -> Result<MeshRange, Box<dyn Error>> {
+        if !(vertices.len() as u64).is_multiple_of(self.vertex_size) {
+            return Err("Vertex data is not made of whole vertices".into());
+        }
+        let range = self.allocate(
+            vertices.len() as u64 / self.vertex_size,
+            indices.len() as u64,
+        )?;
+
+        let pending = self.pending.len();
+        let index_bytes = bytemuck::cast_slice::<_, u8>(indices);
+        for (data, offset, index_buffer) in [
+            (
+                vertices,
+                range.vertex_offset as u64 * self.vertex_size,
+                false,
+            ),
+            (
+                index_bytes,
+                range.first_index as u64 * size_of::<u32>() as u64,
+                true,
+            ),
+        ] {
+            if data.is_empty() {
+                continue;
+            }
+            let src = match renderer.stage(data) {
+                Ok(val) => val,
+                Err(err) => {
+                    self.pending.truncate(pending);
+                    self.release(range);
+                    return Err(err);
+                }
+            };
+            self.pending.push(Copy {
+                src: Some(src),
+                index_buffer,
+                region: vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: offset,
+                    size: data.len() as vk::DeviceSize,
+                },
+            });
+        }
+
+        Ok(range)
+    }
+
+    /// Meshes inserted, updated or moved by compaction since the last call, their
+    /// section draws have to be refreshed
+    pub fn take_moved(&mut self) -> Vec<MeshHandle> {
+        let mut moved = std::mem::take(&mut self.moved);
+        moved.sort_unstable_by_key(|handle| handle.index);
+        moved.dedup();
+        moved.retain(|handle| self.range(*handle).is_some());
+        moved
+    }
+
+    pub fn stats(&self) -> ArenaStats {
+        let index_size = size_of::<u32>() as u64;
+        let lists = [
+            (&self.vertex_list, self.vertex_size),
+            (&self.index_list, index_size),
+        ];
+
+        let mut stats = ArenaStats {
+            meshes: (self.slots.len() - self.free_slots.len()) as u32,
+            ..Default::default()
+        };
+        for (list, size) in lists {
+            let free = list.free_elements();
+            let retired = list.retired_elements();
+            stats.free += free * size;
+            stats.retired += retired * size;
+            stats.fragmented += (free - list.largest_free_block()) * size;
+            stats.used += (list.capacity - free - retired) * size;
+        }
+        stats
+    }
+
+    /// Moves the meshes at the end of the buffers into free blocks before them, up to
+    /// the compaction budget, with copies recorded in `frame`
+    fn compact(&mut self, frame: u64) {
+        if self.stats().fragmentation() <= self.compaction_threshold {
+            return;
+        }
+
+        // highest meshes first, they are the ones keeping the free space split
+        let mut order: Vec<u32> = (0..self.slots.len() as u32)
+            .filter(|index| self.slots[*index as usize].range.is_some())
+            .collect();
+        order.sort_unstable_by_key(|index| {
+            let range = self.slots[*index as usize].range.unwrap();
+            std::cmp::Reverse(range.vertex_offset as u64 * self.vertex_size)
+        });
+
+        let mut budget = self.compaction_budget;
+        for index in order {
+            let range = self.slots[index as usize].range.unwrap();
+            let vertex_bytes = range.vertex_count as u64 * self.vertex_size;
+            let index_bytes = range.index_count as u64 * size_of::<u32>() as u64;
+            if vertex_bytes + index_bytes > budget {
+                break;
+            }
+
+            let vertex_count = range.vertex_count as u64;
+            let Some(vertex_offset) = self
+                .vertex_list
+                .allocate_below(vertex_count, range.vertex_offset as u64)
+            else {
+                continue;
+            };
+            let index_count = range.index_count as u64;
+            let first_index = self
+                .index_list
+                .allocate_below(index_count, range.first_index as u64)
+                .unwrap_or(range.first_index as u64);
+            let moved_indices = first_index != range.first_index as u64;
+
+            // moving within a buffer is fine, the blocks are free so they can't overlap
+            self.pending.push(Copy {
+                src: None,
+                index_buffer: false,
+                region: vk::BufferCopy {
+                    src_offset: range.vertex_offset as u64 * self.vertex_size,
+                    dst_offset: vertex_offset * self.vertex_size,
+                    size: vertex_bytes,
+                },
+            });
+            self.vertex_list.retire(
+                range.vertex_offset as u64..range.vertex_offset as u64 + vertex_count,
+                frame,
+            );
+
+            if moved_indices {
+                self.pending.push(Copy {
+                    src: None,
+                    index_buffer: true,
+                    region: vk::BufferCopy {
+                        src_offset: range.first_index as u64 * size_of::<u32>() as u64,
+                        dst_offset: first_index * size_of::<u32>() as u64,
+                        size: index_bytes,
+                    },
+                });
+                self.index_list.retire(
+                    range.first_index as u64..range.first_index as u64 + index_count,
+                    frame,
+                );
+            }
+
+            self.slots[index as usize].range = Some(MeshRange {
+                vertex_offset: vertex_offset as u32,
+                first_index: first_index as u32,
+                ..range
+            });
+            self.moved.push(MeshHandle {
+                index,
+                generation: self.slots[index as usize].generation,
+            });
+            budget -= vertex_bytes + index_bytes;
+        }
+    }
+
+    /// Records the pending uploads and this frame's compaction moves, returns the arena
+    /// buffers for the passes drawing from them to declare
+    pub fn flush(&mut self, renderer: &mut Renderer) -> (BufferId, BufferId) {
+        let completed = renderer.completed_frame();
+        self.vertex_list.collect(completed);
+        self.index_list.collect(completed);
+
+        // uploads are recorded first, compaction may move what they wrote
+        let uploads = self.pending.len();
+        self.compact(renderer.frame_count() + 1);
+
+        let copies = std::mem::take(&mut self.pending);
+        let graph = renderer.graph();
+        let buffers = self.import(graph);
+        if copies.is_empty() {
+            return buffers;
+        }
+
+        let (vertex_buffer, index_buffer) = (self.vertices.handle, self.indices.handle);
+        graph
+            .add_pass("upload chunk geometry")
+            .read_buffer(buffers.0, BufferUsage::TransferSrc)
+            .read_buffer(buffers.1, BufferUsage::TransferSrc)
+            .write_buffer(buffers.0, BufferUsage::TransferDst)
+            .write_buffer(buffers.1, BufferUsage::TransferDst)
+            .side_effects()
+            .execute(move |ctx| {
+                for (position, copy) in copies.iter().enumerate() {
+                    if position == uploads && uploads > 0 {
+                        let barrier = vk::MemoryBarrier2::default()
+                            .src_stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
+                            .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                            .dst_stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
+                            .dst_access_mask(vk::AccessFlags2::TRANSFER_READ);
+                        let barriers = [barrier];
+                        let dependency = vk::DependencyInfo::default().memory_barriers(&barriers);
+                        unsafe { ctx.device.cmd_pipeline_barrier2(ctx.cmd, &dependency) };
+                    }
+
+                    let dst = if copy.index_buffer {
+                        index_buffer
+                    } else {
+                        vertex_buffer
+                    };
+                    let src = copy.src.unwrap_or(dst);
+                    unsafe {
+                        ctx.device
+                            .cmd_copy_buffer(ctx.cmd, src, dst, &[copy.region])
+                    };
+                }
+            });
+
+        buffers
+    }
+
+    /// Imports the arena buffers in `graph` as (vertices, indices)
+    pub fn import(&self, graph: &mut RenderGraph) -> (BufferId, BufferId) {
+        (
+            graph.import_buffer("chunk vertices", self.vertices.handle),
+            graph.import_buffer("chunk indices", self.indices.handle),
+        )
+    }
+
+    /// Nothing may be in flight that still uses the arena
+    pub fn destroy(self, renderer: &Renderer) {
+        renderer.destroy_buffer(self.vertices);
+        renderer.destroy_buffer(self.indices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERTEX_SIZE: u64 = 8;
+    const INDEX_SIZE: u64 = size_of::<u32>() as u64;
+
+    fn arena(vertex_capacity: u64, index_capacity: u64) -> GeometryArena {
+        GeometryArena {
+            vertices: core::AllocatedBuffer::null(vertex_capacity * VERTEX_SIZE),
+            indices: core::AllocatedBuffer::null(index_capacity * INDEX_SIZE),
+            vertex_address: 0,
+            vertex_size: VERTEX_SIZE,
+            vertex_list: FreeList::new(vertex_capacity),
+            index_list: FreeList::new(index_capacity),
+            slots: vec![],
+            free_slots: vec![],
+            pending: vec![],
+            moved: vec![],
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            compaction_budget: DEFAULT_COMPACTION_BUDGET,
+        }
+    }
+
+    /// Free blocks of `list` as (start, end)
+    fn free_blocks(list: &FreeList) -> Vec<(u64, u64)> {
+        list.free
+            .iter()
+            .map(|block| (block.start, block.end))
+            .collect()
+    }
+
+    /// Same bookkeeping as [`GeometryArena::insert`] without staging any data
+    fn add(arena: &mut GeometryArena, vertices: u64, indices: u64) -> MeshHandle {
+        arena.slots.push(Slot {
+            generation: 0,
+            range: None,
+        });
+        let handle = MeshHandle {
+            index: arena.slots.len() as u32 - 1,
+            generation: 0,
+        };
+        let range = arena.allocate(vertices, indices).unwrap();
+        arena.place(handle, range);
+        handle
+    }
+
+    /// Same bookkeeping as [`GeometryArena::remove`]
+    fn free(arena: &mut GeometryArena, handle: MeshHandle, frame: u64) {
+        let range = arena.slots[handle.index as usize].range.take().unwrap();
+        arena.free_slots.push(handle.index);
+        arena.retire(range, frame);
+    }
+
+    #[test]
+    fn free_list_allocates_first_fit() {
+        let mut list = FreeList::new(100);
+        assert_eq!(list.allocate(30), Some(0));
+        assert_eq!(list.allocate(30), Some(30));
+        list.release(0..30);
+
+        // the hole at the start is too small, the block after the allocations is used
+        assert_eq!(list.allocate(40), Some(60));
+        assert_eq!(list.allocate(20), Some(0));
+        assert_eq!(list.allocate(20), None);
+        assert_eq!(free_blocks(&list), [(20, 30)]);
+    }
+
+    #[test]
+    fn free_list_coalesces_released_blocks() {
+        let mut list = FreeList::new(40);
+        for offset in [0, 10, 20, 30] {
+            assert_eq!(list.allocate(10), Some(offset));
+        }
+
+        list.release(10..20);
+        list.release(30..40);
+        assert_eq!(free_blocks(&list), [(10, 20), (30, 40)]);
+        assert_eq!(list.largest_free_block(), 10);
+
+        // merges with both neighbours at once
+        list.release(20..30);
+        assert_eq!(free_blocks(&list), [(10, 40)]);
+        list.release(0..10);
+        assert_eq!(free_blocks(&list), [(0, 40)]);
+        assert_eq!(list.free_elements(), 40);
+
+        list.release(0..0);
+        assert_eq!(free_blocks(&list), [(0, 40)]);
+    }
+
+    #[test]
+    fn free_list_allocates_below_a_bound() {
+        let mut list = FreeList::new(100);
+        list.allocate(100);
+        list.release(10..20);
+        list.release(50..80);
+
+        assert_eq!(list.allocate_below(20, 60), None);
+        assert_eq!(list.allocate_below(20, 70), Some(50));
+        assert_eq!(list.allocate_below(5, 70), Some(10));
+    }
+
+    #[test]
+    fn retired_blocks_wait_for_their_frame_to_complete() {
+        let mut list = FreeList::new(10);
+        list.allocate(10);
+        list.retire(0..4, 7);
+        list.retire(4..4, 7);
+        list.retire(4..6, 9);
+        assert_eq!(list.retired_elements(), 6);
+
+        list.collect(6);
+        assert_eq!(list.free_elements(), 0);
+        assert_eq!(list.allocate(4), None);
+
+        // however many frames were submitted since, by other surfaces too
+        list.collect(7);
+        assert_eq!(list.retired_elements(), 2);
+        assert_eq!(free_blocks(&list), [(0, 4)]);
+        list.collect(9);
+        assert_eq!(free_blocks(&list), [(0, 6)]);
+    }
+
+    #[test]
+    fn failed_allocations_leave_nothing_behind() {
+        let mut arena = arena(100, 10);
+        assert!(arena.allocate(50, 20).is_err());
+        assert!(arena.allocate(200, 5).is_err());
+        assert_eq!(free_blocks(&arena.vertex_list), [(0, 100)]);
+        assert_eq!(free_blocks(&arena.index_list), [(0, 10)]);
+
+        let range = arena.allocate(50, 10).unwrap();
+        arena.release(range);
+        assert_eq!(free_blocks(&arena.vertex_list), [(0, 100)]);
+        assert_eq!(free_blocks(&arena.index_list), [(0, 10)]);
+    }
+
+    #[test]
+    fn stats_count_both_buffers() {
+        let mut arena = arena(100, 200);
+        let first = add(&mut arena, 10, 20);
+        add(&mut arena, 30, 40);
+        add(&mut arena, 10, 20);
+        free(&mut arena, first, 1);
+
+        let stats = arena.stats();
+        assert_eq!(stats.meshes, 2);
+        assert_eq!(stats.used, 40 * VERTEX_SIZE + 60 * INDEX_SIZE);
+        assert_eq!(stats.retired, 10 * VERTEX_SIZE + 20 * INDEX_SIZE);
+        assert_eq!(stats.free, 50 * VERTEX_SIZE + 120 * INDEX_SIZE);
+        assert_eq!(stats.fragmented, 0);
+
+        arena.vertex_list.collect(1);
+        arena.index_list.collect(1);
+        let stats = arena.stats();
+        assert_eq!(stats.retired, 0);
+        assert_eq!(stats.free, 60 * VERTEX_SIZE + 140 * INDEX_SIZE);
+        assert_eq!(stats.fragmented, 10 * VERTEX_SIZE + 20 * INDEX_SIZE);
+        let fragmentation = stats.fragmented as f32 / stats.free as f32;
+        assert!((stats.fragmentation() - fragmentation).abs() < 1e-6);
+
+        assert_eq!(ArenaStats::default().fragmentation(), 0.0);
+    }
+
+    #[test]
+    fn compaction_moves_the_last_meshes_into_the_holes() {
+        let mut arena = arena(100, 100);
+        let meshes: Vec<_> = (0..5).map(|_| add(&mut arena, 20, 20)).collect();
+        free(&mut arena, meshes[0], 1);
+        free(&mut arena, meshes[2], 1);
+        arena.take_moved();
+
+        let frame = 2;
+        arena.vertex_list.collect(frame - 1);
+        arena.index_list.collect(frame - 1);
+        assert_eq!(arena.stats().fragmentation(), 0.5);
+
+        arena.compact(frame);
+
+        // the two highest meshes fill the two holes, the lowest free block first
+        let moved = arena.take_moved();
+        assert_eq!(moved, [meshes[3], meshes[4]]);
+        assert_eq!(arena.range(meshes[4]).unwrap().vertex_offset, 0);
+        assert_eq!(arena.range(meshes[4]).unwrap().first_index, 0);
+        assert_eq!(arena.range(meshes[3]).unwrap().vertex_offset, 40);
+        assert_eq!(arena.range(meshes[1]).unwrap().vertex_offset, 20);
+
+        // copies within the buffers, from the old ranges to the new ones
+        assert_eq!(arena.pending.len(), 4);
+        assert!(arena.pending.iter().all(|copy| copy.src.is_none()));
+        let vertex_copy = &arena.pending[0];
+        assert!(!vertex_copy.index_buffer);
+        assert_eq!(vertex_copy.region.src_offset, 80 * VERTEX_SIZE);
+        assert_eq!(vertex_copy.region.dst_offset, 0);
+        assert_eq!(vertex_copy.region.size, 20 * VERTEX_SIZE);
+
+        // the old ranges are only reused once the frame copying them is completed
+        arena.vertex_list.collect(frame - 1);
+        assert!(arena.vertex_list.free.is_empty());
+        arena.vertex_list.collect(frame);
+        arena.index_list.collect(frame);
+        assert_eq!(free_blocks(&arena.vertex_list), [(60, 100)]);
+        assert_eq!(arena.stats().fragmentation(), 0.0);
+    }
+
+    #[test]
+    fn compaction_waits_for_the_threshold_and_keeps_to_the_budget() {
+        let mut arena = arena(100, 100);
+        let meshes: Vec<_> = (0..5).map(|_| add(&mut arena, 20, 20)).collect();
+        free(&mut arena, meshes[0], 1);
+        free(&mut arena, meshes[2], 1);
+        arena.take_moved();
+
+        let frame = 2;
+        arena.vertex_list.collect(frame - 1);
+        arena.index_list.collect(frame - 1);
+
+        arena.set_compaction(0.5, DEFAULT_COMPACTION_BUDGET);
+        arena.compact(frame);
+        assert!(arena.take_moved().is_empty());
+        assert!(arena.pending.is_empty());
+
+        // room for a single mesh
+        arena.set_compaction(0.25, 20 * VERTEX_SIZE + 20 * INDEX_SIZE);
+        arena.compact(frame);
+        assert_eq!(arena.take_moved(), [meshes[4]]);
+        assert_eq!(arena.range(meshes[3]).unwrap().vertex_offset, 60);
+    }
+}
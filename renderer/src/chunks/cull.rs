@@ -0,0 +1,699 @@
+use std::{error::Error, mem::size_of, ops::Range};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec3, Mat4, Vec3};
+
+use super::hiz::HiZ;
+use crate::{
+    core,
+    graph::{BufferId, BufferUsage, ImageUsage, PassContext, RenderGraph},
+    shaders, Frustum, MemoryLocation, Renderer,
+};
+
+const CULL_GROUP_SIZE: u32 = 64;
+
+/// Largest update `vkCmdUpdateBuffer` accepts in one call
+const MAX_UPDATE_SIZE: usize = 65536;
+
+/// Lines drawn for the box of a section in the debug view
+const BOX_VERTICES: u32 = 24;
+
+// flags of `CullParams` in `chunk_section.glsl`
+const CULL_OCCLUSION: u32 = 1;
+const CULL_REVERSE_Z: u32 = 2;
+
+/// Pass chunk geometry is drawn in, each with its own blend and depth state
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderLayer {
+    /// Written to depth, drawn first
+    Opaque,
+    /// Opaque with holes, alpha tested, like leaves
+    Cutout,
+    /// Alpha blended without writing depth, like water or glass, drawn last from
+    /// back to front
+    Translucent,
+}
+
+impl RenderLayer {
+    pub const COUNT: usize = 3;
+
+    pub const ALL: [RenderLayer; Self::COUNT] = [
+        RenderLayer::Opaque,
+        RenderLayer::Cutout,
+        RenderLayer::Translucent,
+    ];
+}
+
+/// Layers culled on the GPU and drawn with indirect draws, translucent sections are
+/// sorted on the CPU since the cull shader can't keep them in order
+const CULLED_LAYERS: usize = 2;
+
+/// Where the indices and vertices of a section mesh start in the buffers shared by
+/// every section
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MeshDraw {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub vertex_offset: i32,
+}
+
+/// Draw parameters of one chunk section
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SectionDraw {
+    pub aabb_min: Vec3,
+    pub aabb_max: Vec3,
+    /// Corner of the section its packed vertex positions are relative to
+    pub origin: IVec3,
+    /// Mesh of each [`RenderLayer`]
+    pub layers: [MeshDraw; RenderLayer::COUNT],
+}
+
+/// Layout of `Section` in `chunk_section.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+struct GpuSection {
+    aabb_min: [f32; 4],
+    aabb_max: [f32; 4],
+    origin: [i32; 4],
+    /// First index, index count and vertex offset of every layer
+    layers: [[u32; 4]; RenderLayer::COUNT],
+}
+
+impl GpuSection {
+    fn mesh(&self, layer: RenderLayer) -> MeshDraw {
+        let [first_index, index_count, vertex_offset, _] = self.layers[layer as usize];
+        MeshDraw {
+            first_index,
+            index_count,
+            vertex_offset: vertex_offset as i32,
+        }
+    }
+}
+
+/// Layout of `CullParams` in `chunk_section.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct CullParams {
+    planes: [[f32; 4]; 6],
+    hiz_view_proj: [f32; 16],
+    hiz_size: [f32; 2],
+    section_count: u32,
+    flags: u32,
+    /// Draws of each layer start this many draws after those of the previous one
+    layer_stride: u32,
+    _pad: [u32; 3],
+}
+
+/// Draws left by [`ChunkCuller::cull`] for the frame being recorded
+#[derive(Clone, Copy, Debug)]
+pub struct CulledDraws {
+    /// `VkDrawIndexedIndirectCommand`s of each culled layer one after the other, the
+    /// instance index is the section slot
+    pub draws: BufferId,
+    /// Draw count of each culled layer
+    pub count: BufferId,
+    pub max_draws: u32,
+    /// Draws between the first ones of two layers
+    pub layer_stride: u32,
+}
+
+impl CulledDraws {
+    /// Buffers a pass drawing these has to declare
+    pub fn reads(&self) -> [(BufferId, BufferUsage); 2] {
+        [
+            (self.draws, BufferUsage::Indirect),
+            (self.count, BufferUsage::Indirect),
+        ]
+    }
+
+    /// Issues every visible section of `layer` in a single indirect draw, the pipeline
+    /// and the shared index buffer must already be bound. Translucent sections are not
+    /// culled on the GPU, they are skipped.
+    pub fn draw(&self, ctx: &PassContext, layer: RenderLayer) {
+        let layer = layer as usize;
+        if layer >= CULLED_LAYERS {
+            return;
+        }
+
+        let stride = size_of::<vk::DrawIndexedIndirectCommand>();
+        unsafe {
+            ctx.device.cmd_draw_indexed_indirect_count(
+                ctx.cmd,
+                ctx.buffer(self.draws),
+                (layer * self.layer_stride as usize * stride) as vk::DeviceSize,
+                ctx.buffer(self.count),
+                (layer * size_of::<u32>()) as vk::DeviceSize,
+                self.max_draws,
+                stride as u32,
+            )
+        };
+        ctx.count_draws(1);
+    }
+}
+
+/// Pipeline drawing the section boxes, tied to the attachments it was created for
+struct DebugView {
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    formats: (vk::Format, vk::Format, vk::SampleCountFlags),
+}
+
+/// Keeps the draw parameters of every chunk section in a GPU buffer and culls them in
+/// a compute pass, against the frustum and the depth of the previous frame, so drawing
+/// the world takes one indirect draw
+pub struct ChunkCuller {
+    pipeline: core::ComputePipeline,
+    set_layout: vk::DescriptorSetLayout,
+    set: vk::DescriptorSet,
+    sections: core::AllocatedBuffer,
+    draws: core::AllocatedBuffer,
+    count: core::AllocatedBuffer,
+    params: core::AllocatedBuffer,
+    /// Why each section was culled, read by the debug view
+    status: core::AllocatedBuffer,
+    hiz: HiZ,
+    host: Vec<GpuSection>,
+    /// Slots changed since the last upload
+    dirty: Option<Range<usize>>,
+    /// One past the highest slot ever used, the cull dispatch covers up to it
+    used: usize,
+    occlusion: bool,
+    /// Camera of the last cull
+    view_proj: Mat4,
+    /// Camera the depth pyramid was built with, `None` until it's built once
+    hiz_view_proj: Option<Mat4>,
+    debug: bool,
+    debug_view: Option<DebugView>,
+}
+
+impl ChunkCuller {
+    pub fn new(renderer: &mut Renderer, capacity: u32) -> Result<Self, Box<dyn Error>> {
+        let device = renderer.device_handle().clone();
+
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::STORAGE_BUFFER)
+            .add_binding(1, vk::DescriptorType::STORAGE_BUFFER)
+            .add_binding(2, vk::DescriptorType::STORAGE_BUFFER)
+            .add_binding(3, vk::DescriptorType::UNIFORM_BUFFER)
+            .add_binding(4, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .add_binding(5, vk::DescriptorType::STORAGE_BUFFER)
+            .build(
+                &device,
+                vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX,
+            )?;
+
+        let code = shaders::words(shaders::CULL_CHUNKS);
+        let pipeline = renderer.create_compute_pipeline(&core::ComputePipelineSpec {
+            code: &code,
+            entry: c"main",
+            set_layouts: &[set_layout],
+            push_constant_size: 0,
+        })?;
+
+        let sections = renderer.create_buffer(
+            "chunk sections",
+            (capacity as usize * size_of::<GpuSection>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+        let draws = renderer.create_buffer(
+            "chunk draws",
+            (CULLED_LAYERS * capacity as usize * size_of::<vk::DrawIndexedIndirectCommand>())
+                as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            MemoryLocation::GpuOnly,
+        )?;
+        let count = renderer.create_buffer(
+            "chunk draw count",
+            (CULLED_LAYERS * size_of::<u32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+        let params = renderer.create_buffer(
+            "chunk cull params",
+            size_of::<CullParams>() as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+        let status = renderer.create_buffer(
+            "chunk cull status",
+            (capacity as usize * size_of::<u32>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            MemoryLocation::GpuOnly,
+        )?;
+
+        let hiz = HiZ::new(renderer)?;
+
+        let set = renderer.allocate_descriptor_set(set_layout)?;
+        core::DescriptorWriter::new()
+            .storage_buffer(0, sections.handle)
+            .storage_buffer(1, draws.handle)
+            .storage_buffer(2, count.handle)
+            .write_buffer(
+                3,
+                params.handle,
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::UNIFORM_BUFFER,
+            )
+            .storage_buffer(5, status.handle)
+            .update_set(&device, set);
+
+        let culler = Self {
+            pipeline,
+            set_layout,
+            set,
+            sections,
+            draws,
+            count,
+            params,
+            status,
+            hiz,
+            host: vec![GpuSection::default(); capacity as usize],
+            // empty slots must be uploaded once so the shader skips them
+            dirty: Some(0..capacity as usize),
+            used: 0,
+            occlusion: true,
+            view_proj: Mat4::IDENTITY,
+            hiz_view_proj: None,
+            debug: false,
+            debug_view: None,
+        };
+        culler.write_hiz_descriptor(&device);
+
+        Ok(culler)
+    }
+
+    fn write_hiz_descriptor(&self, device: &ash::Device) {
+        core::DescriptorWriter::new()
+            .write_image(
+                4,
+                self.hiz.view(),
+                self.hiz.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            )
+            .update_set(device, self.set);
+    }
+
+    pub(super) fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    /// Set with the sections at binding 0, readable from vertex shaders
+    pub(super) fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    pub(super) fn import_sections(&self, graph: &mut RenderGraph) -> BufferId {
+        graph.import_buffer("chunk sections", self.sections.handle)
+    }
+
+    /// Sections with geometry in `layer` whose box intersects `frustum`, with their slot
+    /// and the center of their box
+    fn sections_in<'a>(
+        &'a self,
+        frustum: &'a Frustum,
+        layer: RenderLayer,
+    ) -> impl Iterator<Item = (u32, &'a GpuSection, Vec3)> + 'a {
+        self.host[..self.used]
+            .iter()
+            .enumerate()
+            .filter(move |(_, section)| section.mesh(layer).index_count > 0)
+            .filter_map(|(slot, section)| {
+                let min = Vec3::from_slice(&section.aabb_min[..3]);
+                let max = Vec3::from_slice(&section.aabb_max[..3]);
+                frustum
+                    .intersects_aabb(min, max)
+                    .then(|| (slot as u32, section, (min + max) * 0.5))
+            })
+    }
+
+    /// Sections with translucent geometry in `frustum`, farthest from `eye` first, with
+    /// their slot
+    pub(super) fn translucent_sections(
+        &self,
+        frustum: &Frustum,
+        eye: Vec3,
+    ) -> Vec<(u32, MeshDraw)> {
+        let mut sections: Vec<_> = self
+            .sections_in(frustum, RenderLayer::Translucent)
+            .map(|(slot, section, center)| {
+                (
+                    center.distance_squared(eye),
+                    slot,
+                    section.mesh(RenderLayer::Translucent),
+                )
+            })
+            .collect();
+        sections.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        sections
+            .into_iter()
+            .map(|(_, slot, mesh)| (slot, mesh))
+            .collect()
+    }
+
+    /// Sections with `layer` geometry in `frustum` with their slot, in no particular
+    /// order, for passes drawing without the GPU culling like shadow maps
+    pub(super) fn sections_with(
+        &self,
+        frustum: &Frustum,
+        layer: RenderLayer,
+    ) -> Vec<(u32, MeshDraw)> {
+        self.sections_in(frustum, layer)
+            .map(|(slot, section, _)| (slot, section.mesh(layer)))
+            .collect()
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.host.len() as u32
+    }
+
+    /// Occlusion culling against the depth of the previous frame, on by default.
+    /// It's skipped when MSAA is on since multisampled depth can't be reduced.
+    pub fn set_occlusion(&mut self, enabled: bool) {
+        self.occlusion = enabled;
+    }
+
+    /// Draws the box of every section in the frustum, green when drawn and red when
+    /// occluded, see [`ChunkCuller::draw_debug`]
+    pub fn set_debug_view(&mut self, enabled: bool) {
+        self.debug = enabled;
+    }
+
+    fn mark_dirty(&mut self, slot: usize) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(range) => range.start.min(slot)..range.end.max(slot + 1),
+            None => slot..slot + 1,
+        });
+    }
+
+    /// Sets the section drawn from `slot`, visible from the next [`ChunkCuller::cull`]
+    pub fn set_section(&mut self, slot: u32, section: &SectionDraw) {
+        let slot = slot as usize;
+        self.host[slot] = GpuSection {
+            aabb_min: section.aabb_min.extend(0.0).to_array(),
+            aabb_max: section.aabb_max.extend(0.0).to_array(),
+            origin: section.origin.extend(0).to_array(),
+            layers: section.layers.map(|mesh| {
+                [
+                    mesh.first_index,
+                    mesh.index_count,
+                    mesh.vertex_offset as u32,
+                    0,
+                ]
+            }),
+        };
+        self.used = self.used.max(slot + 1);
+        self.mark_dirty(slot);
+    }
+
+    pub fn clear_section(&mut self, slot: u32) {
+        self.host[slot as usize] = GpuSection::default();
+        self.mark_dirty(slot as usize);
+    }
+
+    fn occlusion_available(&self, renderer: &Renderer) -> bool {
+        self.occlusion && renderer.sample_count() == vk::SampleCountFlags::TYPE_1
+    }
+
+    /// Makes sure the depth pyramid matches the depth buffer of the current frame
+    fn prepare_hiz(&mut self, renderer: &mut Renderer) -> Result<(), Box<dyn Error>> {
+        let frame = renderer.frame().ok_or("Culling outside of a frame")?;
+        let depth = renderer
+            .graph()
+            .imported_image(frame.depth)
+            .ok_or("Depth buffer is not imported")?;
+        let (view, extent) = (depth.view, depth.extent);
+
+        if self.hiz.prepare(renderer, view, extent)? {
+            self.write_hiz_descriptor(renderer.device_handle());
+            self.hiz_view_proj = None;
+        }
+        Ok(())
+    }
+
+    /// Adds the passes uploading the changed sections and culling them for the camera
+    /// `view_proj` in the current frame
+    pub fn cull(
+        &mut self,
+        renderer: &mut Renderer,
+        view_proj: &Mat4,
+    ) -> Result<CulledDraws, Box<dyn Error>> {
+        let occlusion = self.occlusion_available(renderer);
+        if occlusion {
+            self.prepare_hiz(renderer)?;
+        }
+
+        let mut flags = 0;
+        if renderer.reverse_z() {
+            flags |= CULL_REVERSE_Z;
+        }
+        if let (true, Some(_)) = (occlusion, self.hiz_view_proj) {
+            flags |= CULL_OCCLUSION;
+        }
+
+        let hiz_extent = self.hiz.extent();
+        let params = CullParams {
+            planes: Frustum::from_view_proj(view_proj)
+                .planes
+                .map(|plane| plane.to_array()),
+            hiz_view_proj: self.hiz_view_proj.unwrap_or_default().to_cols_array(),
+            hiz_size: [hiz_extent.width as f32, hiz_extent.height as f32],
+            section_count: self.used as u32,
+            flags,
+            layer_stride: self.capacity(),
+            _pad: [0; 3],
+        };
+        self.view_proj = *view_proj;
+
+        let graph = renderer.graph();
+        let sections = self.import_sections(graph);
+        let draws = graph.import_buffer("chunk draws", self.draws.handle);
+        let count = graph.import_buffer("chunk draw count", self.count.handle);
+        let params_buffer = graph.import_buffer("chunk cull params", self.params.handle);
+        let status = graph.import_buffer("chunk cull status", self.status.handle);
+        // built at the end of the last frame, the build of this frame is ordered after
+        // the cull reading it
+        let hiz = self.hiz.import(graph);
+
+        if let Some(range) = self.dirty.take() {
+            let offset = (range.start * size_of::<GpuSection>()) as vk::DeviceSize;
+            let data = bytemuck::cast_slice::<_, u8>(&self.host[range]).to_vec();
+
+            // the sections persist across frames, the upload can't be culled
+            graph
+                .add_pass("upload chunk sections")
+                .write_buffer(sections, BufferUsage::TransferDst)
+                .side_effects()
+                .execute(move |ctx| {
+                    for (index, chunk) in data.chunks(MAX_UPDATE_SIZE).enumerate() {
+                        let chunk_offset = offset + (index * MAX_UPDATE_SIZE) as vk::DeviceSize;
+                        unsafe {
+                            ctx.device.cmd_update_buffer(
+                                ctx.cmd,
+                                ctx.buffer(sections),
+                                chunk_offset,
+                                chunk,
+                            )
+                        };
+                    }
+                });
+        }
+
+        graph
+            .add_pass("upload chunk cull params")
+            .write_buffer(params_buffer, BufferUsage::TransferDst)
+            .execute(move |ctx| unsafe {
+                ctx.device.cmd_update_buffer(
+                    ctx.cmd,
+                    ctx.buffer(params_buffer),
+                    0,
+                    bytemuck::bytes_of(&params),
+                )
+            });
+
+        graph
+            .add_pass("reset chunk draw count")
+            .write_buffer(count, BufferUsage::TransferDst)
+            .execute(move |ctx| unsafe {
+                ctx.device
+                    .cmd_fill_buffer(ctx.cmd, ctx.buffer(count), 0, vk::WHOLE_SIZE, 0)
+            });
+
+        let pipeline = self.pipeline;
+        let set = self.set;
+        let groups = (self.used as u32).div_ceil(CULL_GROUP_SIZE);
+
+        graph
+            .add_pass("cull chunks")
+            .read_buffer(sections, BufferUsage::Storage)
+            .read_buffer(params_buffer, BufferUsage::Uniform)
+            .read_image(hiz, ImageUsage::Sampled)
+            .write_buffer(draws, BufferUsage::Storage)
+            .write_buffer(count, BufferUsage::Storage)
+            .write_buffer(status, BufferUsage::Storage)
+            .execute(move |ctx| {
+                ctx.bind_compute(&pipeline, &[set], &[]);
+                if groups > 0 {
+                    ctx.dispatch(groups, 1, 1);
+                }
+            });
+
+        Ok(CulledDraws {
+            draws,
+            count,
+            max_draws: self.used as u32,
+            layer_stride: self.capacity(),
+        })
+    }
+
+    /// Adds the pass building the depth pyramid the next frame is occlusion culled
+    /// with, call it once the occluders of the current frame are drawn
+    pub fn build_hiz(&mut self, renderer: &mut Renderer) -> Result<(), Box<dyn Error>> {
+        if !self.occlusion_available(renderer) {
+            self.hiz_view_proj = None;
+            return Ok(());
+        }
+
+        self.prepare_hiz(renderer)?;
+        let frame = renderer
+            .frame()
+            .ok_or("Building the depth pyramid outside of a frame")?;
+        self.hiz.build(renderer.graph(), frame.depth);
+        self.hiz_view_proj = Some(self.view_proj);
+        Ok(())
+    }
+
+    /// Draws the debug view over the current frame if enabled with
+    /// [`ChunkCuller::set_debug_view`], after [`ChunkCuller::cull`]
+    pub fn draw_debug(
+        &mut self,
+        renderer: &mut Renderer,
+        view_proj: &Mat4,
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.debug {
+            return Ok(());
+        }
+
+        let frame = renderer.frame().ok_or("Debug view outside of a frame")?;
+        let formats = (
+            renderer
+                .draw_format(frame.surface)
+                .ok_or("Unknown surface")?,
+            renderer.depth_format(),
+            renderer.sample_count(),
+        );
+
+        if self.debug_view.as_ref().map(|val| val.formats) != Some(formats) {
+            if let Some(old) = self.debug_view.take() {
+                renderer.wait_idle();
+                Self::destroy_debug_view(renderer.device_handle(), old);
+            }
+            self.debug_view = Some(self.create_debug_view(renderer.device_handle(), formats)?);
+        }
+
+        let debug_view = self.debug_view.as_ref().unwrap();
+        let (pipeline, layout, set) = (debug_view.pipeline, debug_view.layout, self.set);
+        let instances = self.used as u32;
+        let view_proj = *view_proj;
+
+        let graph = renderer.graph();
+        let sections = self.import_sections(graph);
+        let status = graph.import_buffer("chunk cull status", self.status.handle);
+
+        let reads = [
+            (sections, BufferUsage::Storage),
+            (status, BufferUsage::Storage),
+        ];
+        renderer.draw_reading("chunk cull debug", &reads, move |ctx| unsafe {
+            ctx.device
+                .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            ctx.device.cmd_bind_descriptor_sets(
+                ctx.cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[set],
+                &[],
+            );
+            ctx.device.cmd_push_constants(
+                ctx.cmd,
+                layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytemuck::bytes_of(&view_proj),
+            );
+            ctx.device.cmd_draw(ctx.cmd, BOX_VERTICES, instances, 0, 0);
+            ctx.count_draws(1);
+        })
+    }
+
+    fn create_debug_view(
+        &self,
+        device: &ash::Device,
+        formats: (vk::Format, vk::Format, vk::SampleCountFlags),
+    ) -> Result<DebugView, vk::Result> {
+        let layout = core::create_pipeline_layout(
+            device,
+            &[self.set_layout],
+            size_of::<Mat4>() as u32,
+            vk::ShaderStageFlags::VERTEX,
+        )?;
+
+        let vertex = shaders::words(shaders::CULL_DEBUG_VERT);
+        let fragment = shaders::words(shaders::COLOR_FRAG);
+
+        // no depth test, occluded boxes are behind the terrain by definition
+        let pipeline = core::GraphicsPipelineBuilder::new(layout)
+            .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+            .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+            .topology(vk::PrimitiveTopology::LINE_LIST)
+            .color_format(formats.0)
+            .depth(formats.1, None, false)
+            .samples(formats.2)
+            .build(device);
+
+        match pipeline {
+            Ok(pipeline) => Ok(DebugView {
+                pipeline,
+                layout,
+                formats,
+            }),
+            Err(err) => {
+                unsafe { device.destroy_pipeline_layout(layout, None) };
+                Err(err)
+            }
+        }
+    }
+
+    fn destroy_debug_view(device: &ash::Device, debug_view: DebugView) {
+        unsafe {
+            device.destroy_pipeline(debug_view.pipeline, None);
+            device.destroy_pipeline_layout(debug_view.layout, None);
+        }
+    }
+
+    /// Nothing may be in flight that still uses the culler
+    pub fn destroy(mut self, renderer: &Renderer) {
+        if let Some(debug_view) = self.debug_view.take() {
+            Self::destroy_debug_view(renderer.device_handle(), debug_view);
+        }
+        self.hiz.destroy(renderer);
+        renderer.destroy_compute_pipeline(self.pipeline);
+        unsafe {
+            renderer
+                .device_handle()
+                .destroy_descriptor_set_layout(self.set_layout, None)
+        };
+        renderer.destroy_buffer(self.sections);
+        renderer.destroy_buffer(self.draws);
+        renderer.destroy_buffer(self.count);
+        renderer.destroy_buffer(self.params);
+        renderer.destroy_buffer(self.status);
+    }
+}
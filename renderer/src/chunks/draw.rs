@@ -0,0 +1,340 @@
+use std::{collections::HashMap, error::Error, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{IVec3, Mat4, Vec3};
+
+use super::{
+    BlockTextures, ChunkCuller, CulledDraws, GeometryArena, MeshHandle, PackedVertex, RenderLayer,
+    ShadowMaps,
+};
+use crate::{
+    core,
+    graph::BufferUsage,
+    mesher::{SectionMesh, SortTrigger},
+    shaders, Frustum, Renderer,
+};
+
+/// Layout of `Constants` in `voxel.vert` and `voxel.frag`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(super) struct DrawConstants {
+    pub view_proj: [f32; 16],
+    pub vertices: vk::DeviceAddress,
+    pub render_layer: u32,
+    pub _pad: u32,
+}
+
+/// Pipelines of every layer, tied to the attachments they were created for
+struct Pipelines {
+    handles: [vk::Pipeline; RenderLayer::COUNT],
+    layout: vk::PipelineLayout,
+    formats: (vk::Format, vk::Format, vk::SampleCountFlags),
+}
+
+/// Translucent mesh kept on the CPU to sort its quads again as the camera moves
+struct TranslucentMesh {
+    /// Corner of the section the vertex positions are relative to
+    origin: IVec3,
+    mesh: SectionMesh,
+    /// Whether the indices in the arena are sorted for the block the camera is in
+    sorted: bool,
+}
+
+/// Draws the sections left visible by a [`ChunkCuller`], pulling their packed vertices
+/// from a [`GeometryArena`] through its device address. Back faces of opaque geometry
+/// are culled, quads must be counter clockwise seen from outside with a projection
+/// flipping y for Vulkan.
+#[derive(Default)]
+pub struct ChunkRenderer {
+    pipelines: Option<Pipelines>,
+    translucent: HashMap<MeshHandle, TranslucentMesh>,
+    sort_trigger: SortTrigger,
+}
+
+impl ChunkRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the translucent `mesh` of the section at `origin`, uploaded to the arena as
+    /// `handle`, so its quads get sorted by [`ChunkRenderer::sort_translucent`]. Replaces
+    /// the mesh kept for `handle` when the section is meshed again.
+    pub fn set_translucent(&mut self, handle: MeshHandle, origin: IVec3, mesh: SectionMesh) {
+        let mesh = TranslucentMesh {
+            origin,
+            mesh,
+            sorted: false,
+        };
+        self.translucent.insert(handle, mesh);
+    }
+
+    /// Meshes removed from the arena are also dropped by the next sort
+    pub fn remove_translucent(&mut self, handle: MeshHandle) {
+        self.translucent.remove(&handle);
+    }
+
+    /// Sorts the quads of the translucent meshes back to front from `eye` and uploads
+    /// their indices again. It happens for every mesh when `eye` entered another block
+    /// since the last call, and for the meshes set since. Call it every frame before
+    /// [`GeometryArena::flush`], which records the uploads.
+    pub fn sort_translucent(
+        &mut self,
+        renderer: &mut Renderer,
+        arena: &mut GeometryArena,
+        eye: Vec3,
+    ) -> Result<(), Box<dyn Error>> {
+        self.translucent
+            .retain(|handle, _| arena.range(*handle).is_some());
+        if self.sort_trigger.update(eye) {
+            for translucent in self.translucent.values_mut() {
+                translucent.sorted = false;
+            }
+        }
+
+        // a failed upload leaves the rest unsorted, they are tried again next frame
+        for (handle, translucent) in &mut self.translucent {
+            if translucent.sorted {
+                continue;
+            }
+            let mesh = &mut translucent.mesh;
+            mesh.sort_back_to_front(eye - translucent.origin.as_vec3());
+            arena.reorder_indices(renderer, *handle, &mesh.indices)?;
+            translucent.sorted = true;
+        }
+        Ok(())
+    }
+
+    /// Adds the passes drawing the opaque, cutout then translucent geometry of the
+    /// sections in the current frame. Translucent sections are drawn from the farthest
+    /// to `eye`, the camera position, the quads inside them in the order left by
+    /// [`ChunkRenderer::sort_translucent`]. The arena must hold [`PackedVertex`]es whose
+    /// texture layers come from `textures`, `shadows` must be rendered in this frame. Sky
+    /// lit blocks darken with the [`Renderer::time_of_day`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        renderer: &mut Renderer,
+        culler: &ChunkCuller,
+        culled: &CulledDraws,
+        arena: &GeometryArena,
+        textures: &mut BlockTextures,
+        shadows: &mut ShadowMaps,
+        view_proj: &Mat4,
+        eye: Vec3,
+    ) -> Result<(), Box<dyn Error>> {
+        if arena.vertex_size() != size_of::<PackedVertex>() as u64 {
+            return Err("Chunk geometry arena doesn't hold packed vertices".into());
+        }
+
+        if !shadows.is_current(renderer) {
+            return Err("Shadows weren't rendered in this frame".into());
+        }
+        textures.prepare(renderer)?;
+
+        let frame = renderer
+            .frame()
+            .ok_or("Drawing chunks outside of a frame")?;
+        let formats = (
+            renderer
+                .draw_format(frame.surface)
+                .ok_or("Unknown surface")?,
+            renderer.depth_format(),
+            renderer.sample_count(),
+        );
+
+        if self.pipelines.as_ref().map(|val| val.formats) != Some(formats) {
+            if let Some(old) = self.pipelines.take() {
+                renderer.wait_idle();
+                Self::destroy_pipelines(renderer.device_handle(), old);
+            }
+            let depth_compare = renderer.depth_compare_op();
+            self.pipelines = Some(Self::create_pipelines(
+                renderer.device_handle(),
+                [
+                    culler.set_layout(),
+                    textures.set_layout(),
+                    shadows.set_layout(),
+                    renderer.frame_set_layout(),
+                ],
+                formats,
+                depth_compare,
+            )?);
+        }
+
+        let pipelines = self.pipelines.as_ref().unwrap();
+        let layout = pipelines.layout;
+        let sets = [
+            culler.set(),
+            textures.set(),
+            shadows.set(),
+            renderer.frame_set(),
+        ];
+        let vertex_address = arena.vertex_address();
+        let translucent = culler.translucent_sections(&Frustum::from_view_proj(view_proj), eye);
+        let view_proj = *view_proj;
+        let culled = *culled;
+
+        let graph = renderer.graph();
+        let sections = culler.import_sections(graph);
+        let (vertices, indices) = arena.import(graph);
+        let (shadow_params, shadow_map) = shadows.import(graph);
+
+        let mut reads = culled.reads().to_vec();
+        reads.extend([
+            (sections, BufferUsage::Storage),
+            (vertices, BufferUsage::Storage),
+            (indices, BufferUsage::Index),
+            (shadow_params, BufferUsage::Uniform),
+        ]);
+
+        for layer in RenderLayer::ALL {
+            if layer == RenderLayer::Translucent && translucent.is_empty() {
+                continue;
+            }
+
+            let pipeline = pipelines.handles[layer as usize];
+            let constants = DrawConstants {
+                view_proj: view_proj.to_cols_array(),
+                vertices: vertex_address,
+                render_layer: layer as u32,
+                _pad: 0,
+            };
+            let translucent = translucent.clone();
+
+            let name = match layer {
+                RenderLayer::Opaque => "chunks opaque",
+                RenderLayer::Cutout => "chunks cutout",
+                RenderLayer::Translucent => "chunks translucent",
+            };
+            renderer.draw_sampling(name, &reads, &[shadow_map], move |ctx| {
+                unsafe {
+                    ctx.device.cmd_bind_pipeline(
+                        ctx.cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    ctx.device.cmd_bind_descriptor_sets(
+                        ctx.cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        layout,
+                        0,
+                        &sets,
+                        &[],
+                    );
+                    ctx.device.cmd_push_constants(
+                        ctx.cmd,
+                        layout,
+                        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                        0,
+                        bytemuck::bytes_of(&constants),
+                    );
+                    ctx.device.cmd_bind_index_buffer(
+                        ctx.cmd,
+                        ctx.buffer(indices),
+                        0,
+                        vk::IndexType::UINT32,
+                    );
+                }
+
+                if layer != RenderLayer::Translucent {
+                    culled.draw(ctx, layer);
+                    return;
+                }
+
+                // the instance index is the section slot, like for the culled draws
+                for (slot, mesh) in &translucent {
+                    unsafe {
+                        ctx.device.cmd_draw_indexed(
+                            ctx.cmd,
+                            mesh.index_count,
+                            1,
+                            mesh.first_index,
+                            mesh.vertex_offset,
+                            *slot,
+                        )
+                    };
+                }
+                ctx.count_draws(translucent.len() as u32);
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn create_pipelines(
+        device: &ash::Device,
+        set_layouts: [vk::DescriptorSetLayout; 4],
+        formats: (vk::Format, vk::Format, vk::SampleCountFlags),
+        depth_compare: vk::CompareOp,
+    ) -> Result<Pipelines, vk::Result> {
+        let layout = core::create_pipeline_layout(
+            device,
+            &set_layouts,
+            size_of::<DrawConstants>() as u32,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        )?;
+
+        let vertex = shaders::words(shaders::VOXEL_VERT);
+        let fragment = shaders::words(shaders::VOXEL_FRAG);
+
+        let mut handles = [vk::Pipeline::null(); RenderLayer::COUNT];
+        for layer in RenderLayer::ALL {
+            // cutout and translucent blocks are seen from both sides, translucent ones
+            // blend over what is behind them without hiding it
+            let (cull_mode, blend, depth_write) = match layer {
+                RenderLayer::Opaque => (vk::CullModeFlags::BACK, core::Blend::Opaque, true),
+                RenderLayer::Cutout => (vk::CullModeFlags::NONE, core::Blend::Opaque, true),
+                RenderLayer::Translucent => (vk::CullModeFlags::NONE, core::Blend::Alpha, false),
+            };
+
+            let pipeline = core::GraphicsPipelineBuilder::new(layout)
+                .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+                .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+                .cull_mode(cull_mode, vk::FrontFace::COUNTER_CLOCKWISE)
+                .color_format(formats.0)
+                .depth(formats.1, Some(depth_compare), depth_write)
+                .samples(formats.2)
+                .blend(blend)
+                .build(device);
+
+            match pipeline {
+                Ok(handle) => handles[layer as usize] = handle,
+                Err(err) => {
+                    Self::destroy_pipelines(
+                        device,
+                        Pipelines {
+                            handles,
+                            layout,
+                            formats,
+                        },
+                    );
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(Pipelines {
+            handles,
+            layout,
+            formats,
+        })
+    }
+
+    fn destroy_pipelines(device: &ash::Device, pipelines: Pipelines) {
+        unsafe {
+            // pipelines not created yet are null, destroying them is a no-op
+            for pipeline in pipelines.handles {
+                device.destroy_pipeline(pipeline, None);
+            }
+            device.destroy_pipeline_layout(pipelines.layout, None);
+        }
+    }
+
+    /// Nothing may be in flight that still uses the pipelines
+    pub fn destroy(mut self, renderer: &Renderer) {
+        if let Some(pipelines) = self.pipelines.take() {
+            Self::destroy_pipelines(renderer.device_handle(), pipelines);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::{error::Error, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    core,
+    graph::{ImageId, ImageUsage, ImportedImage, RenderGraph, ResourceState},
+    shaders, Renderer,
+};
+
+const REDUCE_GROUP_SIZE: u32 = 8;
+
+const PYRAMID_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ReduceConstants {
+    source_size: [i32; 2],
+    destination_size: [i32; 2],
+    reverse_z: u32,
+}
+
+/// Largest power of two not greater than `value`
+fn floor_pow2(value: u32) -> u32 {
+    1 << (31 - value.max(1).leading_zeros())
+}
+
+struct Pyramid {
+    image: core::AllocatedImage,
+    mip_views: Vec<vk::ImageView>,
+    /// Depth view and extent the first level is reduced from
+    source: (vk::ImageView, vk::Extent2D),
+    /// Whether a frame already left it in SHADER_READ_ONLY_OPTIMAL
+    initialized: bool,
+}
+
+/// Hierarchical depth buffer: every mip keeps the farthest depth of the texels it covers
+/// in the previous one, level 0 is the depth buffer shrunk to a power of two
+pub(super) struct HiZ {
+    pipeline: core::ComputePipeline,
+    set_layout: vk::DescriptorSetLayout,
+    /// One set per level, reading the level above and writing the level itself
+    sets: Vec<vk::DescriptorSet>,
+    pub sampler: vk::Sampler,
+    pyramid: Pyramid,
+    reverse_z: bool,
+}
+
+impl HiZ {
+    /// The pyramid starts as a 1x1 placeholder until [`HiZ::prepare`] sees a depth buffer
+    pub fn new(renderer: &mut Renderer) -> Result<Self, Box<dyn Error>> {
+        let device = renderer.device_handle().clone();
+
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .add_binding(1, vk::DescriptorType::STORAGE_IMAGE)
+            .build(&device, vk::ShaderStageFlags::COMPUTE)?;
+
+        let code = shaders::words(shaders::HIZ_REDUCE);
+        let pipeline = renderer.create_compute_pipeline(&core::ComputePipelineSpec {
+            code: &code,
+            entry: c"main",
+            set_layouts: &[set_layout],
+            push_constant_size: size_of::<ReduceConstants>() as u32,
+        })?;
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let sampler = unsafe { device.create_sampler(&sampler_info, None)? };
+
+        let pyramid = Self::create_pyramid(
+            renderer,
+            vk::ImageView::null(),
+            vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+        )?;
+
+        Ok(Self {
+            pipeline,
+            set_layout,
+            sets: vec![],
+            sampler,
+            pyramid,
+            reverse_z: renderer.reverse_z(),
+        })
+    }
+
+    fn create_pyramid(
+        renderer: &Renderer,
+        depth_view: vk::ImageView,
+        depth_extent: vk::Extent2D,
+    ) -> Result<Pyramid, Box<dyn Error>> {
+        let extent = vk::Extent2D {
+            width: floor_pow2(depth_extent.width),
+            height: floor_pow2(depth_extent.height),
+        };
+        let mip_levels = 32 - extent.width.max(extent.height).leading_zeros();
+
+        let mut spec = core::ImageSpec::color(
+            "depth pyramid",
+            extent,
+            PYRAMID_FORMAT,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+        );
+        spec.mip_levels = mip_levels;
+        let image = renderer.create_image(&spec)?;
+
+        let device = renderer.device_handle();
+        let mut mip_views = Vec::with_capacity(mip_levels as usize);
+        for level in 0..mip_levels {
+            let info = vk::ImageViewCreateInfo::default()
+                .image(image.handle)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(PYRAMID_FORMAT)
+                .subresource_range(
+                    vk::ImageSubresourceRange::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .base_mip_level(level)
+                        .level_count(1)
+                        .layer_count(1),
+                );
+            mip_views.push(unsafe { device.create_image_view(&info, None)? });
+        }
+
+        Ok(Pyramid {
+            image,
+            mip_views,
+            source: (depth_view, depth_extent),
+            initialized: false,
+        })
+    }
+
+    fn destroy_pyramid(renderer: &Renderer, pyramid: Pyramid) {
+        for view in pyramid.mip_views {
+            unsafe { renderer.device_handle().destroy_image_view(view, None) };
+        }
+        renderer.destroy_image(pyramid.image);
+    }
+
+    /// View over every level, for sampling
+    pub fn view(&self) -> vk::ImageView {
+        self.pyramid.image.view
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.pyramid.image.extent_2d()
+    }
+
+    /// Recreates the pyramid when the depth buffer it is built from changed, waiting for
+    /// the device. Returns whether it did, sets sampling the pyramid must be rewritten then.
+    pub fn prepare(
+        &mut self,
+        renderer: &mut Renderer,
+        depth_view: vk::ImageView,
+        depth_extent: vk::Extent2D,
+    ) -> Result<bool, Box<dyn Error>> {
+        if self.pyramid.source == (depth_view, depth_extent) {
+            return Ok(false);
+        }
+
+        renderer.wait_idle();
+        let pyramid = Self::create_pyramid(renderer, depth_view, depth_extent)?;
+        Self::destroy_pyramid(renderer, std::mem::replace(&mut self.pyramid, pyramid));
+
+        while self.sets.len() < self.pyramid.mip_views.len() {
+            self.sets
+                .push(renderer.allocate_descriptor_set(self.set_layout)?);
+        }
+
+        let device = renderer.device_handle();
+        for (level, view) in self.pyramid.mip_views.iter().enumerate() {
+            let (source, layout) = match level {
+                0 => (depth_view, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+                _ => (self.pyramid.mip_views[level - 1], vk::ImageLayout::GENERAL),
+            };
+
+            core::DescriptorWriter::new()
+                .write_image(
+                    0,
+                    source,
+                    self.sampler,
+                    layout,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                )
+                .storage_image(1, *view)
+                .update_set(device, self.sets[level]);
+        }
+
+        log::trace!("Depth pyramid recreated at {:?}", self.extent());
+        Ok(true)
+    }
+
+    /// Imports the pyramid in `graph`, it is always left ready to be sampled
+    pub fn import(&mut self, graph: &mut RenderGraph) -> ImageId {
+        let initial = if self.pyramid.initialized {
+            ResourceState {
+                stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+                access: vk::AccessFlags2::MEMORY_WRITE,
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        } else {
+            ResourceState::UNKNOWN
+        };
+        self.pyramid.initialized = true;
+
+        let mut image =
+            ImportedImage::from_allocated(&self.pyramid.image, vk::SampleCountFlags::TYPE_1);
+        image.initial = initial;
+        image.final_layout = Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        graph.import_image("depth pyramid", image)
+    }
+
+    /// Adds the pass reducing `depth` into the pyramid, it feeds the next frame so it
+    /// is never culled
+    pub fn build(&mut self, graph: &mut RenderGraph, depth: ImageId) {
+        let pyramid = self.import(graph);
+        let pipeline = self.pipeline;
+        let sets = self.sets[..self.pyramid.mip_views.len()].to_vec();
+        let reverse_z = self.reverse_z as u32;
+
+        let source_extent = self.pyramid.source.1;
+        let extent = self.extent();
+
+        graph
+            .add_pass("build depth pyramid")
+            .read_image(depth, ImageUsage::Sampled)
+            .write_image(pyramid, ImageUsage::Storage)
+            .side_effects()
+            .execute(move |ctx| {
+                let mut source = [source_extent.width as i32, source_extent.height as i32];
+                let mut destination = [extent.width as i32, extent.height as i32];
+
+                for (level, set) in sets.iter().enumerate() {
+                    if level > 0 {
+                        // the previous level must be written before it's reduced
+                        let barrier = vk::MemoryBarrier2::default()
+                            .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                            .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+                            .dst_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                            .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ);
+                        let barriers = [barrier];
+                        let dependency = vk::DependencyInfo::default().memory_barriers(&barriers);
+                        unsafe { ctx.device.cmd_pipeline_barrier2(ctx.cmd, &dependency) };
+                    }
+
+                    let constants = ReduceConstants {
+                        source_size: source,
+                        destination_size: destination,
+                        reverse_z,
+                    };
+                    ctx.bind_compute(&pipeline, &[*set], bytemuck::bytes_of(&constants));
+                    ctx.dispatch(
+                        (destination[0] as u32).div_ceil(REDUCE_GROUP_SIZE),
+                        (destination[1] as u32).div_ceil(REDUCE_GROUP_SIZE),
+                        1,
+                    );
+
+                    source = destination;
+                    destination = [(destination[0] / 2).max(1), (destination[1] / 2).max(1)];
+                }
+            });
+    }
+
+    pub fn destroy(self, renderer: &Renderer) {
+        Self::destroy_pyramid(renderer, self.pyramid);
+        renderer.destroy_compute_pipeline(self.pipeline);
+
+        let device = renderer.device_handle();
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! GPU side of the voxel world: chunk section culling, the buffers their meshes live in,
+//! the packed vertex they are drawn from, the block textures they are shaded with and
+//! the sun shadows they cast
+
+mod arena;
+mod cull;
+mod draw;
+mod hiz;
+mod shadow;
+mod textures;
+mod vertex;
+
+pub use arena::{ArenaStats, GeometryArena, MeshHandle, MeshRange};
+pub use cull::{ChunkCuller, CulledDraws, MeshDraw, RenderLayer, SectionDraw};
+pub use draw::ChunkRenderer;
+pub use shadow::{CascadeCamera, ShadowMaps, MAX_CASCADES};
+pub(crate) use textures::load_png;
+pub use textures::{BlockTextureSet, BlockTextures, TextureLayers};
+pub use vertex::{Face, PackedVertex, VoxelVertex};
@@ -0,0 +1,719 @@
+//! Cascaded shadow maps of the sun: the view frustum is split in slices along its depth,
+//! each rendered from the sun in a layer of a depth texture array
+
+use std::{error::Error, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+
+use super::{draw::DrawConstants, BlockTextures, ChunkCuller, GeometryArena, RenderLayer};
+use crate::{
+    core,
+    graph::{
+        BufferId, BufferUsage, ImageId, ImageUsage, ImportedImage, RenderGraph, ResourceState,
+    },
+    shaders, Frustum, MemoryLocation, Renderer,
+};
+
+pub const MAX_CASCADES: usize = 4;
+
+/// Blend between logarithmic and uniform splits, logarithmic splits keep the texel
+/// density even but leave the far cascades huge
+const SPLIT_LAMBDA: f32 = 0.75;
+
+/// How far past a cascade towards the sun casters are still drawn, in blocks
+const CASTER_DISTANCE: f32 = 256.0;
+
+/// Cascade radii are rounded up to this step so float noise doesn't resize them
+const RADIUS_STEP: f32 = 1.0 / 16.0;
+
+const MIN_RESOLUTION: u32 = 256;
+
+// flags of `ShadowParams` in `shadow.glsl`
+const SHADOW_DEBUG_CASCADES: u32 = 1;
+
+const DEPTH_BIAS_CONSTANT: f32 = 1.25;
+const DEPTH_BIAS_SLOPE: f32 = 1.75;
+
+/// Layers drawn in the shadow maps, translucent blocks don't cast shadows
+const CASTER_LAYERS: [RenderLayer; 2] = [RenderLayer::Opaque, RenderLayer::Cutout];
+
+/// Camera the cascades are fitted to
+#[derive(Clone, Copy, Debug)]
+pub struct CascadeCamera {
+    /// World to view, right handed looking down -Z
+    pub view: Mat4,
+    /// Vertical field of view in radians
+    pub fov_y: f32,
+    /// Width over height
+    pub aspect: f32,
+    pub near: f32,
+}
+
+impl CascadeCamera {
+    /// Corners of the slice of the view frustum between the view depths `near` and
+    /// `far`, in world space
+    fn slice_corners(&self, near: f32, far: f32) -> [Vec3; 8] {
+        let to_world = self.view.inverse();
+        let tan = (self.fov_y * 0.5).tan();
+
+        std::array::from_fn(|index| {
+            let depth = if index < 4 { near } else { far };
+            let x = if index & 1 == 0 { -1.0 } else { 1.0 };
+            let y = if index & 2 == 0 { -1.0 } else { 1.0 };
+            let corner = Vec3::new(x * tan * self.aspect * depth, y * tan * depth, -depth);
+            to_world.transform_point3(corner)
+        })
+    }
+
+    fn eye(&self) -> Vec3 {
+        self.view.inverse().w_axis.truncate()
+    }
+
+    fn forward(&self) -> Vec3 {
+        -self.view.inverse().z_axis.truncate().normalize()
+    }
+}
+
+/// View depth each cascade ends at, the last one at `distance`
+fn cascade_splits(near: f32, distance: f32, count: usize) -> [f32; MAX_CASCADES] {
+    let mut splits = [distance; MAX_CASCADES];
+    for (index, split) in splits.iter_mut().enumerate().take(count) {
+        let t = (index + 1) as f32 / count as f32;
+        let log = near * (distance / near).powf(t);
+        let uniform = near + (distance - near) * t;
+        *split = SPLIT_LAMBDA * log + (1.0 - SPLIT_LAMBDA) * uniform;
+    }
+    splits
+}
+
+/// Light view projection of a cascade covering `corners`, with the world size of one of
+/// its texels. The cascade is a box around the bounding sphere of the slice so its size
+/// doesn't change as the camera turns, and it moves by whole texels so shadow edges
+/// don't shimmer as the camera moves.
+fn fit_cascade(corners: &[Vec3; 8], light_dir: Vec3, resolution: u32) -> (Mat4, f32) {
+    let center = corners.iter().sum::<Vec3>() / 8.0;
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0, f32::max);
+    let radius = (radius / RADIUS_STEP).ceil() * RADIUS_STEP;
+    let texel = 2.0 * radius / resolution as f32;
+
+    // rotation only, texels line up on a grid fixed in the world
+    let up = if light_dir.y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let light_view = Mat4::look_to_rh(Vec3::ZERO, light_dir, up);
+    let center = light_view.transform_point3(center);
+    let snapped = (center.truncate() / texel).floor() * texel;
+
+    let proj = Mat4::orthographic_rh(
+        snapped.x - radius,
+        snapped.x + radius,
+        snapped.y - radius,
+        snapped.y + radius,
+        -center.z - radius - CASTER_DISTANCE,
+        -center.z + radius,
+    );
+    (proj * light_view, texel)
+}
+
+/// Layout of `ShadowParams` in `shadow.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct ShadowParams {
+    light_view_proj: [[f32; 16]; MAX_CASCADES],
+    splits: [f32; 4],
+    texel_world: [f32; 4],
+    eye: [f32; 4],
+    forward: [f32; 4],
+    light_dir: [f32; 4],
+    cascade_count: u32,
+    flags: u32,
+    texel_size: f32,
+    _pad: u32,
+}
+
+/// Depth array with a layer per cascade
+struct Cascades {
+    image: core::AllocatedImage,
+    /// Every layer, for sampling
+    array_view: vk::ImageView,
+    /// A view per layer, for rendering
+    layer_views: Vec<vk::ImageView>,
+    resolution: u32,
+    /// Whether a frame already left it in SHADER_READ_ONLY_OPTIMAL
+    initialized: bool,
+}
+
+/// Sun shadows of the chunk geometry, configured with the
+/// [`ShadowSettings`](crate::ShadowSettings) of the render settings
+pub struct ShadowMaps {
+    cascades: Cascades,
+    set_layout: vk::DescriptorSetLayout,
+    set: vk::DescriptorSet,
+    sampler: vk::Sampler,
+    params: core::AllocatedBuffer,
+    pipeline: Option<(vk::Pipeline, vk::PipelineLayout)>,
+    light_dir: Vec3,
+    debug: bool,
+    /// Frame the parameters were last uploaded in
+    uploaded: Option<u64>,
+}
+
+impl ShadowMaps {
+    pub fn new(renderer: &mut Renderer) -> Result<Self, Box<dyn Error>> {
+        let device = renderer.device_handle().clone();
+
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::UNIFORM_BUFFER)
+            .add_binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .build(&device, vk::ShaderStageFlags::FRAGMENT)?;
+
+        // hardware comparison filters 2x2 texels, outside the map is lit
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL);
+        let sampler = unsafe { device.create_sampler(&sampler_info, None)? };
+
+        let params = renderer.create_buffer(
+            "shadow params",
+            size_of::<ShadowParams>() as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+
+        let set = renderer.allocate_descriptor_set(set_layout)?;
+        core::DescriptorWriter::new()
+            .write_buffer(
+                0,
+                params.handle,
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::UNIFORM_BUFFER,
+            )
+            .update_set(&device, set);
+
+        let (count, resolution) = Self::cascade_spec(renderer);
+        let cascades = Self::create_cascades(renderer, count, resolution)?;
+
+        let shadows = Self {
+            cascades,
+            set_layout,
+            set,
+            sampler,
+            params,
+            pipeline: None,
+            light_dir: Vec3::new(0.3, -1.0, 0.2).normalize(),
+            debug: false,
+            uploaded: None,
+        };
+        shadows.write_map_descriptor(&device);
+        Ok(shadows)
+    }
+
+    /// Cascade count and resolution asked for by the render settings, within what the
+    /// device supports
+    fn cascade_spec(renderer: &Renderer) -> (usize, u32) {
+        let settings = renderer.render_settings().shadows;
+        let max_size = renderer.device_limits().max_image_dimension2_d;
+        (
+            (settings.cascades as usize).clamp(1, MAX_CASCADES),
+            settings.resolution.clamp(MIN_RESOLUTION, max_size),
+        )
+    }
+
+    fn create_cascades(
+        renderer: &Renderer,
+        count: usize,
+        resolution: u32,
+    ) -> Result<Cascades, Box<dyn Error>> {
+        let format = renderer.depth_format();
+        let mut spec = core::ImageSpec::depth(
+            "shadow cascades",
+            vk::Extent2D {
+                width: resolution,
+                height: resolution,
+            },
+            format,
+            vk::SampleCountFlags::TYPE_1,
+        );
+        spec.array_layers = count as u32;
+        let image = renderer.create_image(&spec)?;
+
+        let device = renderer.device_handle();
+        let view = |view_type, base_layer, layer_count| {
+            let info = vk::ImageViewCreateInfo::default()
+                .image(image.handle)
+                .view_type(view_type)
+                .format(format)
+                .subresource_range(
+                    vk::ImageSubresourceRange::default()
+                        .aspect_mask(vk::ImageAspectFlags::DEPTH)
+                        .level_count(1)
+                        .base_array_layer(base_layer)
+                        .layer_count(layer_count),
+                );
+            unsafe { device.create_image_view(&info, None) }
+        };
+
+        // the default view of a single layer image isn't an array
+        let array_view = view(vk::ImageViewType::TYPE_2D_ARRAY, 0, count as u32)?;
+        let mut layer_views = Vec::with_capacity(count);
+        for layer in 0..count as u32 {
+            layer_views.push(view(vk::ImageViewType::TYPE_2D, layer, 1)?);
+        }
+
+        Ok(Cascades {
+            image,
+            array_view,
+            layer_views,
+            resolution,
+            initialized: false,
+        })
+    }
+
+    fn destroy_cascades(renderer: &Renderer, cascades: Cascades) {
+        let device = renderer.device_handle();
+        for view in cascades.layer_views {
+            unsafe { device.destroy_image_view(view, None) };
+        }
+        unsafe { device.destroy_image_view(cascades.array_view, None) };
+        renderer.destroy_image(cascades.image);
+    }
+
+    fn write_map_descriptor(&self, device: &ash::Device) {
+        core::DescriptorWriter::new()
+            .write_image(
+                1,
+                self.cascades.array_view,
+                self.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            )
+            .update_set(device, self.set);
+    }
+
+    /// Recreates the cascades when their count or resolution changed in the render
+    /// settings, waiting for the device
+    fn prepare(&mut self, renderer: &mut Renderer) -> Result<(), Box<dyn Error>> {
+        let (count, resolution) = Self::cascade_spec(renderer);
+        if (count, resolution) == (self.cascades.layer_views.len(), self.cascades.resolution) {
+            return Ok(());
+        }
+
+        renderer.wait_idle();
+        let cascades = Self::create_cascades(renderer, count, resolution)?;
+        Self::destroy_cascades(renderer, std::mem::replace(&mut self.cascades, cascades));
+        self.write_map_descriptor(renderer.device_handle());
+
+        log::trace!("{} shadow cascades of {}x{}", count, resolution, resolution);
+        Ok(())
+    }
+
+    /// Direction the sun light travels in, towards the ground at noon
+    pub fn set_light_direction(&mut self, direction: Vec3) {
+        self.light_dir = direction.normalize_or(Vec3::NEG_Y);
+    }
+
+    pub fn light_direction(&self) -> Vec3 {
+        self.light_dir
+    }
+
+    /// Tints the terrain with the color of the cascade it is shadowed by
+    pub fn set_debug_view(&mut self, enabled: bool) {
+        self.debug = enabled;
+    }
+
+    /// Adds the passes rendering the casters of every cascade fitted to `camera`, call it
+    /// every frame before [`ChunkRenderer::draw`](super::ChunkRenderer::draw), even with
+    /// shadows disabled
+    pub fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        culler: &ChunkCuller,
+        arena: &GeometryArena,
+        textures: &BlockTextures,
+        camera: &CascadeCamera,
+    ) -> Result<(), Box<dyn Error>> {
+        renderer
+            .frame()
+            .ok_or("Rendering shadows outside of a frame")?;
+        self.prepare(renderer)?;
+
+        let settings = renderer.render_settings().shadows;
+        let count = self.cascades.layer_views.len();
+        let resolution = self.cascades.resolution;
+        let splits = cascade_splits(camera.near, settings.distance.max(camera.near), count);
+
+        let mut params = ShadowParams {
+            light_view_proj: [Mat4::IDENTITY.to_cols_array(); MAX_CASCADES],
+            splits,
+            texel_world: [0.0; MAX_CASCADES],
+            eye: camera.eye().extend(1.0).to_array(),
+            forward: camera.forward().extend(0.0).to_array(),
+            light_dir: self.light_dir.extend(0.0).to_array(),
+            cascade_count: if settings.enabled { count as u32 } else { 0 },
+            flags: if self.debug { SHADOW_DEBUG_CASCADES } else { 0 },
+            texel_size: 1.0 / resolution as f32,
+            _pad: 0,
+        };
+
+        let mut matrices = Vec::with_capacity(count);
+        let mut near = camera.near;
+        for (cascade, far) in splits.iter().take(count).enumerate() {
+            let corners = camera.slice_corners(near, *far);
+            let (view_proj, texel) = fit_cascade(&corners, self.light_dir, resolution);
+            params.light_view_proj[cascade] = view_proj.to_cols_array();
+            params.texel_world[cascade] = texel;
+            matrices.push(view_proj);
+            near = *far;
+        }
+
+        let (params_buffer, _) = self.import(renderer.graph());
+        renderer
+            .graph()
+            .add_pass("upload shadow params")
+            .write_buffer(params_buffer, BufferUsage::TransferDst)
+            .execute(move |ctx| unsafe {
+                ctx.device.cmd_update_buffer(
+                    ctx.cmd,
+                    ctx.buffer(params_buffer),
+                    0,
+                    bytemuck::bytes_of(&params),
+                )
+            });
+        self.uploaded = Some(renderer.frame_count());
+
+        if !settings.enabled {
+            return Ok(());
+        }
+
+        if self.pipeline.is_none() {
+            self.pipeline = Some(Self::create_pipeline(
+                renderer.device_handle(),
+                [culler.set_layout(), textures.set_layout()],
+                renderer.depth_format(),
+            )?);
+        }
+        let (pipeline, layout) = self.pipeline.unwrap();
+        let sets = [culler.set(), textures.set()];
+        let vertex_address = arena.vertex_address();
+
+        // casters of every cascade, for each layer
+        let casters: Vec<_> = matrices
+            .into_iter()
+            .map(|view_proj| {
+                let frustum = Frustum::from_view_proj(&view_proj);
+                let layers = CASTER_LAYERS.map(|layer| culler.sections_with(&frustum, layer));
+                (view_proj, layers)
+            })
+            .collect();
+        let views = self.cascades.layer_views.clone();
+        let extent = vk::Extent2D {
+            width: resolution,
+            height: resolution,
+        };
+
+        let graph = renderer.graph();
+        let (_, map) = self.import(graph);
+        let sections = culler.import_sections(graph);
+        let (vertices, indices) = arena.import(graph);
+
+        graph
+            .add_pass("shadow cascades")
+            .read_buffer(sections, BufferUsage::Storage)
+            .read_buffer(vertices, BufferUsage::Storage)
+            .read_buffer(indices, BufferUsage::Index)
+            .write_image(map, ImageUsage::DepthAttachment)
+            .execute(move |ctx| {
+                for ((view_proj, layers), view) in casters.iter().zip(&views) {
+                    // every cascade is its own layer, out of reach of `begin_rendering`
+                    let depth = vk::RenderingAttachmentInfo::default()
+                        .image_view(*view)
+                        .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                        .load_op(vk::AttachmentLoadOp::CLEAR)
+                        .store_op(vk::AttachmentStoreOp::STORE)
+                        .clear_value(vk::ClearValue {
+                            depth_stencil: vk::ClearDepthStencilValue {
+                                depth: 1.0,
+                                stencil: 0,
+                            },
+                        });
+                    let rendering = vk::RenderingInfo::default()
+                        .render_area(vk::Rect2D {
+                            offset: vk::Offset2D::default(),
+                            extent,
+                        })
+                        .layer_count(1)
+                        .depth_attachment(&depth);
+
+                    unsafe {
+                        ctx.device.cmd_begin_rendering(ctx.cmd, &rendering);
+                        ctx.set_viewport(extent);
+                        ctx.device.cmd_bind_pipeline(
+                            ctx.cmd,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            pipeline,
+                        );
+                        ctx.device.cmd_bind_descriptor_sets(
+                            ctx.cmd,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            layout,
+                            0,
+                            &sets,
+                            &[],
+                        );
+                        ctx.device.cmd_bind_index_buffer(
+                            ctx.cmd,
+                            ctx.buffer(indices),
+                            0,
+                            vk::IndexType::UINT32,
+                        );
+                    }
+
+                    for (layer, sections) in CASTER_LAYERS.iter().zip(layers) {
+                        let constants = DrawConstants {
+                            view_proj: view_proj.to_cols_array(),
+                            vertices: vertex_address,
+                            render_layer: *layer as u32,
+                            _pad: 0,
+                        };
+                        unsafe {
+                            ctx.device.cmd_push_constants(
+                                ctx.cmd,
+                                layout,
+                                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                                0,
+                                bytemuck::bytes_of(&constants),
+                            )
+                        };
+
+                        // the instance index is the section slot, like for the culled draws
+                        for (slot, mesh) in sections {
+                            unsafe {
+                                ctx.device.cmd_draw_indexed(
+                                    ctx.cmd,
+                                    mesh.index_count,
+                                    1,
+                                    mesh.first_index,
+                                    mesh.vertex_offset,
+                                    *slot,
+                                )
+                            };
+                        }
+                        ctx.count_draws(sections.len() as u32);
+                    }
+
+                    ctx.end_rendering();
+                }
+            });
+
+        Ok(())
+    }
+
+    fn create_pipeline(
+        device: &ash::Device,
+        set_layouts: [vk::DescriptorSetLayout; 2],
+        depth_format: vk::Format,
+    ) -> Result<(vk::Pipeline, vk::PipelineLayout), vk::Result> {
+        let layout = core::create_pipeline_layout(
+            device,
+            &set_layouts,
+            size_of::<DrawConstants>() as u32,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        )?;
+
+        let vertex = shaders::words(shaders::VOXEL_VERT);
+        let fragment = shaders::words(shaders::SHADOW_FRAG);
+
+        // the light projection doesn't flip y, winding is reversed so nothing is culled
+        let pipeline = core::GraphicsPipelineBuilder::new(layout)
+            .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+            .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+            .cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::COUNTER_CLOCKWISE)
+            .depth(depth_format, Some(vk::CompareOp::LESS), true)
+            .depth_bias(DEPTH_BIAS_CONSTANT, DEPTH_BIAS_SLOPE)
+            .build(device);
+
+        match pipeline {
+            Ok(pipeline) => Ok((pipeline, layout)),
+            Err(err) => {
+                unsafe { device.destroy_pipeline_layout(layout, None) };
+                Err(err)
+            }
+        }
+    }
+
+    /// Imports the parameters and the cascades in `graph`, the cascades are always left
+    /// ready to be sampled
+    pub(super) fn import(&mut self, graph: &mut RenderGraph) -> (BufferId, ImageId) {
+        let params = graph.import_buffer("shadow params", self.params.handle);
+
+        let initial = if self.cascades.initialized {
+            ResourceState {
+                stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+                access: vk::AccessFlags2::MEMORY_WRITE,
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        } else {
+            ResourceState::UNKNOWN
+        };
+        self.cascades.initialized = true;
+
+        let mut image =
+            ImportedImage::from_allocated(&self.cascades.image, vk::SampleCountFlags::TYPE_1);
+        image.view = self.cascades.array_view;
+        image.initial = initial;
+        image.final_layout = Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        (params, graph.import_image("shadow cascades", image))
+    }
+
+    /// Whether [`ShadowMaps::render`] ran in the frame being recorded
+    pub(super) fn is_current(&self, renderer: &Renderer) -> bool {
+        self.uploaded == Some(renderer.frame_count())
+    }
+
+    pub(super) fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    pub(super) fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    /// Nothing may be in flight that still uses the shadow maps
+    pub fn destroy(self, renderer: &Renderer) {
+        Self::destroy_cascades(renderer, self.cascades);
+        renderer.destroy_buffer(self.params);
+
+        let device = renderer.device_handle();
+        unsafe {
+            if let Some((pipeline, layout)) = self.pipeline {
+                device.destroy_pipeline(pipeline, None);
+                device.destroy_pipeline_layout(layout, None);
+            }
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec4Swizzles;
+
+    use super::*;
+
+    fn camera(eye: Vec3, target: Vec3) -> CascadeCamera {
+        CascadeCamera {
+            view: Mat4::look_at_rh(eye, target, Vec3::Y),
+            fov_y: 70f32.to_radians(),
+            aspect: 16.0 / 9.0,
+            near: 0.1,
+        }
+    }
+
+    fn light() -> Vec3 {
+        Vec3::new(0.3, -1.0, 0.2).normalize()
+    }
+
+    #[test]
+    fn splits_grow_up_to_the_distance() {
+        for count in 1..=MAX_CASCADES {
+            let splits = cascade_splits(0.1, 128.0, count);
+            assert!(splits[0] > 0.1);
+            assert!(splits[..count].windows(2).all(|pair| pair[0] < pair[1]));
+            assert!((splits[count - 1] - 128.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn cascades_contain_their_slice() {
+        let cameras = [
+            camera(Vec3::new(0.0, 70.0, 0.0), Vec3::new(10.0, 60.0, 3.0)),
+            camera(Vec3::new(-500.5, 12.0, 33.3), Vec3::new(-500.0, 80.0, 33.0)),
+            camera(
+                Vec3::new(1e4, 64.0, -1e4),
+                Vec3::new(1e4 + 1.0, 64.0, -1e4 - 1.0),
+            ),
+        ];
+
+        for camera in cameras {
+            let splits = cascade_splits(camera.near, 128.0, 3);
+            let mut near = camera.near;
+            for far in &splits[..3] {
+                let corners = camera.slice_corners(near, *far);
+                let (view_proj, _) = fit_cascade(&corners, light(), 2048);
+                for corner in corners {
+                    let clip = view_proj * corner.extend(1.0);
+                    let ndc = clip.xyz() / clip.w;
+                    assert!(ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0, "{:?}", ndc);
+                    assert!((0.0..=1.0).contains(&ndc.z), "{:?}", ndc);
+                }
+                near = *far;
+            }
+        }
+    }
+
+    #[test]
+    fn cascades_move_by_whole_texels() {
+        let resolution = 1024;
+        let fit = |eye: Vec3| {
+            let camera = camera(eye, eye + Vec3::new(1.0, -0.3, 0.5));
+            fit_cascade(&camera.slice_corners(0.1, 20.0), light(), resolution)
+        };
+
+        let (first, texel) = fit(Vec3::new(3.0, 70.0, -2.0));
+        for step in 1..20 {
+            let eye = Vec3::new(3.0, 70.0, -2.0) + Vec3::new(0.37, 0.11, -0.23) * step as f32;
+            let (moved, moved_texel) = fit(eye);
+            assert_eq!(texel, moved_texel);
+
+            // a fixed world point lands on the same spot within its texel
+            let point = Vec3::new(10.0, 64.0, 5.0).extend(1.0);
+            let shift = ((moved * point).xy() - (first * point).xy()) * resolution as f32 / 2.0;
+            assert!(
+                (shift - shift.round()).abs().max_element() < 1e-2,
+                "{:?}",
+                shift
+            );
+        }
+    }
+
+    #[test]
+    fn cascade_size_ignores_camera_rotation() {
+        let eye = Vec3::new(0.0, 70.0, 0.0);
+        let texels: Vec<f32> = (0..8)
+            .map(|step| {
+                let angle = step as f32 * 0.7;
+                let target = eye + Vec3::new(angle.cos(), angle.sin() * 0.5, angle.sin());
+                let camera = camera(eye, target);
+                fit_cascade(&camera.slice_corners(5.0, 40.0), light(), 2048).1
+            })
+            .collect();
+        assert!(
+            texels.iter().all(|texel| *texel == texels[0]),
+            "{:?}",
+            texels
+        );
+    }
+
+    #[test]
+    fn vertical_light_has_a_valid_view() {
+        let camera = camera(Vec3::new(0.0, 70.0, 0.0), Vec3::new(1.0, 70.0, 0.0));
+        let (view_proj, _) = fit_cascade(&camera.slice_corners(0.1, 10.0), Vec3::NEG_Y, 512);
+        assert!(view_proj.is_finite());
+    }
+}
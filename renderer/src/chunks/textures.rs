@@ -0,0 +1,593 @@
+//! Block textures: a directory of PNGs loaded in a single 2D texture array, with the
+//! layer of every texture looked up by name when meshing
+
+use std::{collections::HashMap, error::Error, fs::File, path::Path};
+
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+
+use crate::{core, Renderer};
+
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+/// Mip levels of a full chain down to 1x1
+fn mip_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Texture array layer of every block texture by name, cheap to clone for meshing threads
+#[derive(Clone, Debug, Default)]
+pub struct TextureLayers {
+    layers: HashMap<String, u16>,
+}
+
+impl TextureLayers {
+    /// Layer of the placeholder drawn for textures that don't exist
+    pub const MISSING: u16 = 0;
+
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.layers.get(name).copied()
+    }
+
+    /// Falls back to [`Self::MISSING`], for block types naming a texture that wasn't loaded
+    pub fn get_or_missing(&self, name: &str) -> u16 {
+        self.get(name).unwrap_or(Self::MISSING)
+    }
+
+    /// Loaded textures, not counting the placeholder
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+/// Decoded block textures waiting to be uploaded, all the same size in RGBA8.
+/// Layer [`TextureLayers::MISSING`] is a generated magenta and black checker.
+pub struct BlockTextureSet {
+    width: u32,
+    height: u32,
+    layers: TextureLayers,
+    /// Tightly packed layers, in layer order
+    pixels: Vec<u8>,
+}
+
+impl BlockTextureSet {
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let magenta = (x * 2 / width.max(1) + y * 2 / height.max(1)).is_multiple_of(2);
+                pixels.extend(if magenta {
+                    [255, 0, 255, 255]
+                } else {
+                    [0, 0, 0, 255]
+                });
+            }
+        }
+
+        Self {
+            width,
+            height,
+            layers: TextureLayers::default(),
+            pixels,
+        }
+    }
+
+    /// Loads every `.png` in `dir`, named after the file without its extension. Layers
+    /// follow the order of the names so they don't change between runs. Every texture
+    /// must have the size of the first one.
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("png"))
+            {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut set = None;
+        for path in paths {
+            let (width, height, pixels) =
+                load_png(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+            let name = path
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| format!("{}: name isn't valid UTF-8", path.display()))?;
+
+            set.get_or_insert_with(|| Self::new(width, height))
+                .add(name, width, height, &pixels)
+                .map_err(|err| format!("{}: {}", path.display(), err))?;
+        }
+
+        set.ok_or_else(|| format!("No png in {}", dir.as_ref().display()).into())
+    }
+
+    /// Adds a texture of tightly packed RGBA8 `pixels`, replacing the one named `name`
+    pub fn add(
+        &mut self,
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<u16, Box<dyn Error>> {
+        if (width, height) != (self.width, self.height) {
+            return Err(format!(
+                "Texture is {}x{}, expected {}x{}",
+                width, height, self.width, self.height
+            )
+            .into());
+        }
+        let size = self.layer_size();
+        if pixels.len() != size {
+            return Err(format!("Expected {} bytes of RGBA8, got {}", size, pixels.len()).into());
+        }
+
+        if let Some(layer) = self.layers.get(name) {
+            let start = layer as usize * size;
+            self.pixels[start..start + size].copy_from_slice(pixels);
+            return Ok(layer);
+        }
+
+        let layer = u16::try_from(self.layer_count())
+            .map_err(|_| "Too many block textures for the packed vertex")?;
+        self.layers.layers.insert(name.to_owned(), layer);
+        self.pixels.extend_from_slice(pixels);
+        Ok(layer)
+    }
+
+    pub fn layers(&self) -> &TextureLayers {
+        &self.layers
+    }
+
+    /// Layers of the texture array, the placeholder included
+    pub fn layer_count(&self) -> u32 {
+        (self.pixels.len() / self.layer_size()) as u32
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// RGBA8 pixels of `layer`
+    pub fn pixels(&self, layer: u16) -> Option<&[u8]> {
+        let size = self.layer_size();
+        self.pixels
+            .get(layer as usize * size..(layer as usize + 1) * size)
+    }
+
+    fn layer_size(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+}
+
+/// Decodes any PNG to tightly packed RGBA8
+pub(crate) fn load_png(path: &Path) -> Result<(u32, u32, Vec<u8>), Box<dyn Error>> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let data = &buffer[..info.buffer_size()];
+
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => data.to_vec(),
+        png::ColorType::Rgb => data
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Grayscale => data.iter().flat_map(|g| [*g, *g, *g, 255]).collect(),
+        png::ColorType::Indexed => return Err("Palette wasn't expanded".into()),
+    };
+
+    Ok((info.width, info.height, pixels))
+}
+
+/// Block textures on the GPU, sampled by the chunk passes of a
+/// [`ChunkRenderer`](super::ChunkRenderer). Mips are generated on the GPU by blitting
+/// every level from the one above.
+pub struct BlockTextures {
+    image: core::AllocatedImage,
+    mip_levels: u32,
+    layers: TextureLayers,
+    set_layout: vk::DescriptorSetLayout,
+    set: vk::DescriptorSet,
+    sampler: vk::Sampler,
+    /// Anisotropy the sampler was created with
+    anisotropy: f32,
+}
+
+impl BlockTextures {
+    /// Uploads `textures` and generates their mips, waiting for the device to finish
+    pub fn new(
+        renderer: &mut Renderer,
+        textures: &BlockTextureSet,
+    ) -> Result<Self, Box<dyn Error>> {
+        let extent = textures.extent();
+        let layer_count = textures.layer_count();
+        let max_layers = renderer.device_limits().max_image_array_layers;
+        if layer_count > max_layers {
+            return Err(format!(
+                "{} block textures, the device supports {}",
+                layer_count, max_layers
+            )
+            .into());
+        }
+
+        let mip_levels = mip_levels(extent.width, extent.height);
+        let image = renderer.create_image(&core::ImageSpec {
+            name: "block textures",
+            extent: extent.into(),
+            format: TEXTURE_FORMAT,
+            usage: vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            aspect: vk::ImageAspectFlags::COLOR,
+            mip_levels,
+            array_layers: layer_count,
+            samples: vk::SampleCountFlags::TYPE_1,
+        })?;
+
+        if let Err(err) = Self::upload(renderer, &image, textures, mip_levels) {
+            renderer.destroy_image(image);
+            return Err(err);
+        }
+
+        let device = renderer.device_handle().clone();
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .build(&device, vk::ShaderStageFlags::FRAGMENT)?;
+        let set = renderer.allocate_descriptor_set(set_layout)?;
+
+        let mut textures = Self {
+            image,
+            mip_levels,
+            layers: textures.layers().clone(),
+            set_layout,
+            set,
+            sampler: vk::Sampler::null(),
+            anisotropy: 0.0,
+        };
+        textures.prepare(renderer)?;
+
+        log::info!(
+            "Loaded {} block textures of {}x{} with {} mips",
+            layer_count,
+            extent.width,
+            extent.height,
+            mip_levels
+        );
+        Ok(textures)
+    }
+
+    /// Loads and uploads the PNGs of `dir`, see [`BlockTextureSet::load_dir`]
+    pub fn load_dir(
+        renderer: &mut Renderer,
+        dir: impl AsRef<Path>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::new(renderer, &BlockTextureSet::load_dir(dir)?)
+    }
+
+    /// Copies every layer to the first level then blits each level into the next one,
+    /// leaving the whole image in SHADER_READ_ONLY_OPTIMAL
+    fn upload(
+        renderer: &Renderer,
+        image: &core::AllocatedImage,
+        textures: &BlockTextureSet,
+        mip_levels: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut staging = renderer.create_buffer(
+            "block texture staging",
+            textures.pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+        match staging.mapped_mut() {
+            Some(data) => data[..textures.pixels.len()].copy_from_slice(&textures.pixels),
+            None => {
+                renderer.destroy_buffer(staging);
+                return Err("Staging buffer is not host visible".into());
+            }
+        }
+
+        let layer_count = textures.layer_count();
+        let result = renderer.immediate_submit(|cmd| {
+            let device = renderer.device_handle();
+            core::transition_image(
+                device,
+                cmd,
+                image.handle,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+
+            let region = vk::BufferImageCopy::default()
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .layer_count(layer_count),
+                )
+                .image_extent(image.extent);
+            unsafe {
+                device.cmd_copy_buffer_to_image(
+                    cmd,
+                    staging.handle,
+                    image.handle,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                )
+            };
+
+            let size = |level: u32| vk::Offset3D {
+                x: (image.extent.width >> level).max(1) as i32,
+                y: (image.extent.height >> level).max(1) as i32,
+                z: 1,
+            };
+            let layers = |level: u32| {
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(level)
+                    .layer_count(layer_count)
+            };
+
+            for level in 1..mip_levels {
+                level_to_source(device, cmd, image.handle, level - 1);
+
+                let region = vk::ImageBlit2::default()
+                    .src_offsets([vk::Offset3D::default(), size(level - 1)])
+                    .dst_offsets([vk::Offset3D::default(), size(level)])
+                    .src_subresource(layers(level - 1))
+                    .dst_subresource(layers(level));
+                let regions = [region];
+                let info = vk::BlitImageInfo2::default()
+                    .src_image(image.handle)
+                    .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .dst_image(image.handle)
+                    .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                    .filter(vk::Filter::LINEAR)
+                    .regions(&regions);
+                unsafe { device.cmd_blit_image2(cmd, &info) };
+            }
+            level_to_source(device, cmd, image.handle, mip_levels - 1);
+
+            core::transition_image(
+                device,
+                cmd,
+                image.handle,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+        });
+
+        renderer.destroy_buffer(staging);
+        Ok(result?)
+    }
+
+    /// Recreates the sampler when the anisotropy of the render settings changed, waiting
+    /// for the device
+    pub(super) fn prepare(&mut self, renderer: &Renderer) -> Result<(), Box<dyn Error>> {
+        let anisotropy =
+            (renderer.render_settings().anisotropy.max(1) as f32).min(renderer.max_anisotropy());
+        if anisotropy == self.anisotropy {
+            return Ok(());
+        }
+
+        // pixel art stays sharp up close, minification goes through the mips
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT)
+            .anisotropy_enable(anisotropy > 1.0)
+            .max_anisotropy(anisotropy)
+            .max_lod(vk::LOD_CLAMP_NONE);
+
+        let device = renderer.device_handle();
+        let sampler = unsafe { device.create_sampler(&sampler_info, None)? };
+        if self.sampler != vk::Sampler::null() {
+            renderer.wait_idle();
+            unsafe { device.destroy_sampler(self.sampler, None) };
+        }
+        self.sampler = sampler;
+        self.anisotropy = anisotropy;
+
+        core::DescriptorWriter::new()
+            .write_image(
+                0,
+                self.image.view,
+                self.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            )
+            .update_set(device, self.set);
+
+        log::trace!("Block texture anisotropy set to {}", anisotropy);
+        Ok(())
+    }
+
+    pub fn layers(&self) -> &TextureLayers {
+        &self.layers
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub(super) fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    pub(super) fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    /// Nothing may be in flight that still samples the textures
+    pub fn destroy(self, renderer: &Renderer) {
+        renderer.destroy_image(self.image);
+
+        let device = renderer.device_handle();
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+/// Moves `level` from TRANSFER_DST_OPTIMAL to TRANSFER_SRC_OPTIMAL once written
+fn level_to_source(device: &ash::Device, cmd: vk::CommandBuffer, image: vk::Image, level: u32) {
+    let barrier = vk::ImageMemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::TRANSFER)
+        .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)
+        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .image(image)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(level)
+                .level_count(1)
+                .layer_count(vk::REMAINING_ARRAY_LAYERS),
+        );
+
+    let barriers = [barrier];
+    let dependency = vk::DependencyInfo::default().image_memory_barriers(&barriers);
+    unsafe { device.cmd_pipeline_barrier2(cmd, &dependency) };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Empty directory of its own for every test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "minecrust-textures-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn save_png(path: &Path, width: u32, height: u32, color: png::ColorType, data: &[u8]) {
+        let mut encoder = png::Encoder::new(File::create(path).unwrap(), width, height);
+        encoder.set_color(color);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+    }
+
+    #[test]
+    fn layers_follow_names_after_the_placeholder() {
+        let dir = test_dir("names");
+        for (name, value) in [("stone", 1), ("dirt", 2), ("grass_top", 3)] {
+            save_png(
+                &dir.join(format!("{}.png", name)),
+                2,
+                2,
+                png::ColorType::Rgba,
+                &[value; 16],
+            );
+        }
+        std::fs::write(dir.join("notes.txt"), "not a texture").unwrap();
+
+        let set = BlockTextureSet::load_dir(&dir).unwrap();
+        let layers = set.layers();
+        assert_eq!(layers.len(), 3);
+        assert_eq!(set.layer_count(), 4);
+        assert_eq!(layers.get("dirt"), Some(1));
+        assert_eq!(layers.get("grass_top"), Some(2));
+        assert_eq!(layers.get("stone"), Some(3));
+        assert_eq!(layers.get("notes"), None);
+        assert_eq!(layers.get_or_missing("lava"), TextureLayers::MISSING);
+
+        assert_eq!(set.pixels(1), Some(&[2; 16][..]));
+        assert_eq!(set.pixels(3), Some(&[1; 16][..]));
+        assert_eq!(set.pixels(4), None);
+    }
+
+    #[test]
+    fn other_color_types_become_rgba() {
+        let dir = test_dir("colors");
+        save_png(&dir.join("a.png"), 1, 1, png::ColorType::Rgb, &[10, 20, 30]);
+        save_png(
+            &dir.join("b.png"),
+            1,
+            1,
+            png::ColorType::GrayscaleAlpha,
+            &[40, 50],
+        );
+        save_png(&dir.join("c.png"), 1, 1, png::ColorType::Grayscale, &[60]);
+
+        let set = BlockTextureSet::load_dir(&dir).unwrap();
+        assert_eq!(set.pixels(1), Some(&[10, 20, 30, 255][..]));
+        assert_eq!(set.pixels(2), Some(&[40, 40, 40, 50][..]));
+        assert_eq!(set.pixels(3), Some(&[60, 60, 60, 255][..]));
+    }
+
+    #[test]
+    fn textures_must_share_a_size() {
+        let dir = test_dir("sizes");
+        save_png(&dir.join("a.png"), 2, 2, png::ColorType::Rgba, &[0; 16]);
+        save_png(&dir.join("b.png"), 4, 4, png::ColorType::Rgba, &[0; 64]);
+
+        let err = BlockTextureSet::load_dir(&dir).err().unwrap().to_string();
+        assert!(err.contains("b.png"), "{}", err);
+
+        let empty = test_dir("empty");
+        assert!(BlockTextureSet::load_dir(&empty).is_err());
+    }
+
+    #[test]
+    fn adding_a_name_twice_replaces_the_texture() {
+        let mut set = BlockTextureSet::new(1, 1);
+        assert_eq!(set.add("water", 1, 1, &[1, 2, 3, 4]).unwrap(), 1);
+        assert_eq!(set.add("water", 1, 1, &[5, 6, 7, 8]).unwrap(), 1);
+        assert_eq!(set.layer_count(), 2);
+        assert_eq!(set.pixels(1), Some(&[5, 6, 7, 8][..]));
+        assert!(set.add("lava", 1, 1, &[0; 3]).is_err());
+    }
+
+    #[test]
+    fn placeholder_is_a_checker() {
+        let set = BlockTextureSet::new(4, 4);
+        let texel = |x: usize, y: usize| {
+            let start = (y * 4 + x) * 4;
+            &set.pixels(TextureLayers::MISSING).unwrap()[start..start + 4]
+        };
+        assert_eq!(texel(0, 0), [255, 0, 255, 255]);
+        assert_eq!(texel(3, 0), [0, 0, 0, 255]);
+        assert_eq!(texel(0, 3), [0, 0, 0, 255]);
+        assert_eq!(texel(3, 3), [255, 0, 255, 255]);
+    }
+
+    #[test]
+    fn mip_chain_ends_at_one_texel() {
+        assert_eq!(mip_levels(1, 1), 1);
+        assert_eq!(mip_levels(16, 16), 5);
+        assert_eq!(mip_levels(32, 8), 6);
+        assert_eq!(mip_levels(17, 3), 5);
+    }
+}
@@ -0,0 +1,285 @@
+//! Packed vertex of chunk section meshes, decoded by `voxel_vertex.glsl`.
+//!
+//! Two u32s per vertex:
+//! - word 0: x, y, z (5 bits each), face (3 bits), ao (2 bits), u, v (5 bits each)
+//! - word 1: texture layer (16 bits), sky light (4 bits), block light (4 bits),
+//!   emission (4 bits)
+
+use bytemuck::{Pod, Zeroable};
+use glam::IVec3;
+
+const COORD_BITS: u32 = 5;
+const FACE_BITS: u32 = 3;
+const AO_BITS: u32 = 2;
+const LAYER_BITS: u32 = 16;
+const LIGHT_BITS: u32 = 4;
+
+// offsets of the fields in their word, keep in sync with `voxel_vertex.glsl`
+const X_SHIFT: u32 = 0;
+const Y_SHIFT: u32 = X_SHIFT + COORD_BITS;
+const Z_SHIFT: u32 = Y_SHIFT + COORD_BITS;
+const FACE_SHIFT: u32 = Z_SHIFT + COORD_BITS;
+const AO_SHIFT: u32 = FACE_SHIFT + FACE_BITS;
+const U_SHIFT: u32 = AO_SHIFT + AO_BITS;
+const V_SHIFT: u32 = U_SHIFT + COORD_BITS;
+const LAYER_SHIFT: u32 = 0;
+const SKY_SHIFT: u32 = LAYER_SHIFT + LAYER_BITS;
+const BLOCK_SHIFT: u32 = SKY_SHIFT + LIGHT_BITS;
+const EMISSION_SHIFT: u32 = BLOCK_SHIFT + LIGHT_BITS;
+
+const fn mask(bits: u32) -> u32 {
+    (1 << bits) - 1
+}
+
+/// Direction a block face looks towards
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Face {
+    PosX,
+    NegX,
+    PosY,
+    NegY,
+    PosZ,
+    NegZ,
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [
+        Face::PosX,
+        Face::NegX,
+        Face::PosY,
+        Face::NegY,
+        Face::PosZ,
+        Face::NegZ,
+    ];
+
+    pub fn from_index(index: u32) -> Option<Face> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    pub fn normal(self) -> IVec3 {
+        match self {
+            Face::PosX => IVec3::X,
+            Face::NegX => IVec3::NEG_X,
+            Face::PosY => IVec3::Y,
+            Face::NegY => IVec3::NEG_Y,
+            Face::PosZ => IVec3::Z,
+            Face::NegZ => IVec3::NEG_Z,
+        }
+    }
+}
+
+/// Unpacked chunk vertex, positions are relative to the section origin
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoxelVertex {
+    /// Up to [`Self::MAX_COORD`] on every axis
+    pub position: [u8; 3],
+    pub face: Face,
+    /// Texture coordinates in blocks, up to [`Self::MAX_COORD`], the texture repeats
+    /// every block
+    pub uv: [u8; 2],
+    /// Occlusion of the corner by its neighbours from 0, unoccluded, to [`Self::MAX_AO`]
+    pub ao: u8,
+    /// Layer of the block texture array
+    pub layer: u16,
+    /// Up to [`Self::MAX_LIGHT`]
+    pub sky_light: u8,
+    /// Up to [`Self::MAX_LIGHT`]
+    pub block_light: u8,
+    /// Light the block gives off itself, up to [`Self::MAX_LIGHT`]. Emissive faces are
+    /// drawn brighter than white so they bloom.
+    pub emission: u8,
+}
+
+/// Vertex as stored in the [`GeometryArena`](super::GeometryArena)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Pod, Zeroable)]
+pub struct PackedVertex(pub [u32; 2]);
+
+impl VoxelVertex {
+    /// Largest coordinate of a vertex in a section, and largest texture coordinate of a
+    /// quad spanning a whole section
+    pub const MAX_COORD: u8 = 16;
+    pub const MAX_AO: u8 = 3;
+    pub const MAX_LIGHT: u8 = 15;
+
+    /// Fields out of range are truncated, they are only checked in debug builds
+    pub fn pack(&self) -> PackedVertex {
+        debug_assert!(self.position.iter().all(|coord| *coord <= Self::MAX_COORD));
+        debug_assert!(self.uv.iter().all(|coord| *coord <= Self::MAX_COORD));
+        debug_assert!(self.ao <= Self::MAX_AO);
+        debug_assert!(self.sky_light <= Self::MAX_LIGHT && self.block_light <= Self::MAX_LIGHT);
+        debug_assert!(self.emission <= Self::MAX_LIGHT);
+
+        let field = |value: u8, bits: u32, shift: u32| (value as u32 & mask(bits)) << shift;
+
+        let [x, y, z] = self.position;
+        let [u, v] = self.uv;
+        let low = field(x, COORD_BITS, X_SHIFT)
+            | field(y, COORD_BITS, Y_SHIFT)
+            | field(z, COORD_BITS, Z_SHIFT)
+            | (self.face.index() << FACE_SHIFT)
+            | field(self.ao, AO_BITS, AO_SHIFT)
+            | field(u, COORD_BITS, U_SHIFT)
+            | field(v, COORD_BITS, V_SHIFT);
+        let high = ((self.layer as u32) << LAYER_SHIFT)
+            | field(self.sky_light, LIGHT_BITS, SKY_SHIFT)
+            | field(self.block_light, LIGHT_BITS, BLOCK_SHIFT)
+            | field(self.emission, LIGHT_BITS, EMISSION_SHIFT);
+
+        PackedVertex([low, high])
+    }
+}
+
+impl PackedVertex {
+    /// `None` when the face bits don't name a face
+    pub fn unpack(&self) -> Option<VoxelVertex> {
+        let [low, high] = self.0;
+        let field = |word: u32, bits: u32, shift: u32| ((word >> shift) & mask(bits)) as u8;
+
+        Some(VoxelVertex {
+            position: [
+                field(low, COORD_BITS, X_SHIFT),
+                field(low, COORD_BITS, Y_SHIFT),
+                field(low, COORD_BITS, Z_SHIFT),
+            ],
+            face: Face::from_index((low >> FACE_SHIFT) & mask(FACE_BITS))?,
+            uv: [
+                field(low, COORD_BITS, U_SHIFT),
+                field(low, COORD_BITS, V_SHIFT),
+            ],
+            ao: field(low, AO_BITS, AO_SHIFT),
+            layer: ((high >> LAYER_SHIFT) & mask(LAYER_BITS)) as u16,
+            sky_light: field(high, LIGHT_BITS, SKY_SHIFT),
+            block_light: field(high, LIGHT_BITS, BLOCK_SHIFT),
+            emission: field(high, LIGHT_BITS, EMISSION_SHIFT),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex() -> VoxelVertex {
+        VoxelVertex {
+            position: [3, 16, 0],
+            face: Face::NegZ,
+            uv: [7, 16],
+            ao: 2,
+            layer: 513,
+            sky_light: 15,
+            block_light: 4,
+            emission: 9,
+        }
+    }
+
+    #[test]
+    fn round_trips_every_coordinate() {
+        for coord in 0..=VoxelVertex::MAX_COORD {
+            for face in Face::ALL {
+                let vertex = VoxelVertex {
+                    position: [coord, VoxelVertex::MAX_COORD - coord, coord / 2],
+                    face,
+                    uv: [VoxelVertex::MAX_COORD - coord, coord],
+                    ..vertex()
+                };
+                assert_eq!(vertex.pack().unpack(), Some(vertex));
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_every_shading_value() {
+        for ao in 0..=VoxelVertex::MAX_AO {
+            for light in 0..=VoxelVertex::MAX_LIGHT {
+                let vertex = VoxelVertex {
+                    ao,
+                    sky_light: light,
+                    block_light: VoxelVertex::MAX_LIGHT - light,
+                    emission: light,
+                    ..vertex()
+                };
+                assert_eq!(vertex.pack().unpack(), Some(vertex));
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_layer_extremes() {
+        for layer in [0, 1, 255, 256, u16::MAX - 1, u16::MAX] {
+            let vertex = VoxelVertex { layer, ..vertex() };
+            assert_eq!(vertex.pack().unpack(), Some(vertex));
+        }
+    }
+
+    #[test]
+    fn fields_fit_their_words() {
+        let low = [
+            (X_SHIFT, COORD_BITS),
+            (Y_SHIFT, COORD_BITS),
+            (Z_SHIFT, COORD_BITS),
+            (FACE_SHIFT, FACE_BITS),
+            (AO_SHIFT, AO_BITS),
+            (U_SHIFT, COORD_BITS),
+            (V_SHIFT, COORD_BITS),
+        ];
+        let high = [
+            (LAYER_SHIFT, LAYER_BITS),
+            (SKY_SHIFT, LIGHT_BITS),
+            (BLOCK_SHIFT, LIGHT_BITS),
+            (EMISSION_SHIFT, LIGHT_BITS),
+        ];
+
+        for fields in [&low[..], &high[..]] {
+            let mut used = 0u64;
+            for (shift, bits) in fields {
+                let field = ((1u64 << bits) - 1) << shift;
+                assert_eq!(used & field, 0);
+                used |= field;
+            }
+            assert!(used <= u32::MAX as u64);
+        }
+
+        assert!(VoxelVertex::MAX_COORD as u32 <= mask(COORD_BITS));
+        assert!(VoxelVertex::MAX_AO as u32 <= mask(AO_BITS));
+        assert!(VoxelVertex::MAX_LIGHT as u32 <= mask(LIGHT_BITS));
+        assert!(Face::ALL.len() as u32 <= mask(FACE_BITS) + 1);
+    }
+
+    #[test]
+    fn zero_vertex_packs_to_zero() {
+        let vertex = VoxelVertex {
+            position: [0; 3],
+            face: Face::PosX,
+            uv: [0; 2],
+            ao: 0,
+            layer: 0,
+            sky_light: 0,
+            block_light: 0,
+            emission: 0,
+        };
+        assert_eq!(vertex.pack(), PackedVertex([0, 0]));
+        assert_eq!(PackedVertex::default().unpack(), Some(vertex));
+    }
+
+    #[test]
+    fn rejects_unknown_faces() {
+        for index in 6..8 {
+            let packed = PackedVertex([index << FACE_SHIFT, 0]);
+            assert_eq!(packed.unpack(), None);
+        }
+    }
+
+    #[test]
+    fn face_indices_match_normals() {
+        for (index, face) in Face::ALL.into_iter().enumerate() {
+            assert_eq!(face.index(), index as u32);
+            assert_eq!(Face::from_index(index as u32), Some(face));
+            assert_eq!(face.normal().abs().element_sum(), 1);
+        }
+    }
+}
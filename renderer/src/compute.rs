@@ -0,0 +1,98 @@
+use ash::vk;
+
+use crate::{core, graph::PassContext};
+
+/// Command buffers recorded on the compute queue without waiting for the previous ones
+const COMPUTE_SLOTS: usize = 4;
+
+/// Completion point of work submitted with [`crate::Renderer::dispatch_async`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ComputeTicket(pub(crate) u64);
+
+/// Work submitted to the compute queue, ordered on a timeline semaphore that counts
+/// the submissions so both the host and the graphics queue can wait on any of them
+pub(crate) struct AsyncCompute {
+    pool: vk::CommandPool,
+    buffers: [vk::CommandBuffer; COMPUTE_SLOTS],
+    pub timeline: vk::Semaphore,
+    submitted: u64,
+}
+
+impl AsyncCompute {
+    pub fn new(device: &core::Device) -> Result<Self, vk::Result> {
+        let pool =
+            device.create_compute_command_pool(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)?;
+
+        let mut buffers = [vk::CommandBuffer::null(); COMPUTE_SLOTS];
+        for buffer in &mut buffers {
+            *buffer = device.allocate_command_buffer(pool, vk::CommandBufferLevel::PRIMARY)?;
+        }
+
+        let timeline = device.create_timeline_semaphore(0)?;
+
+        Ok(Self {
+            pool,
+            buffers,
+            timeline,
+            submitted: 0,
+        })
+    }
+
+    pub fn submit<F>(
+        &mut self,
+        device: &core::Device,
+        timeout: u64,
+        function: F,
+    ) -> Result<ComputeTicket, vk::Result>
+    where
+        F: FnOnce(&PassContext),
+    {
+        let value = self.submitted + 1;
+        let cmd = self.buffers[value as usize % COMPUTE_SLOTS];
+
+        // the slot is free once the submission that last used it completed
+        let previous = value.saturating_sub(COMPUTE_SLOTS as u64);
+        device.wait_semaphore(self.timeline, previous, timeout)?;
+
+        let handle = device.handle();
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            handle.reset_command_buffer(cmd, vk::CommandBufferResetFlags::empty())?;
+            handle.begin_command_buffer(cmd, &begin_info)?;
+        }
+
+        function(&PassContext::detached(cmd, handle));
+
+        unsafe { handle.end_command_buffer(cmd)? };
+
+        let cmd_infos = [vk::CommandBufferSubmitInfo::default().command_buffer(cmd)];
+        let signal_infos = [vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.timeline)
+            .value(value)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)];
+        let submit = vk::SubmitInfo2::default()
+            .command_buffer_infos(&cmd_infos)
+            .signal_semaphore_infos(&signal_infos);
+
+        unsafe { handle.queue_submit2(device.compute_queue(), &[submit], vk::Fence::null())? };
+
+        self.submitted = value;
+        Ok(ComputeTicket(value))
+    }
+
+    pub fn wait(
+        &self,
+        device: &core::Device,
+        ticket: ComputeTicket,
+        timeout: u64,
+    ) -> Result<(), vk::Result> {
+        device.wait_semaphore(self.timeline, ticket.0, timeout)
+    }
+
+    pub fn destroy(&mut self, device: &core::Device) {
+        device.destroy_command_pool(self.pool);
+        device.destroy_semaphore(self.timeline);
+    }
+}
@@ -0,0 +1,716 @@
+use std::{error::Error, ffi::CString};
+
+use ash::vk;
+
+/// Presentation preference, when the surface doesn't support the requested mode the
+/// closest supported one is picked (FIFO is always available)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Vsync, never tears
+    #[default]
+    Fifo,
+    /// Vsync, but late frames are presented immediately and may tear
+    FifoRelaxed,
+    /// Low latency without tearing, the newest frame replaces the queued one
+    Mailbox,
+    /// No vsync, lowest latency, tears
+    Immediate,
+}
+
+impl PresentMode {
+    /// Vulkan present modes to try in order of preference
+    fn candidates(self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            PresentMode::Fifo => &[vk::PresentModeKHR::FIFO],
+            PresentMode::FifoRelaxed => {
+                &[vk::PresentModeKHR::FIFO_RELAXED, vk::PresentModeKHR::FIFO]
+            }
+            // immediate would tear, vsync is closer to what was asked for
+            PresentMode::Mailbox => &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+            PresentMode::Immediate => &[
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO,
+            ],
+        }
+    }
+
+    pub(crate) fn choose(self, supported: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+        self.candidates()
+            .iter()
+            .copied()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or(vk::PresentModeKHR::FIFO)
+    }
+
+    pub(crate) fn from_vk(mode: vk::PresentModeKHR) -> Option<Self> {
+        match mode {
+            vk::PresentModeKHR::FIFO => Some(PresentMode::Fifo),
+            vk::PresentModeKHR::FIFO_RELAXED => Some(PresentMode::FifoRelaxed),
+            vk::PresentModeKHR::MAILBOX => Some(PresentMode::Mailbox),
+            vk::PresentModeKHR::IMMEDIATE => Some(PresentMode::Immediate),
+            _ => None,
+        }
+    }
+}
+
+pub struct RendererConfig {
+    pub app_name: CString,
+    pub validation: bool,
+    pub present_mode: PresentMode,
+    pub surface_format: SurfaceFormat,
+    /// MSAA sample count (1, 2, 4 or 8), clamped to what the device supports. Ignored
+    /// while [`AntiAliasing::Taa`] is selected.
+    pub msaa_samples: u32,
+    /// Clear depth to 0 and test with GREATER, better precision with a float depth buffer
+    pub reverse_z: bool,
+    pub render_settings: RenderSettings,
+}
+
+/// Quality settings that can be changed while running with
+/// [`Renderer::set_render_settings`](crate::Renderer::set_render_settings)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderSettings {
+    /// Darken block corners surrounded by other blocks, baked in the chunk meshes
+    pub ambient_occlusion: bool,
+    /// Average the light of the blocks around each vertex instead of lighting whole
+    /// faces with the block in front of them, baked in the chunk meshes
+    pub smooth_lighting: bool,
+    /// Anisotropic filtering of the block textures, 1 disables it. Clamped to
+    /// [`Renderer::max_anisotropy`](crate::Renderer::max_anisotropy).
+    pub anisotropy: u32,
+    /// Chunks loaded around the camera in every direction, the fog ends there
+    pub render_distance: u32,
+    pub shadows: ShadowSettings,
+    pub fog: FogSettings,
+    pub ssao: SsaoSettings,
+    pub post: PostSettings,
+    pub resolution: ResolutionSettings,
+}
+
+/// Sun shadows, rendered by [`ShadowMaps`](crate::ShadowMaps)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Slices of the view frustum with a shadow map each, from 1 to
+    /// [`MAX_CASCADES`](crate::MAX_CASCADES)
+    pub cascades: u32,
+    /// Width and height of the shadow map of every cascade
+    pub resolution: u32,
+    /// Distance from the camera shadows stop at, in blocks
+    pub distance: f32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            cascades: 3,
+            resolution: 2048,
+            distance: 128.0,
+        }
+    }
+}
+
+/// Screen-space ambient occlusion computed from the depth buffer by
+/// [`AmbientOcclusion`](crate::AmbientOcclusion). It darkens whatever is drawn, entities
+/// and non-cube models included, on top of the vertex
+/// [`ambient_occlusion`](RenderSettings::ambient_occlusion) of the blocks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    /// Computes the occlusion at half the width and height of the frame
+    pub half_resolution: bool,
+    /// Distance around a surface occluders are looked for, in blocks
+    pub radius: f32,
+    /// Exponent of the occlusion, above 1 darkens it
+    pub intensity: f32,
+    /// Samples per pixel, from 4 to [`SsaoSettings::MAX_SAMPLES`]
+    pub samples: u32,
+    /// Smooths the noise of the samples with a blur that stops at depth edges
+    pub blur: bool,
+}
+
+impl SsaoSettings {
+    pub const MAX_SAMPLES: u32 = 32;
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            half_resolution: true,
+            radius: 1.0,
+            intensity: 1.5,
+            samples: 12,
+            blur: true,
+        }
+    }
+}
+
+/// How the fog thickens with the distance to the camera
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FogMode {
+    /// From nothing at the fog start to opaque at the render distance
+    #[default]
+    Linear,
+    /// Thickens from the camera on with the density of the settings
+    Exponential,
+}
+
+/// Distance fog in the air, matched to the sky color. Whatever the settings the last
+/// blocks before the render distance fade into the fog so chunks don't pop in, the
+/// fog under water and in lava doesn't depend on them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FogSettings {
+    pub enabled: bool,
+    pub mode: FogMode,
+    /// Fraction of the render distance the linear fog starts at
+    pub start: f32,
+    /// Density of the exponential fog, how many times the fog thickens by e over the
+    /// render distance
+    pub density: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: FogMode::Linear,
+            start: 0.75,
+            density: 2.5,
+        }
+    }
+}
+
+/// Curve mapping the HDR scene to the display range
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Tonemap {
+    /// Filmic fit of the ACES reference transform, saturated highlights roll off to white
+    #[default]
+    Aces,
+    /// `c / (1 + c)`, soft and desaturated
+    Reinhard,
+    /// Clamps to the display range
+    None,
+}
+
+/// How edges are smoothed, on top of the MSAA of
+/// [`Renderer::set_msaa_samples`](crate::Renderer::set_msaa_samples)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AntiAliasing {
+    None,
+    /// Blurs the edges found in the tonemapped image, cheap but softens the textures
+    #[default]
+    Fxaa,
+    /// Accumulates jittered frames with
+    /// [`TemporalAntiAliasing`](crate::TemporalAntiAliasing). It replaces MSAA, the scene
+    /// is drawn with a single sample while it is selected.
+    Taa,
+}
+
+/// Stages of the [`PostProcess`](crate::PostProcess) chain, each one can be toggled
+/// while running
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PostSettings {
+    /// Glow around what is brighter than the threshold, like emissive blocks and the sun
+    pub bloom: bool,
+    /// Scene brightness bloom starts at, after the exposure
+    pub bloom_threshold: f32,
+    /// How much of the bloom is added over the scene
+    pub bloom_intensity: f32,
+    pub tonemap: Tonemap,
+    /// Scale of the scene before tonemapping
+    pub exposure: f32,
+    pub anti_aliasing: AntiAliasing,
+    /// Strength of the sharpening after TAA, 0 disables it
+    pub taa_sharpness: f32,
+    /// Grades the colors with the LUT of
+    /// [`PostProcess::set_color_lut`](crate::PostProcess::set_color_lut), if any
+    pub color_grading: bool,
+    /// Scale of the display colors
+    pub brightness: f32,
+    /// Display gamma adjustment, above 1 brightens the dark tones
+    pub gamma: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            bloom: true,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.08,
+            tonemap: Tonemap::Aces,
+            exposure: 1.0,
+            anti_aliasing: AntiAliasing::Fxaa,
+            taa_sharpness: 0.25,
+            color_grading: true,
+            brightness: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+/// Filter bringing the scaled scene up to the size of the output
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Upscale {
+    #[default]
+    Bilinear,
+    /// Bilinear with the contrast of the edges restored by
+    /// [`ResolutionSettings::sharpness`], needs [`PostProcess`](crate::PostProcess)
+    Sharpen,
+}
+
+/// Dynamic resolution: the 3D scene is drawn at a fraction of the output size picked
+/// every frame from the GPU time of the last frames, then upscaled to the output.
+/// What is drawn with [`Renderer::draw_overlay`](crate::Renderer::draw_overlay), like
+/// the UI, stays at the output size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResolutionSettings {
+    /// Without it the scene is drawn at the output size
+    pub dynamic: bool,
+    /// GPU time of a frame the scale aims for, in milliseconds
+    pub target_frame_time: f32,
+    /// Lowest fraction of the output width and height the scene is drawn at
+    pub min_scale: f32,
+    /// Highest fraction, 1 draws the scene at the output size when there's headroom
+    pub max_scale: f32,
+    pub upscale: Upscale,
+    /// Strength of [`Upscale::Sharpen`], 0 leaves the bilinear upscale as it is
+    pub sharpness: f32,
+}
+
+impl Default for ResolutionSettings {
+    fn default() -> Self {
+        Self {
+            dynamic: false,
+            target_frame_time: 1000.0 / 60.0,
+            min_scale: 0.5,
+            max_scale: 1.0,
+            upscale: Upscale::Bilinear,
+            sharpness: 0.5,
+        }
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            ambient_occlusion: true,
+            smooth_lighting: true,
+            anisotropy: 8,
+            render_distance: 12,
+            shadows: ShadowSettings::default(),
+            fog: FogSettings::default(),
+            ssao: SsaoSettings::default(),
+            post: PostSettings::default(),
+            resolution: ResolutionSettings::default(),
+        }
+    }
+}
+
+impl RendererConfig {
+    /// Sample count asked for, TAA takes the place of MSAA
+    pub(crate) fn effective_msaa_samples(&self) -> u32 {
+        match self.render_settings.post.anti_aliasing {
+            AntiAliasing::Taa => 1,
+            _ => self.msaa_samples,
+        }
+    }
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            app_name: CString::new("Placeholder").unwrap(),
+            validation: false,
+            present_mode: PresentMode::default(),
+            surface_format: SurfaceFormat::default(),
+            msaa_samples: 1,
+            reverse_z: false,
+            render_settings: RenderSettings::default(),
+        }
+    }
+}
+
+/// Swapchain format preference, falls back to [`SurfaceFormat::Srgb8`] when the
+/// surface (or the instance, for HDR color spaces) doesn't support it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SurfaceFormat {
+    /// 8-bit sRGB, supported everywhere
+    #[default]
+    Srgb8,
+    /// 10-bit per channel in the sRGB color space, less banding on capable monitors
+    Unorm10,
+    /// 10-bit BT.2020 with the PQ transfer function
+    Hdr10,
+    /// 16-bit float extended sRGB, linear with 1.0 at 80 nits
+    ScRgb,
+}
+
+impl SurfaceFormat {
+    pub(crate) fn is_hdr(self) -> bool {
+        matches!(self, SurfaceFormat::Hdr10 | SurfaceFormat::ScRgb)
+    }
+
+    /// Format and color space pairs to try in order of preference
+    fn candidates(self) -> &'static [(vk::Format, vk::ColorSpaceKHR)] {
+        match self {
+            SurfaceFormat::Srgb8 => &[
+                (vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                (vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            ],
+            SurfaceFormat::Unorm10 => &[
+                (
+                    vk::Format::A2B10G10R10_UNORM_PACK32,
+                    vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                ),
+                (
+                    vk::Format::A2R10G10B10_UNORM_PACK32,
+                    vk::ColorSpaceKHR::SRGB_NONLINEAR,
+                ),
+            ],
+            SurfaceFormat::Hdr10 => &[
+                (
+                    vk::Format::A2B10G10R10_UNORM_PACK32,
+                    vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+                ),
+                (
+                    vk::Format::A2R10G10B10_UNORM_PACK32,
+                    vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+                ),
+            ],
+            SurfaceFormat::ScRgb => &[(
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            )],
+        }
+    }
+
+    pub(crate) fn choose(
+        self,
+        supported: &[vk::SurfaceFormatKHR],
+    ) -> Result<vk::SurfaceFormatKHR, Box<dyn Error>> {
+        let find = |preference: SurfaceFormat| {
+            preference
+                .candidates()
+                .iter()
+                .find_map(|(format, color_space)| {
+                    supported
+                        .iter()
+                        .find(|val| val.format == *format && val.color_space == *color_space)
+                        .copied()
+                })
+        };
+
+        find(self)
+            .or_else(|| find(SurfaceFormat::Srgb8))
+            .or_else(|| supported.first().copied())
+            .ok_or_else(|| "Surface reports no supported formats".into())
+    }
+}
+
+/// How the values written to the output image reach the display,
+/// the last pass before presentation (tonemapping) has to encode accordingly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// The swapchain format is sRGB, the hardware encodes linear values on write
+    Srgb,
+    /// UNORM format in the sRGB color space, the shader must apply the sRGB curve
+    SrgbInShader,
+    /// Linear BT.2020 values must be encoded with the PQ curve, 1.0 = 10000 nits
+    Pq,
+    /// Linear extended sRGB values, 1.0 = 80 nits and values may exceed 1.0
+    ExtendedLinear,
+}
+
+impl OutputEncoding {
+    /// Number of the encoding in `output.glsl`, headless outputs (`None`) are read back
+    /// as linear values like hardware sRGB encodes
+    pub(crate) fn shader_id(encoding: Option<Self>) -> u32 {
+        match encoding {
+            None | Some(Self::Srgb) => 0,
+            Some(Self::SrgbInShader) => 1,
+            Some(Self::Pq) => 2,
+            Some(Self::ExtendedLinear) => 3,
+        }
+    }
+}
+
+/// Format and color space selected for a swapchain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub format: vk::Format,
+    pub color_space: vk::ColorSpaceKHR,
+    pub encoding: OutputEncoding,
+}
+
+impl OutputFormat {
+    pub(crate) fn new(surface_format: vk::SurfaceFormatKHR) -> Self {
+        let encoding = match surface_format.color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => OutputEncoding::Pq,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => OutputEncoding::ExtendedLinear,
+            _ if is_srgb_format(surface_format.format) => OutputEncoding::Srgb,
+            _ => OutputEncoding::SrgbInShader,
+        };
+
+        Self {
+            format: surface_format.format,
+            color_space: surface_format.color_space,
+            encoding,
+        }
+    }
+
+    /// Format of the image the frame is composed in before being copied to the swapchain
+    pub(crate) fn draw_format(&self) -> vk::Format {
+        match self.encoding {
+            OutputEncoding::Pq | OutputEncoding::ExtendedLinear => vk::Format::R16G16B16A16_SFLOAT,
+            OutputEncoding::SrgbInShader
+                if self.format == vk::Format::A2B10G10R10_UNORM_PACK32
+                    || self.format == vk::Format::A2R10G10B10_UNORM_PACK32 =>
+            {
+                vk::Format::A2B10G10R10_UNORM_PACK32
+            }
+            _ => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+/// Highest sample count not above `requested` usable for both color and depth attachments
+pub(crate) fn msaa_sample_count(
+    requested: u32,
+    limits: &vk::PhysicalDeviceLimits,
+) -> vk::SampleCountFlags {
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+    [
+        (8, vk::SampleCountFlags::TYPE_8),
+        (4, vk::SampleCountFlags::TYPE_4),
+        (2, vk::SampleCountFlags::TYPE_2),
+    ]
+    .into_iter()
+    .find(|(count, flag)| *count <= requested && supported.contains(*flag))
+    .map_or(vk::SampleCountFlags::TYPE_1, |(_, flag)| flag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn present_mode_prefers_the_requested_one() {
+        let all = [
+            vk::PresentModeKHR::IMMEDIATE,
+            vk::PresentModeKHR::MAILBOX,
+            vk::PresentModeKHR::FIFO,
+            vk::PresentModeKHR::FIFO_RELAXED,
+        ];
+        for mode in [
+            PresentMode::Fifo,
+            PresentMode::FifoRelaxed,
+            PresentMode::Mailbox,
+            PresentMode::Immediate,
+        ] {
+            assert_eq!(PresentMode::from_vk(mode.choose(&all)), Some(mode));
+        }
+    }
+
+    #[test]
+    fn present_mode_falls_back_when_missing() {
+        let cases = [
+            (
+                PresentMode::FifoRelaxed,
+                &[vk::PresentModeKHR::FIFO][..],
+                vk::PresentModeKHR::FIFO,
+            ),
+            (
+                PresentMode::Mailbox,
+                &[vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::FIFO],
+                vk::PresentModeKHR::FIFO,
+            ),
+            (
+                PresentMode::Immediate,
+                &[vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::FIFO],
+                vk::PresentModeKHR::MAILBOX,
+            ),
+            (
+                PresentMode::Immediate,
+                &[vk::PresentModeKHR::FIFO],
+                vk::PresentModeKHR::FIFO,
+            ),
+            // FIFO is required by the spec, picked even if a broken driver leaves it out
+            (PresentMode::Mailbox, &[], vk::PresentModeKHR::FIFO),
+        ];
+        for (mode, supported, expected) in cases {
+            assert_eq!(
+                mode.choose(supported),
+                expected,
+                "{:?} {:?}",
+                mode,
+                supported
+            );
+        }
+    }
+
+    fn surface_format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        }
+    }
+
+    fn sdr(format: vk::Format) -> vk::SurfaceFormatKHR {
+        surface_format(format, vk::ColorSpaceKHR::SRGB_NONLINEAR)
+    }
+
+    #[test]
+    fn surface_format_prefers_the_requested_one() {
+        let supported = [
+            sdr(vk::Format::B8G8R8A8_UNORM),
+            sdr(vk::Format::B8G8R8A8_SRGB),
+            sdr(vk::Format::A2R10G10B10_UNORM_PACK32),
+            surface_format(
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+            ),
+        ];
+
+        let cases = [
+            (SurfaceFormat::Srgb8, supported[1]),
+            (SurfaceFormat::Unorm10, supported[2]),
+            (SurfaceFormat::Hdr10, supported[3]),
+        ];
+        for (preference, expected) in cases {
+            assert_eq!(preference.choose(&supported).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn surface_format_falls_back_to_srgb() {
+        let supported = [
+            sdr(vk::Format::B8G8R8A8_UNORM),
+            sdr(vk::Format::R8G8B8A8_SRGB),
+        ];
+        for preference in [
+            SurfaceFormat::Unorm10,
+            SurfaceFormat::Hdr10,
+            SurfaceFormat::ScRgb,
+        ] {
+            assert_eq!(preference.choose(&supported).unwrap(), supported[1]);
+        }
+
+        // 10-bit in the wrong color space is not HDR
+        let supported = [
+            sdr(vk::Format::B8G8R8A8_SRGB),
+            sdr(vk::Format::A2B10G10R10_UNORM_PACK32),
+        ];
+        assert_eq!(
+            SurfaceFormat::Hdr10.choose(&supported).unwrap(),
+            supported[0]
+        );
+    }
+
+    #[test]
+    fn surface_format_without_srgb_takes_the_first() {
+        let supported = [
+            sdr(vk::Format::B8G8R8A8_UNORM),
+            sdr(vk::Format::R8G8B8A8_UNORM),
+        ];
+        assert_eq!(
+            SurfaceFormat::Srgb8.choose(&supported).unwrap(),
+            supported[0]
+        );
+        assert!(SurfaceFormat::Srgb8.choose(&[]).is_err());
+    }
+
+    #[test]
+    fn output_encoding_follows_format_and_color_space() {
+        let cases = [
+            (
+                sdr(vk::Format::B8G8R8A8_SRGB),
+                OutputEncoding::Srgb,
+                vk::Format::R8G8B8A8_UNORM,
+            ),
+            (
+                sdr(vk::Format::B8G8R8A8_UNORM),
+                OutputEncoding::SrgbInShader,
+                vk::Format::R8G8B8A8_UNORM,
+            ),
+            (
+                sdr(vk::Format::A2R10G10B10_UNORM_PACK32),
+                OutputEncoding::SrgbInShader,
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+            ),
+            (
+                surface_format(
+                    vk::Format::A2B10G10R10_UNORM_PACK32,
+                    vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+                ),
+                OutputEncoding::Pq,
+                vk::Format::R16G16B16A16_SFLOAT,
+            ),
+            (
+                surface_format(
+                    vk::Format::R16G16B16A16_SFLOAT,
+                    vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+                ),
+                OutputEncoding::ExtendedLinear,
+                vk::Format::R16G16B16A16_SFLOAT,
+            ),
+        ];
+        for (surface, encoding, draw_format) in cases {
+            let output = OutputFormat::new(surface);
+            assert_eq!(output.encoding, encoding, "{:?}", surface);
+            assert_eq!(output.draw_format(), draw_format, "{:?}", surface);
+        }
+    }
+
+    #[test]
+    fn msaa_samples_are_clamped_to_the_device() {
+        let limits = |color, depth| vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: color,
+            framebuffer_depth_sample_counts: depth,
+            ..Default::default()
+        };
+        let up_to_8 = vk::SampleCountFlags::TYPE_1
+            | vk::SampleCountFlags::TYPE_2
+            | vk::SampleCountFlags::TYPE_4
+            | vk::SampleCountFlags::TYPE_8;
+        let up_to_4 = vk::SampleCountFlags::TYPE_1
+            | vk::SampleCountFlags::TYPE_2
+            | vk::SampleCountFlags::TYPE_4;
+
+        let cases = [
+            (1, limits(up_to_8, up_to_8), vk::SampleCountFlags::TYPE_1),
+            (4, limits(up_to_8, up_to_8), vk::SampleCountFlags::TYPE_4),
+            (8, limits(up_to_8, up_to_8), vk::SampleCountFlags::TYPE_8),
+            // not a power of two, rounded down
+            (6, limits(up_to_8, up_to_8), vk::SampleCountFlags::TYPE_4),
+            (16, limits(up_to_8, up_to_8), vk::SampleCountFlags::TYPE_8),
+            // both attachments must support the count
+            (8, limits(up_to_8, up_to_4), vk::SampleCountFlags::TYPE_4),
+            (8, limits(up_to_4, up_to_8), vk::SampleCountFlags::TYPE_4),
+            (
+                4,
+                limits(vk::SampleCountFlags::TYPE_1, up_to_8),
+                vk::SampleCountFlags::TYPE_1,
+            ),
+            (0, limits(up_to_8, up_to_8), vk::SampleCountFlags::TYPE_1),
+        ];
+        for (requested, limits, expected) in cases {
+            assert_eq!(
+                msaa_sample_count(requested, &limits),
+                expected,
+                "{}",
+                requested
+            );
+        }
+    }
+}
@@ -0,0 +1,29 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocation;
+
+pub struct AllocatedBuffer {
+    pub handle: vk::Buffer,
+    pub size: vk::DeviceSize,
+    pub(in crate::core) allocation: Option<Allocation>,
+}
+
+impl AllocatedBuffer {
+    /// Buffer without a handle nor memory, for tests of what is tracked on the host
+    #[cfg(test)]
+    pub(crate) fn null(size: vk::DeviceSize) -> Self {
+        Self {
+            handle: vk::Buffer::null(),
+            size,
+            allocation: None,
+        }
+    }
+
+    /// Host visible contents, `None` if the buffer lives in device local memory
+    pub fn mapped(&self) -> Option<&[u8]> {
+        self.allocation.as_ref()?.mapped_slice()
+    }
+
+    pub fn mapped_mut(&mut self) -> Option<&mut [u8]> {
+        self.allocation.as_mut()?.mapped_slice_mut()
+    }
+}
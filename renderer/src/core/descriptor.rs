@@ -0,0 +1,236 @@
+use ash::vk;
+
+/// Collects the bindings of a descriptor set layout
+#[derive(Default)]
+pub struct DescriptorLayoutBuilder<'a> {
+    bindings: Vec<vk::DescriptorSetLayoutBinding<'a>>,
+}
+
+impl<'a> DescriptorLayoutBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_binding(mut self, binding: u32, ty: vk::DescriptorType) -> Self {
+        self.bindings.push(
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(1),
+        );
+        self
+    }
+
+    /// Every binding is made visible to `stages`
+    pub fn build(
+        mut self,
+        device: &ash::Device,
+        stages: vk::ShaderStageFlags,
+    ) -> Result<vk::DescriptorSetLayout, vk::Result> {
+        for binding in &mut self.bindings {
+            binding.stage_flags |= stages;
+        }
+
+        let info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&self.bindings);
+        unsafe { device.create_descriptor_set_layout(&info, None) }
+    }
+}
+
+/// Descriptors of a type to reserve for every set a pool is created for
+#[derive(Clone, Copy, Debug)]
+pub struct PoolSizeRatio {
+    pub ty: vk::DescriptorType,
+    pub ratio: f32,
+}
+
+const MAX_SETS_PER_POOL: u32 = 4092;
+
+/// Allocates descriptor sets, creating a new pool whenever the current one runs out
+pub struct DescriptorAllocator {
+    ratios: Vec<PoolSizeRatio>,
+    full_pools: Vec<vk::DescriptorPool>,
+    ready_pools: Vec<vk::DescriptorPool>,
+    sets_per_pool: u32,
+}
+
+impl DescriptorAllocator {
+    pub fn new(
+        device: &ash::Device,
+        initial_sets: u32,
+        ratios: &[PoolSizeRatio],
+    ) -> Result<Self, vk::Result> {
+        let pool = Self::create_pool(device, initial_sets, ratios)?;
+
+        Ok(Self {
+            ratios: ratios.to_vec(),
+            full_pools: vec![],
+            ready_pools: vec![pool],
+            sets_per_pool: (initial_sets * 3 / 2).min(MAX_SETS_PER_POOL),
+        })
+    }
+
+    fn create_pool(
+        device: &ash::Device,
+        set_count: u32,
+        ratios: &[PoolSizeRatio],
+    ) -> Result<vk::DescriptorPool, vk::Result> {
+        let sizes: Vec<_> = ratios
+            .iter()
+            .map(|ratio| vk::DescriptorPoolSize {
+                ty: ratio.ty,
+                descriptor_count: ((ratio.ratio * set_count as f32) as u32).max(1),
+            })
+            .collect();
+
+        let info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(set_count)
+            .pool_sizes(&sizes);
+
+        unsafe { device.create_descriptor_pool(&info, None) }
+    }
+
+    fn get_pool(&mut self, device: &ash::Device) -> Result<vk::DescriptorPool, vk::Result> {
+        if let Some(pool) = self.ready_pools.pop() {
+            return Ok(pool);
+        }
+
+        let pool = Self::create_pool(device, self.sets_per_pool, &self.ratios)?;
+        self.sets_per_pool = (self.sets_per_pool * 3 / 2).min(MAX_SETS_PER_POOL);
+        Ok(pool)
+    }
+
+    pub fn allocate(
+        &mut self,
+        device: &ash::Device,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        let layouts = [layout];
+        let mut pool = self.get_pool(device)?;
+
+        let info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool)
+            .set_layouts(&layouts);
+
+        let mut result = unsafe { device.allocate_descriptor_sets(&info) };
+        if let Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) =
+            result
+        {
+            self.full_pools.push(pool);
+            pool = self.get_pool(device)?;
+
+            let info = info.descriptor_pool(pool);
+            result = unsafe { device.allocate_descriptor_sets(&info) };
+        }
+
+        self.ready_pools.push(pool);
+        Ok(result?[0])
+    }
+
+    /// Frees every set allocated so far, the pools are kept for reuse
+    pub fn clear_pools(&mut self, device: &ash::Device) -> Result<(), vk::Result> {
+        self.ready_pools.append(&mut self.full_pools);
+        for pool in &self.ready_pools {
+            unsafe { device.reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())? };
+        }
+        Ok(())
+    }
+
+    pub fn destroy_pools(&mut self, device: &ash::Device) {
+        for pool in self.ready_pools.drain(..).chain(self.full_pools.drain(..)) {
+            unsafe { device.destroy_descriptor_pool(pool, None) };
+        }
+    }
+}
+
+enum WriteInfo {
+    Buffer(vk::DescriptorBufferInfo),
+    Image(vk::DescriptorImageInfo),
+}
+
+/// Batches descriptor writes and applies them to a set in one call
+#[derive(Default)]
+pub struct DescriptorWriter {
+    writes: Vec<(u32, vk::DescriptorType, WriteInfo)>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_buffer(
+        mut self,
+        binding: u32,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+        ty: vk::DescriptorType,
+    ) -> Self {
+        let info = vk::DescriptorBufferInfo {
+            buffer,
+            offset,
+            range,
+        };
+        self.writes.push((binding, ty, WriteInfo::Buffer(info)));
+        self
+    }
+
+    pub fn write_image(
+        mut self,
+        binding: u32,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+        ty: vk::DescriptorType,
+    ) -> Self {
+        let info = vk::DescriptorImageInfo {
+            sampler,
+            image_view: view,
+            image_layout: layout,
+        };
+        self.writes.push((binding, ty, WriteInfo::Image(info)));
+        self
+    }
+
+    /// Whole `buffer` bound as a storage buffer
+    pub fn storage_buffer(self, binding: u32, buffer: vk::Buffer) -> Self {
+        self.write_buffer(
+            binding,
+            buffer,
+            0,
+            vk::WHOLE_SIZE,
+            vk::DescriptorType::STORAGE_BUFFER,
+        )
+    }
+
+    /// `view` bound as a storage image, storage images are always in the GENERAL layout
+    pub fn storage_image(self, binding: u32, view: vk::ImageView) -> Self {
+        self.write_image(
+            binding,
+            view,
+            vk::Sampler::null(),
+            vk::ImageLayout::GENERAL,
+            vk::DescriptorType::STORAGE_IMAGE,
+        )
+    }
+
+    pub fn update_set(&self, device: &ash::Device, set: vk::DescriptorSet) {
+        let writes: Vec<_> = self
+            .writes
+            .iter()
+            .map(|(binding, ty, info)| {
+                let write = vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(*binding)
+                    .descriptor_type(*ty);
+
+                match info {
+                    WriteInfo::Buffer(info) => write.buffer_info(std::slice::from_ref(info)),
+                    WriteInfo::Image(info) => write.image_info(std::slice::from_ref(info)),
+                }
+            })
+            .collect();
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+}
@@ -1,24 +1,106 @@
+use std::{error::Error, mem::ManuallyDrop, sync::Mutex};
+
 use ash::vk;
+use gpu_allocator::{
+    vulkan::{AllocationCreateDesc, AllocationScheme, Allocator},
+    MemoryLocation,
+};
+
+use super::{AllocatedBuffer, AllocatedImage, ImageSpec};
+
+/// Memory the renderer allocated through the device
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Bytes used by live allocations
+    pub allocated: u64,
+    /// Bytes of the memory blocks the allocations come from, the memory taken from the gpu
+    pub reserved: u64,
+    pub allocations: usize,
+}
 
 pub struct Device {
     gpu: vk::PhysicalDevice,
+    properties: vk::PhysicalDeviceProperties,
+    sampler_anisotropy: bool,
     handle: ash::Device,
     graphics: vk::Queue,
     graphics_idx: u32,
+    /// Queue of a compute only family when the gpu has one, the graphics queue otherwise
+    compute: vk::Queue,
+    compute_idx: u32,
+    // NOTE: the allocator must be dropped before the device is destroyed
+    allocator: ManuallyDrop<Mutex<Allocator>>,
 }
 
 impl Device {
     pub(in crate::core) fn new(
         gpu: vk::PhysicalDevice,
+        properties: vk::PhysicalDeviceProperties,
+        sampler_anisotropy: bool,
         handle: ash::Device,
-        graphics: vk::Queue,
-        graphics_idx: u32,
+        graphics: (vk::Queue, u32),
+        compute: (vk::Queue, u32),
+        allocator: Allocator,
     ) -> Self {
         Self {
             gpu,
+            properties,
+            sampler_anisotropy,
             handle,
-            graphics,
-            graphics_idx,
+            graphics: graphics.0,
+            graphics_idx: graphics.1,
+            compute: compute.0,
+            compute_idx: compute.1,
+            allocator: ManuallyDrop::new(Mutex::new(allocator)),
+        }
+    }
+
+    pub fn handle(&self) -> &ash::Device {
+        &self.handle
+    }
+
+    pub fn gpu(&self) -> vk::PhysicalDevice {
+        self.gpu
+    }
+
+    pub fn properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    /// Highest anisotropy samplers can use, 1 when anisotropic filtering isn't enabled
+    pub fn max_anisotropy(&self) -> f32 {
+        if self.sampler_anisotropy {
+            self.properties.limits.max_sampler_anisotropy
+        } else {
+            1.0
+        }
+    }
+
+    pub fn graphics_queue(&self) -> vk::Queue {
+        self.graphics
+    }
+
+    pub fn graphics_family(&self) -> u32 {
+        self.graphics_idx
+    }
+
+    pub fn compute_queue(&self) -> vk::Queue {
+        self.compute
+    }
+
+    /// Whether compute work can run on a queue of its own next to the graphics one
+    pub fn has_async_compute(&self) -> bool {
+        self.compute_idx != self.graphics_idx
+    }
+
+    /// Sharing mode for resources used from both the graphics and the compute queue,
+    /// concurrent when they belong to different families so no ownership transfer is needed
+    fn sharing(&self) -> (vk::SharingMode, [u32; 2]) {
+        let families = [self.graphics_idx, self.compute_idx];
+        if self.has_async_compute() {
+            (vk::SharingMode::CONCURRENT, families)
+        } else {
+            (vk::SharingMode::EXCLUSIVE, families)
         }
     }
 
@@ -33,6 +115,17 @@ impl Device {
         unsafe { self.handle.create_command_pool(&info, None) }
     }
 
+    pub fn create_compute_command_pool(
+        &self,
+        flags: vk::CommandPoolCreateFlags,
+    ) -> Result<vk::CommandPool, vk::Result> {
+        let info = vk::CommandPoolCreateInfo::default()
+            .flags(flags)
+            .queue_family_index(self.compute_idx);
+
+        unsafe { self.handle.create_command_pool(&info, None) }
+    }
+
     pub fn allocate_command_buffer(
         &self,
         pool: vk::CommandPool,
@@ -48,6 +141,16 @@ impl Device {
         Ok(buffers[0])
     }
 
+    /// Device memory taken by the allocations and reserved in memory blocks, in bytes
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let report = self.allocator.lock().unwrap().generate_report();
+        MemoryUsage {
+            allocated: report.total_allocated_bytes,
+            reserved: report.total_reserved_bytes,
+            allocations: report.allocations.len(),
+        }
+    }
+
     pub fn wait_idle(&self) {
         let _ = unsafe { self.handle.device_wait_idle() };
     }
@@ -60,6 +163,29 @@ impl Device {
         unsafe { self.handle.create_semaphore(&info, None) }
     }
 
+    pub fn create_timeline_semaphore(&self, initial: u64) -> Result<vk::Semaphore, vk::Result> {
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial);
+        let info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+        unsafe { self.handle.create_semaphore(&info, None) }
+    }
+
+    /// Waits until the timeline `semaphore` reaches `value`
+    pub fn wait_semaphore(
+        &self,
+        semaphore: vk::Semaphore,
+        value: u64,
+        timeout: u64,
+    ) -> Result<(), vk::Result> {
+        let semaphores = [semaphore];
+        let values = [value];
+        let info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        unsafe { self.handle.wait_semaphores(&info, timeout) }
+    }
+
     pub fn create_fence(&self, flags: vk::FenceCreateFlags) -> Result<vk::Fence, vk::Result> {
         let info = vk::FenceCreateInfo::default().flags(flags);
         unsafe { self.handle.create_fence(&info, None) }
@@ -73,9 +199,143 @@ impl Device {
         unsafe { self.handle.reset_fences(&[fence]) }
     }
 
+    /// Pool of `count` timestamp queries, they must be reset before being written
+    pub fn create_timestamp_pool(&self, count: u32) -> Result<vk::QueryPool, vk::Result> {
+        let info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(count);
+
+        unsafe { self.handle.create_query_pool(&info, None) }
+    }
+
+    pub fn create_image(&self, spec: &ImageSpec) -> Result<AllocatedImage, Box<dyn Error>> {
+        // images with depth are volumes, like color grading LUTs
+        let volume = spec.extent.depth > 1;
+        let info = vk::ImageCreateInfo::default()
+            .image_type(if volume {
+                vk::ImageType::TYPE_3D
+            } else {
+                vk::ImageType::TYPE_2D
+            })
+            .format(spec.format)
+            .extent(spec.extent)
+            .mip_levels(spec.mip_levels)
+            .array_layers(spec.array_layers)
+            .samples(spec.samples)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(spec.usage);
+
+        // only storage images are expected to be touched by async compute
+        let (sharing_mode, families) = self.sharing();
+        let info = if spec.usage.contains(vk::ImageUsageFlags::STORAGE) {
+            info.sharing_mode(sharing_mode)
+                .queue_family_indices(&families)
+        } else {
+            info
+        };
+
+        let handle = unsafe { self.handle.create_image(&info, None)? };
+        let requirements = unsafe { self.handle.get_image_memory_requirements(handle) };
+
+        let allocation = self
+            .allocator
+            .lock()
+            .unwrap()
+            .allocate(&AllocationCreateDesc {
+                name: spec.name,
+                requirements,
+                location: MemoryLocation::GpuOnly,
+                linear: false,
+                allocation_scheme: AllocationScheme::DedicatedImage(handle),
+            })?;
+
+        unsafe {
+            self.handle
+                .bind_image_memory(handle, allocation.memory(), allocation.offset())?
+        };
+
+        let view_type = if volume {
+            vk::ImageViewType::TYPE_3D
+        } else if spec.array_layers > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+
+        let view_info = vk::ImageViewCreateInfo::default()
+            .image(handle)
+            .view_type(view_type)
+            .format(spec.format)
+            .subresource_range(
+                vk::ImageSubresourceRange::default()
+                    .aspect_mask(spec.aspect)
+                    .level_count(spec.mip_levels)
+                    .layer_count(spec.array_layers),
+            );
+
+        let view = unsafe { self.handle.create_image_view(&view_info, None)? };
+
+        Ok(AllocatedImage {
+            handle,
+            view,
+            extent: spec.extent,
+            format: spec.format,
+            allocation: Some(allocation),
+        })
+    }
+
+    pub fn create_buffer(
+        &self,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+    ) -> Result<AllocatedBuffer, Box<dyn Error>> {
+        let (sharing_mode, families) = self.sharing();
+        let info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(sharing_mode)
+            .queue_family_indices(&families);
+
+        let handle = unsafe { self.handle.create_buffer(&info, None)? };
+        let requirements = unsafe { self.handle.get_buffer_memory_requirements(handle) };
+
+        let allocation = self
+            .allocator
+            .lock()
+            .unwrap()
+            .allocate(&AllocationCreateDesc {
+                name,
+                requirements,
+                location,
+                linear: true,
+                allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+            })?;
+
+        unsafe {
+            self.handle
+                .bind_buffer_memory(handle, allocation.memory(), allocation.offset())?
+        };
+
+        Ok(AllocatedBuffer {
+            handle,
+            size,
+            allocation: Some(allocation),
+        })
+    }
+
+    /// Address of a buffer created with `SHADER_DEVICE_ADDRESS` usage, for shaders to
+    /// read it through `buffer_reference`
+    pub fn buffer_address(&self, buffer: vk::Buffer) -> vk::DeviceAddress {
+        let info = vk::BufferDeviceAddressInfo::default().buffer(buffer);
+        unsafe { self.handle.get_buffer_device_address(&info) }
+    }
+
     pub fn destroy_command_pool(&self, pool: vk::CommandPool) {
         unsafe { self.handle.destroy_command_pool(pool, None) };
     }
+
     pub fn destroy_semaphore(&self, semaphore: vk::Semaphore) {
         unsafe { self.handle.destroy_semaphore(semaphore, None) };
     }
@@ -83,10 +343,33 @@ impl Device {
     pub fn destroy_fence(&self, fence: vk::Fence) {
         unsafe { self.handle.destroy_fence(fence, None) };
     }
+
+    pub fn destroy_query_pool(&self, pool: vk::QueryPool) {
+        unsafe { self.handle.destroy_query_pool(pool, None) };
+    }
+
+    pub fn destroy_image(&self, mut image: AllocatedImage) {
+        unsafe {
+            self.handle.destroy_image_view(image.view, None);
+            self.handle.destroy_image(image.handle, None);
+        }
+        if let Some(allocation) = image.allocation.take() {
+            let _ = self.allocator.lock().unwrap().free(allocation);
+        }
+    }
+
+    pub fn destroy_buffer(&self, mut buffer: AllocatedBuffer) {
+        unsafe { self.handle.destroy_buffer(buffer.handle, None) };
+        if let Some(allocation) = buffer.allocation.take() {
+            let _ = self.allocator.lock().unwrap().free(allocation);
+        }
+    }
 }
 
 impl Drop for Device {
     fn drop(&mut self) {
+        log::trace!("Destroying memory allocator");
+        unsafe { ManuallyDrop::drop(&mut self.allocator) };
         log::trace!("Destroying vulkan device");
         unsafe { self.handle.destroy_device(None) }
     }
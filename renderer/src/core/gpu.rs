@@ -12,7 +12,7 @@ use std::{
 /// If ok returns the gpu and the index of the graphics family
 pub fn select_gpu(
     instance: &ash::Instance,
-    surface: &Surface,
+    surface: Option<&Surface>,
     extensions: &[*const c_char],
 ) -> Result<(vk::PhysicalDevice, u32), Box<dyn Error>> {
     let mut scoreboard: BTreeMap<i32, (vk::PhysicalDevice, u32)> = BTreeMap::new();
@@ -45,14 +45,54 @@ pub fn select_gpu(
     }
 
     let chosen = scoreboard.last_key_value().unwrap().1.to_owned();
+    let info = GpuInfo::query(instance, chosen.0)?;
+    log::info!("Selected {} ({})", info.name, info.driver);
     Ok(chosen)
 }
 
+/// What the selected gpu is and the driver running it
+#[derive(Clone, Debug)]
+pub struct GpuInfo {
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    /// Driver name followed by its own description of its version
+    pub driver: String,
+    /// Vulkan version supported by the driver, `major.minor.patch`
+    pub api_version: String,
+}
+
+impl GpuInfo {
+    pub fn query(
+        instance: &ash::Instance,
+        gpu: vk::PhysicalDevice,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut driver = vk::PhysicalDeviceDriverProperties::default();
+        let mut props = vk::PhysicalDeviceProperties2::default().push_next(&mut driver);
+        unsafe { instance.get_physical_device_properties2(gpu, &mut props) };
+        let props = props.properties;
+
+        let driver_name = driver.driver_name_as_c_str()?.to_string_lossy();
+        let driver_info = driver.driver_info_as_c_str()?.to_string_lossy();
+        let version = props.api_version;
+        Ok(Self {
+            name: props.device_name_as_c_str()?.to_string_lossy().into_owned(),
+            device_type: props.device_type,
+            driver: format!("{} {}", driver_name, driver_info).trim().to_owned(),
+            api_version: format!(
+                "{}.{}.{}",
+                vk::api_version_major(version),
+                vk::api_version_minor(version),
+                vk::api_version_patch(version)
+            ),
+        })
+    }
+}
+
 fn is_suitable(
     instance: &ash::Instance,
     gpu: vk::PhysicalDevice,
     extensions: &[*const c_char],
-    surface: &Surface,
+    surface: Option<&Surface>,
 ) -> Result<Option<u32>, Box<dyn Error>> {
     // check that gpu supports all the required extensions
     let supported_extensions = unsafe { instance.enumerate_device_extension_properties(gpu)? };
@@ -78,13 +118,37 @@ fn is_suitable(
         }
     }
 
+    // dynamic rendering and synchronization2 are core in 1.3, the device is created with them
+    let props = unsafe { instance.get_physical_device_properties(gpu) };
+    if props.api_version < vk::API_VERSION_1_3 {
+        log::error!(
+            "Device only supports Vulkan {}.{}",
+            vk::api_version_major(props.api_version),
+            vk::api_version_minor(props.api_version)
+        );
+        return Ok(None);
+    }
+
+    // check that gpu supports every feature the device is created with
+    let missing = missing_features(&query_features(instance, gpu));
+    if !missing.is_empty() {
+        log::error!("Device features {:?} are not supported", missing);
+        return Ok(None);
+    }
+
     // TODO: check that gpu supports swapchain
 
-    // check that gpu has a graphics queue family that can present to the surface
+    // check that gpu has a graphics queue family that can present to the surface,
+    // when running headless any graphics queue family will do
     let queue_props = unsafe { instance.get_physical_device_queue_family_properties(gpu) };
     for (index, props) in queue_props.iter().enumerate() {
-        let support_graphics = props.queue_flags.contains(vk::QueueFlags::GRAPHICS);
-        let support_presenting = surface.support_presenting(gpu, index as u32)?;
+        let support_graphics = props
+            .queue_flags
+            .contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE);
+        let support_presenting = match surface {
+            Some(surface) => surface.support_presenting(gpu, index as u32)?,
+            None => true,
+        };
 
         if support_graphics && support_presenting {
             log::trace!("Device supports a graphics queue that can present to the surface");
@@ -95,6 +159,83 @@ fn is_suitable(
     Ok(None)
 }
 
+/// Core features of Vulkan 1.0, 1.2 and 1.3 the gpu supports
+struct Features {
+    core: vk::PhysicalDeviceFeatures,
+    vulkan12: vk::PhysicalDeviceVulkan12Features<'static>,
+    vulkan13: vk::PhysicalDeviceVulkan13Features<'static>,
+}
+
+fn query_features(instance: &ash::Instance, gpu: vk::PhysicalDevice) -> Features {
+    let mut vulkan12 = vk::PhysicalDeviceVulkan12Features::default();
+    let mut vulkan13 = vk::PhysicalDeviceVulkan13Features::default();
+    let mut features = vk::PhysicalDeviceFeatures2::default()
+        .push_next(&mut vulkan12)
+        .push_next(&mut vulkan13);
+    unsafe { instance.get_physical_device_features2(gpu, &mut features) };
+    let core = features.features;
+
+    // the chain points into the locals, it isn't kept
+    vulkan12.p_next = std::ptr::null_mut();
+    vulkan13.p_next = std::ptr::null_mut();
+    Features {
+        core,
+        vulkan12,
+        vulkan13,
+    }
+}
+
+/// Features [`Instance::create_device`](super::Instance::create_device) turns on that
+/// the gpu doesn't support, anisotropic filtering is optional and not listed
+fn missing_features(features: &Features) -> Vec<&'static str> {
+    let Features {
+        core,
+        vulkan12,
+        vulkan13,
+    } = features;
+    [
+        (
+            "drawIndirectFirstInstance",
+            core.draw_indirect_first_instance,
+        ),
+        ("bufferDeviceAddress", vulkan12.buffer_device_address),
+        ("descriptorIndexing", vulkan12.descriptor_indexing),
+        ("timelineSemaphore", vulkan12.timeline_semaphore),
+        ("drawIndirectCount", vulkan12.draw_indirect_count),
+        ("dynamicRendering", vulkan13.dynamic_rendering),
+        ("synchronization2", vulkan13.synchronization2),
+    ]
+    .into_iter()
+    .filter(|(_, supported)| *supported != vk::TRUE)
+    .map(|(name, _)| name)
+    .collect()
+}
+
+/// Index of a queue family supporting compute but not graphics, used for async compute
+pub fn find_compute_family(instance: &ash::Instance, gpu: vk::PhysicalDevice) -> Option<u32> {
+    let queue_props = unsafe { instance.get_physical_device_queue_family_properties(gpu) };
+    queue_props
+        .iter()
+        .position(|props| {
+            props.queue_flags.contains(vk::QueueFlags::COMPUTE)
+                && !props.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        })
+        .map(|index| index as u32)
+}
+
+/// Returns the first format in `candidates` that supports `features` with optimal tiling
+pub fn find_supported_format(
+    instance: &ash::Instance,
+    gpu: vk::PhysicalDevice,
+    candidates: &[vk::Format],
+    features: vk::FormatFeatureFlags,
+) -> Option<vk::Format> {
+    candidates.iter().copied().find(|format| {
+        let props = unsafe { instance.get_physical_device_format_properties(gpu, *format) };
+        props.optimal_tiling_features.contains(features)
+    })
+}
+
 fn rate(props: &vk::PhysicalDeviceProperties) -> i32 {
     let mut score = match props.device_type {
         vk::PhysicalDeviceType::DISCRETE_GPU => 1000,
@@ -107,3 +248,36 @@ fn rate(props: &vk::PhysicalDeviceProperties) -> i32 {
     score += props.limits.max_image_dimension2_d as i32;
     score
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_features_are_named() {
+        let mut features = Features {
+            core: vk::PhysicalDeviceFeatures::default().draw_indirect_first_instance(true),
+            vulkan12: vk::PhysicalDeviceVulkan12Features::default()
+                .buffer_device_address(true)
+                .descriptor_indexing(true)
+                .timeline_semaphore(true)
+                .draw_indirect_count(true),
+            vulkan13: vk::PhysicalDeviceVulkan13Features::default()
+                .dynamic_rendering(true)
+                .synchronization2(true),
+        };
+        assert!(missing_features(&features).is_empty());
+
+        features.vulkan12.timeline_semaphore = vk::FALSE;
+        features.vulkan12.draw_indirect_count = vk::FALSE;
+        features.core.draw_indirect_first_instance = vk::FALSE;
+        assert_eq!(
+            missing_features(&features),
+            [
+                "drawIndirectFirstInstance",
+                "timelineSemaphore",
+                "drawIndirectCount"
+            ]
+        );
+    }
+}
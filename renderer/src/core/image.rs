@@ -0,0 +1,164 @@
+use ash::vk;
+use gpu_allocator::vulkan::Allocation;
+
+pub struct ImageSpec<'a> {
+    pub name: &'a str,
+    pub extent: vk::Extent3D,
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub aspect: vk::ImageAspectFlags,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl<'a> ImageSpec<'a> {
+    /// Single mip, single layer, single sample color image
+    pub fn color(
+        name: &'a str,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+    ) -> Self {
+        Self {
+            name,
+            extent: extent.into(),
+            format,
+            usage,
+            aspect: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+}
+
+impl<'a> ImageSpec<'a> {
+    /// Single mip, single layer depth image usable as attachment and sampled texture
+    pub fn depth(
+        name: &'a str,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        samples: vk::SampleCountFlags,
+    ) -> Self {
+        Self {
+            name,
+            extent: extent.into(),
+            format,
+            usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            aspect: vk::ImageAspectFlags::DEPTH,
+            mip_levels: 1,
+            array_layers: 1,
+            samples,
+        }
+    }
+}
+
+/// Aspects a barrier on an image of `format` has to cover
+pub fn format_aspect(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+pub struct AllocatedImage {
+    pub handle: vk::Image,
+    pub view: vk::ImageView,
+    pub extent: vk::Extent3D,
+    pub format: vk::Format,
+    pub(in crate::core) allocation: Option<Allocation>,
+}
+
+impl AllocatedImage {
+    pub fn extent_2d(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.extent.width,
+            height: self.extent.height,
+        }
+    }
+}
+
+/// Records a full barrier on every mip and layer of `image`.
+/// Good enough until passes declare their own stages and accesses.
+pub fn transition_image(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    image: vk::Image,
+    aspect: vk::ImageAspectFlags,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) {
+    let barrier = vk::ImageMemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .dst_access_mask(vk::AccessFlags2::MEMORY_WRITE | vk::AccessFlags2::MEMORY_READ)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .image(image)
+        .subresource_range(
+            vk::ImageSubresourceRange::default()
+                .aspect_mask(aspect)
+                .level_count(vk::REMAINING_MIP_LEVELS)
+                .layer_count(vk::REMAINING_ARRAY_LAYERS),
+        );
+
+    let barriers = [barrier];
+    let dependency = vk::DependencyInfo::default().image_memory_barriers(&barriers);
+    unsafe { device.cmd_pipeline_barrier2(cmd, &dependency) };
+}
+
+/// Blits the whole `src` image into the whole `dst` image, scaling if needed.
+/// `src` must be in TRANSFER_SRC_OPTIMAL and `dst` in TRANSFER_DST_OPTIMAL.
+pub fn blit_image(
+    device: &ash::Device,
+    cmd: vk::CommandBuffer,
+    src: vk::Image,
+    dst: vk::Image,
+    src_size: vk::Extent2D,
+    dst_size: vk::Extent2D,
+) {
+    let subresource = vk::ImageSubresourceLayers::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .layer_count(1);
+
+    let region = vk::ImageBlit2::default()
+        .src_offsets([
+            vk::Offset3D::default(),
+            vk::Offset3D {
+                x: src_size.width as i32,
+                y: src_size.height as i32,
+                z: 1,
+            },
+        ])
+        .dst_offsets([
+            vk::Offset3D::default(),
+            vk::Offset3D {
+                x: dst_size.width as i32,
+                y: dst_size.height as i32,
+                z: 1,
+            },
+        ])
+        .src_subresource(subresource)
+        .dst_subresource(subresource);
+
+    let regions = [region];
+    let info = vk::BlitImageInfo2::default()
+        .src_image(src)
+        .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .dst_image(dst)
+        .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .filter(vk::Filter::LINEAR)
+        .regions(&regions);
+
+    unsafe { device.cmd_blit_image2(cmd, &info) };
+}
@@ -4,9 +4,10 @@ use std::{
 };
 
 use ash::{self, ext, khr, vk};
+use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
-use super::{surface::Surface, Device};
+use super::{find_compute_family, surface::Surface, Device};
 
 pub struct InstanceSpec {
     pub app_name: CString,
@@ -97,25 +98,96 @@ impl Instance {
         gpu: vk::PhysicalDevice,
         graphics_index: u32,
         extensions: &[*const c_char],
-    ) -> Result<Device, vk::Result> {
+    ) -> Result<Device, Box<dyn Error>> {
         let priority = &[1.0_f32];
-        let queue_info = vk::DeviceQueueCreateInfo::default()
+        let compute_index = find_compute_family(&self.instance, gpu);
+
+        let mut queue_infos = vec![vk::DeviceQueueCreateInfo::default()
             .queue_family_index(graphics_index)
-            .queue_priorities(priority);
+            .queue_priorities(priority)];
+        if let Some(index) = compute_index {
+            queue_infos.push(
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(index)
+                    .queue_priorities(priority),
+            );
+        }
+
+        let mut features12 = vk::PhysicalDeviceVulkan12Features::default()
+            .buffer_device_address(true)
+            .descriptor_indexing(true)
+            .timeline_semaphore(true)
+            .draw_indirect_count(true);
+        let mut features13 = vk::PhysicalDeviceVulkan13Features::default()
+            .dynamic_rendering(true)
+            .synchronization2(true);
+
+        // anisotropic filtering is optional, samplers fall back to plain trilinear
+        let supported = unsafe { self.instance.get_physical_device_features(gpu) };
+        let sampler_anisotropy = supported.sampler_anisotropy == vk::TRUE;
+        let features = vk::PhysicalDeviceFeatures::default()
+            .draw_indirect_first_instance(true)
+            .sampler_anisotropy(sampler_anisotropy);
 
-        let binding = [queue_info];
         let create_info = vk::DeviceCreateInfo::default()
             .enabled_extension_names(extensions)
-            .queue_create_infos(&binding);
+            .enabled_features(&features)
+            .queue_create_infos(&queue_infos)
+            .push_next(&mut features12)
+            .push_next(&mut features13);
 
         let handle = unsafe { self.instance.create_device(gpu, &create_info, None) }?;
 
         let graphics = unsafe { handle.get_device_queue(graphics_index, 0) };
+        let compute = match compute_index {
+            Some(index) => (unsafe { handle.get_device_queue(index, 0) }, index),
+            None => (graphics, graphics_index),
+        };
+        log::info!("Async compute queue family: {:?}", compute_index);
+
+        let allocator = Allocator::new(&AllocatorCreateDesc {
+            instance: self.instance.clone(),
+            device: handle.clone(),
+            physical_device: gpu,
+            debug_settings: Default::default(),
+            buffer_device_address: true,
+            allocation_sizes: Default::default(),
+        });
+
+        let allocator = match allocator {
+            Ok(val) => val,
+            Err(err) => {
+                unsafe { handle.destroy_device(None) };
+                return Err(err.into());
+            }
+        };
 
-        Ok(Device::new(gpu, handle, graphics, graphics_index))
+        let properties = unsafe { self.instance.get_physical_device_properties(gpu) };
+
+        Ok(Device::new(
+            gpu,
+            properties,
+            sampler_anisotropy,
+            handle,
+            (graphics, graphics_index),
+            compute,
+            allocator,
+        ))
     }
 }
 
+/// Whether the Vulkan implementation exposes the instance extension `name`
+pub fn instance_extension_supported(name: &CStr) -> bool {
+    let entry = ash::Entry::linked();
+    let Ok(supported) = (unsafe { entry.enumerate_instance_extension_properties(None) }) else {
+        return false;
+    };
+
+    supported
+        .iter()
+        .any(|ext| ext.extension_name_as_c_str() == Ok(name))
+}
+
 impl Drop for Instance {
     fn drop(&mut self) {
         if let Some(dbg_loader) = &self.dbg_loader {
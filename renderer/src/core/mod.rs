@@ -1,9 +1,19 @@
+mod buffer;
+mod descriptor;
 mod device;
 mod gpu;
+mod image;
 pub mod instance;
+mod pipeline;
 pub mod surface;
+mod swapchain;
 
+pub use buffer::*;
+pub use descriptor::*;
 pub use device::*;
 pub use gpu::*;
+pub use image::*;
 pub use instance::*;
+pub use pipeline::*;
 pub use surface::*;
+pub use swapchain::*;
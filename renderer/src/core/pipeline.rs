@@ -0,0 +1,321 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+/// Everything a compute pipeline is created from
+pub struct ComputePipelineSpec<'a> {
+    /// SPIR-V words of the compute shader
+    pub code: &'a [u32],
+    pub entry: &'a CStr,
+    pub set_layouts: &'a [vk::DescriptorSetLayout],
+    /// Size in bytes of the push constant block, 0 if the shader has none
+    pub push_constant_size: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ComputePipeline {
+    pub handle: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+}
+
+pub fn create_shader_module(
+    device: &ash::Device,
+    code: &[u32],
+) -> Result<vk::ShaderModule, vk::Result> {
+    let info = vk::ShaderModuleCreateInfo::default().code(code);
+    unsafe { device.create_shader_module(&info, None) }
+}
+
+/// Layout with the given sets and, if `push_constant_size` isn't 0, one push constant
+/// range starting at 0 visible to `stages`
+pub fn create_pipeline_layout(
+    device: &ash::Device,
+    set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_size: u32,
+    stages: vk::ShaderStageFlags,
+) -> Result<vk::PipelineLayout, vk::Result> {
+    let ranges = [vk::PushConstantRange {
+        stage_flags: stages,
+        offset: 0,
+        size: push_constant_size,
+    }];
+
+    let mut info = vk::PipelineLayoutCreateInfo::default().set_layouts(set_layouts);
+    if push_constant_size > 0 {
+        info = info.push_constant_ranges(&ranges);
+    }
+
+    unsafe { device.create_pipeline_layout(&info, None) }
+}
+
+impl ComputePipeline {
+    pub fn new(device: &ash::Device, spec: &ComputePipelineSpec) -> Result<Self, vk::Result> {
+        let layout = create_pipeline_layout(
+            device,
+            spec.set_layouts,
+            spec.push_constant_size,
+            vk::ShaderStageFlags::COMPUTE,
+        )?;
+
+        let module = match create_shader_module(device, spec.code) {
+            Ok(val) => val,
+            Err(err) => {
+                unsafe { device.destroy_pipeline_layout(layout, None) };
+                return Err(err);
+            }
+        };
+
+        let stage = vk::PipelineShaderStageCreateInfo::default()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(module)
+            .name(spec.entry);
+
+        let info = vk::ComputePipelineCreateInfo::default()
+            .stage(stage)
+            .layout(layout);
+
+        let result =
+            unsafe { device.create_compute_pipelines(vk::PipelineCache::null(), &[info], None) };
+        // the module is only needed while the pipeline is created
+        unsafe { device.destroy_shader_module(module, None) };
+
+        match result {
+            Ok(pipelines) => Ok(Self {
+                handle: pipelines[0],
+                layout,
+            }),
+            Err((_, err)) => {
+                unsafe { device.destroy_pipeline_layout(layout, None) };
+                Err(err)
+            }
+        }
+    }
+
+    pub fn destroy(self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.handle, None);
+            device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+/// How the color output of a pipeline is combined with the attachment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Blend {
+    Opaque,
+    Alpha,
+    Additive,
+    /// Scales the attachment by the color, the alpha is kept
+    Multiply,
+}
+
+/// Graphics pipeline for dynamic rendering, without vertex input: vertices are pulled
+/// from buffers in the shaders. Viewport and scissor are dynamic.
+pub struct GraphicsPipelineBuilder<'a> {
+    layout: vk::PipelineLayout,
+    stages: Vec<(vk::ShaderStageFlags, &'a [u32])>,
+    topology: vk::PrimitiveTopology,
+    polygon_mode: vk::PolygonMode,
+    cull_mode: vk::CullModeFlags,
+    front_face: vk::FrontFace,
+    color_formats: Vec<vk::Format>,
+    depth_format: vk::Format,
+    depth_compare: Option<vk::CompareOp>,
+    depth_write: bool,
+    /// Constant and slope scaled depth bias
+    depth_bias: Option<(f32, f32)>,
+    samples: vk::SampleCountFlags,
+    blend: Blend,
+}
+
+impl<'a> GraphicsPipelineBuilder<'a> {
+    pub fn new(layout: vk::PipelineLayout) -> Self {
+        Self {
+            layout,
+            stages: vec![],
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            color_formats: vec![],
+            depth_format: vk::Format::UNDEFINED,
+            depth_compare: None,
+            depth_write: false,
+            depth_bias: None,
+            samples: vk::SampleCountFlags::TYPE_1,
+            blend: Blend::Opaque,
+        }
+    }
+
+    /// Adds a stage from its SPIR-V words, the entry point is `main`
+    pub fn shader(mut self, stage: vk::ShaderStageFlags, code: &'a [u32]) -> Self {
+        self.stages.push((stage, code));
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn polygon_mode(mut self, mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, mode: vk::CullModeFlags, front_face: vk::FrontFace) -> Self {
+        self.cull_mode = mode;
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn color_format(mut self, format: vk::Format) -> Self {
+        self.color_formats.push(format);
+        self
+    }
+
+    /// Depth attachment of the rendering the pipeline is used in, `compare` enables
+    /// the depth test
+    pub fn depth(
+        mut self,
+        format: vk::Format,
+        compare: Option<vk::CompareOp>,
+        write: bool,
+    ) -> Self {
+        self.depth_format = format;
+        self.depth_compare = compare;
+        self.depth_write = write;
+        self
+    }
+
+    /// Pushes the depth written away from the viewer, against shadow acne
+    pub fn depth_bias(mut self, constant: f32, slope: f32) -> Self {
+        self.depth_bias = Some((constant, slope));
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn blend(mut self, blend: Blend) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn build(self, device: &ash::Device) -> Result<vk::Pipeline, vk::Result> {
+        let mut modules = Vec::with_capacity(self.stages.len());
+        for (_, code) in &self.stages {
+            match create_shader_module(device, code) {
+                Ok(val) => modules.push(val),
+                Err(err) => {
+                    for module in modules {
+                        unsafe { device.destroy_shader_module(module, None) };
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let stages: Vec<_> = self
+            .stages
+            .iter()
+            .zip(&modules)
+            .map(|((stage, _), module)| {
+                vk::PipelineShaderStageCreateInfo::default()
+                    .stage(*stage)
+                    .module(*module)
+                    .name(c"main")
+            })
+            .collect();
+
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly =
+            vk::PipelineInputAssemblyStateCreateInfo::default().topology(self.topology);
+        let viewport = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(self.polygon_mode)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .line_width(1.0);
+        let rasterization = match self.depth_bias {
+            Some((constant, slope)) => rasterization
+                .depth_bias_enable(true)
+                .depth_bias_constant_factor(constant)
+                .depth_bias_slope_factor(slope),
+            None => rasterization,
+        };
+        let multisample =
+            vk::PipelineMultisampleStateCreateInfo::default().rasterization_samples(self.samples);
+        let depth_stencil = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(self.depth_compare.is_some())
+            .depth_write_enable(self.depth_write)
+            .depth_compare_op(self.depth_compare.unwrap_or(vk::CompareOp::ALWAYS))
+            .max_depth_bounds(1.0);
+
+        let attachment = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA);
+        let attachment = match self.blend {
+            Blend::Opaque => attachment,
+            Blend::Alpha => attachment
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .alpha_blend_op(vk::BlendOp::ADD),
+            Blend::Additive => attachment
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+                .alpha_blend_op(vk::BlendOp::ADD),
+            Blend::Multiply => attachment
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::DST_COLOR)
+                .dst_color_blend_factor(vk::BlendFactor::ZERO)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ZERO)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+                .alpha_blend_op(vk::BlendOp::ADD),
+        };
+        let attachments = vec![attachment; self.color_formats.len()];
+        let color_blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&attachments);
+
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic = vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+        let mut rendering = vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&self.color_formats)
+            .depth_attachment_format(self.depth_format);
+
+        let info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .depth_stencil_state(&depth_stencil)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic)
+            .layout(self.layout)
+            .push_next(&mut rendering);
+
+        let result =
+            unsafe { device.create_graphics_pipelines(vk::PipelineCache::null(), &[info], None) };
+
+        for module in modules {
+            unsafe { device.destroy_shader_module(module, None) };
+        }
+
+        result.map(|pipelines| pipelines[0]).map_err(|(_, err)| err)
+    }
+}
@@ -13,6 +13,10 @@ impl Surface {
         Self { loader, handle }
     }
 
+    pub fn handle(&self) -> vk::SurfaceKHR {
+        self.handle
+    }
+
     pub fn support_presenting(
         &self,
         gpu: vk::PhysicalDevice,
@@ -23,6 +27,36 @@ impl Surface {
                 .get_physical_device_surface_support(gpu, queue_index, self.handle)
         }
     }
+
+    pub fn capabilities(
+        &self,
+        gpu: vk::PhysicalDevice,
+    ) -> Result<vk::SurfaceCapabilitiesKHR, vk::Result> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_capabilities(gpu, self.handle)
+        }
+    }
+
+    pub fn formats(
+        &self,
+        gpu: vk::PhysicalDevice,
+    ) -> Result<Vec<vk::SurfaceFormatKHR>, vk::Result> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_formats(gpu, self.handle)
+        }
+    }
+
+    pub fn present_modes(
+        &self,
+        gpu: vk::PhysicalDevice,
+    ) -> Result<Vec<vk::PresentModeKHR>, vk::Result> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_present_modes(gpu, self.handle)
+        }
+    }
 }
 
 impl Drop for Surface {
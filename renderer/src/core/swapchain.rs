@@ -0,0 +1,157 @@
+use ash::{khr, vk};
+
+use super::{Device, Instance, Surface};
+
+pub struct SwapchainSpec {
+    pub extent: vk::Extent2D,
+    pub format: vk::SurfaceFormatKHR,
+    pub present_mode: vk::PresentModeKHR,
+}
+
+pub struct Swapchain {
+    loader: khr::swapchain::Device,
+    device: ash::Device,
+    handle: vk::SwapchainKHR,
+    images: Vec<vk::Image>,
+    views: Vec<vk::ImageView>,
+    format: vk::SurfaceFormatKHR,
+    present_mode: vk::PresentModeKHR,
+    extent: vk::Extent2D,
+}
+
+impl Swapchain {
+    /// Creates a swapchain for `surface`, passing `old` lets the driver recycle its resources
+    pub fn new(
+        instance: &Instance,
+        device: &Device,
+        surface: &Surface,
+        spec: &SwapchainSpec,
+        old: Option<&Swapchain>,
+    ) -> Result<Self, vk::Result> {
+        let loader = khr::swapchain::Device::new(instance.handle(), device.handle());
+        let caps = surface.capabilities(device.gpu())?;
+
+        // current_extent is u32::MAX when the surface size is determined by the swapchain
+        let extent = if caps.current_extent.width != u32::MAX {
+            caps.current_extent
+        } else {
+            vk::Extent2D {
+                width: spec
+                    .extent
+                    .width
+                    .clamp(caps.min_image_extent.width, caps.max_image_extent.width),
+                height: spec
+                    .extent
+                    .height
+                    .clamp(caps.min_image_extent.height, caps.max_image_extent.height),
+            }
+        };
+
+        let mut image_count = caps.min_image_count + 1;
+        if caps.max_image_count > 0 {
+            image_count = image_count.min(caps.max_image_count);
+        }
+
+        let info = vk::SwapchainCreateInfoKHR::default()
+            .surface(surface.handle())
+            .min_image_count(image_count)
+            .image_format(spec.format.format)
+            .image_color_space(spec.format.color_space)
+            .image_extent(extent)
+            .image_array_layers(1)
+            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST)
+            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .pre_transform(caps.current_transform)
+            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .present_mode(spec.present_mode)
+            .clipped(true)
+            .old_swapchain(old.map_or(vk::SwapchainKHR::null(), |old| old.handle));
+
+        let handle = unsafe { loader.create_swapchain(&info, None)? };
+        let images = unsafe { loader.get_swapchain_images(handle)? };
+
+        let mut views = Vec::with_capacity(images.len());
+        for image in &images {
+            let view_info = vk::ImageViewCreateInfo::default()
+                .image(*image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(spec.format.format)
+                .subresource_range(
+                    vk::ImageSubresourceRange::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .level_count(1)
+                        .layer_count(1),
+                );
+            views.push(unsafe { device.handle().create_image_view(&view_info, None)? });
+        }
+
+        Ok(Self {
+            loader,
+            device: device.handle().clone(),
+            handle,
+            images,
+            views,
+            format: spec.format,
+            present_mode: spec.present_mode,
+            extent,
+        })
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+
+    pub fn format(&self) -> vk::SurfaceFormatKHR {
+        self.format
+    }
+
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
+
+    pub fn image(&self, index: u32) -> vk::Image {
+        self.images[index as usize]
+    }
+
+    /// Returns the index of the acquired image and whether the swapchain is suboptimal
+    pub fn acquire_next_image(
+        &self,
+        semaphore: vk::Semaphore,
+        timeout: u64,
+    ) -> Result<(u32, bool), vk::Result> {
+        unsafe {
+            self.loader
+                .acquire_next_image(self.handle, timeout, semaphore, vk::Fence::null())
+        }
+    }
+
+    /// Returns whether the swapchain is suboptimal
+    pub fn present(
+        &self,
+        queue: vk::Queue,
+        index: u32,
+        wait: vk::Semaphore,
+    ) -> Result<bool, vk::Result> {
+        let swapchains = [self.handle];
+        let indices = [index];
+        let wait = [wait];
+        let info = vk::PresentInfoKHR::default()
+            .swapchains(&swapchains)
+            .image_indices(&indices)
+            .wait_semaphores(&wait);
+
+        unsafe { self.loader.queue_present(queue, &info) }
+    }
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        log::trace!("Destroying SwapchainKHR");
+        unsafe {
+            for view in &self.views {
+                self.device.destroy_image_view(*view, None);
+            }
+            self.loader.destroy_swapchain(self.handle, None);
+        }
+    }
+}
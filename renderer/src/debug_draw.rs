@@ -0,0 +1,458 @@
+//! Immediate mode shapes for debugging, like chunk borders, collision boxes, raycasts and
+//! culling frustums. The lines queued with [`line`], [`aabb`], [`sphere`], [`arrow`] and
+//! [`frustum`] are drawn in the scene by a single draw added with [`render`], whatever
+//! wasn't drawn is dropped at the end of the frame.
+
+use std::{collections::HashMap, error::Error, f32::consts::TAU, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+use gpu_allocator::MemoryLocation;
+
+use crate::{core, graph::BufferUsage, shaders, Renderer};
+
+/// Vertices the buffer is first created for, it doubles whenever a frame needs more
+const MIN_CAPACITY: usize = 4096;
+
+/// Lines of every circle of a sphere
+const SPHERE_SEGMENTS: usize = 32;
+
+/// Length of the head of an arrow, as a fraction of the arrow
+const ARROW_HEAD: f32 = 0.2;
+
+/// Distance infinite far planes of a frustum are drawn at
+const FAR_DISTANCE: f32 = 256.0;
+
+/// Pairs of corners of a box, the bits of a corner pick the max coordinate on X, Y and Z
+const BOX_EDGES: [usize; 24] = [
+    0, 1, 2, 3, 4, 5, 6, 7, // along X
+    0, 2, 1, 3, 4, 6, 5, 7, // along Y
+    0, 4, 1, 5, 2, 6, 3, 7, // along Z
+];
+
+/// How a debug shape is drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DebugStyle {
+    /// sRGB color and opacity
+    pub color: [f32; 4],
+    /// Hides the parts of the shape behind the scene, otherwise it is drawn over it
+    pub depth_test: bool,
+}
+
+impl Default for DebugStyle {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 4],
+            depth_test: true,
+        }
+    }
+}
+
+/// Layout of `Vertex` in `debug_draw.vert`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub(crate) struct DebugVertex {
+    pub position: [f32; 3],
+    pub depth_test: u32,
+    pub color: [f32; 4],
+}
+
+/// Layout of `Constants` in `debug_draw.vert`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct DebugConstants {
+    view_proj: [f32; 16],
+    vertices: vk::DeviceAddress,
+    reverse_z: u32,
+    _pad: u32,
+}
+
+/// Attachments a pipeline draws into and its depth test
+type PipelineKey = (vk::Format, vk::SampleCountFlags, vk::Format, vk::CompareOp);
+
+/// Lines queued for the frame and what draws them
+#[derive(Default)]
+pub(crate) struct DebugDraw {
+    /// Pairs of vertices, one line each
+    vertices: Vec<DebugVertex>,
+    layout: Option<vk::PipelineLayout>,
+    pipelines: HashMap<PipelineKey, vk::Pipeline>,
+    buffer: Option<core::AllocatedBuffer>,
+}
+
+impl DebugDraw {
+    fn line(&mut self, from: Vec3, to: Vec3, style: &DebugStyle) {
+        let vertex = |position: Vec3| DebugVertex {
+            position: position.to_array(),
+            depth_test: style.depth_test as u32,
+            color: style.color,
+        };
+        self.vertices.extend([vertex(from), vertex(to)]);
+    }
+
+    fn aabb(&mut self, min: Vec3, max: Vec3, style: &DebugStyle) {
+        let corner = |index: usize| {
+            Vec3::select(
+                glam::BVec3::new(index & 1 != 0, index & 2 != 0, index & 4 != 0),
+                max,
+                min,
+            )
+        };
+        for edge in BOX_EDGES.chunks_exact(2) {
+            self.line(corner(edge[0]), corner(edge[1]), style);
+        }
+    }
+
+    /// Circles of the sphere around the X, Y and Z axes
+    fn sphere(&mut self, center: Vec3, radius: f32, style: &DebugStyle) {
+        let axes = [(Vec3::Y, Vec3::Z), (Vec3::X, Vec3::Z), (Vec3::X, Vec3::Y)];
+        for (u, v) in axes {
+            let point = |segment: usize| {
+                let (sin, cos) = (segment as f32 / SPHERE_SEGMENTS as f32 * TAU).sin_cos();
+                center + (u * cos + v * sin) * radius
+            };
+            for segment in 0..SPHERE_SEGMENTS {
+                self.line(point(segment), point(segment + 1), style);
+            }
+        }
+    }
+
+    /// Line with four lines going back from the tip
+    fn arrow(&mut self, from: Vec3, to: Vec3, style: &DebugStyle) {
+        self.line(from, to, style);
+
+        let Some(direction) = (to - from).try_normalize() else {
+            return;
+        };
+        let head = from.distance(to) * ARROW_HEAD;
+        let back = to - direction * head;
+        let (u, v) = direction.any_orthonormal_pair();
+        for side in [u, -u, v, -v] {
+            self.line(to, back + side * head * 0.5, style);
+        }
+    }
+
+    fn frustum(&mut self, view_proj: &Mat4, style: &DebugStyle) {
+        let corners = frustum_corners(view_proj);
+        for edge in BOX_EDGES.chunks_exact(2) {
+            self.line(corners[edge[0]], corners[edge[1]], style);
+        }
+    }
+
+    fn pipeline(
+        &mut self,
+        device: &core::Device,
+        key: PipelineKey,
+    ) -> Result<vk::Pipeline, vk::Result> {
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            return Ok(*pipeline);
+        }
+
+        let layout = match self.layout {
+            Some(val) => val,
+            None => {
+                let layout = core::create_pipeline_layout(
+                    device.handle(),
+                    &[],
+                    size_of::<DebugConstants>() as u32,
+                    vk::ShaderStageFlags::VERTEX,
+                )?;
+                *self.layout.insert(layout)
+            }
+        };
+
+        let (color_format, samples, depth_format, compare) = key;
+        let vertex = shaders::words(shaders::DEBUG_DRAW_VERT);
+        let fragment = shaders::words(shaders::COLOR_FRAG);
+        let pipeline = core::GraphicsPipelineBuilder::new(layout)
+            .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+            .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+            .topology(vk::PrimitiveTopology::LINE_LIST)
+            .color_format(color_format)
+            .depth(depth_format, Some(compare), false)
+            .samples(samples)
+            .blend(core::Blend::Alpha)
+            .build(device.handle())?;
+
+        self.pipelines.insert(key, pipeline);
+        Ok(pipeline)
+    }
+
+    /// Buffer `count` vertices are copied into and its address, grown when they don't
+    /// fit anymore, waiting for the device
+    fn buffer(
+        &mut self,
+        device: &core::Device,
+        count: usize,
+    ) -> Result<(vk::Buffer, vk::DeviceAddress), Box<dyn Error>> {
+        let size = (count * size_of::<DebugVertex>()) as vk::DeviceSize;
+        if self
+            .buffer
+            .as_ref()
+            .is_some_and(|buffer| buffer.size >= size)
+        {
+            let buffer = self.buffer.as_ref().unwrap();
+            return Ok((buffer.handle, device.buffer_address(buffer.handle)));
+        }
+
+        if let Some(buffer) = self.buffer.take() {
+            device.wait_idle();
+            device.destroy_buffer(buffer);
+        }
+        let capacity = count.max(MIN_CAPACITY).next_power_of_two();
+        let buffer = device.create_buffer(
+            "debug lines",
+            (capacity * size_of::<DebugVertex>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+
+        let result = (buffer.handle, device.buffer_address(buffer.handle));
+        self.buffer = Some(buffer);
+        Ok(result)
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn destroy(&mut self, device: &core::Device) {
+        if let Some(buffer) = self.buffer.take() {
+            device.destroy_buffer(buffer);
+        }
+        unsafe {
+            for (_, pipeline) in self.pipelines.drain() {
+                device.handle().destroy_pipeline(pipeline, None);
+            }
+            if let Some(layout) = self.layout.take() {
+                device.handle().destroy_pipeline_layout(layout, None);
+            }
+        }
+    }
+}
+
+/// World space corners of the frustum of `view_proj`, the bits of their index pick the
+/// +X, +Y and far side of the clip volume. Far planes at infinity are brought back to
+/// [`FAR_DISTANCE`] from the near plane.
+fn frustum_corners(view_proj: &Mat4) -> [Vec3; 8] {
+    let inverse = view_proj.inverse();
+    let unproject = |x: f32, y: f32, z: f32| inverse * Vec4::new(x, y, z, 1.0);
+
+    std::array::from_fn(|index| {
+        let x = if index & 1 != 0 { 1.0 } else { -1.0 };
+        let y = if index & 2 != 0 { 1.0 } else { -1.0 };
+        let far = index & 4 != 0;
+
+        // which of depth 0 and 1 is the far plane depends on the projection, the near
+        // one is the one closer to depth 0.5
+        let (zero, one, middle) = (
+            unproject(x, y, 0.0),
+            unproject(x, y, 1.0),
+            unproject(x, y, 0.5),
+        );
+        let finite = |point: Vec4| point.w.abs() > f32::EPSILON;
+        let middle = middle.truncate() / middle.w;
+        let (near, far_point) = match (finite(zero), finite(one)) {
+            (true, true) => {
+                let (zero, one) = (zero.truncate() / zero.w, one.truncate() / one.w);
+                if zero.distance(middle) < one.distance(middle) {
+                    (zero, Some(one))
+                } else {
+                    (one, Some(zero))
+                }
+            }
+            (true, false) => (zero.truncate() / zero.w, None),
+            _ => (one.truncate() / one.w, None),
+        };
+
+        match (far, far_point) {
+            (false, _) => near,
+            (true, Some(point)) => point,
+            (true, None) => near + (middle - near).normalize_or_zero() * FAR_DISTANCE,
+        }
+    })
+}
+
+/// Queues a line from `from` to `to`
+pub fn line(renderer: &mut Renderer, from: Vec3, to: Vec3, style: &DebugStyle) {
+    renderer.debug.line(from, to, style);
+}
+
+/// Queues the edges of the axis aligned box from `min` to `max`
+pub fn aabb(renderer: &mut Renderer, min: Vec3, max: Vec3, style: &DebugStyle) {
+    renderer.debug.aabb(min, max, style);
+}
+
+/// Queues three circles of the sphere, one around each axis
+pub fn sphere(renderer: &mut Renderer, center: Vec3, radius: f32, style: &DebugStyle) {
+    renderer.debug.sphere(center, radius, style);
+}
+
+/// Queues an arrow pointing from `from` to `to`
+pub fn arrow(renderer: &mut Renderer, from: Vec3, to: Vec3, style: &DebugStyle) {
+    renderer.debug.arrow(from, to, style);
+}
+
+/// Queues the edges of the frustum of `view_proj`, like the camera a culling pass used
+pub fn frustum(renderer: &mut Renderer, view_proj: &Mat4, style: &DebugStyle) {
+    renderer.debug.frustum(view_proj, style);
+}
+
+/// Adds the pass drawing the queued shapes into the color and depth attachments of the
+/// frame, seen through `view_proj`. Call it before the scene gets resolved, like the
+/// other draws of the scene.
+pub fn render(renderer: &mut Renderer, view_proj: &Mat4) -> Result<(), Box<dyn Error>> {
+    let frame = renderer
+        .frame()
+        .ok_or("debug_draw::render called without begin_frame")?;
+    if renderer.debug.vertices.is_empty() {
+        return Ok(());
+    }
+
+    let mut vertices = std::mem::take(&mut renderer.debug.vertices);
+    let count = vertices.len();
+    let staged = renderer.stage(bytemuck::cast_slice(&vertices));
+    vertices.clear();
+    renderer.debug.vertices = vertices;
+    let staging = staged?;
+
+    let key = (
+        renderer
+            .draw_format(frame.surface)
+            .ok_or("Unknown surface")?,
+        renderer.sample_count(),
+        renderer.depth_format(),
+        renderer.depth_compare_op(),
+    );
+    let pipeline = renderer.debug.pipeline(&renderer.device, key)?;
+    let layout = renderer.debug.layout.unwrap();
+    let (buffer, address) = renderer.debug.buffer(&renderer.device, count)?;
+    let constants = DebugConstants {
+        view_proj: view_proj.to_cols_array(),
+        vertices: address,
+        reverse_z: renderer.reverse_z() as u32,
+        _pad: 0,
+    };
+
+    let size = (count * size_of::<DebugVertex>()) as vk::DeviceSize;
+    let lines = renderer.graph().import_buffer("debug lines", buffer);
+    renderer
+        .graph()
+        .add_pass("upload debug lines")
+        .write_buffer(lines, BufferUsage::TransferDst)
+        .execute(move |ctx| {
+            let region = vk::BufferCopy::default().size(size);
+            unsafe {
+                ctx.device
+                    .cmd_copy_buffer(ctx.cmd, staging, ctx.buffer(lines), &[region])
+            };
+        });
+
+    renderer.draw_reading("debug draw", &[(lines, BufferUsage::Storage)], move |ctx| {
+        unsafe {
+            ctx.device
+                .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            ctx.device.cmd_push_constants(
+                ctx.cmd,
+                layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytemuck::bytes_of(&constants),
+            );
+            ctx.device.cmd_draw(ctx.cmd, count as u32, 1, 0, 0);
+        }
+        ctx.count_draws(1);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-3;
+
+    fn positions(debug: &DebugDraw) -> Vec<Vec3> {
+        debug
+            .vertices
+            .iter()
+            .map(|vertex| Vec3::from_array(vertex.position))
+            .collect()
+    }
+
+    #[test]
+    fn boxes_have_twelve_axis_aligned_edges() {
+        let mut debug = DebugDraw::default();
+        let style = DebugStyle {
+            color: [1.0, 0.0, 0.0, 1.0],
+            depth_test: false,
+        };
+        debug.aabb(Vec3::ZERO, Vec3::new(1.0, 2.0, 3.0), &style);
+
+        assert_eq!(debug.vertices.len(), 24);
+        assert!(debug
+            .vertices
+            .iter()
+            .all(|vertex| vertex.color == style.color && vertex.depth_test == 0));
+        for line in positions(&debug).chunks_exact(2) {
+            let delta = (line[1] - line[0]).abs();
+            let axes = delta.to_array().iter().filter(|val| **val > 0.0).count();
+            assert_eq!(axes, 1, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn spheres_stay_on_their_surface() {
+        let mut debug = DebugDraw::default();
+        let center = Vec3::new(1.0, -2.0, 3.0);
+        debug.sphere(center, 2.5, &DebugStyle::default());
+
+        assert_eq!(debug.vertices.len(), 3 * SPHERE_SEGMENTS * 2);
+        for position in positions(&debug) {
+            assert!((position.distance(center) - 2.5).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn arrow_heads_point_back_from_the_tip() {
+        let mut debug = DebugDraw::default();
+        let (from, to) = (Vec3::ZERO, Vec3::new(0.0, 0.0, -10.0));
+        debug.arrow(from, to, &DebugStyle::default());
+
+        let positions = positions(&debug);
+        assert_eq!(positions.len(), 10);
+        for line in positions[2..].chunks_exact(2) {
+            assert_eq!(line[0], to);
+            assert!((line[1].z + 8.0).abs() < EPSILON);
+            assert!((line[1].truncate().length() - 1.0).abs() < EPSILON);
+        }
+
+        // a zero length arrow has no direction for its head
+        debug.clear();
+        debug.arrow(to, to, &DebugStyle::default());
+        assert_eq!(debug.vertices.len(), 2);
+    }
+
+    #[test]
+    fn frustum_corners_match_the_planes() {
+        let view = Mat4::look_at_rh(Vec3::ZERO, Vec3::NEG_Z, Vec3::Y);
+        let finite = Mat4::perspective_rh(90f32.to_radians(), 1.0, 1.0, 100.0) * view;
+        let corners = frustum_corners(&finite);
+        assert!(corners[0].abs_diff_eq(Vec3::new(-1.0, -1.0, -1.0), EPSILON));
+        assert!(corners[7].abs_diff_eq(Vec3::new(100.0, 100.0, -100.0), 0.1));
+
+        let projections = [
+            Mat4::perspective_infinite_rh(90f32.to_radians(), 1.0, 1.0),
+            Mat4::perspective_infinite_reverse_rh(90f32.to_radians(), 1.0, 1.0),
+        ];
+        for projection in projections {
+            let corners = frustum_corners(&(projection * view));
+            assert!(corners[0].abs_diff_eq(Vec3::new(-1.0, -1.0, -1.0), EPSILON));
+            let far = corners[4];
+            assert!((far.distance(corners[0]) - FAR_DISTANCE).abs() < 0.1);
+            assert!(far.normalize().abs_diff_eq(corners[0].normalize(), EPSILON));
+        }
+    }
+}
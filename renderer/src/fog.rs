@@ -0,0 +1,189 @@
+//! Distance fog of the frame, resolved from the [`FogSettings`], the medium the camera
+//! is in and the sky of the time of day
+
+use glam::Vec3;
+
+use crate::{
+    config::{FogMode, FogSettings},
+    mesher::SECTION_SIZE,
+    sky::SkyState,
+};
+
+/// Fraction of the render distance the edge fade starts at, past it the fog always
+/// thickens to opaque so chunks don't pop in
+const EDGE_FADE_START: f32 = 0.9;
+
+const WATER_COLOR: Vec3 = Vec3::new(0.05, 0.2, 0.42);
+/// Blocks under water the fog is opaque at
+const WATER_END: f32 = 48.0;
+const WATER_DENSITY: f32 = 3.0 / WATER_END;
+/// Light left in the water fog at night, the water gets darker with the sky
+const WATER_MIN_LIGHT: f32 = 0.15;
+
+const LAVA_COLOR: Vec3 = Vec3::new(0.6, 0.1, 0.0);
+const LAVA_START: f32 = 0.25;
+const LAVA_END: f32 = 2.0;
+
+/// What the camera is in, the fog of water and lava hides the world much closer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Medium {
+    #[default]
+    Air,
+    Water,
+    Lava,
+}
+
+/// Fog of a frame: `amount = max(linear(start, end), 1 - exp(-density * distance))`,
+/// see `fog_amount` in `frame.glsl`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct FogState {
+    pub color: Vec3,
+    pub start: f32,
+    pub end: f32,
+    pub density: f32,
+}
+
+impl FogState {
+    pub fn resolve(
+        settings: &FogSettings,
+        render_distance: u32,
+        medium: Medium,
+        sky: &SkyState,
+    ) -> Self {
+        match medium {
+            Medium::Air => Self::air(settings, render_distance, sky),
+            Medium::Water => Self {
+                color: WATER_COLOR * sky.sky_light.max(WATER_MIN_LIGHT),
+                start: WATER_END * EDGE_FADE_START,
+                end: WATER_END,
+                density: WATER_DENSITY,
+            },
+            Medium::Lava => Self {
+                color: LAVA_COLOR,
+                start: LAVA_START,
+                end: LAVA_END,
+                density: 0.0,
+            },
+        }
+    }
+
+    fn air(settings: &FogSettings, render_distance: u32, sky: &SkyState) -> Self {
+        let end = (render_distance.max(1) * SECTION_SIZE as u32) as f32;
+        let edge = end * EDGE_FADE_START;
+        if !settings.enabled {
+            return Self {
+                color: sky.horizon_color,
+                start: edge,
+                end,
+                density: 0.0,
+            };
+        }
+
+        let (start, density) = match settings.mode {
+            FogMode::Linear => (end * settings.start.clamp(0.0, EDGE_FADE_START), 0.0),
+            FogMode::Exponential => (edge, settings.density.max(0.0) / end),
+        };
+        Self {
+            color: sky.horizon_color,
+            start,
+            end,
+            density,
+        }
+    }
+
+    /// How much of a surface `distance` away is hidden by the fog, from 0 to 1
+    #[cfg(test)]
+    fn amount(&self, distance: f32) -> f32 {
+        let range = (self.end - self.start).max(f32::EPSILON);
+        let linear = ((distance - self.start) / range).clamp(0.0, 1.0);
+        let exponential = 1.0 - (-self.density * distance).exp();
+        linear.max(exponential)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(mode: FogMode) -> FogSettings {
+        FogSettings {
+            mode,
+            ..FogSettings::default()
+        }
+    }
+
+    #[test]
+    fn fog_ends_at_the_render_distance() {
+        let sky = SkyState::at(0.5);
+        for mode in [FogMode::Linear, FogMode::Exponential] {
+            for render_distance in [2, 8, 32] {
+                let fog = FogState::resolve(&settings(mode), render_distance, Medium::Air, &sky);
+                let end = (render_distance * 16) as f32;
+
+                assert_eq!(fog.end, end);
+                assert_eq!(fog.amount(end), 1.0);
+                assert_eq!(fog.amount(end + 100.0), 1.0);
+                assert!(fog.amount(end * 0.5) < 1.0, "{:?}", fog);
+            }
+        }
+    }
+
+    #[test]
+    fn fog_thickens_with_distance() {
+        let sky = SkyState::at(0.3);
+        for mode in [FogMode::Linear, FogMode::Exponential] {
+            let fog = FogState::resolve(&settings(mode), 12, Medium::Air, &sky);
+            assert_eq!(fog.amount(0.0), 0.0);
+
+            let mut last = 0.0;
+            for distance in 0..250 {
+                let amount = fog.amount(distance as f32);
+                assert!(amount >= last);
+                last = amount;
+            }
+        }
+    }
+
+    #[test]
+    fn disabled_fog_only_fades_the_edge() {
+        let disabled = FogSettings {
+            enabled: false,
+            ..FogSettings::default()
+        };
+        let fog = FogState::resolve(&disabled, 8, Medium::Air, &SkyState::at(0.5));
+
+        assert_eq!(fog.amount(128.0 * EDGE_FADE_START), 0.0);
+        assert_eq!(fog.amount(128.0), 1.0);
+    }
+
+    #[test]
+    fn fog_color_follows_the_sky() {
+        let fog_settings = FogSettings::default();
+        for time in [0.0, 0.25, 0.5, 0.75] {
+            let sky = SkyState::at(time);
+            let fog = FogState::resolve(&fog_settings, 12, Medium::Air, &sky);
+            assert_eq!(fog.color, sky.horizon_color);
+        }
+    }
+
+    #[test]
+    fn water_and_lava_hide_the_world_closer() {
+        let sky = SkyState::at(0.5);
+        let air = FogState::resolve(&FogSettings::default(), 12, Medium::Air, &sky);
+        let water = FogState::resolve(&FogSettings::default(), 12, Medium::Water, &sky);
+        let lava = FogState::resolve(&FogSettings::default(), 12, Medium::Lava, &sky);
+
+        assert!(lava.end < water.end && water.end < air.end);
+        assert_eq!(lava.amount(LAVA_END), 1.0);
+        assert!(water.amount(16.0) > air.amount(16.0));
+
+        // the water is darker at night
+        let night = FogState::resolve(
+            &FogSettings::default(),
+            12,
+            Medium::Water,
+            &SkyState::at(0.0),
+        );
+        assert!(night.color.length() < water.color.length());
+    }
+}
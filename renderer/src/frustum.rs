@@ -0,0 +1,49 @@
+use glam::{Mat4, Vec3, Vec4};
+
+/// View frustum as six planes pointing inwards, `xyz` is the normal and `w` the distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a Vulkan style projection (depth in 0..1) times view matrix,
+    /// works with reversed and infinite depth as well
+    pub fn from_view_proj(view_proj: &Mat4) -> Self {
+        let rows = [
+            view_proj.row(0),
+            view_proj.row(1),
+            view_proj.row(2),
+            view_proj.row(3),
+        ];
+
+        let planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[2],
+            rows[3] - rows[2],
+        ];
+
+        Self {
+            planes: planes.map(|plane| {
+                let length = plane.truncate().length();
+                if length > 0.0 {
+                    plane / length
+                } else {
+                    plane
+                }
+            }),
+        }
+    }
+
+    /// Same test the GPU culling runs, conservative near the corners of the frustum
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), max, min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt::Write;
+
+use super::{EdgeKind, GraphSummary, Resource};
+
+impl GraphSummary {
+    /// Graphviz description of the frame: passes are boxes numbered in execution order,
+    /// resources are ellipses (dashed when transient) and culled passes are greyed out
+    pub fn to_dot(&self) -> String {
+        let mut out =
+            String::from("digraph frame {\n    rankdir=LR;\n    node [fontname=\"monospace\"];\n");
+        let schedule = &self.schedule;
+
+        for (index, name) in self.passes.iter().enumerate() {
+            let label = match schedule.order.iter().position(|val| *val == index) {
+                Some(position) => format!("{}: {}", position, name),
+                None => format!("{} (culled)", name),
+            };
+            let style = if schedule.alive[index] {
+                "shape=box, style=filled, fillcolor=lightblue"
+            } else {
+                "shape=box, style=dashed, fontcolor=grey"
+            };
+            let _ = writeln!(out, "    p{} [label={:?}, {}];", index, label, style);
+        }
+
+        for (index, (name, transient)) in self.images.iter().enumerate() {
+            let style = if *transient { ", style=dashed" } else { "" };
+            let _ = writeln!(
+                out,
+                "    i{} [label={:?}, shape=ellipse{}];",
+                index, name, style
+            );
+        }
+
+        for (index, name) in self.buffers.iter().enumerate() {
+            let _ = writeln!(out, "    b{} [label={:?}, shape=cylinder];", index, name);
+        }
+
+        let node = |resource: &Resource| match resource {
+            Resource::Image(id, usage) => (format!("i{}", id.0), format!("{:?}", usage)),
+            Resource::Buffer(id, usage) => (format!("b{}", id.0), format!("{:?}", usage)),
+        };
+
+        for (index, (reads, writes)) in self.pass_resources.iter().enumerate() {
+            for read in reads {
+                let (resource, label) = node(read);
+                let _ = writeln!(out, "    {} -> p{} [label={:?}];", resource, index, label);
+            }
+            for write in writes {
+                let (resource, label) = node(write);
+                let _ = writeln!(
+                    out,
+                    "    p{} -> {} [label={:?}, color=red];",
+                    index, resource, label
+                );
+            }
+        }
+
+        // ordering only dependencies are not visible through the resources
+        for (from, to, kind) in &schedule.edges {
+            if *kind == EdgeKind::Order {
+                let _ = writeln!(
+                    out,
+                    "    p{} -> p{} [style=dotted, constraint=false];",
+                    from, to
+                );
+            }
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
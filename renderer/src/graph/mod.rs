@@ -0,0 +1,1281 @@
+//! Frame render graph: passes declare the images and buffers they read and write, the
+//! graph culls the ones nothing depends on, allocates transient images and records the
+//! synchronization2 barriers between them.
+
+mod dot;
+mod transient;
+mod usage;
+
+use std::{cell::Cell, error::Error};
+
+use ash::vk;
+
+use crate::core;
+
+pub(crate) use transient::TransientPool;
+pub use usage::{BufferUsage, ImageUsage, ResourceState};
+use usage::{Tracker, Transition};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ImageId(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferId(usize);
+
+/// Description of a transient image, owned by the graph for the duration of a frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageDesc {
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+    pub mip_levels: u32,
+    pub array_layers: u32,
+}
+
+impl ImageDesc {
+    pub fn new(extent: vk::Extent2D, format: vk::Format) -> Self {
+        Self {
+            extent,
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            mip_levels: 1,
+            array_layers: 1,
+        }
+    }
+}
+
+/// Image owned outside the graph
+#[derive(Clone, Copy, Debug)]
+pub struct ImportedImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub samples: vk::SampleCountFlags,
+    pub initial: ResourceState,
+    /// Layout the image is transitioned to after its last use, if any
+    pub final_layout: Option<vk::ImageLayout>,
+}
+
+impl ImportedImage {
+    pub(crate) fn from_allocated(
+        image: &core::AllocatedImage,
+        samples: vk::SampleCountFlags,
+    ) -> Self {
+        Self {
+            image: image.handle,
+            view: image.view,
+            format: image.format,
+            extent: image.extent_2d(),
+            samples,
+            initial: ResourceState::UNKNOWN,
+            final_layout: None,
+        }
+    }
+}
+
+enum ImageSource {
+    Imported(ImportedImage),
+    Transient(ImageDesc),
+}
+
+struct ImageNode {
+    name: String,
+    source: ImageSource,
+    output: bool,
+}
+
+impl ImageNode {
+    fn format(&self) -> vk::Format {
+        match &self.source {
+            ImageSource::Imported(val) => val.format,
+            ImageSource::Transient(val) => val.format,
+        }
+    }
+
+    fn extent(&self) -> vk::Extent2D {
+        match &self.source {
+            ImageSource::Imported(val) => val.extent,
+            ImageSource::Transient(val) => val.extent,
+        }
+    }
+}
+
+struct BufferNode {
+    name: String,
+    buffer: vk::Buffer,
+    output: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Resource {
+    Image(ImageId, ImageUsage),
+    Buffer(BufferId, BufferUsage),
+}
+
+impl Resource {
+    /// Key identifying the underlying resource regardless of usage
+    fn key(&self) -> (bool, usize) {
+        match self {
+            Resource::Image(id, _) => (true, id.0),
+            Resource::Buffer(id, _) => (false, id.0),
+        }
+    }
+}
+
+type PassFn = Box<dyn FnOnce(&PassContext)>;
+
+struct PassNode {
+    name: String,
+    reads: Vec<Resource>,
+    writes: Vec<Resource>,
+    side_effects: bool,
+    execute: Option<PassFn>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EdgeKind {
+    /// The destination consumes what the source produced
+    Data,
+    /// The destination overwrites what the source read, only ordering matters
+    Order,
+}
+
+/// Passes of a frame, run in the order they were declared. Dependencies are worked out
+/// from that order so a pass declared later can't feed an earlier one, and with a single
+/// graphics queue there is nothing to gain from running independent passes in another
+/// order. The dependencies are what culling follows and the barriers are built from.
+#[derive(Default)]
+pub struct RenderGraph {
+    images: Vec<ImageNode>,
+    buffers: Vec<BufferNode>,
+    passes: Vec<PassNode>,
+}
+
+/// Declares the resources of a pass, the pass is added to the graph by [`PassBuilder::execute`]
+pub struct PassBuilder<'a> {
+    graph: &'a mut RenderGraph,
+    node: PassNode,
+}
+
+impl PassBuilder<'_> {
+    pub fn read_image(mut self, image: ImageId, usage: ImageUsage) -> Self {
+        self.node.reads.push(Resource::Image(image, usage));
+        self
+    }
+
+    pub fn write_image(mut self, image: ImageId, usage: ImageUsage) -> Self {
+        self.node.writes.push(Resource::Image(image, usage));
+        self
+    }
+
+    pub fn read_buffer(mut self, buffer: BufferId, usage: BufferUsage) -> Self {
+        self.node.reads.push(Resource::Buffer(buffer, usage));
+        self
+    }
+
+    pub fn write_buffer(mut self, buffer: BufferId, usage: BufferUsage) -> Self {
+        self.node.writes.push(Resource::Buffer(buffer, usage));
+        self
+    }
+
+    /// Keeps the pass even if none of its writes are consumed (readbacks, persistent buffers)
+    pub fn side_effects(mut self) -> Self {
+        self.node.side_effects = true;
+        self
+    }
+
+    pub fn execute<F>(mut self, function: F)
+    where
+        F: FnOnce(&PassContext) + 'static,
+    {
+        self.node.execute = Some(Box::new(function));
+        self.graph.passes.push(self.node);
+    }
+}
+
+/// Physical image a graph image resolved to
+#[derive(Clone, Copy, Debug)]
+pub struct GraphImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+#[derive(Clone, Copy)]
+pub enum LoadOp {
+    Load,
+    Clear(vk::ClearValue),
+    DontCare,
+}
+
+/// Attachment of a dynamic rendering instance started with [`PassContext::begin_rendering`]
+#[derive(Clone, Copy)]
+pub struct Attachment {
+    pub image: ImageId,
+    pub load: LoadOp,
+    pub store: bool,
+    /// Single sampled image the attachment is averaged into when rendering ends
+    pub resolve: Option<ImageId>,
+}
+
+impl Attachment {
+    pub fn load(image: ImageId) -> Self {
+        Self {
+            image,
+            load: LoadOp::Load,
+            store: true,
+            resolve: None,
+        }
+    }
+
+    pub fn clear(image: ImageId, value: vk::ClearValue) -> Self {
+        Self {
+            image,
+            load: LoadOp::Clear(value),
+            store: true,
+            resolve: None,
+        }
+    }
+}
+
+/// What a pass gets when executed
+pub struct PassContext<'a> {
+    pub cmd: vk::CommandBuffer,
+    pub device: &'a ash::Device,
+    images: &'a [GraphImage],
+    buffers: &'a [vk::Buffer],
+    draws: Cell<u32>,
+}
+
+impl<'a> PassContext<'a> {
+    /// Context outside of a graph, no graph resource can be resolved through it
+    pub(crate) fn detached(cmd: vk::CommandBuffer, device: &'a ash::Device) -> Self {
+        Self {
+            cmd,
+            device,
+            images: &[],
+            buffers: &[],
+            draws: Cell::new(0),
+        }
+    }
+
+    pub fn image(&self, id: ImageId) -> &GraphImage {
+        &self.images[id.0]
+    }
+
+    pub fn buffer(&self, id: BufferId) -> vk::Buffer {
+        self.buffers[id.0]
+    }
+
+    /// Counts `count` draw commands recorded by the pass, see [`GraphSummary::draw_calls`]
+    pub fn count_draws(&self, count: u32) {
+        self.draws.set(self.draws.get() + count);
+    }
+
+    /// Begins dynamic rendering over the whole extent of the first attachment
+    pub fn begin_rendering(&self, colors: &[Attachment], depth: Option<Attachment>) {
+        let info = |attachment: &Attachment, layout: vk::ImageLayout| {
+            let mut info = vk::RenderingAttachmentInfo::default()
+                .image_view(self.image(attachment.image).view)
+                .image_layout(layout)
+                .store_op(if attachment.store {
+                    vk::AttachmentStoreOp::STORE
+                } else {
+                    vk::AttachmentStoreOp::DONT_CARE
+                });
+
+            info = match attachment.load {
+                LoadOp::Load => info.load_op(vk::AttachmentLoadOp::LOAD),
+                LoadOp::Clear(value) => {
+                    info.load_op(vk::AttachmentLoadOp::CLEAR).clear_value(value)
+                }
+                LoadOp::DontCare => info.load_op(vk::AttachmentLoadOp::DONT_CARE),
+            };
+
+            if let Some(resolve) = attachment.resolve {
+                info = info
+                    .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                    .resolve_image_view(self.image(resolve).view)
+                    .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+            }
+
+            info
+        };
+
+        let color_infos: Vec<_> = colors
+            .iter()
+            .map(|val| info(val, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL))
+            .collect();
+        let depth_info =
+            depth.map(|val| info(&val, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL));
+
+        let first = colors.first().or(depth.as_ref()).expect("no attachments");
+        let extent = self.image(first.image).extent;
+
+        let mut rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D {
+                offset: vk::Offset2D::default(),
+                extent,
+            })
+            .layer_count(1)
+            .color_attachments(&color_infos);
+
+        if let Some(depth_info) = &depth_info {
+            rendering_info = rendering_info.depth_attachment(depth_info);
+        }
+
+        unsafe { self.device.cmd_begin_rendering(self.cmd, &rendering_info) };
+    }
+
+    pub fn end_rendering(&self) {
+        unsafe { self.device.cmd_end_rendering(self.cmd) };
+    }
+
+    /// Binds `pipeline` with its descriptor sets starting at set 0 and, if not empty,
+    /// the push constants starting at offset 0
+    pub fn bind_compute(
+        &self,
+        pipeline: &core::ComputePipeline,
+        sets: &[vk::DescriptorSet],
+        push_constants: &[u8],
+    ) {
+        unsafe {
+            self.device.cmd_bind_pipeline(
+                self.cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline.handle,
+            );
+
+            if !sets.is_empty() {
+                self.device.cmd_bind_descriptor_sets(
+                    self.cmd,
+                    vk::PipelineBindPoint::COMPUTE,
+                    pipeline.layout,
+                    0,
+                    sets,
+                    &[],
+                );
+            }
+
+            if !push_constants.is_empty() {
+                self.device.cmd_push_constants(
+                    self.cmd,
+                    pipeline.layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    push_constants,
+                );
+            }
+        }
+    }
+
+    pub fn dispatch(&self, group_x: u32, group_y: u32, group_z: u32) {
+        unsafe {
+            self.device
+                .cmd_dispatch(self.cmd, group_x, group_y, group_z)
+        };
+    }
+
+    /// Dispatch whose group counts are read from a `VkDispatchIndirectCommand` in `buffer`
+    pub fn dispatch_indirect(&self, buffer: vk::Buffer, offset: vk::DeviceSize) {
+        unsafe { self.device.cmd_dispatch_indirect(self.cmd, buffer, offset) };
+    }
+
+    /// Sets a viewport and scissor covering `extent`
+    pub fn set_viewport(&self, extent: vk::Extent2D) {
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D::default(),
+            extent,
+        };
+
+        unsafe {
+            self.device.cmd_set_viewport(self.cmd, 0, &[viewport]);
+            self.device.cmd_set_scissor(self.cmd, 0, &[scissor]);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct ImageBarrier {
+    image: ImageId,
+    transition: Transition,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BufferBarrier {
+    buffer: BufferId,
+    transition: Transition,
+}
+
+#[derive(Default, Debug)]
+struct Barriers {
+    images: Vec<ImageBarrier>,
+    buffers: Vec<BufferBarrier>,
+}
+
+impl Barriers {
+    fn is_empty(&self) -> bool {
+        self.images.is_empty() && self.buffers.is_empty()
+    }
+}
+
+/// Result of [`RenderGraph::compile`], everything but the physical resources
+struct Schedule {
+    /// Alive passes in execution order
+    order: Vec<usize>,
+    /// Barriers to record before each pass of `order`
+    barriers: Vec<Barriers>,
+    /// Barriers moving imported images to their final layout
+    final_barriers: Barriers,
+    /// Layout every image is left in
+    final_layouts: Vec<vk::ImageLayout>,
+    edges: Vec<(usize, usize, EdgeKind)>,
+    alive: Vec<bool>,
+}
+
+/// Pass and resource names plus the compiled schedule, kept around for debug dumps
+pub struct GraphSummary {
+    passes: Vec<String>,
+    images: Vec<(String, bool)>,
+    buffers: Vec<String>,
+    pass_resources: Vec<(Vec<Resource>, Vec<Resource>)>,
+    schedule: Schedule,
+    draw_calls: u32,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Importing an image already in the graph returns the id it was imported with,
+    /// its state is tracked once
+    pub fn import_image(&mut self, name: &str, image: ImportedImage) -> ImageId {
+        let existing = self.images.iter().position(|node| match &node.source {
+            ImageSource::Imported(val) => val.image == image.image,
+            ImageSource::Transient(_) => false,
+        });
+        if let Some(index) = existing {
+            return ImageId(index);
+        }
+
+        self.images.push(ImageNode {
+            name: name.to_owned(),
+            source: ImageSource::Imported(image),
+            output: false,
+        });
+        ImageId(self.images.len() - 1)
+    }
+
+    /// Declares an image that only lives for this frame, its usage flags are
+    /// inferred from the passes using it
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageId {
+        self.images.push(ImageNode {
+            name: name.to_owned(),
+            source: ImageSource::Transient(desc),
+            output: false,
+        });
+        ImageId(self.images.len() - 1)
+    }
+
+    /// Same as [`RenderGraph::import_image`], a buffer is only tracked once
+    pub fn import_buffer(&mut self, name: &str, buffer: vk::Buffer) -> BufferId {
+        if let Some(index) = self.buffers.iter().position(|node| node.buffer == buffer) {
+            return BufferId(index);
+        }
+
+        self.buffers.push(BufferNode {
+            name: name.to_owned(),
+            buffer,
+            output: false,
+        });
+        BufferId(self.buffers.len() - 1)
+    }
+
+    /// Marks the image as a result of the frame, passes writing it are never culled
+    pub fn mark_image_output(&mut self, image: ImageId) {
+        self.images[image.0].output = true;
+    }
+
+    pub fn mark_buffer_output(&mut self, buffer: BufferId) {
+        self.buffers[buffer.0].output = true;
+    }
+
+    /// Description of an image imported with [`RenderGraph::import_image`]
+    pub fn imported_image(&self, image: ImageId) -> Option<&ImportedImage> {
+        match &self.images[image.0].source {
+            ImageSource::Imported(val) => Some(val),
+            ImageSource::Transient(_) => None,
+        }
+    }
+
+    pub fn image_format(&self, image: ImageId) -> vk::Format {
+        self.images[image.0].format()
+    }
+
+    pub fn image_extent(&self, image: ImageId) -> vk::Extent2D {
+        self.images[image.0].extent()
+    }
+
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_> {
+        PassBuilder {
+            graph: self,
+            node: PassNode {
+                name: name.to_owned(),
+                reads: vec![],
+                writes: vec![],
+                side_effects: false,
+                execute: None,
+            },
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.buffers.clear();
+        self.passes.clear();
+    }
+
+    /// Dependencies between passes. A read depends on the last earlier writer of the
+    /// resource, with none it sees what the resource held before the frame (the last
+    /// frame for a persistent one). Writes are ordered after earlier accesses, so a
+    /// resource read before being written in the same frame is read before the writer.
+    fn edges(&self) -> Vec<(usize, usize, EdgeKind)> {
+        let mut edges = vec![];
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            for read in &pass.reads {
+                let key = read.key();
+                let writer = (0..index)
+                    .rev()
+                    .find(|other| self.passes[*other].writes.iter().any(|w| w.key() == key));
+
+                if let Some(writer) = writer {
+                    edges.push((writer, index, EdgeKind::Data));
+                }
+            }
+
+            for write in &pass.writes {
+                let key = write.key();
+                for other in (0..index).rev() {
+                    let other_pass = &self.passes[other];
+                    if other_pass.writes.iter().any(|w| w.key() == key) {
+                        edges.push((other, index, EdgeKind::Data));
+                        break;
+                    } else if other_pass.reads.iter().any(|r| r.key() == key) {
+                        edges.push((other, index, EdgeKind::Order));
+                    }
+                }
+            }
+        }
+
+        // a data edge between the same passes wins over an order one, culling follows it
+        edges.sort_unstable_by_key(|(from, to, kind)| (*from, *to, *kind != EdgeKind::Data));
+        edges.dedup_by_key(|(from, to, _)| (*from, *to));
+        edges
+    }
+
+    /// Passes with side effects or writing an output are alive, and so is every pass
+    /// they consume data from
+    fn cull(&self, edges: &[(usize, usize, EdgeKind)]) -> Vec<bool> {
+        let mut alive: Vec<bool> = self
+            .passes
+            .iter()
+            .map(|pass| {
+                pass.side_effects
+                    || pass.writes.iter().any(|write| match write {
+                        Resource::Image(id, _) => self.images[id.0].output,
+                        Resource::Buffer(id, _) => self.buffers[id.0].output,
+                    })
+            })
+            .collect();
+
+        let mut stack: Vec<usize> = (0..alive.len()).filter(|val| alive[*val]).collect();
+        while let Some(pass) = stack.pop() {
+            for (from, to, kind) in edges {
+                if *to == pass && *kind == EdgeKind::Data && !alive[*from] {
+                    alive[*from] = true;
+                    stack.push(*from);
+                }
+            }
+        }
+
+        alive
+    }
+
+    fn schedule(&self) -> Schedule {
+        let edges = self.edges();
+        let alive = self.cull(&edges);
+        let order: Vec<usize> = (0..self.passes.len()).filter(|pass| alive[*pass]).collect();
+
+        let mut image_trackers: Vec<Tracker> = self
+            .images
+            .iter()
+            .map(|image| match &image.source {
+                ImageSource::Imported(val) => Tracker::new(val.initial),
+                ImageSource::Transient(_) => Tracker::new(ResourceState::UNKNOWN),
+            })
+            .collect();
+        let mut buffer_trackers = vec![Tracker::new(ResourceState::UNKNOWN); self.buffers.len()];
+
+        let mut barriers = Vec::with_capacity(order.len());
+        for pass in &order {
+            let pass = &self.passes[*pass];
+            let mut pass_barriers = Barriers::default();
+
+            let accesses = pass
+                .reads
+                .iter()
+                .map(|val| (val, false))
+                .chain(pass.writes.iter().map(|val| (val, true)));
+
+            for (resource, write) in accesses {
+                match resource {
+                    Resource::Image(id, usage) => {
+                        let tracker = &mut image_trackers[id.0];
+                        if let Some(transition) = tracker.access(usage.state(write), write) {
+                            pass_barriers.images.push(ImageBarrier {
+                                image: *id,
+                                transition,
+                            });
+                        }
+                    }
+                    Resource::Buffer(id, usage) => {
+                        let tracker = &mut buffer_trackers[id.0];
+                        if let Some(transition) = tracker.access(usage.state(write), write) {
+                            pass_barriers.buffers.push(BufferBarrier {
+                                buffer: *id,
+                                transition,
+                            });
+                        }
+                    }
+                }
+            }
+
+            barriers.push(pass_barriers);
+        }
+
+        let mut final_barriers = Barriers::default();
+        for (index, image) in self.images.iter().enumerate() {
+            let ImageSource::Imported(imported) = &image.source else {
+                continue;
+            };
+            let Some(layout) = imported.final_layout else {
+                continue;
+            };
+
+            let tracker = &mut image_trackers[index];
+            if tracker.layout == layout {
+                continue;
+            }
+
+            let state = ResourceState {
+                stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+                access: vk::AccessFlags2::MEMORY_READ,
+                layout,
+            };
+            if let Some(transition) = tracker.access(state, false) {
+                final_barriers.images.push(ImageBarrier {
+                    image: ImageId(index),
+                    transition,
+                });
+            }
+        }
+
+        Schedule {
+            order,
+            barriers,
+            final_barriers,
+            final_layouts: image_trackers.iter().map(|val| val.layout).collect(),
+            edges,
+            alive,
+        }
+    }
+
+    /// Culls the passes, allocates transient images and records everything
+    /// into `cmd`. The graph is left empty, ready for the next frame.
+    pub(crate) fn execute(
+        &mut self,
+        device: &core::Device,
+        pool: &mut TransientPool,
+        frame: u64,
+        cmd: vk::CommandBuffer,
+    ) -> Result<GraphSummary, Box<dyn Error>> {
+        let schedule = self.schedule();
+
+        // usage flags of transient images are the union of what alive passes do with them
+        let mut usages = vec![vk::ImageUsageFlags::empty(); self.images.len()];
+        for pass in &schedule.order {
+            let pass = &self.passes[*pass];
+            for resource in pass.reads.iter().chain(&pass.writes) {
+                if let Resource::Image(id, usage) = resource {
+                    usages[id.0] |= usage.flags();
+                }
+            }
+        }
+
+        let mut images = Vec::with_capacity(self.images.len());
+        for (index, image) in self.images.iter().enumerate() {
+            let resolved = match &image.source {
+                ImageSource::Imported(val) => GraphImage {
+                    image: val.image,
+                    view: val.view,
+                    format: val.format,
+                    extent: val.extent,
+                },
+                ImageSource::Transient(_) if usages[index].is_empty() => GraphImage {
+                    image: vk::Image::null(),
+                    view: vk::ImageView::null(),
+                    format: image.format(),
+                    extent: image.extent(),
+                },
+                ImageSource::Transient(desc) => {
+                    let entry = pool.acquire(device, desc, usages[index], frame)?;
+                    let allocated = pool.image(entry);
+                    GraphImage {
+                        image: allocated.handle,
+                        view: allocated.view,
+                        format: allocated.format,
+                        extent: allocated.extent_2d(),
+                    }
+                }
+            };
+            images.push(resolved);
+        }
+
+        let buffers: Vec<vk::Buffer> = self.buffers.iter().map(|val| val.buffer).collect();
+
+        let context = PassContext {
+            cmd,
+            device: device.handle(),
+            images: &images,
+            buffers: &buffers,
+            draws: Cell::new(0),
+        };
+
+        for (position, pass) in schedule.order.iter().enumerate() {
+            self.record_barriers(&context, &schedule.barriers[position]);
+            if let Some(execute) = self.passes[*pass].execute.take() {
+                execute(&context);
+            }
+        }
+        self.record_barriers(&context, &schedule.final_barriers);
+
+        let summary = GraphSummary {
+            passes: self.passes.iter().map(|val| val.name.clone()).collect(),
+            images: self
+                .images
+                .iter()
+                .map(|val| {
+                    (
+                        val.name.clone(),
+                        matches!(val.source, ImageSource::Transient(_)),
+                    )
+                })
+                .collect(),
+            buffers: self.buffers.iter().map(|val| val.name.clone()).collect(),
+            pass_resources: self
+                .passes
+                .iter()
+                .map(|val| (val.reads.clone(), val.writes.clone()))
+                .collect(),
+            schedule,
+            draw_calls: context.draws.get(),
+        };
+
+        self.clear();
+        Ok(summary)
+    }
+
+    fn record_barriers(&self, context: &PassContext, barriers: &Barriers) {
+        if barriers.is_empty() {
+            return;
+        }
+
+        let image_barriers: Vec<_> = barriers
+            .images
+            .iter()
+            .map(|barrier| {
+                let image = context.image(barrier.image);
+                let transition = &barrier.transition;
+                vk::ImageMemoryBarrier2::default()
+                    .src_stage_mask(transition.src_stage)
+                    .src_access_mask(transition.src_access)
+                    .dst_stage_mask(transition.dst.stage)
+                    .dst_access_mask(transition.dst.access)
+                    .old_layout(transition.old_layout)
+                    .new_layout(transition.dst.layout)
+                    .image(image.image)
+                    .subresource_range(
+                        vk::ImageSubresourceRange::default()
+                            .aspect_mask(core::format_aspect(image.format))
+                            .level_count(vk::REMAINING_MIP_LEVELS)
+                            .layer_count(vk::REMAINING_ARRAY_LAYERS),
+                    )
+            })
+            .collect();
+
+        let buffer_barriers: Vec<_> = barriers
+            .buffers
+            .iter()
+            .map(|barrier| {
+                let transition = &barrier.transition;
+                vk::BufferMemoryBarrier2::default()
+                    .src_stage_mask(transition.src_stage)
+                    .src_access_mask(transition.src_access)
+                    .dst_stage_mask(transition.dst.stage)
+                    .dst_access_mask(transition.dst.access)
+                    .buffer(context.buffer(barrier.buffer))
+                    .size(vk::WHOLE_SIZE)
+            })
+            .collect();
+
+        let dependency = vk::DependencyInfo::default()
+            .image_memory_barriers(&image_barriers)
+            .buffer_memory_barriers(&buffer_barriers);
+
+        unsafe {
+            context
+                .device
+                .cmd_pipeline_barrier2(context.cmd, &dependency)
+        };
+    }
+}
+
+impl GraphSummary {
+    /// Layout `image` was left in at the end of the frame
+    pub fn final_layout(&self, image: ImageId) -> vk::ImageLayout {
+        self.schedule.final_layouts[image.0]
+    }
+
+    /// Draw commands the passes counted with [`PassContext::count_draws`]
+    pub fn draw_calls(&self) -> u32 {
+        self.draw_calls
+    }
+
+    pub fn culled_passes(&self) -> impl Iterator<Item = &str> {
+        self.passes
+            .iter()
+            .zip(&self.schedule.alive)
+            .filter(|(_, alive)| !**alive)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+
+    use super::*;
+
+    const EXTENT: vk::Extent2D = vk::Extent2D {
+        width: 64,
+        height: 64,
+    };
+
+    fn imported(raw: u64) -> ImportedImage {
+        ImportedImage {
+            image: vk::Image::from_raw(raw),
+            view: vk::ImageView::from_raw(raw),
+            format: vk::Format::R8G8B8A8_UNORM,
+            extent: EXTENT,
+            samples: vk::SampleCountFlags::TYPE_1,
+            initial: ResourceState::UNKNOWN,
+            final_layout: None,
+        }
+    }
+
+    fn transient(graph: &mut RenderGraph, name: &str) -> ImageId {
+        graph.create_image(name, ImageDesc::new(EXTENT, vk::Format::R8G8B8A8_UNORM))
+    }
+
+    /// Names of the alive passes in execution order
+    fn order(graph: &RenderGraph) -> Vec<&str> {
+        let schedule = graph.schedule();
+        schedule
+            .order
+            .iter()
+            .map(|pass| graph.passes[*pass].name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn passes_keep_declaration_order_without_dependencies() {
+        let mut graph = RenderGraph::new();
+        for (raw, name) in ["a", "b", "c"].into_iter().enumerate() {
+            let buffer = graph.import_buffer(name, vk::Buffer::from_raw(raw as u64 + 1));
+            graph
+                .add_pass(name)
+                .write_buffer(buffer, BufferUsage::TransferDst)
+                .side_effects()
+                .execute(|_| {});
+        }
+        assert_eq!(order(&graph), ["a", "b", "c"]);
+        assert!(graph.edges().is_empty());
+    }
+
+    #[test]
+    fn readers_follow_the_last_earlier_writer() {
+        let mut graph = RenderGraph::new();
+        let output = graph.import_image("output", imported(1));
+        graph.mark_image_output(output);
+        let scene = transient(&mut graph, "scene");
+
+        graph
+            .add_pass("first")
+            .write_image(scene, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("second")
+            .write_image(scene, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("copy")
+            .read_image(scene, ImageUsage::TransferSrc)
+            .write_image(output, ImageUsage::TransferDst)
+            .execute(|_| {});
+
+        assert_eq!(
+            graph.edges(),
+            [(0, 1, EdgeKind::Data), (1, 2, EdgeKind::Data)]
+        );
+        assert_eq!(order(&graph), ["first", "second", "copy"]);
+    }
+
+    #[test]
+    fn reads_before_any_writer_run_before_it() {
+        let mut graph = RenderGraph::new();
+        let history = graph.import_image("history", imported(1));
+        graph.mark_image_output(history);
+        let status = graph.import_buffer("status", vk::Buffer::from_raw(2));
+        graph.mark_buffer_output(status);
+
+        // reads what the last frame left in the history, then overwrites it
+        graph
+            .add_pass("read history")
+            .read_image(history, ImageUsage::Sampled)
+            .write_buffer(status, BufferUsage::Storage)
+            .execute(|_| {});
+        graph
+            .add_pass("write history")
+            .write_image(history, ImageUsage::Storage)
+            .execute(|_| {});
+
+        assert_eq!(graph.edges(), [(0, 1, EdgeKind::Order)]);
+        assert_eq!(order(&graph), ["read history", "write history"]);
+    }
+
+    #[test]
+    fn depth_pyramid_is_read_before_being_rebuilt() {
+        let mut graph = RenderGraph::new();
+        let output = graph.import_image("output", imported(1));
+        graph.mark_image_output(output);
+        let depth = transient(&mut graph, "depth");
+        let draws = graph.import_buffer("chunk draws", vk::Buffer::from_raw(2));
+
+        let mut pyramid = imported(3);
+        pyramid.initial = ResourceState {
+            stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+            access: vk::AccessFlags2::MEMORY_WRITE,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        };
+        pyramid.final_layout = Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        // the culler and the pyramid build import the pyramid separately, like ChunkCuller
+        let last_pyramid = graph.import_image("depth pyramid", pyramid);
+        graph
+            .add_pass("cull chunks")
+            .read_image(last_pyramid, ImageUsage::Sampled)
+            .write_buffer(draws, BufferUsage::Storage)
+            .execute(|_| {});
+        graph
+            .add_pass("chunks")
+            .read_buffer(draws, BufferUsage::Indirect)
+            .write_image(output, ImageUsage::ColorAttachment)
+            .write_image(depth, ImageUsage::DepthAttachment)
+            .execute(|_| {});
+        let next_pyramid = graph.import_image("depth pyramid", pyramid);
+        graph
+            .add_pass("build depth pyramid")
+            .read_image(depth, ImageUsage::Sampled)
+            .write_image(next_pyramid, ImageUsage::Storage)
+            .side_effects()
+            .execute(|_| {});
+
+        assert_eq!(last_pyramid, next_pyramid);
+        assert_eq!(
+            graph.edges(),
+            [
+                (0, 1, EdgeKind::Data),
+                (0, 2, EdgeKind::Order),
+                (1, 2, EdgeKind::Data)
+            ]
+        );
+        assert_eq!(
+            order(&graph),
+            ["cull chunks", "chunks", "build depth pyramid"]
+        );
+
+        // the build waits for the cull to be done sampling before writing
+        let schedule = graph.schedule();
+        let build = schedule.barriers[2]
+            .images
+            .iter()
+            .find(|val| val.image == next_pyramid)
+            .unwrap();
+        assert_eq!(
+            build.transition.old_layout,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+        );
+        assert_eq!(build.transition.dst.layout, vk::ImageLayout::GENERAL);
+        assert!(build
+            .transition
+            .src_stage
+            .contains(vk::PipelineStageFlags2::COMPUTE_SHADER));
+    }
+
+    #[test]
+    fn data_edges_win_over_order_edges() {
+        let mut graph = RenderGraph::new();
+        let a = transient(&mut graph, "a");
+        let b = transient(&mut graph, "b");
+        let output = graph.import_image("output", imported(1));
+        graph.mark_image_output(output);
+
+        graph
+            .add_pass("producer")
+            .read_image(b, ImageUsage::Sampled)
+            .write_image(a, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        // consumes "a" and overwrites "b", the producer must stay alive
+        graph
+            .add_pass("consumer")
+            .read_image(a, ImageUsage::Sampled)
+            .write_image(b, ImageUsage::ColorAttachment)
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+
+        assert_eq!(graph.edges(), [(0, 1, EdgeKind::Data)]);
+        assert_eq!(order(&graph), ["producer", "consumer"]);
+    }
+
+    #[test]
+    fn passes_nothing_consumes_are_culled() {
+        let mut graph = RenderGraph::new();
+        let output = graph.import_image("output", imported(1));
+        graph.mark_image_output(output);
+        let scene = transient(&mut graph, "scene");
+        let unused = transient(&mut graph, "unused");
+        let readback = graph.import_buffer("readback", vk::Buffer::from_raw(2));
+
+        graph
+            .add_pass("scene")
+            .write_image(scene, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("unused")
+            .read_image(scene, ImageUsage::Sampled)
+            .write_image(unused, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("readback")
+            .read_image(scene, ImageUsage::TransferSrc)
+            .write_buffer(readback, BufferUsage::TransferDst)
+            .side_effects()
+            .execute(|_| {});
+        graph
+            .add_pass("overwrite scene")
+            .write_image(scene, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("copy")
+            .read_image(scene, ImageUsage::TransferSrc)
+            .write_image(output, ImageUsage::TransferDst)
+            .execute(|_| {});
+
+        let schedule = graph.schedule();
+        assert_eq!(schedule.alive, [true, false, true, true, true]);
+        assert_eq!(
+            order(&graph),
+            ["scene", "readback", "overwrite scene", "copy"]
+        );
+    }
+
+    #[test]
+    fn order_edges_alone_keep_nothing_alive() {
+        let mut graph = RenderGraph::new();
+        let output = graph.import_image("output", imported(1));
+        graph.mark_image_output(output);
+        let scratch = transient(&mut graph, "scratch");
+        let unused = transient(&mut graph, "unused");
+
+        graph
+            .add_pass("reader")
+            .read_image(scratch, ImageUsage::Sampled)
+            .write_image(unused, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("writer")
+            .write_image(scratch, ImageUsage::ColorAttachment)
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+
+        assert_eq!(graph.edges(), [(0, 1, EdgeKind::Order)]);
+        assert_eq!(order(&graph), ["writer"]);
+    }
+
+    #[test]
+    fn images_transition_between_passes() {
+        let mut graph = RenderGraph::new();
+        let mut image = imported(1);
+        image.final_layout = Some(vk::ImageLayout::PRESENT_SRC_KHR);
+        let output = graph.import_image("output", image);
+        graph.mark_image_output(output);
+        let scene = transient(&mut graph, "scene");
+
+        graph
+            .add_pass("draw")
+            .write_image(scene, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("blit")
+            .read_image(scene, ImageUsage::Sampled)
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+        graph
+            .add_pass("sample again")
+            .read_image(scene, ImageUsage::Sampled)
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+
+        let schedule = graph.schedule();
+        let transitions = |position: usize| -> Vec<_> {
+            schedule.barriers[position]
+                .images
+                .iter()
+                .map(|val| {
+                    (
+                        val.image,
+                        val.transition.old_layout,
+                        val.transition.dst.layout,
+                    )
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            transitions(0),
+            [(
+                scene,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            )]
+        );
+        assert_eq!(
+            transitions(1),
+            [
+                (
+                    scene,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                ),
+                (
+                    output,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                ),
+            ]
+        );
+        // the scene was already made visible to the fragment shader, the output is
+        // written again by the same stage
+        assert_eq!(
+            transitions(2),
+            [(
+                output,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            )]
+        );
+
+        let barrier = &schedule.barriers[1].images[0].transition;
+        assert_eq!(
+            barrier.src_access,
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+        );
+        assert_eq!(barrier.dst.access, vk::AccessFlags2::SHADER_SAMPLED_READ);
+
+        assert_eq!(
+            schedule.final_barriers.images[0].transition.dst.layout,
+            vk::ImageLayout::PRESENT_SRC_KHR
+        );
+        assert_eq!(
+            schedule.final_layouts,
+            [
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+            ]
+        );
+    }
+
+    #[test]
+    fn buffers_wait_for_their_writers() {
+        let mut graph = RenderGraph::new();
+        let draws = graph.import_buffer("draws", vk::Buffer::from_raw(1));
+        let output = graph.import_image("output", imported(2));
+        graph.mark_image_output(output);
+
+        graph
+            .add_pass("cull")
+            .write_buffer(draws, BufferUsage::Storage)
+            .execute(|_| {});
+        graph
+            .add_pass("draw")
+            .read_buffer(draws, BufferUsage::Indirect)
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(|_| {});
+
+        let schedule = graph.schedule();
+        let barrier = &schedule.barriers[1].buffers[0];
+        assert_eq!(barrier.buffer, draws);
+        assert_eq!(
+            barrier.transition.src_access,
+            vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE
+        );
+        assert_eq!(
+            barrier.transition.dst.stage,
+            vk::PipelineStageFlags2::DRAW_INDIRECT
+        );
+    }
+
+    #[test]
+    fn imports_are_deduplicated_by_handle() {
+        let mut graph = RenderGraph::new();
+        let first = graph.import_image("first", imported(1));
+        let other = graph.import_image("other", imported(2));
+        let again = graph.import_image("again", imported(1));
+        assert_eq!(first, again);
+        assert_ne!(first, other);
+        assert_eq!(graph.images.len(), 2);
+        assert_eq!(graph.images[first.0].name, "first");
+
+        // transient images are never merged
+        assert_ne!(transient(&mut graph, "a"), transient(&mut graph, "a"));
+
+        let buffer = graph.import_buffer("buffer", vk::Buffer::from_raw(1));
+        assert_eq!(graph.import_buffer("same", vk::Buffer::from_raw(1)), buffer);
+        assert_ne!(
+            graph.import_buffer("other", vk::Buffer::from_raw(2)),
+            buffer
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use std::error::Error;
+
+use ash::vk;
+use gpu_allocator::MemoryLocation;
+
+use crate::core;
+
+/// Frames an image is kept unused for in case the next frames need it again, it's only
+/// destroyed once the frame that used it last is completed as well
+const MAX_IDLE_FRAMES: u64 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct TransientKey {
+    extent: vk::Extent2D,
+    format: vk::Format,
+    usage: vk::ImageUsageFlags,
+    samples: vk::SampleCountFlags,
+    mip_levels: u32,
+    array_layers: u32,
+}
+
+struct Entry {
+    key: TransientKey,
+    image: core::AllocatedImage,
+    last_used: u64,
+}
+
+/// Keeps the images backing transient graph resources alive across frames, so a graph
+/// rebuilt every frame doesn't allocate every frame
+#[derive(Default)]
+pub(crate) struct TransientPool {
+    entries: Vec<Entry>,
+    /// Host visible buffers filled at record time, with the frame they were used in
+    staging: Vec<(core::AllocatedBuffer, u64)>,
+}
+
+impl TransientPool {
+    /// Returns the index of an image matching the description that isn't used yet this frame
+    pub fn acquire(
+        &mut self,
+        device: &core::Device,
+        desc: &super::ImageDesc,
+        usage: vk::ImageUsageFlags,
+        frame: u64,
+    ) -> Result<usize, Box<dyn Error>> {
+        let key = TransientKey {
+            extent: desc.extent,
+            format: desc.format,
+            usage,
+            samples: desc.samples,
+            mip_levels: desc.mip_levels,
+            array_layers: desc.array_layers,
+        };
+
+        let free = self
+            .entries
+            .iter()
+            .position(|entry| entry.key == key && entry.last_used != frame);
+
+        if let Some(index) = free {
+            self.entries[index].last_used = frame;
+            return Ok(index);
+        }
+
+        let aspect = core::format_aspect(desc.format);
+        let image = device.create_image(&core::ImageSpec {
+            name: "transient",
+            extent: desc.extent.into(),
+            format: desc.format,
+            usage,
+            // views of depth/stencil formats can only select one aspect
+            aspect: if aspect.contains(vk::ImageAspectFlags::DEPTH) {
+                vk::ImageAspectFlags::DEPTH
+            } else {
+                aspect
+            },
+            mip_levels: desc.mip_levels,
+            array_layers: desc.array_layers,
+            samples: desc.samples,
+        })?;
+
+        self.entries.push(Entry {
+            key,
+            image,
+            last_used: frame,
+        });
+        Ok(self.entries.len() - 1)
+    }
+
+    /// Copies `data` into a host visible buffer that lives until no frame in flight can
+    /// be reading it anymore
+    pub fn stage(
+        &mut self,
+        device: &core::Device,
+        data: &[u8],
+        frame: u64,
+    ) -> Result<vk::Buffer, Box<dyn Error>> {
+        let mut buffer = device.create_buffer(
+            "staging",
+            data.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+
+        match buffer.mapped_mut() {
+            Some(mapped) => mapped[..data.len()].copy_from_slice(data),
+            None => {
+                device.destroy_buffer(buffer);
+                return Err("Staging buffer is not host visible".into());
+            }
+        }
+
+        let handle = buffer.handle;
+        self.staging.push((buffer, frame));
+        Ok(handle)
+    }
+
+    pub fn image(&self, index: usize) -> &core::AllocatedImage {
+        &self.entries[index].image
+    }
+
+    /// Destroys the staging buffers of the frames up to `completed`, and the images idle
+    /// since then. Frames count across every surface, a surface may be further behind
+    /// than the frames in flight of one surface.
+    pub fn collect(&mut self, device: &core::Device, frame: u64, completed: u64) {
+        let (expired, staging) = self
+            .staging
+            .drain(..)
+            .partition(|(_, used)| *used <= completed);
+        self.staging = staging;
+        for (buffer, _) in expired {
+            device.destroy_buffer(buffer);
+        }
+
+        let mut index = 0;
+        while index < self.entries.len() {
+            let last_used = self.entries[index].last_used;
+            if last_used <= completed && frame.saturating_sub(last_used) > MAX_IDLE_FRAMES {
+                device.destroy_image(self.entries.swap_remove(index).image);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, device: &core::Device) {
+        for entry in self.entries.drain(..) {
+            device.destroy_image(entry.image);
+        }
+        for (buffer, _) in self.staging.drain(..) {
+            device.destroy_buffer(buffer);
+        }
+    }
+}
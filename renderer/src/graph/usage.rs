@@ -0,0 +1,249 @@
+use ash::vk;
+
+const SHADER_STAGES: vk::PipelineStageFlags2 = vk::PipelineStageFlags2::from_raw(
+    vk::PipelineStageFlags2::VERTEX_SHADER.as_raw()
+        | vk::PipelineStageFlags2::FRAGMENT_SHADER.as_raw()
+        | vk::PipelineStageFlags2::COMPUTE_SHADER.as_raw(),
+);
+
+const DEPTH_TESTS: vk::PipelineStageFlags2 = vk::PipelineStageFlags2::from_raw(
+    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS.as_raw()
+        | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS.as_raw(),
+);
+
+/// How a pass uses an image, together with read/write it decides stages, accesses and layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageUsage {
+    ColorAttachment,
+    DepthAttachment,
+    /// Depth test without depth writes
+    DepthRead,
+    Sampled,
+    Storage,
+    TransferSrc,
+    TransferDst,
+}
+
+/// How a pass uses a buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    Storage,
+    Uniform,
+    Indirect,
+    Vertex,
+    Index,
+    TransferSrc,
+    TransferDst,
+}
+
+/// Synchronization state a resource is left in (or needs to be in) around a pass
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceState {
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+    pub layout: vk::ImageLayout,
+}
+
+impl ResourceState {
+    /// State of a resource whose previous contents and users are unknown,
+    /// waits on everything submitted before it
+    pub const UNKNOWN: ResourceState = ResourceState {
+        stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+        access: vk::AccessFlags2::MEMORY_WRITE,
+        layout: vk::ImageLayout::UNDEFINED,
+    };
+}
+
+impl ImageUsage {
+    pub(crate) fn state(self, write: bool) -> ResourceState {
+        use vk::AccessFlags2 as A;
+
+        let (stage, read, written, layout) = match self {
+            ImageUsage::ColorAttachment => (
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                A::COLOR_ATTACHMENT_READ,
+                A::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            ImageUsage::DepthAttachment => (
+                DEPTH_TESTS,
+                A::DEPTH_STENCIL_ATTACHMENT_READ,
+                A::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ),
+            ImageUsage::DepthRead => (
+                DEPTH_TESTS,
+                A::DEPTH_STENCIL_ATTACHMENT_READ,
+                A::NONE,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+            ImageUsage::Sampled => (
+                SHADER_STAGES,
+                A::SHADER_SAMPLED_READ,
+                A::NONE,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ),
+            ImageUsage::Storage => (
+                SHADER_STAGES,
+                A::SHADER_STORAGE_READ,
+                A::SHADER_STORAGE_WRITE,
+                vk::ImageLayout::GENERAL,
+            ),
+            ImageUsage::TransferSrc => (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                A::TRANSFER_READ,
+                A::NONE,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+            ImageUsage::TransferDst => (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                A::NONE,
+                A::TRANSFER_WRITE,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ),
+        };
+
+        ResourceState {
+            stage,
+            access: if write { read | written } else { read },
+            layout,
+        }
+    }
+
+    pub(crate) fn flags(self) -> vk::ImageUsageFlags {
+        match self {
+            ImageUsage::ColorAttachment => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            ImageUsage::DepthAttachment | ImageUsage::DepthRead => {
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            }
+            ImageUsage::Sampled => vk::ImageUsageFlags::SAMPLED,
+            ImageUsage::Storage => vk::ImageUsageFlags::STORAGE,
+            ImageUsage::TransferSrc => vk::ImageUsageFlags::TRANSFER_SRC,
+            ImageUsage::TransferDst => vk::ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+impl BufferUsage {
+    pub(crate) fn state(self, write: bool) -> ResourceState {
+        use vk::AccessFlags2 as A;
+
+        let (stage, read, written) = match self {
+            BufferUsage::Storage => (
+                SHADER_STAGES,
+                A::SHADER_STORAGE_READ,
+                A::SHADER_STORAGE_WRITE,
+            ),
+            BufferUsage::Uniform => (SHADER_STAGES, A::UNIFORM_READ, A::NONE),
+            BufferUsage::Indirect => (
+                vk::PipelineStageFlags2::DRAW_INDIRECT,
+                A::INDIRECT_COMMAND_READ,
+                A::NONE,
+            ),
+            BufferUsage::Vertex => (
+                vk::PipelineStageFlags2::VERTEX_ATTRIBUTE_INPUT,
+                A::VERTEX_ATTRIBUTE_READ,
+                A::NONE,
+            ),
+            BufferUsage::Index => (vk::PipelineStageFlags2::INDEX_INPUT, A::INDEX_READ, A::NONE),
+            BufferUsage::TransferSrc => (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                A::TRANSFER_READ,
+                A::NONE,
+            ),
+            BufferUsage::TransferDst => (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                A::NONE,
+                A::TRANSFER_WRITE,
+            ),
+        };
+
+        ResourceState {
+            stage,
+            access: if write { read | written } else { read },
+            layout: vk::ImageLayout::UNDEFINED,
+        }
+    }
+}
+
+/// Tracks the synchronization state of one resource while walking the passes in order
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Tracker {
+    pub layout: vk::ImageLayout,
+    /// Stage and accesses of the last write (or layout transition)
+    write_stage: vk::PipelineStageFlags2,
+    write_access: vk::AccessFlags2,
+    /// Stages that read the resource since the last write
+    read_stage: vk::PipelineStageFlags2,
+    /// Stages and accesses already made to wait on the last write
+    synced_stage: vk::PipelineStageFlags2,
+    synced_access: vk::AccessFlags2,
+}
+
+/// Source half of a barrier, the destination half is the new [`ResourceState`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Transition {
+    pub src_stage: vk::PipelineStageFlags2,
+    pub src_access: vk::AccessFlags2,
+    pub dst: ResourceState,
+    pub old_layout: vk::ImageLayout,
+}
+
+impl Tracker {
+    pub fn new(initial: ResourceState) -> Self {
+        Self {
+            layout: initial.layout,
+            write_stage: initial.stage,
+            write_access: initial.access,
+            read_stage: vk::PipelineStageFlags2::NONE,
+            synced_stage: vk::PipelineStageFlags2::NONE,
+            synced_access: vk::AccessFlags2::NONE,
+        }
+    }
+
+    /// Advances the tracker to `next`, returning the barrier needed before it if any
+    pub fn access(&mut self, next: ResourceState, write: bool) -> Option<Transition> {
+        let layout_change = self.layout != next.layout;
+
+        if !write && !layout_change {
+            self.read_stage |= next.stage;
+            if self.synced_stage.contains(next.stage) && self.synced_access.contains(next.access) {
+                return None;
+            }
+
+            self.synced_stage |= next.stage;
+            self.synced_access |= next.access;
+            return Some(Transition {
+                src_stage: self.write_stage,
+                src_access: self.write_access,
+                dst: next,
+                old_layout: self.layout,
+            });
+        }
+
+        // writes and layout transitions must wait for every previous reader too
+        let transition = Transition {
+            src_stage: self.write_stage | self.read_stage,
+            src_access: self.write_access,
+            dst: next,
+            old_layout: self.layout,
+        };
+
+        self.layout = next.layout;
+        self.write_stage = next.stage;
+        if write {
+            self.write_access = next.access;
+            self.read_stage = vk::PipelineStageFlags2::NONE;
+            self.synced_stage = vk::PipelineStageFlags2::NONE;
+            self.synced_access = vk::AccessFlags2::NONE;
+        } else {
+            // the transition is visible to the stages it was made for
+            self.write_access = vk::AccessFlags2::NONE;
+            self.read_stage = next.stage;
+            self.synced_stage = next.stage;
+            self.synced_access = next.access;
+        }
+
+        Some(transition)
+    }
+}
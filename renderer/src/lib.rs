@@ -1,9 +1,63 @@
-use std::ffi::CString;
+use std::{collections::HashMap, error::Error};
 
-use ash::{ext, khr, vk};
+use ash::{ext, khr};
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
+mod chunks;
+mod compute;
+mod config;
 mod core;
+pub mod debug_draw;
+mod fog;
+mod frustum;
+pub mod graph;
+pub mod mesher;
+mod post;
+mod resolution;
+mod shaders;
+mod sky;
+mod target;
+mod text;
+mod uniforms;
+
+pub use ash::vk;
+pub use chunks::{
+    ArenaStats, BlockTextureSet, BlockTextures, CascadeCamera, ChunkCuller, ChunkRenderer,
+    CulledDraws, Face, GeometryArena, MeshDraw, MeshHandle, MeshRange, PackedVertex, RenderLayer,
+    SectionDraw, ShadowMaps, TextureLayers, VoxelVertex, MAX_CASCADES,
+};
+pub use compute::ComputeTicket;
+pub use config::{
+    AntiAliasing, FogMode, FogSettings, OutputEncoding, OutputFormat, PostSettings, PresentMode,
+    RenderSettings, RendererConfig, ResolutionSettings, ShadowSettings, SsaoSettings,
+    SurfaceFormat, Tonemap, Upscale,
+};
+pub use core::{
+    create_pipeline_layout, AllocatedBuffer, AllocatedImage, Blend, ComputePipeline,
+    ComputePipelineSpec, DescriptorAllocator, DescriptorLayoutBuilder, DescriptorWriter, GpuInfo,
+    GraphicsPipelineBuilder, ImageSpec, MemoryUsage, PoolSizeRatio,
+};
+pub use debug_draw::DebugStyle;
+pub use fog::Medium;
+pub use frustum::Frustum;
+pub use glam;
+pub use gpu_allocator::MemoryLocation;
+pub use post::{AmbientOcclusion, ColorLut, PostProcess, TemporalAntiAliasing};
+pub use sky::{SkyRenderer, SkyState};
+pub use target::{Frame, SurfaceId};
+pub use text::{Font, TextStyle, FORMATTING_CODE};
+
+use compute::AsyncCompute;
+use fog::FogState;
+use glam::{Vec2, Vec3};
+use graph::{
+    Attachment, BufferId, BufferUsage, GraphSummary, ImageId, ImageUsage, ImportedImage, LoadOp,
+    PassContext, RenderGraph, ResourceState, TransientPool,
+};
+use resolution::ResolutionScaler;
+use target::{AttachmentSpec, FrameData, RenderTarget, FRAME_TIMESTAMPS};
+use text::{TextConstants, TextRenderer};
+use uniforms::{FrameInputs, FrameUniforms};
 
 /*
 *NOTE:
@@ -61,42 +115,135 @@ mod core;
 
 // TODO: remove panics and unwrapping and move them to be handled in client code
 
-const MAX_FRAMES_IN_FLIGHT: usize = 2;
+const DRAW_IMAGE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+const DEPTH_FORMATS: [vk::Format; 3] = [
+    vk::Format::D32_SFLOAT,
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+];
+const FENCE_TIMEOUT: u64 = 1_000_000_000;
+/// Descriptor sets the first pool of the renderer allocator is sized for
+const DESCRIPTOR_SETS: u32 = 64;
 
 // NOTE: rust calls Drop implementations in order of member declaration.
 // This is stupid imho but it is what it is
-#[allow(dead_code)]
+/// Records a frame of every surface as a [`graph::RenderGraph`] between
+/// [`Renderer::begin_frame`] and [`Renderer::end_frame`]. The passes of a frame read
+/// what earlier ones wrote, so they are added in the order below:
+///
+/// ```no_run
+/// # use std::error::Error;
+/// # use renderer::glam::{vec2, Mat4, Vec3};
+/// # use renderer::*;
+/// struct World {
+///     sky: SkyRenderer,
+///     arena: GeometryArena,
+///     culler: ChunkCuller,
+///     shadows: ShadowMaps,
+///     textures: BlockTextures,
+///     chunks: ChunkRenderer,
+///     ssao: AmbientOcclusion,
+///     taa: TemporalAntiAliasing,
+///     post: PostProcess,
+/// }
+///
+/// fn draw_frame(
+///     renderer: &mut Renderer,
+///     world: &mut World,
+///     camera: &CascadeCamera,
+///     proj: &Mat4,
+///     eye: Vec3,
+/// ) -> Result<(), Box<dyn Error>> {
+///     renderer.set_camera(eye, Medium::Air);
+///     renderer.begin_frame(SurfaceId::PRIMARY)?;
+///
+///     // with TAA the scene is drawn with a sub-pixel jitter changing every frame
+///     let view_proj = world.taa.jittered(renderer, proj) * camera.view;
+///     world.sky.draw(renderer, &view_proj, eye)?;
+///
+///     // meshes updated in the arena are uploaded before the sections are culled, with
+///     // the quads of the translucent ones sorted for the camera
+///     world.chunks.sort_translucent(renderer, &mut world.arena, eye)?;
+///     world.arena.flush(renderer);
+///     let culled = world.culler.cull(renderer, &view_proj)?;
+///
+///     world.shadows.set_light_direction(renderer.sky_state().light_direction);
+///     world.shadows.render(renderer, &world.culler, &world.arena, &world.textures, camera)?;
+///     world.chunks.draw(
+///         renderer,
+///         &world.culler,
+///         &culled,
+///         &world.arena,
+///         &mut world.textures,
+///         &mut world.shadows,
+///         &view_proj,
+///         eye,
+///     )?;
+///     debug_draw::render(renderer, &view_proj)?;
+///
+///     // the next frame is occlusion culled against the depth drawn so far
+///     world.culler.build_hiz(renderer)?;
+///     world.ssao.render(renderer, proj)?;
+///     world.taa.render(renderer, &(*proj * camera.view))?;
+///     world.post.render(renderer)?;
+///
+///     renderer.draw_text(vec2(2.0, 2.0), "§eFPS§r 60", &TextStyle::default())?;
+///     renderer.end_frame()
+/// }
+/// ```
 pub struct Renderer {
-    frame_number: usize,
-    frames: [FrameData; MAX_FRAMES_IN_FLIGHT],
+    targets: HashMap<SurfaceId, RenderTarget>,
+    next_surface: SurfaceId,
+    /// Frame being recorded between begin_frame and end_frame
+    frame: Option<Frame>,
+    /// Whether a pass of the frame being recorded writes its output
+    output_written: bool,
+    /// Whether the resolve pass of the frame was already added
+    scene_resolved: bool,
+    graph: RenderGraph,
+    transients: TransientPool,
+    /// Frames submitted across every surface, transient images are aged with it
+    frame_count: u64,
+    /// Latest frame whose fence was waited on, see [`Renderer::completed_frame`]
+    completed_frame: u64,
+    last_graph: Option<GraphSummary>,
+    immediate: ImmediateData,
+    compute: AsyncCompute,
+    /// Latest async compute submission the frame being recorded has to wait for
+    compute_wait: Option<ComputeTicket>,
+    descriptors: core::DescriptorAllocator,
+    uniforms: FrameUniforms,
+    /// Glyphs queued by [`Renderer::draw_text`] and the font they come from
+    text: TextRenderer,
+    /// Lines queued with the functions of [`debug_draw`]
+    debug: debug_draw::DebugDraw,
+    /// Fraction of a day, see [`SkyState`]
+    time_of_day: f32,
+    eye: Vec3,
+    medium: Medium,
+    clear_color: [f32; 4],
+    depth_format: vk::Format,
+    msaa_samples: vk::SampleCountFlags,
+    /// Nanoseconds per timestamp tick, `None` when the graphics queue has no timestamps
+    timestamp_period: Option<f32>,
+    /// Bits of the timestamps that are valid
+    timestamp_mask: u64,
+    gpu_info: GpuInfo,
+    config: RendererConfig,
+    headless: bool,
     device: core::Device,
-    surface: core::Surface,
     instance: core::Instance,
 }
 
-struct FrameData {
+/// Command buffer used for one-off work outside of the frame loop (uploads, readbacks)
+struct ImmediateData {
     pub pool: vk::CommandPool,
     pub buffer: vk::CommandBuffer,
-
-    pub swapchain_sem: vk::Semaphore,
-    pub render_sem: vk::Semaphore,
-    pub render_fen: vk::Fence,
-}
-
-impl Default for FrameData {
-    fn default() -> Self {
-        Self {
-            pool: vk::CommandPool::null(),
-            buffer: vk::CommandBuffer::null(),
-            swapchain_sem: vk::Semaphore::null(),
-            render_sem: vk::Semaphore::null(),
-            render_fen: vk::Fence::null(),
-        }
-    }
+    pub fence: vk::Fence,
 }
 
 impl Renderer {
-    pub fn new<T>(window: &T, app_name: CString, validation: bool) -> Self
+    pub fn new<T>(window: &T, width: u32, height: u32, config: RendererConfig) -> Self
     where
         T: HasDisplayHandle + HasWindowHandle,
     {
@@ -106,16 +253,25 @@ impl Renderer {
             .unwrap()
             .to_vec();
 
-        if validation {
+        if config.validation {
             extensions.push(ash::ext::debug_utils::NAME.as_ptr());
             layers.push(c"VK_LAYER_KHRONOS_validation".as_ptr());
         }
 
+        // HDR color spaces are only reported by the surface with this extension enabled
+        if config.surface_format.is_hdr() {
+            if core::instance_extension_supported(ext::swapchain_colorspace::NAME) {
+                extensions.push(ext::swapchain_colorspace::NAME.as_ptr());
+            } else {
+                log::warn!("HDR output requested but VK_EXT_swapchain_colorspace is not available");
+            }
+        }
+
         let instance_spec = core::InstanceSpec {
-            app_name,
+            app_name: config.app_name.clone(),
             extensions,
             layers,
-            validation,
+            validation: config.validation,
         };
 
         let instance = match core::Instance::new(instance_spec) {
@@ -145,7 +301,7 @@ impl Renderer {
         ];
 
         let (gpu, graphics_family_index) =
-            match core::select_gpu(instance.handle(), &surface, &extensions) {
+            match core::select_gpu(instance.handle(), Some(&surface), &extensions) {
                 Ok(val) => val,
                 Err(err) => {
                     log::error!("GPU selection failed: {}", err);
@@ -162,48 +318,1351 @@ impl Renderer {
         };
         log::info!("Device created succesfully");
 
-        let frames = match Self::create_frames_structs(&device) {
+        let mut renderer = match Self::from_parts(instance, device, config, false) {
             Ok(val) => val,
             Err(err) => {
-                log::error!("Failed to initialize frames data: {}", err);
+                log::error!("Failed to initialize renderer: {}", err);
                 panic!();
             }
         };
 
-        Self {
-            frame_number: 0,
-            frames,
+        if let Err(err) = renderer.attach_target(Some(surface), vk::Extent2D { width, height }) {
+            log::error!("Failed to create swapchain: {}", err);
+            panic!();
+        }
+        log::info!("Swapchain created successfully");
+
+        renderer
+    }
+
+    /// Creates a renderer that draws into an offscreen image of the given size
+    /// without any window, the result can be read back with [`Renderer::read_pixels`]
+    pub fn headless(
+        width: u32,
+        height: u32,
+        config: RendererConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut layers = vec![];
+        let mut extensions = vec![];
+
+        if config.validation {
+            extensions.push(ash::ext::debug_utils::NAME.as_ptr());
+            layers.push(c"VK_LAYER_KHRONOS_validation".as_ptr());
+        }
+
+        let instance = core::Instance::new(core::InstanceSpec {
+            app_name: config.app_name.clone(),
+            extensions,
+            layers,
+            validation: config.validation,
+        })?;
+        log::info!("Vulkan instance created successfully");
+
+        let extensions = vec![
+            khr::dynamic_rendering::NAME.as_ptr(),
+            khr::synchronization2::NAME.as_ptr(),
+            khr::buffer_device_address::NAME.as_ptr(),
+            ext::descriptor_indexing::NAME.as_ptr(),
+        ];
+
+        let (gpu, graphics_family_index) = core::select_gpu(instance.handle(), None, &extensions)?;
+        let device = instance.create_device(gpu, graphics_family_index, &extensions)?;
+        log::info!("Device created succesfully");
+
+        let mut renderer = Self::from_parts(instance, device, config, true)?;
+        renderer.attach_target(None, vk::Extent2D { width, height })?;
+        Ok(renderer)
+    }
+
+    fn from_parts(
+        instance: core::Instance,
+        device: core::Device,
+        config: RendererConfig,
+        headless: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        let depth_format = core::find_supported_format(
+            instance.handle(),
+            device.gpu(),
+            &DEPTH_FORMATS,
+            vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+        )
+        .ok_or("No supported depth format")?;
+
+        let msaa_samples =
+            config::msaa_sample_count(config.effective_msaa_samples(), &device.properties().limits);
+        log::info!("Depth format {:?}, MSAA {:?}", depth_format, msaa_samples);
+
+        let families = unsafe {
+            instance
+                .handle()
+                .get_physical_device_queue_family_properties(device.gpu())
+        };
+        let timestamp_bits = families[device.graphics_family() as usize].timestamp_valid_bits;
+        let timestamp_period =
+            (timestamp_bits > 0).then_some(device.properties().limits.timestamp_period);
+        let timestamp_mask = match timestamp_bits {
+            64.. => u64::MAX,
+            bits => (1 << bits) - 1,
+        };
+
+        let gpu_info = core::GpuInfo::query(instance.handle(), device.gpu())?;
+        let immediate = Self::create_immediate_struct(&device)?;
+        let compute = AsyncCompute::new(&device)?;
+        let mut descriptors = core::DescriptorAllocator::new(
+            device.handle(),
+            DESCRIPTOR_SETS,
+            &[
+                core::PoolSizeRatio {
+                    ty: vk::DescriptorType::STORAGE_BUFFER,
+                    ratio: 4.0,
+                },
+                core::PoolSizeRatio {
+                    ty: vk::DescriptorType::STORAGE_IMAGE,
+                    ratio: 2.0,
+                },
+                core::PoolSizeRatio {
+                    ty: vk::DescriptorType::UNIFORM_BUFFER,
+                    ratio: 2.0,
+                },
+                core::PoolSizeRatio {
+                    ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    ratio: 4.0,
+                },
+            ],
+        )?;
+        let uniforms = FrameUniforms::new(&device, &mut descriptors)?;
+        let text = TextRenderer::new(&device, &mut descriptors)?;
+
+        Ok(Self {
+            targets: HashMap::new(),
+            next_surface: SurfaceId::PRIMARY,
+            frame: None,
+            output_written: false,
+            scene_resolved: false,
+            graph: RenderGraph::new(),
+            transients: TransientPool::default(),
+            frame_count: 0,
+            completed_frame: 0,
+            last_graph: None,
+            immediate,
+            compute,
+            compute_wait: None,
+            descriptors,
+            uniforms,
+            text,
+            debug: debug_draw::DebugDraw::default(),
+            time_of_day: 0.5,
+            eye: Vec3::ZERO,
+            medium: Medium::Air,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            depth_format,
+            msaa_samples,
+            timestamp_period,
+            timestamp_mask,
+            gpu_info,
+            config,
+            headless,
             instance,
-            surface,
             device,
+        })
+    }
+
+    fn create_immediate_struct(device: &core::Device) -> Result<ImmediateData, vk::Result> {
+        let pool = device.create_command_pool(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)?;
+        let buffer = device.allocate_command_buffer(pool, vk::CommandBufferLevel::PRIMARY)?;
+        let fence = device.create_fence(vk::FenceCreateFlags::empty())?;
+
+        Ok(ImmediateData {
+            pool,
+            buffer,
+            fence,
+        })
+    }
+
+    fn create_swapchain(
+        &self,
+        surface: &core::Surface,
+        extent: vk::Extent2D,
+        old: Option<&core::Swapchain>,
+    ) -> Result<core::Swapchain, Box<dyn Error>> {
+        let formats = surface.formats(self.device.gpu())?;
+        let format = self.config.surface_format.choose(&formats)?;
+        log::info!(
+            "Surface format {:?} in {:?} selected for {:?}",
+            format.format,
+            format.color_space,
+            self.config.surface_format
+        );
+
+        let present_modes = surface.present_modes(self.device.gpu())?;
+        let present_mode = self.config.present_mode.choose(&present_modes);
+        if PresentMode::from_vk(present_mode) != Some(self.config.present_mode) {
+            log::warn!(
+                "Present mode {:?} is not supported, falling back to {:?}",
+                self.config.present_mode,
+                present_mode
+            );
+        }
+
+        let spec = core::SwapchainSpec {
+            extent,
+            format,
+            present_mode,
+        };
+
+        Ok(core::Swapchain::new(
+            &self.instance,
+            &self.device,
+            surface,
+            &spec,
+            old,
+        )?)
+    }
+
+    fn attach_target(
+        &mut self,
+        surface: Option<core::Surface>,
+        extent: vk::Extent2D,
+    ) -> Result<SurfaceId, Box<dyn Error>> {
+        let swapchain = match &surface {
+            Some(surface) => Some(self.create_swapchain(surface, extent, None)?),
+            None => None,
+        };
+
+        let (draw_extent, draw_format) = match &swapchain {
+            Some(val) => (val.extent(), OutputFormat::new(val.format()).draw_format()),
+            None => (extent, DRAW_IMAGE_FORMAT),
+        };
+        let scaler = ResolutionScaler::new(&self.config.render_settings.resolution);
+        let attachments = self.attachment_spec(draw_extent, scaler.scale(), draw_format);
+        let target = RenderTarget::new(
+            &self.device,
+            surface,
+            swapchain,
+            extent,
+            attachments,
+            scaler,
+        )?;
+
+        let id = self.next_surface;
+        self.next_surface = id.next();
+        self.targets.insert(id, target);
+        Ok(id)
+    }
+
+    /// Attaches another window to the renderer, sharing the device and every resource
+    /// with the existing surfaces. Frames for it are recorded with `begin_frame(id)`.
+    pub fn add_surface<T>(
+        &mut self,
+        window: &T,
+        width: u32,
+        height: u32,
+    ) -> Result<SurfaceId, Box<dyn Error>>
+    where
+        T: HasDisplayHandle + HasWindowHandle,
+    {
+        if self.headless {
+            return Err("Cannot add a surface to a headless renderer".into());
+        }
+
+        let surface = self.instance.create_surface(window)?;
+        if !surface.support_presenting(self.device.gpu(), self.device.graphics_family())? {
+            return Err("The selected queue family cannot present to this surface".into());
+        }
+
+        let id = self.attach_target(Some(surface), vk::Extent2D { width, height })?;
+        log::info!("Surface {:?} attached", id);
+        Ok(id)
+    }
+
+    /// Destroys the swapchain and the surface, the window can be dropped afterwards
+    pub fn remove_surface(&mut self, id: SurfaceId) {
+        if let Some(mut target) = self.targets.remove(&id) {
+            self.device.wait_idle();
+            self.completed_frame = self.frame_count;
+            target.destroy(&self.device);
+            log::info!("Surface {:?} removed", id);
+        }
+    }
+
+    /// Schedules the swapchain of `id` to be recreated with the new size on the next frame
+    pub fn resize_surface(&mut self, id: SurfaceId, width: u32, height: u32) {
+        if let Some(target) = self.targets.get_mut(&id) {
+            target.extent = vk::Extent2D { width, height };
+            target.out_of_date = true;
+        }
+    }
+
+    /// Changes the present mode of every surface, the swapchains are recreated
+    /// on their next frame while the device and every other resource are kept
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        if self.config.present_mode == mode {
+            return;
+        }
+
+        self.config.present_mode = mode;
+        for target in self.targets.values_mut() {
+            target.out_of_date = true;
+        }
+    }
+
+    /// Present mode the swapchain of `id` is actually using, `None` when headless
+    pub fn present_mode(&self, id: SurfaceId) -> Option<PresentMode> {
+        let swapchain = self.targets.get(&id)?.swapchain.as_ref()?;
+        PresentMode::from_vk(swapchain.present_mode())
+    }
+
+    /// GPU time of the last finished frame of `id`, in milliseconds, `None` until one
+    /// was measured or when the device can't measure it
+    pub fn gpu_frame_time(&self, id: SurfaceId) -> Option<f32> {
+        self.targets.get(&id)?.gpu_time
+    }
+
+    /// Fraction of the output width and height the scene of `id` is drawn at, see
+    /// [`ResolutionSettings`]
+    pub fn render_scale(&self, id: SurfaceId) -> Option<f32> {
+        let attachments = &self.targets.get(&id)?.attachments;
+        Some(attachments.scene_extent.width as f32 / attachments.extent.width.max(1) as f32)
+    }
+
+    pub fn render_settings(&self) -> RenderSettings {
+        self.config.render_settings
+    }
+
+    /// Settings baked in the chunk meshes only show once the sections are meshed again
+    /// with the new settings, see [`mesher`]. The anisotropy applies from the next
+    /// [`ChunkRenderer::draw`], the resolution from the next [`Renderer::begin_frame`].
+    pub fn set_render_settings(&mut self, settings: RenderSettings) {
+        self.config.render_settings = settings;
+        self.update_msaa_samples();
+    }
+
+    /// Changes the MSAA sample count, clamped to what the device supports.
+    /// Attachments are recreated on the next frame, pipelines drawing into them
+    /// have to be recreated with [`Renderer::sample_count`]. TAA keeps the scene
+    /// single sampled, the count applies once it is deselected.
+    pub fn set_msaa_samples(&mut self, samples: u32) {
+        self.config.msaa_samples = samples;
+        self.update_msaa_samples();
+    }
+
+    fn update_msaa_samples(&mut self) {
+        let samples = config::msaa_sample_count(
+            self.config.effective_msaa_samples(),
+            &self.device.properties().limits,
+        );
+        if self.msaa_samples == samples {
+            return;
+        }
+
+        self.msaa_samples = samples;
+        for target in self.targets.values_mut() {
+            target.out_of_date = true;
+        }
+    }
+
+    fn attachment_spec(
+        &self,
+        extent: vk::Extent2D,
+        scale: f32,
+        color_format: vk::Format,
+    ) -> AttachmentSpec {
+        AttachmentSpec {
+            extent,
+            scene_extent: resolution::scaled_extent(extent, scale),
+            color_format,
+            depth_format: self.depth_format,
+            samples: self.msaa_samples,
+        }
+    }
+
+    /// Recreates the swapchain of `id` and any attachment whose spec changed
+    fn recreate_target(&mut self, id: SurfaceId) -> Result<(), Box<dyn Error>> {
+        self.device.wait_idle();
+
+        let target = &self.targets[&id];
+        let mut extent = target.extent;
+        let mut color_format = target.attachments.color_format;
+
+        if let (Some(surface), Some(old)) = (&target.surface, &target.swapchain) {
+            let swapchain = self.create_swapchain(surface, target.extent, Some(old))?;
+            extent = swapchain.extent();
+            color_format = OutputFormat::new(swapchain.format()).draw_format();
+            self.targets.get_mut(&id).unwrap().swapchain = Some(swapchain);
+        }
+
+        let scale = self.targets[&id].scaler.scale();
+        let spec = self.attachment_spec(extent, scale, color_format);
+        let target = self.targets.get_mut(&id).unwrap();
+        target.out_of_date = false;
+        if target.attachments != spec {
+            target.create_attachments(&self.device, spec)?;
+        }
+
+        log::trace!("Surface {:?} recreated", id);
+        Ok(())
+    }
+
+    fn target(&self, id: SurfaceId) -> Result<&RenderTarget, Box<dyn Error>> {
+        self.targets
+            .get(&id)
+            .ok_or_else(|| format!("Unknown surface {:?}", id).into())
+    }
+
+    /// GPU picked when the renderer was created and its driver
+    pub fn gpu_info(&self) -> &GpuInfo {
+        &self.gpu_info
+    }
+
+    /// Device memory allocated by the renderer and its clients so far
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.device.memory_usage()
+    }
+
+    /// Draw commands recorded by the last submitted frame, counted by its passes with
+    /// [`PassContext::count_draws`]
+    pub fn draw_calls(&self) -> u32 {
+        self.last_graph
+            .as_ref()
+            .map_or(0, |graph| graph.draw_calls())
+    }
+
+    pub fn device_limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.device.properties().limits
+    }
+
+    /// Highest anisotropy samplers can use, 1 when the device doesn't support it
+    pub fn max_anisotropy(&self) -> f32 {
+        self.device.max_anisotropy()
+    }
+
+    /// Logical device handle, used by the client to create its own pipelines
+    pub fn device_handle(&self) -> &ash::Device {
+        self.device.handle()
+    }
+
+    /// Waits for every frame in flight, resources can be destroyed afterwards
+    pub fn wait_idle(&self) {
+        self.device.wait_idle();
+    }
+
+    /// Frame being recorded, between [`Renderer::begin_frame`] and [`Renderer::end_frame`]
+    pub fn frame(&self) -> Option<Frame> {
+        self.frame
+    }
+
+    pub fn draw_extent(&self, id: SurfaceId) -> Option<vk::Extent2D> {
+        Some(self.targets.get(&id)?.draw_image().extent_2d())
+    }
+
+    /// Format of the HDR image frames for `id` are rendered into, pipelines drawing
+    /// in [`Renderer::begin_frame`] must use it as their color attachment format
+    pub fn draw_format(&self, id: SurfaceId) -> Option<vk::Format> {
+        Some(self.targets.get(&id)?.scene_image().format)
+    }
+
+    /// Format of [`Frame::output`], pipelines of [`Renderer::draw_output`] passes must
+    /// use it as their color attachment format
+    pub fn output_image_format(&self, id: SurfaceId) -> Option<vk::Format> {
+        Some(self.targets.get(&id)?.draw_image().format)
+    }
+
+    /// Format and color space of the swapchain of `id`, `None` when headless
+    pub fn output_format(&self, id: SurfaceId) -> Option<OutputFormat> {
+        let swapchain = self.targets.get(&id)?.swapchain.as_ref()?;
+        Some(OutputFormat::new(swapchain.format()))
+    }
+
+    pub fn depth_format(&self) -> vk::Format {
+        self.depth_format
+    }
+
+    /// Sample count of the color and depth attachments used in [`Renderer::begin_frame`]
+    pub fn sample_count(&self) -> vk::SampleCountFlags {
+        self.msaa_samples
+    }
+
+    pub fn reverse_z(&self) -> bool {
+        self.config.reverse_z
+    }
+
+    /// Depth compare op pipelines should use, depends on [`RendererConfig::reverse_z`]
+    pub fn depth_compare_op(&self) -> vk::CompareOp {
+        if self.config.reverse_z {
+            vk::CompareOp::GREATER_OR_EQUAL
+        } else {
+            vk::CompareOp::LESS_OR_EQUAL
+        }
+    }
+
+    /// Value the depth buffer is cleared to, the farthest possible depth
+    pub fn depth_clear_value(&self) -> f32 {
+        if self.config.reverse_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = color;
+    }
+
+    /// Moves the sun and the moon, `time` is a fraction of a day wrapping at 1 (0.5 is
+    /// noon, see [`SkyState`]). Applies from the next [`Renderer::begin_frame`].
+    pub fn set_time_of_day(&mut self, time: f32) {
+        self.time_of_day = time.rem_euclid(1.0);
+    }
+
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    /// Camera position the fog is measured from and what the camera is in, applies
+    /// from the next [`Renderer::begin_frame`]
+    pub fn set_camera(&mut self, eye: Vec3, medium: Medium) {
+        self.eye = eye;
+        self.medium = medium;
+    }
+
+    /// Sun, moon and sky colors of the current time of day
+    pub fn sky_state(&self) -> SkyState {
+        SkyState::at(self.time_of_day)
+    }
+
+    /// Layout of the set holding the uniforms shared by every pass of a frame (time of
+    /// day, sky colors, fog), pipelines include it to bind [`Renderer::frame_set`].
+    /// Shaders declare it by including `frame.glsl` and fog their surfaces with
+    /// `apply_fog`.
+    pub fn frame_set_layout(&self) -> vk::DescriptorSetLayout {
+        self.uniforms.set_layout()
+    }
+
+    /// Set of the frame uniforms, every pass added with [`Renderer::draw`] waits for
+    /// them to be uploaded
+    pub fn frame_set(&self) -> vk::DescriptorSet {
+        self.uniforms.set()
+    }
+
+    /// Render graph of the frame being recorded, passes added between
+    /// [`Renderer::begin_frame`] and [`Renderer::end_frame`] run in that frame
+    pub fn graph(&mut self) -> &mut RenderGraph {
+        &mut self.graph
+    }
+
+    /// Graphviz dump of the last compiled frame graph
+    pub fn graph_dot(&self) -> Option<String> {
+        Some(self.last_graph.as_ref()?.to_dot())
+    }
+
+    /// Waits for the next frame slot of surface `id` to be free and starts building its
+    /// graph. The attachments are imported and cleared by a first pass, the returned
+    /// [`Frame`] holds their ids to declare further passes against.
+    pub fn begin_frame(&mut self, id: SurfaceId) -> Result<Frame, Box<dyn Error>> {
+        let target = self.target(id)?;
+        let frame = target.current_frame();
+        self.device.wait_fence(frame.render_fen, FENCE_TIMEOUT)?;
+
+        // the frame that used this slot is done, its time picks the scale of this one
+        let gpu_time = self.read_gpu_time(frame);
+        self.completed_frame = self.completed_frame.max(frame.submitted);
+        let settings = self.config.render_settings.resolution;
+        let target = self.targets.get_mut(&id).unwrap();
+        if gpu_time.is_some() {
+            target.gpu_time = gpu_time;
+        }
+        let scale = target.scaler.update(&settings, gpu_time);
+        let spec = &target.attachments;
+        if resolution::scaled_extent(spec.extent, scale) != spec.scene_extent {
+            log::trace!("Surface {:?} drawn at {:.2} of its size", id, scale);
+            target.out_of_date = true;
+        }
+
+        if target.out_of_date {
+            self.recreate_target(id)?;
         }
+
+        let mut image_index = 0;
+        if let Some(swapchain) = &self.targets[&id].swapchain {
+            let frame = self.targets[&id].current_frame();
+            image_index = match swapchain.acquire_next_image(frame.swapchain_sem, FENCE_TIMEOUT) {
+                Ok((index, suboptimal)) => {
+                    self.targets.get_mut(&id).unwrap().out_of_date |= suboptimal;
+                    index
+                }
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.recreate_target(id)?;
+                    let target = &self.targets[&id];
+                    let swapchain = target.swapchain.as_ref().unwrap();
+                    let frame = target.current_frame();
+                    swapchain
+                        .acquire_next_image(frame.swapchain_sem, FENCE_TIMEOUT)?
+                        .0
+                }
+                Err(err) => return Err(err.into()),
+            };
+        }
+
+        let depth_clear_value = self.depth_clear_value();
+        let sky = self.sky_state();
+        let settings = self.config.render_settings;
+        let inputs = FrameInputs {
+            fog: FogState::resolve(&settings.fog, settings.render_distance, self.medium, &sky),
+            sky,
+            eye: self.eye,
+            medium: self.medium,
+        };
+        let target = self.targets.get_mut(&id).unwrap();
+        target.image_index = image_index;
+        self.device.reset_fence(target.current_frame().render_fen)?;
+
+        // contents of the attachments are never kept across frames
+        self.graph.clear();
+        let output = self.graph.import_image(
+            "draw image",
+            ImportedImage::from_allocated(target.draw_image(), vk::SampleCountFlags::TYPE_1),
+        );
+        let resolved = self.graph.import_image(
+            "scene image",
+            ImportedImage::from_allocated(target.scene_image(), vk::SampleCountFlags::TYPE_1),
+        );
+        let depth = self.graph.import_image(
+            "depth image",
+            ImportedImage::from_allocated(target.depth_image(), self.msaa_samples),
+        );
+        let color = match &target.msaa_image {
+            Some(msaa_image) => self.graph.import_image(
+                "msaa image",
+                ImportedImage::from_allocated(msaa_image, self.msaa_samples),
+            ),
+            None => resolved,
+        };
+
+        let uniforms = self.uniforms.upload(&mut self.graph, &inputs);
+
+        let frame = Frame {
+            surface: id,
+            extent: target.scene_image().extent_2d(),
+            output_extent: target.draw_image().extent_2d(),
+            color,
+            depth,
+            resolved,
+            output,
+            uniforms,
+        };
+
+        let clear_color = vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: self.clear_color,
+            },
+        };
+        let clear_depth = vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: depth_clear_value,
+                stencil: 0,
+            },
+        };
+
+        self.graph
+            .add_pass("clear")
+            .write_image(color, ImageUsage::ColorAttachment)
+            .write_image(depth, ImageUsage::DepthAttachment)
+            .execute(move |ctx| {
+                ctx.begin_rendering(
+                    &[Attachment::clear(color, clear_color)],
+                    Some(Attachment::clear(depth, clear_depth)),
+                );
+                ctx.end_rendering();
+            });
+
+        self.frame = Some(frame);
+        self.output_written = false;
+        self.scene_resolved = false;
+        self.text.clear();
+        Ok(frame)
+    }
+
+    /// Adds a pass drawing into the color and depth attachments of the current frame,
+    /// `function` is called inside a dynamic rendering instance with the viewport set
+    pub fn draw<F>(&mut self, name: &str, function: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&PassContext) + 'static,
+    {
+        self.draw_reading(name, &[], function)
+    }
+
+    /// Same as [`Renderer::draw`] for a pass that also reads `buffers`, like the indirect
+    /// draws written by a culling pass
+    pub fn draw_reading<F>(
+        &mut self,
+        name: &str,
+        buffers: &[(BufferId, BufferUsage)],
+        function: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&PassContext) + 'static,
+    {
+        self.draw_sampling(name, buffers, &[], function)
+    }
+
+    /// Same as [`Renderer::draw_reading`] for a pass that also samples `images`, like
+    /// shadow maps rendered earlier in the frame
+    pub fn draw_sampling<F>(
+        &mut self,
+        name: &str,
+        buffers: &[(BufferId, BufferUsage)],
+        images: &[ImageId],
+        function: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&PassContext) + 'static,
+    {
+        let frame = self.frame.ok_or("draw called without begin_frame")?;
+
+        let mut pass = self
+            .graph
+            .add_pass(name)
+            .read_buffer(frame.uniforms, BufferUsage::Uniform);
+        for (buffer, usage) in buffers {
+            pass = pass.read_buffer(*buffer, *usage);
+        }
+        for image in images {
+            pass = pass.read_image(*image, ImageUsage::Sampled);
+        }
+
+        pass.write_image(frame.color, ImageUsage::ColorAttachment)
+            .write_image(frame.depth, ImageUsage::DepthAttachment)
+            .execute(move |ctx| {
+                ctx.begin_rendering(
+                    &[Attachment::load(frame.color)],
+                    Some(Attachment::load(frame.depth)),
+                );
+                ctx.set_viewport(frame.extent);
+                function(ctx);
+                ctx.end_rendering();
+            });
+
+        Ok(())
+    }
+
+    /// Adds a pass drawing over the whole [`Frame::output`] with `images` sampled, like
+    /// the last pass of a post-processing chain. The previous contents of the output
+    /// are undefined and end_frame doesn't copy the resolved scene into it anymore.
+    pub fn draw_output<F>(
+        &mut self,
+        name: &str,
+        images: &[ImageId],
+        function: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&PassContext) + 'static,
+    {
+        let frame = self.frame.ok_or("draw_output called without begin_frame")?;
+
+        let mut pass = self
+            .graph
+            .add_pass(name)
+            .read_buffer(frame.uniforms, BufferUsage::Uniform);
+        for image in images {
+            pass = pass.read_image(*image, ImageUsage::Sampled);
+        }
+
+        pass.write_image(frame.output, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                let attachment = Attachment {
+                    image: frame.output,
+                    load: LoadOp::DontCare,
+                    store: true,
+                    resolve: None,
+                };
+                ctx.begin_rendering(&[attachment], None);
+                ctx.set_viewport(frame.output_extent);
+                function(ctx);
+                ctx.end_rendering();
+            });
+
+        self.output_written = true;
+        Ok(())
+    }
+
+    /// Adds a pass drawing over [`Frame::output`] at the size of the surface, whatever
+    /// the scale of the scene, like the UI. It must come after post-processing, the
+    /// scene is copied into the output first if nothing wrote it yet.
+    pub fn draw_overlay<F>(
+        &mut self,
+        name: &str,
+        images: &[ImageId],
+        function: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&PassContext) + 'static,
+    {
+        let frame = self
+            .frame
+            .ok_or("draw_overlay called without begin_frame")?;
+        if !self.output_written {
+            self.add_output_copy(&frame);
+        }
+
+        let mut pass = self
+            .graph
+            .add_pass(name)
+            .read_buffer(frame.uniforms, BufferUsage::Uniform);
+        for image in images {
+            pass = pass.read_image(*image, ImageUsage::Sampled);
+        }
+
+        pass.write_image(frame.output, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                ctx.begin_rendering(&[Attachment::load(frame.output)], None);
+                ctx.set_viewport(frame.output_extent);
+                function(ctx);
+                ctx.end_rendering();
+            });
+
+        Ok(())
+    }
+
+    /// Uploads `font` and draws the text of the next frames with it, waits for the device
+    pub fn set_font(&mut self, font: Font) -> Result<(), Box<dyn Error>> {
+        let image = text::upload_atlas(self, &font)?;
+        self.device.wait_idle();
+        self.text.set_font(&self.device, font, image)
+    }
+
+    /// Font set with [`Renderer::set_font`]
+    pub fn font(&self) -> Option<&Font> {
+        self.text.font()
+    }
+
+    /// Queues `text` with its top left corner at `position`, in pixels of the output.
+    /// All the text of a frame is drawn in a single draw at its end, over the overlays.
+    pub fn draw_text(
+        &mut self,
+        position: Vec2,
+        text: &str,
+        style: &TextStyle,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.frame.is_none() {
+            return Err("draw_text called without begin_frame".into());
+        }
+        self.text.queue(position, text, style)
+    }
+
+    /// Size `text` takes when drawn at `scale`, in pixels, `None` without a font
+    pub fn measure_text(&self, text: &str, scale: f32) -> Option<Vec2> {
+        self.text.measure(text, scale)
+    }
+
+    /// Adds the pass resolving the MSAA samples of the scene into [`Frame::resolved`],
+    /// passes sampling the scene have to come after it. Passes drawing into
+    /// [`Frame::color`] afterwards don't reach the resolved scene anymore. Without MSAA the
+    /// scene is drawn in the resolved image and there's nothing to do.
+    pub fn resolve_scene(&mut self) -> Result<ImageId, Box<dyn Error>> {
+        let frame = self
+            .frame
+            .ok_or("resolve_scene called without begin_frame")?;
+        if !self.scene_resolved {
+            self.add_resolve_pass(&frame);
+            self.scene_resolved = true;
+        }
+        Ok(frame.resolved)
+    }
+
+    fn add_resolve_pass(&mut self, frame: &Frame) {
+        if frame.color == frame.resolved {
+            return;
+        }
+
+        // an empty rendering instance is enough for the samples to be averaged
+        let (color, resolved) = (frame.color, frame.resolved);
+        self.graph
+            .add_pass("resolve")
+            .read_image(color, ImageUsage::ColorAttachment)
+            .write_image(resolved, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                let attachment = Attachment {
+                    image: color,
+                    load: LoadOp::Load,
+                    store: false,
+                    resolve: Some(resolved),
+                };
+                ctx.begin_rendering(&[attachment], None);
+                ctx.end_rendering();
+            });
+    }
+
+    /// Copies the resolved scene into the output, without post-processing the HDR scene
+    /// is clamped and a scaled one upscaled by the linear filter of the blit
+    fn add_output_copy(&mut self, frame: &Frame) {
+        if !self.scene_resolved {
+            self.add_resolve_pass(frame);
+            self.scene_resolved = true;
+        }
+
+        let (resolved, output) = (frame.resolved, frame.output);
+        self.graph
+            .add_pass("copy to output")
+            .read_image(resolved, ImageUsage::TransferSrc)
+            .write_image(output, ImageUsage::TransferDst)
+            .execute(move |ctx| {
+                let src = ctx.image(resolved);
+                let dst = ctx.image(output);
+                core::blit_image(
+                    ctx.device, ctx.cmd, src.image, dst.image, src.extent, dst.extent,
+                );
+            });
+        self.output_written = true;
+    }
+
+    /// Uploads the glyphs queued during the frame and draws them over the output
+    fn add_text_pass(&mut self, frame: &Frame) -> Result<(), Box<dyn Error>> {
+        let count = self.text.glyphs().len() as u32;
+        let size = size_of_val(self.text.glyphs()) as vk::DeviceSize;
+        let staging = self.transients.stage(
+            &self.device,
+            bytemuck::cast_slice(self.text.glyphs()),
+            self.frame_count + 1,
+        )?;
+        self.text.clear();
+
+        let format = self.targets[&frame.surface].draw_image().format;
+        let pipeline = self.text.pipeline(&self.device, format)?;
+        let (buffer, address) = self.text.buffer(&self.device, count as usize)?;
+        let (layout, set) = (self.text.layout(), self.text.set());
+        let extent = frame.output_extent;
+        let constants = TextConstants {
+            glyphs: address,
+            screen_size: [extent.width as f32, extent.height as f32],
+            encoding: OutputEncoding::shader_id(
+                self.output_format(frame.surface).map(|val| val.encoding),
+            ),
+            _pad: 0,
+        };
+
+        let glyphs = self.graph.import_buffer("glyphs", buffer);
+        self.graph
+            .add_pass("upload glyphs")
+            .write_buffer(glyphs, BufferUsage::TransferDst)
+            .execute(move |ctx| {
+                let region = vk::BufferCopy::default().size(size);
+                unsafe {
+                    ctx.device
+                        .cmd_copy_buffer(ctx.cmd, staging, ctx.buffer(glyphs), &[region])
+                };
+            });
+
+        let output = frame.output;
+        self.graph
+            .add_pass("text")
+            .read_buffer(glyphs, BufferUsage::Storage)
+            .write_image(output, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                ctx.begin_rendering(&[Attachment::load(output)], None);
+                ctx.set_viewport(extent);
+                unsafe {
+                    ctx.device.cmd_bind_pipeline(
+                        ctx.cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    );
+                    ctx.device.cmd_bind_descriptor_sets(
+                        ctx.cmd,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        layout,
+                        0,
+                        &[set],
+                        &[],
+                    );
+                    ctx.device.cmd_push_constants(
+                        ctx.cmd,
+                        layout,
+                        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                        0,
+                        bytemuck::bytes_of(&constants),
+                    );
+                    // one strip of 4 vertices per glyph
+                    ctx.device.cmd_draw(ctx.cmd, 4, count, 0, 0);
+                }
+                ctx.count_draws(1);
+                ctx.end_rendering();
+            });
+
+        Ok(())
+    }
+
+    /// GPU time of the last submission of `frame` in milliseconds, its fence must have
+    /// been waited on
+    fn read_gpu_time(&self, frame: &FrameData) -> Option<f32> {
+        let period = self.timestamp_period?;
+        if !frame.timestamps_written {
+            return None;
+        }
+
+        let mut ticks = [0u64; FRAME_TIMESTAMPS as usize];
+        unsafe {
+            self.device.handle().get_query_pool_results(
+                frame.timestamps,
+                0,
+                &mut ticks,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        }
+        .ok()?;
+
+        let elapsed = ticks[1].wrapping_sub(ticks[0]) & self.timestamp_mask;
+        Some(elapsed as f32 * period / 1_000_000.0)
     }
 
-    fn create_frames_structs(device: &core::Device) -> Result<[FrameData; 2], vk::Result> {
-        // Init frames data
-        let mut frames: [FrameData; MAX_FRAMES_IN_FLIGHT] =
-            [FrameData::default(), FrameData::default()];
+    /// Compiles and records the frame graph, submits it and presents the frame if the
+    /// surface has a swapchain
+    pub fn end_frame(&mut self) -> Result<(), Box<dyn Error>> {
+        let frame = self
+            .frame
+            .take()
+            .ok_or("end_frame called without begin_frame")?;
 
-        for frame in &mut frames {
-            let pool =
-                device.create_command_pool(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)?;
-            let buffer = device.allocate_command_buffer(pool, vk::CommandBufferLevel::PRIMARY)?;
-            let swapchain_sem = device.create_semaphore(vk::SemaphoreCreateFlags::default())?;
-            let render_sem = device.create_semaphore(vk::SemaphoreCreateFlags::default())?;
-            let render_fen = device.create_fence(vk::FenceCreateFlags::SIGNALED)?;
+        if !self.scene_resolved {
+            self.add_resolve_pass(&frame);
+        }
+
+        if !self.output_written {
+            self.add_output_copy(&frame);
+        }
+        if !self.text.glyphs().is_empty() {
+            self.add_text_pass(&frame)?;
+        }
+
+        let target = self.targets.get_mut(&frame.surface).unwrap();
+        let device = self.device.handle();
+        let frame_data = target.current_frame();
+        let cmd = frame_data.buffer;
+
+        match &target.swapchain {
+            Some(swapchain) => {
+                let swapchain_image = self.graph.import_image(
+                    "swapchain image",
+                    ImportedImage {
+                        image: swapchain.image(target.image_index),
+                        view: vk::ImageView::null(),
+                        format: swapchain.format().format,
+                        extent: swapchain.extent(),
+                        samples: vk::SampleCountFlags::TYPE_1,
+                        // the acquire semaphore is waited on at this stage
+                        initial: ResourceState {
+                            stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                            access: vk::AccessFlags2::NONE,
+                            layout: vk::ImageLayout::UNDEFINED,
+                        },
+                        final_layout: Some(vk::ImageLayout::PRESENT_SRC_KHR),
+                    },
+                );
+                self.graph.mark_image_output(swapchain_image);
+
+                self.graph
+                    .add_pass("present")
+                    .read_image(frame.output, ImageUsage::TransferSrc)
+                    .write_image(swapchain_image, ImageUsage::TransferDst)
+                    .execute(move |ctx| {
+                        let src = ctx.image(frame.output);
+                        let dst = ctx.image(swapchain_image);
+                        core::blit_image(
+                            ctx.device, ctx.cmd, src.image, dst.image, src.extent, dst.extent,
+                        );
+                    });
+            }
+            None => self.graph.mark_image_output(frame.output),
+        }
+
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        let timestamps = self.timestamp_period.map(|_| frame_data.timestamps);
+        unsafe {
+            device.reset_command_buffer(cmd, vk::CommandBufferResetFlags::empty())?;
+            device.begin_command_buffer(cmd, &begin_info)?;
+            if let Some(pool) = timestamps {
+                device.cmd_reset_query_pool(cmd, pool, 0, FRAME_TIMESTAMPS);
+                device.cmd_write_timestamp2(cmd, vk::PipelineStageFlags2::TOP_OF_PIPE, pool, 0);
+            }
+        }
+
+        self.frame_count += 1;
+        let summary =
+            self.graph
+                .execute(&self.device, &mut self.transients, self.frame_count, cmd)?;
+
+        unsafe {
+            if let Some(pool) = timestamps {
+                device.cmd_write_timestamp2(cmd, vk::PipelineStageFlags2::BOTTOM_OF_PIPE, pool, 1);
+            }
+            device.end_command_buffer(cmd)?
+        };
+
+        let cmd_infos = [vk::CommandBufferSubmitInfo::default().command_buffer(cmd)];
+        let mut wait_infos = vec![];
+        if target.swapchain.is_some() {
+            wait_infos.push(
+                vk::SemaphoreSubmitInfo::default()
+                    .semaphore(frame_data.swapchain_sem)
+                    .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT),
+            );
+        }
+        if let Some(ticket) = self.compute_wait.take() {
+            wait_infos.push(
+                vk::SemaphoreSubmitInfo::default()
+                    .semaphore(self.compute.timeline)
+                    .value(ticket.0)
+                    .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+            );
+        }
+        let signal_infos = [vk::SemaphoreSubmitInfo::default()
+            .semaphore(frame_data.render_sem)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)];
+
+        let mut submit = vk::SubmitInfo2::default()
+            .command_buffer_infos(&cmd_infos)
+            .wait_semaphore_infos(&wait_infos);
+        if target.swapchain.is_some() {
+            submit = submit.signal_semaphore_infos(&signal_infos);
+        }
+
+        unsafe {
+            device.queue_submit2(
+                self.device.graphics_queue(),
+                &[submit],
+                frame_data.render_fen,
+            )?
+        };
+
+        if let Some(swapchain) = &target.swapchain {
+            let queue = self.device.graphics_queue();
+            match swapchain.present(queue, target.image_index, frame_data.render_sem) {
+                Ok(suboptimal) => target.out_of_date |= suboptimal,
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => target.out_of_date = true,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        target.draw_layout = summary.final_layout(frame.output);
+        target.current_frame_mut().timestamps_written = timestamps.is_some();
+        target.current_frame_mut().submitted = self.frame_count;
+        target.frame_number += 1;
+        self.debug.clear();
+        self.transients
+            .collect(&self.device, self.frame_count, self.completed_frame);
+        self.last_graph = Some(summary);
+        Ok(())
+    }
+
+    pub fn create_buffer(
+        &self,
+        name: &str,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: MemoryLocation,
+    ) -> Result<AllocatedBuffer, Box<dyn Error>> {
+        self.device.create_buffer(name, size, usage, location)
+    }
+
+    /// The buffer must not be in use by a frame in flight
+    pub fn destroy_buffer(&self, buffer: AllocatedBuffer) {
+        self.device.destroy_buffer(buffer);
+    }
+
+    /// Device address of a buffer created with `SHADER_DEVICE_ADDRESS` usage
+    pub fn buffer_address(&self, buffer: &AllocatedBuffer) -> vk::DeviceAddress {
+        self.device.buffer_address(buffer.handle)
+    }
+
+    /// Copies `data` in a host visible buffer to be copied from by a pass of the frame
+    /// being recorded, the buffer is destroyed once that frame is done
+    pub fn stage(&mut self, data: &[u8]) -> Result<vk::Buffer, Box<dyn Error>> {
+        if self.frame.is_none() {
+            return Err("stage called without begin_frame".into());
+        }
+        self.transients
+            .stage(&self.device, data, self.frame_count + 1)
+    }
+
+    /// Frames submitted so far across every surface
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Latest frame the GPU is known to be done with, across every surface. Frames are
+    /// submitted to one queue so waiting for a frame covers every frame before it, what
+    /// a frame used can be freed once it's reached.
+    pub fn completed_frame(&self) -> u64 {
+        self.completed_frame
+    }
+
+    pub fn create_image(&self, spec: &ImageSpec) -> Result<AllocatedImage, Box<dyn Error>> {
+        self.device.create_image(spec)
+    }
+
+    /// The image must not be in use by a frame in flight
+    pub fn destroy_image(&self, image: AllocatedImage) {
+        self.device.destroy_image(image);
+    }
+
+    pub fn create_compute_pipeline(
+        &self,
+        spec: &ComputePipelineSpec,
+    ) -> Result<ComputePipeline, vk::Result> {
+        ComputePipeline::new(self.device.handle(), spec)
+    }
+
+    /// The pipeline must not be in use by a frame in flight
+    pub fn destroy_compute_pipeline(&self, pipeline: ComputePipeline) {
+        pipeline.destroy(self.device.handle());
+    }
+
+    /// Allocates a set from the renderer pools, it lives as long as the renderer
+    pub fn allocate_descriptor_set(
+        &mut self,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        self.descriptors.allocate(self.device.handle(), layout)
+    }
+
+    /// Whether [`Renderer::dispatch_async`] runs on a queue separate from the graphics one
+    pub fn has_async_compute(&self) -> bool {
+        self.device.has_async_compute()
+    }
 
-            frame.pool = pool;
-            frame.buffer = buffer;
-            frame.render_fen = render_fen;
-            frame.render_sem = render_sem;
-            frame.swapchain_sem = swapchain_sem;
+    /// Records `function` and submits it to the compute queue right away, without waiting
+    /// for it. Resources it writes must not be used by a frame until it's done, see
+    /// [`Renderer::frame_wait_compute`] and [`Renderer::wait_compute`].
+    pub fn dispatch_async<F>(&mut self, function: F) -> Result<ComputeTicket, vk::Result>
+    where
+        F: FnOnce(&PassContext),
+    {
+        self.compute.submit(&self.device, FENCE_TIMEOUT, function)
+    }
+
+    /// Blocks until the compute work of `ticket` is done
+    pub fn wait_compute(&self, ticket: ComputeTicket) -> Result<(), vk::Result> {
+        self.compute.wait(&self.device, ticket, FENCE_TIMEOUT)
+    }
+
+    /// Makes the GPU wait for the compute work of `ticket` before running the frame
+    /// being recorded
+    pub fn frame_wait_compute(&mut self, ticket: ComputeTicket) -> Result<(), Box<dyn Error>> {
+        if self.frame.is_none() {
+            return Err("frame_wait_compute called without begin_frame".into());
         }
 
-        Ok(frames)
+        // submissions complete in order, waiting on the latest one covers the others
+        self.compute_wait = self.compute_wait.max(Some(ticket));
+        Ok(())
     }
 
-    fn get_current_frame(&self) -> &FrameData {
-        &self.frames[self.frame_number % MAX_FRAMES_IN_FLIGHT]
+    /// Records `function` in a throwaway command buffer, submits it and waits for completion
+    pub fn immediate_submit<F>(&self, function: F) -> Result<(), vk::Result>
+    where
+        F: FnOnce(vk::CommandBuffer),
+    {
+        let device = self.device.handle();
+        let cmd = self.immediate.buffer;
+
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            device.reset_command_buffer(cmd, vk::CommandBufferResetFlags::empty())?;
+            device.begin_command_buffer(cmd, &begin_info)?;
+        }
+
+        function(cmd);
+
+        unsafe { device.end_command_buffer(cmd)? };
+
+        let cmd_infos = [vk::CommandBufferSubmitInfo::default().command_buffer(cmd)];
+        let submit = vk::SubmitInfo2::default().command_buffer_infos(&cmd_infos);
+
+        unsafe {
+            device.queue_submit2(
+                self.device.graphics_queue(),
+                &[submit],
+                self.immediate.fence,
+            )?
+        };
+
+        self.device
+            .wait_fence(self.immediate.fence, FENCE_TIMEOUT)?;
+        self.device.reset_fence(self.immediate.fence)
+    }
+
+    /// Copies the last frame rendered for `id` back to the host as tightly packed RGBA8 rows.
+    /// Meant for tests and screenshots, it stalls the whole device.
+    pub fn read_pixels(&mut self, id: SurfaceId) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.device.wait_idle();
+        self.completed_frame = self.frame_count;
+
+        let target = self.target(id)?;
+        let draw_image = target.draw_image();
+        let draw_layout = target.draw_layout;
+        if draw_image.format != DRAW_IMAGE_FORMAT {
+            return Err(format!("Cannot read back {:?} pixels", draw_image.format).into());
+        }
+        let extent = draw_image.extent_2d();
+        let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4;
+
+        let readback = self.device.create_buffer(
+            "readback",
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuToCpu,
+        )?;
+
+        let result = self.immediate_submit(|cmd| {
+            let device = self.device.handle();
+
+            core::transition_image(
+                device,
+                cmd,
+                draw_image.handle,
+                vk::ImageAspectFlags::COLOR,
+                draw_layout,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+
+            let region = vk::BufferImageCopy::default()
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .layer_count(1),
+                )
+                .image_extent(draw_image.extent);
+
+            unsafe {
+                device.cmd_copy_image_to_buffer(
+                    cmd,
+                    draw_image.handle,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    readback.handle,
+                    &[region],
+                )
+            };
+
+            core::transition_image(
+                device,
+                cmd,
+                draw_image.handle,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                draw_layout,
+            );
+        });
+
+        let pixels = readback
+            .mapped()
+            .map(|data| data[..readback.size as usize].to_vec());
+        self.device.destroy_buffer(readback);
+        result?;
+
+        pixels.ok_or_else(|| "Readback buffer is not host visible".into())
     }
 }
 
@@ -212,11 +1671,17 @@ impl Drop for Renderer {
         log::trace!("Destroying Renderer");
         self.device.wait_idle();
 
-        for frame in &self.frames {
-            self.device.destroy_command_pool(frame.pool);
-            self.device.destroy_semaphore(frame.swapchain_sem);
-            self.device.destroy_semaphore(frame.render_sem);
-            self.device.destroy_fence(frame.render_fen);
+        for target in self.targets.values_mut() {
+            target.destroy(&self.device);
         }
+        self.transients.destroy(&self.device);
+
+        self.device.destroy_command_pool(self.immediate.pool);
+        self.device.destroy_fence(self.immediate.fence);
+        self.compute.destroy(&self.device);
+        self.uniforms.destroy(&self.device);
+        self.text.destroy(&self.device);
+        self.debug.destroy(&self.device);
+        self.descriptors.destroy_pools(self.device.handle());
     }
 }
@@ -0,0 +1,92 @@
+use super::{
+    face_key, face_plane, slice_block, BlockTypes, FaceKey, Quad, SectionBlocks, SectionMeshes,
+    SECTION_SIZE,
+};
+use crate::{Face, RenderSettings};
+
+const SIZE: usize = SECTION_SIZE as usize;
+
+/// Meshes the visible block faces, merging neighbouring faces of every slice of the
+/// section into rectangles when they look the same. Faces whose corners are shaded
+/// differently stay single quads, merging them would change the interpolation.
+pub fn mesh_greedy(
+    section: &SectionBlocks,
+    blocks: &impl BlockTypes,
+    settings: &RenderSettings,
+) -> SectionMeshes {
+    let mut mesh = SectionMeshes::default();
+    // visible faces of the current slice, indexed by [v][u]
+    let mut mask: [[Option<FaceKey>; SIZE]; SIZE] = [[None; SIZE]; SIZE];
+
+    for face in Face::ALL {
+        for slice in 0..SECTION_SIZE {
+            let plane = face_plane(face, slice);
+            let mut visible = false;
+
+            for (v, row) in mask.iter_mut().enumerate() {
+                for (u, cell) in row.iter_mut().enumerate() {
+                    let pos = slice_block(face, plane, u as i32, v as i32);
+                    *cell = face_key(section, blocks, settings, pos, face);
+                    visible |= cell.is_some();
+                }
+            }
+
+            if visible {
+                merge_slice(&mut mesh, &mut mask, face, plane as u8);
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Emits the faces of `mask` as the largest rectangles found scanning it row by row,
+/// clearing it along the way
+fn merge_slice(
+    mesh: &mut SectionMeshes,
+    mask: &mut [[Option<FaceKey>; SIZE]; SIZE],
+    face: Face,
+    plane: u8,
+) {
+    for v in 0..SIZE {
+        let mut u = 0;
+        while u < SIZE {
+            let Some(key) = mask[v][u] else {
+                u += 1;
+                continue;
+            };
+
+            let (mut width, mut height) = (1, 1);
+            if key.shading.is_uniform() {
+                while u + width < SIZE && mask[v][u + width] == Some(key) {
+                    width += 1;
+                }
+                while v + height < SIZE
+                    && mask[v + height][u..u + width]
+                        .iter()
+                        .all(|cell| *cell == Some(key))
+                {
+                    height += 1;
+                }
+            }
+
+            for row in &mut mask[v..v + height] {
+                row[u..u + width].fill(None);
+            }
+
+            mesh.push_quad(
+                key.render_layer,
+                &Quad {
+                    face,
+                    plane,
+                    start: [u as u8, v as u8],
+                    size: [width as u8, height as u8],
+                    layer: key.layer,
+                    emission: key.emission,
+                    shading: key.shading,
+                },
+            );
+            u += width;
+        }
+    }
+}
@@ -0,0 +1,857 @@
+//! CPU meshing of chunk sections into [`PackedVertex`]es for the
+//! [`GeometryArena`](crate::GeometryArena).
+//!
+//! [`mesh_greedy`] merges coplanar neighbouring faces into large quads and is the one
+//! to use, [`mesh_naive`] emits a quad per visible block face and is kept as the
+//! reference the greedy mesher is tested against.
+//!
+//! Every [`RenderLayer`] gets its own mesh, translucent meshes have to be sorted back to
+//! front with [`SectionMesh::sort_back_to_front`] whenever the camera enters another
+//! block, see [`SortTrigger`]. Meshes handed to
+//! [`ChunkRenderer::set_translucent`](crate::ChunkRenderer::set_translucent) are sorted
+//! that way by the renderer.
+
+mod greedy;
+mod naive;
+
+use glam::{IVec3, Vec3};
+
+use crate::{Face, PackedVertex, RenderLayer, RenderSettings, VoxelVertex};
+
+pub use greedy::mesh_greedy;
+pub use naive::mesh_naive;
+
+/// Blocks along every axis of a section
+pub const SECTION_SIZE: i32 = 16;
+
+/// Section plus the layer of blocks around it
+const PADDED_SIZE: i32 = SECTION_SIZE + 2;
+
+pub type BlockId = u16;
+
+pub const AIR: BlockId = 0;
+
+/// How blocks look, implemented by the game's block registry
+pub trait BlockTypes {
+    /// Pass the block is drawn in, `None` for blocks without faces like air
+    fn render_layer(&self, block: BlockId) -> Option<RenderLayer>;
+
+    /// Texture array layer of a face of the block, usually resolved once per block type
+    /// from the texture name with [`TextureLayers`](crate::TextureLayers)
+    fn texture(&self, block: BlockId, face: Face) -> u16;
+
+    /// Light the block gives off, up to [`VoxelVertex::MAX_LIGHT`]. Faces of emissive
+    /// blocks are drawn bright enough to bloom, the light they spread to the blocks
+    /// around them is up to the light engine.
+    fn emission(&self, _block: BlockId) -> u8 {
+        0
+    }
+}
+
+/// Blocks and light of a section and of the layer of blocks around it, coordinates go
+/// from -1 to [`SECTION_SIZE`] on every axis. The outer layer is only looked at to
+/// know which faces are hidden and how they are lit.
+#[derive(Clone, Debug)]
+pub struct SectionBlocks {
+    blocks: Vec<BlockId>,
+    /// Sky light in the high nibble, block light in the low one
+    light: Vec<u8>,
+}
+
+impl Default for SectionBlocks {
+    fn default() -> Self {
+        let len = (PADDED_SIZE * PADDED_SIZE * PADDED_SIZE) as usize;
+        Self {
+            blocks: vec![AIR; len],
+            light: vec![0; len],
+        }
+    }
+}
+
+impl SectionBlocks {
+    /// Air everywhere, without light
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn index(pos: IVec3) -> usize {
+        debug_assert!(
+            pos.cmpge(IVec3::splat(-1)).all() && pos.cmple(IVec3::splat(SECTION_SIZE)).all()
+        );
+        let pos = pos + 1;
+        ((pos.y * PADDED_SIZE + pos.z) * PADDED_SIZE + pos.x) as usize
+    }
+
+    pub fn block(&self, pos: IVec3) -> BlockId {
+        self.blocks[Self::index(pos)]
+    }
+
+    pub fn set_block(&mut self, pos: IVec3, block: BlockId) {
+        self.blocks[Self::index(pos)] = block;
+    }
+
+    /// Sky and block light, up to [`VoxelVertex::MAX_LIGHT`]
+    pub fn light(&self, pos: IVec3) -> (u8, u8) {
+        let light = self.light[Self::index(pos)];
+        (light >> 4, light & 0xf)
+    }
+
+    pub fn set_light(&mut self, pos: IVec3, sky: u8, block: u8) {
+        self.light[Self::index(pos)] = (sky.min(15) << 4) | block.min(15);
+    }
+}
+
+/// Mesh of a section, vertex positions are relative to the section origin
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SectionMesh {
+    pub vertices: Vec<PackedVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl SectionMesh {
+    /// Reorders the quads so the farthest from `eye` are drawn first, `eye` is relative
+    /// to the section origin. Only the indices change.
+    pub fn sort_back_to_front(&mut self, eye: Vec3) {
+        let distance = |quad: &[u32]| {
+            let base = quad[0] as usize / 4 * 4;
+            let center = self.vertices[base..base + 4]
+                .iter()
+                .filter_map(PackedVertex::unpack)
+                .map(|vertex| Vec3::from_array(vertex.position.map(f32::from)))
+                .sum::<Vec3>()
+                / 4.0;
+            center.distance_squared(eye)
+        };
+
+        let mut quads: Vec<(f32, [u32; 6])> = self
+            .indices
+            .chunks_exact(6)
+            .map(|quad| (distance(quad), quad.try_into().unwrap()))
+            .collect();
+        quads.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        self.indices = quads.into_iter().flat_map(|(_, quad)| quad).collect();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    /// Vertices as uploaded to the arena
+    pub fn vertex_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.vertices)
+    }
+
+    fn push_quad(&mut self, quad: &Quad) {
+        let base = self.vertices.len() as u32;
+        let (d, u, v) = axes(quad.face);
+        let positive = quad.face.normal().max_element() > 0;
+
+        let corners = [
+            (quad.start[0], quad.start[1]),
+            (quad.start[0] + quad.size[0], quad.start[1]),
+            (quad.start[0] + quad.size[0], quad.start[1] + quad.size[1]),
+            (quad.start[0], quad.start[1] + quad.size[1]),
+        ];
+        for (corner, (cu, cv)) in corners.into_iter().enumerate() {
+            let mut position = [0; 3];
+            position[d] = quad.plane;
+            position[u] = cu;
+            position[v] = cv;
+
+            let vertex = VoxelVertex {
+                position,
+                face: quad.face,
+                uv: texture_coords(quad.face, position),
+                ao: quad.shading.ao[corner],
+                layer: quad.layer,
+                sky_light: quad.shading.light[corner][0],
+                block_light: quad.shading.light[corner][1],
+                emission: quad.emission,
+            };
+            self.vertices.push(vertex.pack());
+        }
+
+        // split along the most occluded diagonal, otherwise occlusion at one corner
+        // ends in a crease and the quad looks different depending on its orientation
+        let ao = quad.shading.ao.map(u32::from);
+        let mut triangles = if ao[1] + ao[3] > ao[0] + ao[2] {
+            [[1, 2, 3], [1, 3, 0]]
+        } else {
+            [[0, 1, 2], [0, 2, 3]]
+        };
+        // counter clockwise seen from outside, u x v points along positive normals
+        if !positive {
+            for triangle in &mut triangles {
+                triangle.swap(1, 2);
+            }
+        }
+        self.indices
+            .extend(triangles.as_flattened().iter().map(|corner| base + corner));
+    }
+}
+
+/// Meshes of a section, one per [`RenderLayer`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SectionMeshes {
+    pub layers: [SectionMesh; RenderLayer::COUNT],
+}
+
+impl SectionMeshes {
+    pub fn layer(&self, layer: RenderLayer) -> &SectionMesh {
+        &self.layers[layer as usize]
+    }
+
+    pub fn layer_mut(&mut self, layer: RenderLayer) -> &mut SectionMesh {
+        &mut self.layers[layer as usize]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.iter().all(SectionMesh::is_empty)
+    }
+
+    pub fn quad_count(&self) -> usize {
+        self.layers.iter().map(SectionMesh::quad_count).sum()
+    }
+
+    fn push_quad(&mut self, layer: RenderLayer, quad: &Quad) {
+        self.layer_mut(layer).push_quad(quad);
+    }
+}
+
+/// Tells when translucent meshes have to be sorted again, that is when the camera
+/// enters another block
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SortTrigger {
+    block: Option<IVec3>,
+}
+
+impl SortTrigger {
+    /// Whether `camera`, in world space, moved to another block since the last call
+    pub fn update(&mut self, camera: Vec3) -> bool {
+        let block = camera.floor().as_ivec3();
+        let moved = self.block != Some(block);
+        self.block = Some(block);
+        moved
+    }
+}
+
+/// Axis of the normal of a face, then the two axes its quads extend along
+fn axes(face: Face) -> (usize, usize, usize) {
+    let d = match face {
+        Face::PosX | Face::NegX => 0,
+        Face::PosY | Face::NegY => 1,
+        Face::PosZ | Face::NegZ => 2,
+    };
+    (d, (d + 1) % 3, (d + 2) % 3)
+}
+
+/// Texture coordinates of a corner so that textures are upright on the sides
+fn texture_coords(face: Face, position: [u8; 3]) -> [u8; 2] {
+    let [x, y, z] = position;
+    let top = SECTION_SIZE as u8;
+    match face {
+        Face::PosX | Face::NegX => [z, top - y],
+        Face::PosY | Face::NegY => [x, z],
+        Face::PosZ | Face::NegZ => [x, top - y],
+    }
+}
+
+/// Corner values of a face, in the order of the quad corners: start, +u, +u+v, +v
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+struct Shading {
+    ao: [u8; 4],
+    /// Sky and block light
+    light: [[u8; 2]; 4],
+}
+
+impl Shading {
+    /// Faces with the same value at every corner can be merged without changing how
+    /// the values are interpolated over them
+    fn is_uniform(&self) -> bool {
+        self.ao.iter().all(|ao| *ao == self.ao[0])
+            && self.light.iter().all(|light| *light == self.light[0])
+    }
+}
+
+/// What a visible block face looks like, faces merge when they are equal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct FaceKey {
+    render_layer: RenderLayer,
+    layer: u16,
+    emission: u8,
+    shading: Shading,
+}
+
+/// Directions of the quad corners along the two axes of the plane, same order as
+/// [`Shading`]
+const CORNERS: [(i32, i32); 4] = [(-1, -1), (1, -1), (1, 1), (-1, 1)];
+
+/// Key of `face` of the block at `pos`, `None` when the face is hidden
+fn face_key(
+    section: &SectionBlocks,
+    blocks: &impl BlockTypes,
+    settings: &RenderSettings,
+    pos: IVec3,
+    face: Face,
+) -> Option<FaceKey> {
+    let block = section.block(pos);
+    let render_layer = blocks.render_layer(block)?;
+
+    // opaque blocks hide every face behind them, translucent blocks only hide the faces
+    // of their own kind so water or glass don't show their inner faces
+    let front = pos + face.normal();
+    let neighbour = section.block(front);
+    match blocks.render_layer(neighbour) {
+        Some(RenderLayer::Opaque) => return None,
+        Some(RenderLayer::Translucent)
+            if render_layer == RenderLayer::Translucent && neighbour == block =>
+        {
+            return None
+        }
+        _ => (),
+    }
+
+    let (sky, light) = section.light(front);
+    let mut shading = Shading {
+        ao: [0; 4],
+        light: [[sky, light]; 4],
+    };
+
+    if settings.ambient_occlusion || settings.smooth_lighting {
+        let (_, u, v) = axes(face);
+        let (mut du, mut dv) = (IVec3::ZERO, IVec3::ZERO);
+        du[u] = 1;
+        dv[v] = 1;
+
+        for (index, (su, sv)) in CORNERS.into_iter().enumerate() {
+            // the three blocks touching the corner in the layer in front of the face
+            let side_u = front + du * su;
+            let side_v = front + dv * sv;
+            let diagonal = front + du * su + dv * sv;
+            let opaque =
+                |pos: IVec3| blocks.render_layer(section.block(pos)) == Some(RenderLayer::Opaque);
+            let (occludes_u, occludes_v) = (opaque(side_u), opaque(side_v));
+            // light can't reach the diagonal block through two opaque sides
+            let occludes_diagonal = (occludes_u && occludes_v) || opaque(diagonal);
+
+            if settings.ambient_occlusion {
+                shading.ao[index] = if occludes_u && occludes_v {
+                    VoxelVertex::MAX_AO
+                } else {
+                    occludes_u as u8 + occludes_v as u8 + occludes_diagonal as u8
+                };
+            }
+
+            if settings.smooth_lighting {
+                let lit = [
+                    (front, false),
+                    (side_u, occludes_u),
+                    (side_v, occludes_v),
+                    (diagonal, occludes_diagonal),
+                ];
+                let (mut total, mut count) = ([0u32; 2], 0);
+                for (pos, occluded) in lit {
+                    if !occluded {
+                        let (sky, light) = section.light(pos);
+                        total[0] += sky as u32;
+                        total[1] += light as u32;
+                        count += 1;
+                    }
+                }
+                shading.light[index] = total.map(|val| ((val + count / 2) / count) as u8);
+            }
+        }
+    }
+
+    Some(FaceKey {
+        render_layer,
+        layer: blocks.texture(block, face),
+        emission: blocks.emission(block).min(VoxelVertex::MAX_LIGHT),
+        shading,
+    })
+}
+
+/// Face of one or more blocks lying on the plane `plane` along the normal axis
+#[derive(Clone, Copy, Debug)]
+struct Quad {
+    face: Face,
+    plane: u8,
+    /// Corner with the smallest coordinates along the two axes of the plane
+    start: [u8; 2],
+    /// In blocks along both axes
+    size: [u8; 2],
+    layer: u16,
+    emission: u8,
+    shading: Shading,
+}
+
+/// Block the face on `plane` at `(u, v)` of a section slice belongs to
+fn slice_block(face: Face, plane: i32, u: i32, v: i32) -> IVec3 {
+    let (d, ua, va) = axes(face);
+    let mut pos = IVec3::ZERO;
+    // positive faces lie on the far side of their block
+    pos[d] = if face.normal().max_element() > 0 {
+        plane - 1
+    } else {
+        plane
+    };
+    pos[ua] = u;
+    pos[va] = v;
+    pos
+}
+
+/// Plane the faces of the blocks in `slice` lie on
+fn face_plane(face: Face, slice: i32) -> i32 {
+    if face.normal().max_element() > 0 {
+        slice + 1
+    } else {
+        slice
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const WATER: BlockId = 1;
+    const GLASS: BlockId = 3;
+    const LEAVES: BlockId = 5;
+    const GLOWSTONE: BlockId = 6;
+
+    /// Blocks with an even id are opaque, their face layers are `n * 8 + face`
+    struct TestBlocks;
+
+    impl BlockTypes for TestBlocks {
+        fn render_layer(&self, block: BlockId) -> Option<RenderLayer> {
+            match block {
+                AIR => None,
+                WATER | GLASS => Some(RenderLayer::Translucent),
+                LEAVES => Some(RenderLayer::Cutout),
+                _ => Some(RenderLayer::Opaque),
+            }
+        }
+
+        fn texture(&self, block: BlockId, face: Face) -> u16 {
+            block * 8 + face.index() as u16
+        }
+
+        fn emission(&self, block: BlockId) -> u8 {
+            if block == GLOWSTONE {
+                VoxelVertex::MAX_LIGHT
+            } else {
+                0
+            }
+        }
+    }
+
+    /// Block face as covered by a mesh: render layer, face, block, texture layer, then
+    /// ao, sky and block light at each corner of the block face
+    type UnitFace = (RenderLayer, Face, [i32; 3], u16, [(u8, u8, u8); 4]);
+
+    /// Every block face covered by `meshes`, with how many times it is covered
+    fn coverage(meshes: &SectionMeshes) -> HashMap<UnitFace, u32> {
+        let mut faces = HashMap::new();
+        for layer in RenderLayer::ALL {
+            cover_layer(&mut faces, meshes.layer(layer), layer);
+        }
+        faces
+    }
+
+    fn cover_layer(faces: &mut HashMap<UnitFace, u32>, mesh: &SectionMesh, layer: RenderLayer) {
+        assert_eq!(mesh.vertices.len() % 4, 0);
+        assert_eq!(mesh.indices.len(), mesh.quad_count() * 6);
+
+        for quad in mesh.vertices.chunks(4) {
+            let corners: Vec<_> = quad.iter().map(|val| val.unpack().unwrap()).collect();
+            let face = corners[0].face;
+            let (d, u, v) = axes(face);
+
+            let min = |axis: usize| corners.iter().map(|val| val.position[axis]).min().unwrap();
+            let max = |axis: usize| corners.iter().map(|val| val.position[axis]).max().unwrap();
+            assert_eq!(min(d), max(d));
+
+            // corner values are uniform over merged quads, only unit quads can vary
+            let shade = |corner: &VoxelVertex| (corner.ao, corner.sky_light, corner.block_light);
+            let mut shading = [(0, 0, 0); 4];
+            for corner in &corners {
+                let index = match (corner.position[u] == min(u), corner.position[v] == min(v)) {
+                    (true, true) => 0,
+                    (false, true) => 1,
+                    (false, false) => 2,
+                    (true, false) => 3,
+                };
+                shading[index] = shade(corner);
+            }
+
+            for cu in min(u)..max(u) {
+                for cv in min(v)..max(v) {
+                    let pos = slice_block(face, min(d) as i32, cu as i32, cv as i32);
+                    let key = (layer, face, pos.to_array(), corners[0].layer, shading);
+                    *faces.entry(key).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    fn section_strategy() -> impl Strategy<Value = SectionBlocks> {
+        let len = (PADDED_SIZE * PADDED_SIZE * PADDED_SIZE) as usize;
+        // few block types so that neighbouring faces often match
+        let blocks = prop::collection::vec(
+            prop_oneof![
+                6 => Just(AIR),
+                8 => Just(2u16),
+                4 => Just(4u16),
+                2 => Just(WATER),
+                1 => Just(GLASS),
+                2 => Just(LEAVES),
+            ],
+            len,
+        );
+        let light = prop::collection::vec(prop_oneof![4 => Just(0xf0u8), 1 => 0..=255u8], len);
+        (blocks, light).prop_map(|(blocks, light)| SectionBlocks { blocks, light })
+    }
+
+    fn settings_strategy() -> impl Strategy<Value = RenderSettings> {
+        (any::<bool>(), any::<bool>()).prop_map(|(ambient_occlusion, smooth_lighting)| {
+            RenderSettings {
+                ambient_occlusion,
+                smooth_lighting,
+                ..RenderSettings::default()
+            }
+        })
+    }
+
+    /// Normal of a triangle from its winding, and the face its vertices belong to
+    fn triangle_normal(mesh: &SectionMesh, triangle: &[u32]) -> (IVec3, Face) {
+        let triangle: [u32; 3] = triangle.try_into().unwrap();
+        let corners = triangle.map(|index| mesh.vertices[index as usize].unpack().unwrap());
+        let [a, b, c] = corners.map(|val| IVec3::from_array(val.position.map(i32::from)));
+        ((b - a).cross(c - a), corners[0].face)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn greedy_covers_the_naive_surface(
+            section in section_strategy(),
+            settings in settings_strategy(),
+        ) {
+            let naive = coverage(&mesh_naive(&section, &TestBlocks, &settings));
+            let greedy = coverage(&mesh_greedy(&section, &TestBlocks, &settings));
+
+            prop_assert!(naive.values().all(|count| *count == 1));
+            prop_assert!(greedy.values().all(|count| *count == 1));
+            prop_assert_eq!(naive, greedy);
+        }
+
+        #[test]
+        fn greedy_never_emits_more_quads(
+            section in section_strategy(),
+            settings in settings_strategy(),
+        ) {
+            let naive = mesh_naive(&section, &TestBlocks, &settings);
+            let greedy = mesh_greedy(&section, &TestBlocks, &settings);
+            prop_assert!(greedy.quad_count() <= naive.quad_count());
+        }
+
+        #[test]
+        fn triangles_face_outwards(section in section_strategy()) {
+            let meshes = mesh_greedy(&section, &TestBlocks, &RenderSettings::default());
+            for mesh in &meshes.layers {
+                for triangle in mesh.indices.chunks_exact(3) {
+                    let (normal, face) = triangle_normal(mesh, triangle);
+                    prop_assert_eq!(normal.signum(), face.normal());
+                }
+            }
+        }
+
+        #[test]
+        fn sorted_quads_go_back_to_front(
+            section in section_strategy(),
+            eye in prop::array::uniform3(-8.0f32..24.0),
+        ) {
+            let meshes = mesh_greedy(&section, &TestBlocks, &RenderSettings::default());
+            let mut mesh = meshes.layer(RenderLayer::Translucent).clone();
+            let eye = Vec3::from_array(eye);
+            mesh.sort_back_to_front(eye);
+
+            let mut sorted = mesh.indices.clone();
+            let mut original = meshes.layer(RenderLayer::Translucent).indices.clone();
+            sorted.sort_unstable();
+            original.sort_unstable();
+            prop_assert_eq!(sorted, original);
+
+            let center = |quad: &[u32]| {
+                let base = quad[0] as usize / 4 * 4;
+                mesh.vertices[base..base + 4]
+                    .iter()
+                    .map(|vertex| Vec3::from_array(vertex.unpack().unwrap().position.map(f32::from)))
+                    .sum::<Vec3>()
+                    / 4.0
+            };
+            let quads: Vec<&[u32]> = mesh.indices.chunks_exact(6).collect();
+            for pair in quads.windows(2) {
+                prop_assert!(center(pair[0]).distance_squared(eye) >= center(pair[1]).distance_squared(eye));
+            }
+        }
+    }
+
+    /// Ambient occlusion of the corners of a face, in `Shading` order
+    fn corner_ao(section: &SectionBlocks, pos: IVec3, face: Face) -> [u8; 4] {
+        face_key(section, &TestBlocks, &RenderSettings::default(), pos, face)
+            .unwrap()
+            .shading
+            .ao
+    }
+
+    fn filled(block: BlockId) -> SectionBlocks {
+        let mut section = SectionBlocks::new();
+        for x in 0..SECTION_SIZE {
+            for y in 0..SECTION_SIZE {
+                for z in 0..SECTION_SIZE {
+                    section.set_block(IVec3::new(x, y, z), block);
+                }
+            }
+        }
+        section
+    }
+
+    #[test]
+    fn empty_section_has_no_mesh() {
+        let section = SectionBlocks::new();
+        assert!(mesh_naive(&section, &TestBlocks, &RenderSettings::default()).is_empty());
+        assert!(mesh_greedy(&section, &TestBlocks, &RenderSettings::default()).is_empty());
+    }
+
+    #[test]
+    fn full_section_is_one_quad_per_side() {
+        let section = filled(2);
+        assert_eq!(
+            mesh_greedy(&section, &TestBlocks, &RenderSettings::default()).quad_count(),
+            6
+        );
+        assert_eq!(
+            mesh_naive(&section, &TestBlocks, &RenderSettings::default()).quad_count(),
+            6 * (SECTION_SIZE * SECTION_SIZE) as usize
+        );
+    }
+
+    #[test]
+    fn hidden_faces_are_skipped() {
+        let mut section = filled(2);
+        // an opaque neighbour hides the side it touches
+        for u in -1..=SECTION_SIZE {
+            for v in -1..=SECTION_SIZE {
+                section.set_block(IVec3::new(u, SECTION_SIZE, v), 4);
+            }
+        }
+        // without occlusion darkening the edges the sides stay single quads
+        let settings = RenderSettings {
+            ambient_occlusion: false,
+            ..Default::default()
+        };
+        let mesh = mesh_greedy(&section, &TestBlocks, &settings);
+        assert_eq!(mesh.quad_count(), 5);
+        assert!(coverage(&mesh).keys().all(|face| face.1 != Face::PosY));
+    }
+
+    #[test]
+    fn corners_next_to_blocks_are_occluded() {
+        let mut section = SectionBlocks::new();
+        let block = IVec3::new(4, 4, 4);
+        section.set_block(block, 2);
+
+        // nothing around the top face
+        assert_eq!(corner_ao(&section, block, Face::PosY), [0; 4]);
+
+        // a block on top of the +x neighbour occludes the two +x corners of the top face,
+        // the top face axes are z then x
+        section.set_block(block + IVec3::new(1, 1, 0), 2);
+        assert_eq!(corner_ao(&section, block, Face::PosY), [0, 0, 1, 1]);
+
+        // with a second side the corner between both is fully occluded
+        section.set_block(block + IVec3::new(0, 1, 1), 2);
+        assert_eq!(
+            corner_ao(&section, block, Face::PosY),
+            [0, 1, VoxelVertex::MAX_AO, 1]
+        );
+
+        let flat = RenderSettings {
+            ambient_occlusion: false,
+            ..Default::default()
+        };
+        let key = face_key(&section, &TestBlocks, &flat, block, Face::PosY).unwrap();
+        assert_eq!(key.shading.ao, [0; 4]);
+    }
+
+    #[test]
+    fn anisotropic_quads_are_flipped() {
+        let mut section = SectionBlocks::new();
+        let block = IVec3::new(4, 4, 4);
+        section.set_block(block, 2);
+        // occludes the start corner of the top face only
+        section.set_block(block + IVec3::new(-1, 1, -1), 2);
+        assert_eq!(corner_ao(&section, block, Face::PosY), [1, 0, 0, 0]);
+
+        let settings = RenderSettings::default();
+        let top = |section: &SectionBlocks| {
+            let meshes = mesh_naive(section, &TestBlocks, &settings);
+            let mesh = meshes.layer(RenderLayer::Opaque);
+            let quad = (0..mesh.quad_count())
+                .find(|quad| mesh.vertices[quad * 4].unpack().unwrap().face == Face::PosY)
+                .unwrap();
+            let base = quad as u32 * 4;
+            mesh.indices[quad * 6..quad * 6 + 6]
+                .iter()
+                .map(|index| index - base)
+                .collect::<Vec<_>>()
+        };
+
+        // split through the occluded corner
+        assert_eq!(top(&section), [0, 1, 2, 0, 2, 3]);
+
+        // occluding the other diagonal flips the split
+        section.set_block(block + IVec3::new(-1, 1, -1), AIR);
+        section.set_block(block + IVec3::new(1, 1, -1), 2);
+        assert_eq!(corner_ao(&section, block, Face::PosY), [0, 0, 0, 1]);
+        assert_eq!(top(&section), [1, 2, 3, 1, 3, 0]);
+    }
+
+    #[test]
+    fn flipped_quads_keep_facing_outwards() {
+        let block = IVec3::new(4, 4, 4);
+        let settings = RenderSettings::default();
+
+        // a single occluder around the block occludes one corner or one edge of its faces,
+        // splitting them along either diagonal
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    let mut section = SectionBlocks::new();
+                    section.set_block(block, 2);
+                    section.set_block(block + IVec3::new(x, y, z), 2);
+
+                    for meshes in [
+                        mesh_naive(&section, &TestBlocks, &settings),
+                        mesh_greedy(&section, &TestBlocks, &settings),
+                    ] {
+                        let mesh = meshes.layer(RenderLayer::Opaque);
+                        for triangle in mesh.indices.chunks_exact(3) {
+                            let (normal, face) = triangle_normal(mesh, triangle);
+                            assert_eq!(normal.signum(), face.normal(), "{} {} {}", x, y, z);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_light_averages_unoccluded_blocks() {
+        let mut section = SectionBlocks::new();
+        let block = IVec3::new(4, 4, 4);
+        section.set_block(block, 2);
+        section.set_light(block + IVec3::Y, 15, 0);
+        section.set_light(block + IVec3::new(-1, 1, 0), 15, 8);
+        // opaque blocks don't contribute their light
+        section.set_block(block + IVec3::new(0, 1, -1), 2);
+        section.set_light(block + IVec3::new(0, 1, -1), 15, 15);
+
+        let key = face_key(
+            &section,
+            &TestBlocks,
+            &RenderSettings::default(),
+            block,
+            Face::PosY,
+        )
+        .unwrap();
+
+        // start corner: front (15, 0) and the -x side (15, 8), the -z side is opaque and
+        // the diagonal (0, 0) still counts
+        assert_eq!(key.shading.light[0], [10, 3]);
+        // +z +x corner: front, two unlit sides and an unlit diagonal
+        assert_eq!(key.shading.light[2], [4, 0]);
+
+        let flat = RenderSettings {
+            smooth_lighting: false,
+            ..Default::default()
+        };
+        let key = face_key(&section, &TestBlocks, &flat, block, Face::PosY).unwrap();
+        assert_eq!(key.shading.light, [[15, 0]; 4]);
+    }
+
+    #[test]
+    fn translucent_blocks_hide_faces_of_their_kind() {
+        let settings = RenderSettings::default();
+        let mut section = SectionBlocks::new();
+        let water = IVec3::new(4, 4, 4);
+        section.set_block(water, WATER);
+        section.set_block(water + IVec3::X, WATER);
+
+        // two water blocks make one box
+        let meshes = mesh_greedy(&section, &TestBlocks, &settings);
+        assert_eq!(meshes.layer(RenderLayer::Translucent).quad_count(), 6);
+
+        // water against glass shows both faces
+        section.set_block(water + IVec3::X, GLASS);
+        let meshes = mesh_greedy(&section, &TestBlocks, &settings);
+        assert_eq!(meshes.layer(RenderLayer::Translucent).quad_count(), 12);
+
+        // stone hides the water face against it but stays visible through the water
+        section.set_block(water + IVec3::X, 2);
+        let meshes = mesh_greedy(&section, &TestBlocks, &settings);
+        assert_eq!(meshes.layer(RenderLayer::Translucent).quad_count(), 5);
+        assert_eq!(meshes.layer(RenderLayer::Opaque).quad_count(), 6);
+    }
+
+    #[test]
+    fn cutout_blocks_keep_their_inner_faces() {
+        let mut section = SectionBlocks::new();
+        section.set_block(IVec3::new(4, 4, 4), LEAVES);
+        section.set_block(IVec3::new(5, 4, 4), LEAVES);
+
+        let meshes = mesh_naive(&section, &TestBlocks, &RenderSettings::default());
+        assert_eq!(meshes.layer(RenderLayer::Cutout).quad_count(), 12);
+        assert!(meshes.layer(RenderLayer::Opaque).is_empty());
+    }
+
+    #[test]
+    fn emissive_blocks_glow_on_every_face() {
+        let mut section = SectionBlocks::new();
+        section.set_block(IVec3::new(4, 4, 4), GLOWSTONE);
+        section.set_block(IVec3::new(5, 4, 4), 2);
+
+        let meshes = mesh_greedy(&section, &TestBlocks, &RenderSettings::default());
+        let vertices: Vec<_> = meshes
+            .layer(RenderLayer::Opaque)
+            .vertices
+            .iter()
+            .map(|val| val.unpack().unwrap())
+            .collect();
+        assert_eq!(vertices.len(), 10 * 4);
+
+        for vertex in vertices {
+            let glowing = vertex.layer / 8 == GLOWSTONE;
+            assert_eq!(vertex.emission, glowing as u8 * VoxelVertex::MAX_LIGHT);
+        }
+    }
+
+    #[test]
+    fn sort_trigger_fires_on_block_change() {
+        let mut trigger = SortTrigger::default();
+        assert!(trigger.update(Vec3::new(0.5, 0.5, 0.5)));
+        assert!(!trigger.update(Vec3::new(0.9, 0.1, 0.5)));
+        assert!(trigger.update(Vec3::new(1.1, 0.1, 0.5)));
+        assert!(trigger.update(Vec3::new(1.1, 0.1, -0.5)));
+    }
+}
@@ -0,0 +1,44 @@
+use glam::IVec3;
+
+use super::{
+    axes, face_key, face_plane, BlockTypes, Quad, SectionBlocks, SectionMeshes, SECTION_SIZE,
+};
+use crate::{Face, RenderSettings};
+
+/// Meshes every visible block face as its own quad
+pub fn mesh_naive(
+    section: &SectionBlocks,
+    blocks: &impl BlockTypes,
+    settings: &RenderSettings,
+) -> SectionMeshes {
+    let mut mesh = SectionMeshes::default();
+
+    for y in 0..SECTION_SIZE {
+        for z in 0..SECTION_SIZE {
+            for x in 0..SECTION_SIZE {
+                let pos = IVec3::new(x, y, z);
+                for face in Face::ALL {
+                    let Some(key) = face_key(section, blocks, settings, pos, face) else {
+                        continue;
+                    };
+
+                    let (d, u, v) = axes(face);
+                    mesh.push_quad(
+                        key.render_layer,
+                        &Quad {
+                            face,
+                            plane: face_plane(face, pos[d]) as u8,
+                            start: [pos[u] as u8, pos[v] as u8],
+                            size: [1, 1],
+                            layer: key.layer,
+                            emission: key.emission,
+                            shading: key.shading,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    mesh
+}
@@ -0,0 +1,234 @@
+//! 3D lookup tables grading the colors at the end of the post-processing chain
+
+use std::{error::Error, path::Path};
+
+use ash::vk;
+
+use crate::{chunks::load_png, core, MemoryLocation, Renderer};
+
+/// Color grading table: the sRGB encoded display color indexes a cube of RGBA8 texels,
+/// red varying fastest then green then blue
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColorLut {
+    size: u32,
+    texels: Vec<u8>,
+}
+
+impl ColorLut {
+    pub const MIN_SIZE: u32 = 2;
+    pub const MAX_SIZE: u32 = 64;
+
+    /// Table leaving every color as it is
+    pub fn identity(size: u32) -> Self {
+        let size = size.clamp(Self::MIN_SIZE, Self::MAX_SIZE);
+        let value = |index: u32| ((index * 255 + (size - 1) / 2) / (size - 1)) as u8;
+
+        let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    texels.extend_from_slice(&[value(r), value(g), value(b), 255]);
+                }
+            }
+        }
+        Self { size, texels }
+    }
+
+    /// Reads the common strip layout: `size` squares of `size` by `size` side by side,
+    /// blue increasing from one square to the next, red along x and green along y
+    pub fn from_strip(width: u32, height: u32, pixels: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let size = height;
+        if width != size * size || !(Self::MIN_SIZE..=Self::MAX_SIZE).contains(&size) {
+            return Err(format!("{}x{} isn't a LUT strip", width, height).into());
+        }
+        if pixels.len() != (width * height * 4) as usize {
+            return Err("LUT strip pixels don't match its size".into());
+        }
+
+        let mut texels = Vec::with_capacity(pixels.len());
+        for b in 0..size {
+            for g in 0..size {
+                let row = ((g * width + b * size) * 4) as usize;
+                texels.extend_from_slice(&pixels[row..row + (size * 4) as usize]);
+            }
+        }
+        Ok(Self { size, texels })
+    }
+
+    /// Loads a PNG strip, see [`ColorLut::from_strip`]
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let (width, height, pixels) = load_png(path.as_ref())?;
+        Self::from_strip(width, height, &pixels)
+    }
+
+    /// Entries along each axis
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// RGBA8 texel at the given coordinates
+    pub fn texel(&self, r: u32, g: u32, b: u32) -> [u8; 4] {
+        let index = (((b * self.size + g) * self.size + r) * 4) as usize;
+        self.texels[index..index + 4].try_into().unwrap()
+    }
+}
+
+/// [`ColorLut`] uploaded to a 3D texture
+pub(super) struct LutTexture {
+    pub image: core::AllocatedImage,
+    pub size: u32,
+}
+
+impl LutTexture {
+    /// Uploads `lut`, waiting for the device to finish
+    pub fn new(renderer: &Renderer, lut: &ColorLut) -> Result<Self, Box<dyn Error>> {
+        let extent = vk::Extent3D {
+            width: lut.size,
+            height: lut.size,
+            depth: lut.size,
+        };
+        let image = renderer.create_image(&core::ImageSpec {
+            name: "color lut",
+            extent,
+            format: vk::Format::R8G8B8A8_UNORM,
+            usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+            aspect: vk::ImageAspectFlags::COLOR,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+        })?;
+
+        if let Err(err) = Self::upload(renderer, &image, lut) {
+            renderer.destroy_image(image);
+            return Err(err);
+        }
+
+        Ok(Self {
+            image,
+            size: lut.size,
+        })
+    }
+
+    fn upload(
+        renderer: &Renderer,
+        image: &core::AllocatedImage,
+        lut: &ColorLut,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut staging = renderer.create_buffer(
+            "color lut staging",
+            lut.texels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            MemoryLocation::CpuToGpu,
+        )?;
+        match staging.mapped_mut() {
+            Some(data) => data[..lut.texels.len()].copy_from_slice(&lut.texels),
+            None => {
+                renderer.destroy_buffer(staging);
+                return Err("Staging buffer is not host visible".into());
+            }
+        }
+
+        let result = renderer.immediate_submit(|cmd| {
+            let device = renderer.device_handle();
+            core::transition_image(
+                device,
+                cmd,
+                image.handle,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+
+            let region = vk::BufferImageCopy::default()
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .layer_count(1),
+                )
+                .image_extent(image.extent);
+            unsafe {
+                device.cmd_copy_buffer_to_image(
+                    cmd,
+                    staging.handle,
+                    image.handle,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                )
+            };
+
+            core::transition_image(
+                device,
+                cmd,
+                image.handle,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+        });
+
+        renderer.destroy_buffer(staging);
+        Ok(result?)
+    }
+
+    pub fn destroy(self, renderer: &Renderer) {
+        renderer.destroy_image(self.image);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Strip of `lut`, the layout [`ColorLut::from_strip`] reads
+    fn strip(lut: &ColorLut) -> Vec<u8> {
+        let size = lut.size();
+        let mut pixels = vec![];
+        for g in 0..size {
+            for b in 0..size {
+                for r in 0..size {
+                    pixels.extend_from_slice(&lut.texel(r, g, b));
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn identity_maps_colors_to_themselves() {
+        let lut = ColorLut::identity(16);
+        assert_eq!(lut.texel(0, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(lut.texel(15, 15, 15), [255, 255, 255, 255]);
+        assert_eq!(lut.texel(15, 0, 0), [255, 0, 0, 255]);
+        assert_eq!(lut.texel(0, 15, 0), [0, 255, 0, 255]);
+        assert_eq!(lut.texel(0, 0, 15), [0, 0, 255, 255]);
+        assert_eq!(lut.texel(5, 10, 3), [85, 170, 51, 255]);
+    }
+
+    #[test]
+    fn strips_round_trip() {
+        for size in [2, 16, 33] {
+            let lut = ColorLut::identity(size);
+            let pixels = strip(&lut);
+            assert_eq!(
+                ColorLut::from_strip(size * size, size, &pixels).unwrap(),
+                lut
+            );
+        }
+    }
+
+    #[test]
+    fn strips_must_be_square_slices() {
+        let lut = ColorLut::identity(4);
+        let pixels = strip(&lut);
+        assert!(ColorLut::from_strip(16, 4, &pixels).is_ok());
+        assert!(ColorLut::from_strip(8, 8, &pixels).is_err());
+        assert!(ColorLut::from_strip(16, 4, &pixels[4..]).is_err());
+        assert!(ColorLut::from_strip(1, 1, &pixels[..4]).is_err());
+    }
+
+    #[test]
+    fn sizes_are_clamped() {
+        assert_eq!(ColorLut::identity(0).size(), ColorLut::MIN_SIZE);
+        assert_eq!(ColorLut::identity(1000).size(), ColorLut::MAX_SIZE);
+    }
+}
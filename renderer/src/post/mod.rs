@@ -0,0 +1,653 @@
+//! Screen-space passes: ambient occlusion darkening the scene, and the HDR
+//! post-processing from the resolved scene to the output image, bloom, exposure and
+//! tonemapping, color grading, brightness and gamma, then FXAA and the upscale of a
+//! scene drawn with dynamic resolution
+
+mod lut;
+mod ssao;
+mod taa;
+
+use std::{error::Error, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+pub use lut::ColorLut;
+pub use ssao::AmbientOcclusion;
+pub use taa::TemporalAntiAliasing;
+
+use crate::{
+    config::{AntiAliasing, OutputEncoding, Tonemap, Upscale},
+    core,
+    graph::{Attachment, ImageId, ImageUsage, ImportedImage, LoadOp, PassContext},
+    shaders,
+    target::SCENE_FORMAT,
+    Renderer,
+};
+use lut::LutTexture;
+
+/// Bloom levels below the scene, each half the size of the previous one
+const BLOOM_LEVELS: usize = 6;
+
+/// Width of the soft knee around the bloom threshold, as a fraction of it
+const BLOOM_KNEE: f32 = 0.5;
+
+/// Format of the tonemapped images FXAA and the upscale read, sRGB encoded with the
+/// luma in alpha
+const LDR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+
+// flags of `Constants` in `post.glsl`
+const POST_BLOOM: u32 = 1;
+const POST_LUT: u32 = 2;
+const POST_ENCODE: u32 = 4;
+const POST_SHARPEN: u32 = 8;
+
+/// Layout of `Constants` in `post.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct PostConstants {
+    texel_size: [f32; 2],
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    exposure: f32,
+    brightness: f32,
+    gamma: f32,
+    tonemap: u32,
+    flags: u32,
+    encoding: u32,
+    lut_size: f32,
+    sharpness: f32,
+}
+
+impl PostConstants {
+    fn texel_size(mut self, extent: vk::Extent2D) -> Self {
+        self.texel_size = [1.0 / extent.width as f32, 1.0 / extent.height as f32];
+        self
+    }
+
+    fn flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+/// Images the chain renders through, sized after the scene
+struct Targets {
+    bloom: Vec<core::AllocatedImage>,
+    ldr: core::AllocatedImage,
+    /// FXAA output the upscale reads, only when the scene is scaled
+    fxaa: Option<core::AllocatedImage>,
+    /// View and extent of the scene image the sets sample, and whether it is scaled
+    source: (vk::ImageView, vk::Extent2D, bool),
+}
+
+struct Pipelines {
+    layout: vk::PipelineLayout,
+    prefilter: vk::Pipeline,
+    down: vk::Pipeline,
+    up: vk::Pipeline,
+    /// Tonemapping into the LDR image, before FXAA
+    tonemap_ldr: vk::Pipeline,
+    /// Tonemapping into the output image
+    tonemap: vk::Pipeline,
+    /// FXAA into the LDR image the upscale reads
+    fxaa_ldr: vk::Pipeline,
+    fxaa: vk::Pipeline,
+    upscale: vk::Pipeline,
+    /// Format of the output image
+    output_format: vk::Format,
+}
+
+impl Pipelines {
+    fn new(
+        device: &ash::Device,
+        set_layout: vk::DescriptorSetLayout,
+        output_format: vk::Format,
+    ) -> Result<Self, vk::Result> {
+        // source, bloom and LUT, every set has a single sampled image
+        let layout = core::create_pipeline_layout(
+            device,
+            &[set_layout; 3],
+            size_of::<PostConstants>() as u32,
+            vk::ShaderStageFlags::FRAGMENT,
+        )?;
+
+        let vertex = shaders::words(shaders::POST_VERT);
+        let mut pipelines = vec![];
+        let stages = [
+            (
+                shaders::BLOOM_PREFILTER_FRAG,
+                SCENE_FORMAT,
+                core::Blend::Opaque,
+            ),
+            (shaders::BLOOM_DOWN_FRAG, SCENE_FORMAT, core::Blend::Opaque),
+            (shaders::BLOOM_UP_FRAG, SCENE_FORMAT, core::Blend::Additive),
+            (shaders::TONEMAP_FRAG, LDR_FORMAT, core::Blend::Opaque),
+            (shaders::TONEMAP_FRAG, output_format, core::Blend::Opaque),
+            (shaders::FXAA_FRAG, LDR_FORMAT, core::Blend::Opaque),
+            (shaders::FXAA_FRAG, output_format, core::Blend::Opaque),
+            (shaders::UPSCALE_FRAG, output_format, core::Blend::Opaque),
+        ];
+        for (code, format, blend) in stages {
+            let fragment = shaders::words(code);
+            let pipeline = core::GraphicsPipelineBuilder::new(layout)
+                .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+                .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+                .color_format(format)
+                .blend(blend)
+                .build(device);
+
+            match pipeline {
+                Ok(pipeline) => pipelines.push(pipeline),
+                Err(err) => {
+                    unsafe {
+                        for pipeline in pipelines {
+                            device.destroy_pipeline(pipeline, None);
+                        }
+                        device.destroy_pipeline_layout(layout, None);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(Self {
+            layout,
+            prefilter: pipelines[0],
+            down: pipelines[1],
+            up: pipelines[2],
+            tonemap_ldr: pipelines[3],
+            tonemap: pipelines[4],
+            fxaa_ldr: pipelines[5],
+            fxaa: pipelines[6],
+            upscale: pipelines[7],
+            output_format,
+        })
+    }
+
+    fn destroy(self, device: &ash::Device) {
+        let pipelines = [
+            self.prefilter,
+            self.down,
+            self.up,
+            self.tonemap_ldr,
+            self.tonemap,
+            self.fxaa_ldr,
+            self.fxaa,
+            self.upscale,
+        ];
+        unsafe {
+            for pipeline in pipelines {
+                device.destroy_pipeline(pipeline, None);
+            }
+            device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+/// Post-processing chain turning the HDR scene of a surface into its output image,
+/// configured with the [`PostSettings`](crate::PostSettings) of the render settings.
+/// Its images follow the size of the scene, so use one per surface.
+pub struct PostProcess {
+    set_layout: vk::DescriptorSetLayout,
+    sampler: vk::Sampler,
+    scene_set: vk::DescriptorSet,
+    /// One set per bloom level
+    bloom_sets: Vec<vk::DescriptorSet>,
+    ldr_set: vk::DescriptorSet,
+    fxaa_set: vk::DescriptorSet,
+    lut_set: vk::DescriptorSet,
+    /// Placeholder until a LUT is set, so the set is always valid
+    lut: LutTexture,
+    has_lut: bool,
+    targets: Option<Targets>,
+    pipelines: Option<Pipelines>,
+}
+
+impl PostProcess {
+    pub fn new(renderer: &mut Renderer) -> Result<Self, Box<dyn Error>> {
+        let device = renderer.device_handle().clone();
+
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .build(&device, vk::ShaderStageFlags::FRAGMENT)?;
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe { device.create_sampler(&sampler_info, None)? };
+
+        let scene_set = renderer.allocate_descriptor_set(set_layout)?;
+        let mut bloom_sets = Vec::with_capacity(BLOOM_LEVELS);
+        for _ in 0..BLOOM_LEVELS {
+            bloom_sets.push(renderer.allocate_descriptor_set(set_layout)?);
+        }
+        let ldr_set = renderer.allocate_descriptor_set(set_layout)?;
+        let fxaa_set = renderer.allocate_descriptor_set(set_layout)?;
+        let lut_set = renderer.allocate_descriptor_set(set_layout)?;
+
+        let lut = LutTexture::new(renderer, &ColorLut::identity(ColorLut::MIN_SIZE))?;
+
+        let post = Self {
+            set_layout,
+            sampler,
+            scene_set,
+            bloom_sets,
+            ldr_set,
+            fxaa_set,
+            lut_set,
+            lut,
+            has_lut: false,
+            targets: None,
+            pipelines: None,
+        };
+        post.write_set(&device, post.lut_set, post.lut.image.view);
+        Ok(post)
+    }
+
+    fn write_set(&self, device: &ash::Device, set: vk::DescriptorSet, view: vk::ImageView) {
+        core::DescriptorWriter::new()
+            .write_image(
+                0,
+                view,
+                self.sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            )
+            .update_set(device, set);
+    }
+
+    /// Grades the colors with `lut` when [`PostSettings::color_grading`] is on, `None`
+    /// leaves them as they are. Waits for the device.
+    ///
+    /// [`PostSettings::color_grading`]: crate::PostSettings::color_grading
+    pub fn set_color_lut(
+        &mut self,
+        renderer: &mut Renderer,
+        lut: Option<&ColorLut>,
+    ) -> Result<(), Box<dyn Error>> {
+        let placeholder = ColorLut::identity(ColorLut::MIN_SIZE);
+        let texture = LutTexture::new(renderer, lut.unwrap_or(&placeholder))?;
+
+        renderer.wait_idle();
+        std::mem::replace(&mut self.lut, texture).destroy(renderer);
+        self.has_lut = lut.is_some();
+        self.write_set(renderer.device_handle(), self.lut_set, self.lut.image.view);
+        Ok(())
+    }
+
+    /// Recreates the bloom levels and the LDR images when the scene image changed,
+    /// waiting for the device
+    fn prepare(
+        &mut self,
+        renderer: &mut Renderer,
+        scene_view: vk::ImageView,
+        extent: vk::Extent2D,
+        scaled: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let source = (scene_view, extent, scaled);
+        if let Some(targets) = &self.targets {
+            if targets.source == source {
+                return Ok(());
+            }
+        }
+
+        renderer.wait_idle();
+        if let Some(targets) = self.targets.take() {
+            Self::destroy_targets(renderer, targets);
+        }
+
+        let mut bloom = Vec::with_capacity(BLOOM_LEVELS);
+        let mut level_extent = extent;
+        while bloom.len() < BLOOM_LEVELS {
+            level_extent = vk::Extent2D {
+                width: (level_extent.width / 2).max(1),
+                height: (level_extent.height / 2).max(1),
+            };
+            bloom.push(renderer.create_image(&core::ImageSpec::color(
+                "bloom",
+                level_extent,
+                SCENE_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            ))?);
+            if level_extent.width.min(level_extent.height) <= 2 {
+                break;
+            }
+        }
+
+        let ldr_spec = |name| {
+            core::ImageSpec::color(
+                name,
+                extent,
+                LDR_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            )
+        };
+        let ldr = renderer.create_image(&ldr_spec("ldr image"))?;
+        let fxaa = match scaled {
+            true => Some(renderer.create_image(&ldr_spec("fxaa image"))?),
+            false => None,
+        };
+
+        let device = renderer.device_handle();
+        self.write_set(device, self.scene_set, scene_view);
+        for (image, set) in bloom.iter().zip(&self.bloom_sets) {
+            self.write_set(device, *set, image.view);
+        }
+        self.write_set(device, self.ldr_set, ldr.view);
+        if let Some(fxaa) = &fxaa {
+            self.write_set(device, self.fxaa_set, fxaa.view);
+        }
+
+        log::trace!("{} bloom levels below {:?}", bloom.len(), extent);
+        self.targets = Some(Targets {
+            bloom,
+            ldr,
+            fxaa,
+            source,
+        });
+        Ok(())
+    }
+
+    fn destroy_targets(renderer: &Renderer, targets: Targets) {
+        for image in targets.bloom {
+            renderer.destroy_image(image);
+        }
+        renderer.destroy_image(targets.ldr);
+        if let Some(fxaa) = targets.fxaa {
+            renderer.destroy_image(fxaa);
+        }
+    }
+
+    /// Adds the passes of the chain, writing the output image of the frame. Call it once
+    /// everything has been drawn in the scene.
+    pub fn render(&mut self, renderer: &mut Renderer) -> Result<(), Box<dyn Error>> {
+        let frame = renderer
+            .frame()
+            .ok_or("Post-processing outside of a frame")?;
+        let settings = renderer.render_settings().post;
+        let resolution = renderer.render_settings().resolution;
+        let output_format = renderer
+            .output_image_format(frame.surface)
+            .ok_or("Unknown surface")?;
+        let encoding = OutputEncoding::shader_id(
+            renderer
+                .output_format(frame.surface)
+                .map(|val| val.encoding),
+        );
+
+        let resolved = renderer.resolve_scene()?;
+        let scene = *renderer
+            .graph()
+            .imported_image(resolved)
+            .ok_or("The scene image isn't imported")?;
+        let scaled = frame.extent != frame.output_extent;
+        self.prepare(renderer, scene.view, frame.extent, scaled)?;
+
+        if self.pipelines.as_ref().map(|val| val.output_format) != Some(output_format) {
+            if let Some(pipelines) = self.pipelines.take() {
+                renderer.wait_idle();
+                pipelines.destroy(renderer.device_handle());
+            }
+            self.pipelines = Some(Pipelines::new(
+                renderer.device_handle(),
+                self.set_layout,
+                output_format,
+            )?);
+        }
+
+        let targets = self.targets.as_ref().unwrap();
+        let pipelines = self.pipelines.as_ref().unwrap();
+        let layout = pipelines.layout;
+
+        let mut flags = 0;
+        if settings.color_grading && self.has_lut {
+            flags |= POST_LUT;
+        }
+        let constants = PostConstants {
+            texel_size: [0.0; 2],
+            threshold: settings.bloom_threshold.max(0.0),
+            knee: settings.bloom_threshold.max(0.0) * BLOOM_KNEE,
+            intensity: settings.bloom_intensity.max(0.0),
+            exposure: settings.exposure.max(0.0),
+            brightness: settings.brightness.max(0.0),
+            gamma: settings.gamma.max(0.01),
+            tonemap: match settings.tonemap {
+                Tonemap::Aces => 0,
+                Tonemap::Reinhard => 1,
+                Tonemap::None => 2,
+            },
+            flags,
+            encoding,
+            lut_size: self.lut.size as f32,
+            sharpness: resolution.sharpness.max(0.0),
+        };
+
+        let graph = renderer.graph();
+        let bloom_images = if settings.bloom {
+            &targets.bloom[..]
+        } else {
+            &[]
+        };
+        let bloom: Vec<_> = bloom_images
+            .iter()
+            .enumerate()
+            .map(|(level, image)| {
+                let name = format!("bloom {}", level);
+                let imported = ImportedImage::from_allocated(image, vk::SampleCountFlags::TYPE_1);
+                (graph.import_image(&name, imported), image.extent_2d())
+            })
+            .collect();
+
+        if settings.bloom {
+            flags |= POST_BLOOM;
+
+            let (first, first_extent) = bloom[0];
+            let pipeline = pipelines.prefilter;
+            let sets = [self.scene_set];
+            graph
+                .add_pass("bloom prefilter")
+                .read_image(frame.resolved, ImageUsage::Sampled)
+                .write_image(first, ImageUsage::ColorAttachment)
+                .execute(move |ctx| {
+                    let constants = constants.texel_size(frame.extent);
+                    fullscreen(ctx, first, first_extent, LoadOp::DontCare, |ctx| {
+                        draw(ctx, pipeline, layout, &sets, &constants)
+                    });
+                });
+
+            for level in 1..bloom.len() {
+                let (source, source_extent) = bloom[level - 1];
+                let (target, extent) = bloom[level];
+                let pipeline = pipelines.down;
+                let sets = [self.bloom_sets[level - 1]];
+                graph
+                    .add_pass("bloom downsample")
+                    .read_image(source, ImageUsage::Sampled)
+                    .write_image(target, ImageUsage::ColorAttachment)
+                    .execute(move |ctx| {
+                        let constants = constants.texel_size(source_extent);
+                        fullscreen(ctx, target, extent, LoadOp::DontCare, |ctx| {
+                            draw(ctx, pipeline, layout, &sets, &constants)
+                        });
+                    });
+            }
+
+            // each level is added over the one above it, the first one ends up with all
+            for level in (0..bloom.len() - 1).rev() {
+                let (source, source_extent) = bloom[level + 1];
+                let (target, extent) = bloom[level];
+                let pipeline = pipelines.up;
+                let sets = [self.bloom_sets[level + 1]];
+                graph
+                    .add_pass("bloom upsample")
+                    .read_image(source, ImageUsage::Sampled)
+                    .write_image(target, ImageUsage::ColorAttachment)
+                    .execute(move |ctx| {
+                        let constants = constants.texel_size(source_extent);
+                        fullscreen(ctx, target, extent, LoadOp::Load, |ctx| {
+                            draw(ctx, pipeline, layout, &sets, &constants)
+                        });
+                    });
+            }
+        }
+
+        // the bloom set is never sampled when bloom is off, any valid set does
+        let bloom_set = if settings.bloom {
+            self.bloom_sets[0]
+        } else {
+            self.scene_set
+        };
+        let tonemap_sets = [self.scene_set, bloom_set, self.lut_set];
+        let mut tonemap_reads = vec![frame.resolved];
+        if settings.bloom {
+            tonemap_reads.push(bloom[0].0);
+        }
+
+        // TAA already ran on the scene
+        let fxaa = settings.anti_aliasing == AntiAliasing::Fxaa;
+        if !fxaa && !scaled {
+            let pipeline = pipelines.tonemap;
+            let constants = constants.flags(flags | POST_ENCODE);
+            return renderer.draw_output("tonemap", &tonemap_reads, move |ctx| {
+                draw(ctx, pipeline, layout, &tonemap_sets, &constants)
+            });
+        }
+
+        let ldr = graph.import_image(
+            "ldr image",
+            ImportedImage::from_allocated(&targets.ldr, vk::SampleCountFlags::TYPE_1),
+        );
+        let pipeline = pipelines.tonemap_ldr;
+        let mut pass = graph.add_pass("tonemap");
+        for image in &tonemap_reads {
+            pass = pass.read_image(*image, ImageUsage::Sampled);
+        }
+        pass.write_image(ldr, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                let constants = constants.flags(flags);
+                fullscreen(ctx, ldr, frame.extent, LoadOp::DontCare, |ctx| {
+                    draw(ctx, pipeline, layout, &tonemap_sets, &constants)
+                });
+            });
+
+        if !scaled {
+            let pipeline = pipelines.fxaa;
+            let sets = [self.ldr_set];
+            let constants = constants.texel_size(frame.extent).flags(POST_ENCODE);
+            return renderer.draw_output("fxaa", &[ldr], move |ctx| {
+                draw(ctx, pipeline, layout, &sets, &constants)
+            });
+        }
+
+        // a scaled scene is anti-aliased at its own size, then upscaled to the output
+        let (mut upscale_source, mut upscale_set) = (ldr, self.ldr_set);
+        if fxaa {
+            let image = graph.import_image(
+                "fxaa image",
+                ImportedImage::from_allocated(
+                    targets.fxaa.as_ref().unwrap(),
+                    vk::SampleCountFlags::TYPE_1,
+                ),
+            );
+            let pipeline = pipelines.fxaa_ldr;
+            let sets = [self.ldr_set];
+            graph
+                .add_pass("fxaa")
+                .read_image(ldr, ImageUsage::Sampled)
+                .write_image(image, ImageUsage::ColorAttachment)
+                .execute(move |ctx| {
+                    let constants = constants.texel_size(frame.extent).flags(0);
+                    fullscreen(ctx, image, frame.extent, LoadOp::DontCare, |ctx| {
+                        draw(ctx, pipeline, layout, &sets, &constants)
+                    });
+                });
+            (upscale_source, upscale_set) = (image, self.fxaa_set);
+        }
+
+        let mut flags = POST_ENCODE;
+        if resolution.upscale == Upscale::Sharpen {
+            flags |= POST_SHARPEN;
+        }
+        let pipeline = pipelines.upscale;
+        let sets = [upscale_set];
+        let constants = constants.texel_size(frame.extent).flags(flags);
+        renderer.draw_output("upscale", &[upscale_source], move |ctx| {
+            draw(ctx, pipeline, layout, &sets, &constants)
+        })
+    }
+
+    /// Nothing may be in flight that still uses the chain
+    pub fn destroy(mut self, renderer: &Renderer) {
+        if let Some(targets) = self.targets.take() {
+            Self::destroy_targets(renderer, targets);
+        }
+        self.lut.destroy(renderer);
+
+        let device = renderer.device_handle();
+        if let Some(pipelines) = self.pipelines.take() {
+            pipelines.destroy(device);
+        }
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+/// Renders over the whole of `image` with `function`
+fn fullscreen(
+    ctx: &PassContext,
+    image: ImageId,
+    extent: vk::Extent2D,
+    load: LoadOp,
+    function: impl FnOnce(&PassContext),
+) {
+    let attachment = Attachment {
+        image,
+        load,
+        store: true,
+        resolve: None,
+    };
+    ctx.begin_rendering(&[attachment], None);
+    ctx.set_viewport(extent);
+    function(ctx);
+    ctx.end_rendering();
+}
+
+/// Draws the screen covering triangle of `post.vert`, `constants` are pushed to the
+/// fragment shader
+fn draw<T: Pod>(
+    ctx: &PassContext,
+    pipeline: vk::Pipeline,
+    layout: vk::PipelineLayout,
+    sets: &[vk::DescriptorSet],
+    constants: &T,
+) {
+    unsafe {
+        ctx.device
+            .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+        ctx.device.cmd_bind_descriptor_sets(
+            ctx.cmd,
+            vk::PipelineBindPoint::GRAPHICS,
+            layout,
+            0,
+            sets,
+            &[],
+        );
+        ctx.device.cmd_push_constants(
+            ctx.cmd,
+            layout,
+            vk::ShaderStageFlags::FRAGMENT,
+            0,
+            bytemuck::bytes_of(constants),
+        );
+        ctx.device.cmd_draw(ctx.cmd, 3, 1, 0, 0);
+    }
+    ctx.count_draws(1);
+}
@@ -0,0 +1,510 @@
+//! Screen-space ambient occlusion from the depth buffer, multiplied over the scene
+
+use std::{error::Error, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+
+use super::{draw, fullscreen};
+use crate::{
+    config::SsaoSettings,
+    core,
+    graph::{ImageUsage, ImportedImage, LoadOp},
+    shaders, Renderer,
+};
+
+const DEPTH_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+const OCCLUSION_FORMAT: vk::Format = vk::Format::R8_UNORM;
+
+const MIN_SAMPLES: u32 = 4;
+
+/// Layout of `Constants` in `ssao.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SsaoConstants {
+    proj_scale: [f32; 2],
+    depth_params: [f32; 2],
+    texel_size: [f32; 2],
+    direction: [f32; 2],
+    radius: f32,
+    intensity: f32,
+    sky_depth: f32,
+    samples: u32,
+    scale: u32,
+    _pad: [u32; 3],
+}
+
+impl SsaoConstants {
+    fn blur(mut self, direction: [f32; 2]) -> Self {
+        self.direction = direction;
+        self
+    }
+}
+
+/// `(a, b)` such that the view depth of a depth buffer value `d` is `a / (d + b)`, for
+/// any perspective projection looking down -Z, reversed or infinite
+fn linear_depth_params(proj: &Mat4) -> [f32; 2] {
+    [proj.w_axis.z, proj.z_axis.z]
+}
+
+/// Extent the occlusion is computed at and the depth texels per occlusion texel
+fn occlusion_extent(extent: vk::Extent2D, half_resolution: bool) -> (vk::Extent2D, u32) {
+    if !half_resolution {
+        return (extent, 1);
+    }
+    let half = vk::Extent2D {
+        width: (extent.width / 2).max(1),
+        height: (extent.height / 2).max(1),
+    };
+    (half, 2)
+}
+
+/// What the images and the depth buffer set were made for
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct TargetKey {
+    depth_view: vk::ImageView,
+    extent: vk::Extent2D,
+    half_resolution: bool,
+}
+
+struct Targets {
+    /// Linear view depth at the occlusion resolution
+    depth: core::AllocatedImage,
+    occlusion: core::AllocatedImage,
+    /// Horizontal pass of the blur
+    blurred: core::AllocatedImage,
+    key: TargetKey,
+}
+
+struct Pipelines {
+    layout: vk::PipelineLayout,
+    depth: vk::Pipeline,
+    occlusion: vk::Pipeline,
+    blur: vk::Pipeline,
+    apply: vk::Pipeline,
+    /// Scene format, sample count and depth format the apply pass draws with
+    formats: (vk::Format, vk::SampleCountFlags, vk::Format),
+}
+
+impl Pipelines {
+    fn new(
+        device: &ash::Device,
+        set_layout: vk::DescriptorSetLayout,
+        formats: (vk::Format, vk::SampleCountFlags, vk::Format),
+    ) -> Result<Self, vk::Result> {
+        let layout = core::create_pipeline_layout(
+            device,
+            &[set_layout; 2],
+            size_of::<SsaoConstants>() as u32,
+            vk::ShaderStageFlags::FRAGMENT,
+        )?;
+
+        // the multisampled depth buffer needs its own sampler type
+        let (scene_format, samples, depth_format) = formats;
+        let depth_shader = if samples == vk::SampleCountFlags::TYPE_1 {
+            shaders::SSAO_DEPTH_FRAG
+        } else {
+            shaders::SSAO_DEPTH_MS_FRAG
+        };
+
+        let vertex = shaders::words(shaders::POST_VERT);
+        let mut pipelines = vec![];
+        let stages = [
+            (depth_shader, DEPTH_FORMAT, false),
+            (shaders::SSAO_FRAG, OCCLUSION_FORMAT, false),
+            (shaders::SSAO_BLUR_FRAG, OCCLUSION_FORMAT, false),
+            (shaders::SSAO_APPLY_FRAG, scene_format, true),
+        ];
+        for (code, format, over_scene) in stages {
+            let fragment = shaders::words(code);
+            let mut builder = core::GraphicsPipelineBuilder::new(layout)
+                .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+                .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+                .color_format(format);
+            // the last pass draws over the scene like any other frame pass
+            if over_scene {
+                builder = builder
+                    .depth(depth_format, None, false)
+                    .samples(samples)
+                    .blend(core::Blend::Multiply);
+            }
+
+            match builder.build(device) {
+                Ok(pipeline) => pipelines.push(pipeline),
+                Err(err) => {
+                    unsafe {
+                        for pipeline in pipelines {
+                            device.destroy_pipeline(pipeline, None);
+                        }
+                        device.destroy_pipeline_layout(layout, None);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(Self {
+            layout,
+            depth: pipelines[0],
+            occlusion: pipelines[1],
+            blur: pipelines[2],
+            apply: pipelines[3],
+            formats,
+        })
+    }
+
+    fn destroy(self, device: &ash::Device) {
+        unsafe {
+            for pipeline in [self.depth, self.occlusion, self.blur, self.apply] {
+                device.destroy_pipeline(pipeline, None);
+            }
+            device.destroy_pipeline_layout(self.layout, None);
+        }
+    }
+}
+
+/// Ambient occlusion of everything drawn in the scene, configured with the
+/// [`SsaoSettings`] of the render settings. Its images follow the size of the scene, so
+/// use one per surface.
+pub struct AmbientOcclusion {
+    set_layout: vk::DescriptorSetLayout,
+    /// Depth is never filtered, the occlusion is upsampled bilinearly
+    nearest: vk::Sampler,
+    linear: vk::Sampler,
+    depth_buffer_set: vk::DescriptorSet,
+    depth_set: vk::DescriptorSet,
+    occlusion_set: vk::DescriptorSet,
+    blurred_set: vk::DescriptorSet,
+    targets: Option<Targets>,
+    pipelines: Option<Pipelines>,
+}
+
+impl AmbientOcclusion {
+    pub fn new(renderer: &mut Renderer) -> Result<Self, Box<dyn Error>> {
+        let device = renderer.device_handle().clone();
+
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .build(&device, vk::ShaderStageFlags::FRAGMENT)?;
+
+        let sampler = |filter| {
+            let info = vk::SamplerCreateInfo::default()
+                .mag_filter(filter)
+                .min_filter(filter)
+                .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+            unsafe { device.create_sampler(&info, None) }
+        };
+        let nearest = sampler(vk::Filter::NEAREST)?;
+        let linear = sampler(vk::Filter::LINEAR)?;
+
+        Ok(Self {
+            set_layout,
+            nearest,
+            linear,
+            depth_buffer_set: renderer.allocate_descriptor_set(set_layout)?,
+            depth_set: renderer.allocate_descriptor_set(set_layout)?,
+            occlusion_set: renderer.allocate_descriptor_set(set_layout)?,
+            blurred_set: renderer.allocate_descriptor_set(set_layout)?,
+            targets: None,
+            pipelines: None,
+        })
+    }
+
+    fn write_set(
+        device: &ash::Device,
+        set: vk::DescriptorSet,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) {
+        core::DescriptorWriter::new()
+            .write_image(
+                0,
+                view,
+                sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            )
+            .update_set(device, set);
+    }
+
+    /// Recreates the images when the depth buffer or the resolution changed, waiting
+    /// for the device
+    fn prepare(&mut self, renderer: &mut Renderer, key: TargetKey) -> Result<(), Box<dyn Error>> {
+        if self.targets.as_ref().map(|val| val.key) == Some(key) {
+            return Ok(());
+        }
+
+        renderer.wait_idle();
+        if let Some(targets) = self.targets.take() {
+            Self::destroy_targets(renderer, targets);
+        }
+
+        let (extent, _) = occlusion_extent(key.extent, key.half_resolution);
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+        let targets = Targets {
+            depth: renderer.create_image(&core::ImageSpec::color(
+                "ssao depth",
+                extent,
+                DEPTH_FORMAT,
+                usage,
+            ))?,
+            occlusion: renderer.create_image(&core::ImageSpec::color(
+                "ssao",
+                extent,
+                OCCLUSION_FORMAT,
+                usage,
+            ))?,
+            blurred: renderer.create_image(&core::ImageSpec::color(
+                "ssao blur",
+                extent,
+                OCCLUSION_FORMAT,
+                usage,
+            ))?,
+            key,
+        };
+
+        let device = renderer.device_handle();
+        Self::write_set(device, self.depth_buffer_set, key.depth_view, self.nearest);
+        Self::write_set(device, self.depth_set, targets.depth.view, self.nearest);
+        Self::write_set(
+            device,
+            self.occlusion_set,
+            targets.occlusion.view,
+            self.linear,
+        );
+        Self::write_set(device, self.blurred_set, targets.blurred.view, self.linear);
+
+        log::trace!("Ambient occlusion at {:?}", extent);
+        self.targets = Some(targets);
+        Ok(())
+    }
+
+    fn destroy_targets(renderer: &Renderer, targets: Targets) {
+        renderer.destroy_image(targets.depth);
+        renderer.destroy_image(targets.occlusion);
+        renderer.destroy_image(targets.blurred);
+    }
+
+    /// Adds the passes computing the occlusion of the depth buffer seen through the
+    /// perspective `proj` and darkening the scene with it. Call it once the scene has
+    /// been drawn, before [`PostProcess::render`](crate::PostProcess::render). Does
+    /// nothing when disabled in the settings.
+    pub fn render(&mut self, renderer: &mut Renderer, proj: &Mat4) -> Result<(), Box<dyn Error>> {
+        let settings = renderer.render_settings().ssao;
+        if !settings.enabled {
+            return Ok(());
+        }
+
+        let frame = renderer
+            .frame()
+            .ok_or("Ambient occlusion outside of a frame")?;
+        let formats = (
+            renderer
+                .draw_format(frame.surface)
+                .ok_or("Unknown surface")?,
+            renderer.sample_count(),
+            renderer.depth_format(),
+        );
+        let depth_view = renderer
+            .graph()
+            .imported_image(frame.depth)
+            .ok_or("Depth buffer is not imported")?
+            .view;
+
+        self.prepare(
+            renderer,
+            TargetKey {
+                depth_view,
+                extent: frame.extent,
+                half_resolution: settings.half_resolution,
+            },
+        )?;
+
+        if self.pipelines.as_ref().map(|val| val.formats) != Some(formats) {
+            if let Some(pipelines) = self.pipelines.take() {
+                renderer.wait_idle();
+                pipelines.destroy(renderer.device_handle());
+            }
+            self.pipelines = Some(Pipelines::new(
+                renderer.device_handle(),
+                self.set_layout,
+                formats,
+            )?);
+        }
+
+        let constants =
+            Self::constants(&settings, proj, renderer.depth_clear_value(), frame.extent);
+        let targets = self.targets.as_ref().unwrap();
+        let pipelines = self.pipelines.as_ref().unwrap();
+        let layout = pipelines.layout;
+        let extent = targets.depth.extent_2d();
+
+        let graph = renderer.graph();
+        let import = |image: &core::AllocatedImage| {
+            ImportedImage::from_allocated(image, vk::SampleCountFlags::TYPE_1)
+        };
+        let depth = graph.import_image("ssao depth", import(&targets.depth));
+        let occlusion = graph.import_image("ssao", import(&targets.occlusion));
+        let blurred = graph.import_image("ssao blur", import(&targets.blurred));
+
+        let pipeline = pipelines.depth;
+        let sets = [self.depth_buffer_set];
+        graph
+            .add_pass("ssao depth")
+            .read_image(frame.depth, ImageUsage::Sampled)
+            .write_image(depth, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                fullscreen(ctx, depth, extent, LoadOp::DontCare, |ctx| {
+                    draw(ctx, pipeline, layout, &sets, &constants)
+                });
+            });
+
+        let pipeline = pipelines.occlusion;
+        let sets = [self.depth_set];
+        graph
+            .add_pass("ssao")
+            .read_image(depth, ImageUsage::Sampled)
+            .write_image(occlusion, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                fullscreen(ctx, occlusion, extent, LoadOp::DontCare, |ctx| {
+                    draw(ctx, pipeline, layout, &sets, &constants)
+                });
+            });
+
+        if settings.blur {
+            let passes = [
+                (
+                    "ssao blur x",
+                    occlusion,
+                    self.occlusion_set,
+                    blurred,
+                    [1.0, 0.0],
+                ),
+                (
+                    "ssao blur y",
+                    blurred,
+                    self.blurred_set,
+                    occlusion,
+                    [0.0, 1.0],
+                ),
+            ];
+            for (name, source, source_set, target, direction) in passes {
+                let pipeline = pipelines.blur;
+                let sets = [source_set, self.depth_set];
+                let constants = constants.blur(direction);
+                graph
+                    .add_pass(name)
+                    .read_image(source, ImageUsage::Sampled)
+                    .read_image(depth, ImageUsage::Sampled)
+                    .write_image(target, ImageUsage::ColorAttachment)
+                    .execute(move |ctx| {
+                        fullscreen(ctx, target, extent, LoadOp::DontCare, |ctx| {
+                            draw(ctx, pipeline, layout, &sets, &constants)
+                        });
+                    });
+            }
+        }
+
+        let pipeline = pipelines.apply;
+        let sets = [self.occlusion_set];
+        renderer.draw_sampling("ssao apply", &[], &[occlusion], move |ctx| {
+            draw(ctx, pipeline, layout, &sets, &constants)
+        })
+    }
+
+    fn constants(
+        settings: &SsaoSettings,
+        proj: &Mat4,
+        sky_depth: f32,
+        extent: vk::Extent2D,
+    ) -> SsaoConstants {
+        let (extent, scale) = occlusion_extent(extent, settings.half_resolution);
+        SsaoConstants {
+            proj_scale: [1.0 / proj.x_axis.x, 1.0 / proj.y_axis.y],
+            depth_params: linear_depth_params(proj),
+            texel_size: [1.0 / extent.width as f32, 1.0 / extent.height as f32],
+            direction: [0.0; 2],
+            radius: settings.radius.max(0.01),
+            intensity: settings.intensity.max(0.0),
+            sky_depth,
+            samples: settings
+                .samples
+                .clamp(MIN_SAMPLES, SsaoSettings::MAX_SAMPLES),
+            scale,
+            _pad: [0; 3],
+        }
+    }
+
+    /// Nothing may be in flight that still uses the occlusion
+    pub fn destroy(mut self, renderer: &Renderer) {
+        if let Some(targets) = self.targets.take() {
+            Self::destroy_targets(renderer, targets);
+        }
+
+        let device = renderer.device_handle();
+        if let Some(pipelines) = self.pipelines.take() {
+            pipelines.destroy(device);
+        }
+        unsafe {
+            device.destroy_sampler(self.nearest, None);
+            device.destroy_sampler(self.linear, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::*;
+
+    fn linearize(params: [f32; 2], depth: f32) -> f32 {
+        params[0] / (depth + params[1])
+    }
+
+    #[test]
+    fn depth_is_linearized_for_every_projection() {
+        let projections = [
+            Mat4::perspective_rh(1.2, 1.5, 0.1, 500.0),
+            Mat4::perspective_infinite_rh(1.2, 1.5, 0.1),
+            Mat4::perspective_infinite_reverse_rh(1.2, 1.5, 0.1),
+        ];
+
+        for proj in projections {
+            let params = linear_depth_params(&proj);
+            for distance in [0.1, 1.0, 7.5, 64.0, 300.0] {
+                let clip = proj * Vec3::new(0.3, -0.2, -distance).extend(1.0);
+                let linear = linearize(params, clip.z / clip.w);
+                assert!(
+                    (linear - distance).abs() < distance * 1e-3,
+                    "{} != {}",
+                    linear,
+                    distance
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn half_resolution_halves_the_extent() {
+        let extent = vk::Extent2D {
+            width: 1279,
+            height: 720,
+        };
+        assert_eq!(occlusion_extent(extent, false), (extent, 1));
+
+        let (half, scale) = occlusion_extent(extent, true);
+        assert_eq!((half.width, half.height, scale), (639, 360, 2));
+
+        let tiny = vk::Extent2D {
+            width: 1,
+            height: 1,
+        };
+        assert_eq!(occlusion_extent(tiny, true).0, tiny);
+    }
+}
@@ -0,0 +1,524 @@
+//! Temporal anti-aliasing: the projection is jittered by a sub-pixel offset every frame
+//! and the frames are accumulated in a history reprojected with the motion of the pixels
+
+use std::{error::Error, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2};
+
+use super::{draw, fullscreen};
+use crate::{
+    config::AntiAliasing,
+    core,
+    graph::{Attachment, ImageId, ImageUsage, ImportedImage, LoadOp, PassContext, ResourceState},
+    shaders,
+    target::SCENE_FORMAT,
+    Renderer,
+};
+
+/// Jitter offsets before the sequence repeats
+const JITTER_PHASES: u32 = 8;
+
+/// Weight of the current frame against the history
+const FEEDBACK: f32 = 0.1;
+
+const MOTION_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+// flags of `Constants` in `taa.glsl`
+const TAA_HISTORY: u32 = 1;
+const TAA_OBJECT_MOTION: u32 = 2;
+const TAA_REVERSE_Z: u32 = 4;
+
+/// Layout of `Constants` in `taa.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct TaaConstants {
+    reprojection: [f32; 16],
+    jitter: [f32; 2],
+    texel_size: [f32; 2],
+    feedback: f32,
+    sharpness: f32,
+    flags: u32,
+    _pad: u32,
+}
+
+/// Element `index` of the Halton low discrepancy sequence in `base`, in [0, 1)
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Sub-pixel offset of a phase of the sequence, in pixels within [-0.5, 0.5)
+fn jitter_offset(phase: u32) -> Vec2 {
+    let index = phase % JITTER_PHASES + 1;
+    Vec2::new(halton(index, 2), halton(index, 3)) - 0.5
+}
+
+/// Moves everything `proj` projects by `jitter` in NDC
+fn jitter_projection(proj: &Mat4, jitter: Vec2) -> Mat4 {
+    Mat4::from_translation(jitter.extend(0.0)) * *proj
+}
+
+struct Targets {
+    /// Accumulated frames, read and written in turn
+    history: [core::AllocatedImage; 2],
+    /// Whether a frame already left the history in SHADER_READ_ONLY_OPTIMAL
+    initialized: [bool; 2],
+    /// Motion of the moving objects, where alpha is set
+    object_motion: core::AllocatedImage,
+    /// Scene and depth views the sets sample
+    source: (vk::ImageView, vk::ImageView, vk::Extent2D),
+}
+
+/// Temporal anti-aliasing of the scene, selected with
+/// [`AntiAliasing::Taa`] in the [`PostSettings`](crate::PostSettings). Draw the frame
+/// with the projection of [`TemporalAntiAliasing::jittered`], then call
+/// [`TemporalAntiAliasing::render`] before [`PostProcess::render`](crate::PostProcess).
+/// Its images follow the size of the scene, so use one per surface.
+pub struct TemporalAntiAliasing {
+    set_layout: vk::DescriptorSetLayout,
+    linear: vk::Sampler,
+    nearest: vk::Sampler,
+    scene_set: vk::DescriptorSet,
+    depth_set: vk::DescriptorSet,
+    history_sets: [vk::DescriptorSet; 2],
+    object_motion_set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    resolve: vk::Pipeline,
+    sharpen: vk::Pipeline,
+    targets: Option<Targets>,
+    /// History written by the last frame
+    current: usize,
+    /// Whether the last frame left a history to blend with
+    history_valid: bool,
+    phase: u32,
+    previous_view_proj: Option<Mat4>,
+    /// Frame the object motion was cleared in
+    object_motion_frame: Option<u64>,
+}
+
+impl TemporalAntiAliasing {
+    pub fn new(renderer: &mut Renderer) -> Result<Self, Box<dyn Error>> {
+        let device = renderer.device_handle().clone();
+
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .build(&device, vk::ShaderStageFlags::FRAGMENT)?;
+
+        let sampler = |filter| {
+            let info = vk::SamplerCreateInfo::default()
+                .mag_filter(filter)
+                .min_filter(filter)
+                .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+                .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+                .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+            unsafe { device.create_sampler(&info, None) }
+        };
+        let linear = sampler(vk::Filter::LINEAR)?;
+        let nearest = sampler(vk::Filter::NEAREST)?;
+
+        // scene, history, depth and object motion
+        let layout = core::create_pipeline_layout(
+            &device,
+            &[set_layout; 4],
+            size_of::<TaaConstants>() as u32,
+            vk::ShaderStageFlags::FRAGMENT,
+        )?;
+
+        let vertex = shaders::words(shaders::POST_VERT);
+        let pipeline = |code| {
+            let fragment = shaders::words(code);
+            core::GraphicsPipelineBuilder::new(layout)
+                .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+                .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+                .color_format(SCENE_FORMAT)
+                .build(&device)
+        };
+        let resolve = pipeline(shaders::TAA_RESOLVE_FRAG)?;
+        let sharpen = pipeline(shaders::TAA_SHARPEN_FRAG)?;
+
+        Ok(Self {
+            set_layout,
+            linear,
+            nearest,
+            scene_set: renderer.allocate_descriptor_set(set_layout)?,
+            depth_set: renderer.allocate_descriptor_set(set_layout)?,
+            history_sets: [
+                renderer.allocate_descriptor_set(set_layout)?,
+                renderer.allocate_descriptor_set(set_layout)?,
+            ],
+            object_motion_set: renderer.allocate_descriptor_set(set_layout)?,
+            layout,
+            resolve,
+            sharpen,
+            targets: None,
+            current: 0,
+            history_valid: false,
+            phase: 0,
+            previous_view_proj: None,
+            object_motion_frame: None,
+        })
+    }
+
+    fn is_selected(renderer: &Renderer) -> bool {
+        renderer.render_settings().post.anti_aliasing == AntiAliasing::Taa
+    }
+
+    /// NDC offset the frame being recorded is drawn with, zero when TAA isn't selected
+    pub fn jitter(&self, renderer: &Renderer) -> Vec2 {
+        let Some(frame) = renderer.frame() else {
+            return Vec2::ZERO;
+        };
+        if !Self::is_selected(renderer) {
+            return Vec2::ZERO;
+        }
+
+        let extent = Vec2::new(frame.extent.width as f32, frame.extent.height as f32);
+        jitter_offset(self.phase) * 2.0 / extent
+    }
+
+    /// `proj` with the jitter of the frame being recorded, the scene must be drawn with
+    /// it. Motion vectors are computed without it.
+    pub fn jittered(&self, renderer: &Renderer, proj: &Mat4) -> Mat4 {
+        jitter_projection(proj, self.jitter(renderer))
+    }
+
+    /// Format of the motion vectors of [`TemporalAntiAliasing::draw_motion`]
+    pub fn motion_format(&self) -> vk::Format {
+        MOTION_FORMAT
+    }
+
+    /// Adds a pass drawing the motion of moving objects, like entities, over the motion
+    /// found from the camera. Pipelines write the UV offset from where the object was
+    /// in the last frame to where it is now in rg and 1 in alpha, both unjittered, in a
+    /// [`TemporalAntiAliasing::motion_format`] attachment tested against the frame
+    /// depth. Does nothing when TAA isn't selected.
+    pub fn draw_motion<F>(
+        &mut self,
+        renderer: &mut Renderer,
+        name: &str,
+        function: F,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        F: FnOnce(&PassContext) + 'static,
+    {
+        if !Self::is_selected(renderer) {
+            return Ok(());
+        }
+        let frame = renderer
+            .frame()
+            .ok_or("Drawing motion outside of a frame")?;
+        self.prepare(renderer)?;
+
+        // the first pass of the frame clears the motion of the last one
+        let clear = self.object_motion_frame != Some(renderer.frame_count());
+        self.object_motion_frame = Some(renderer.frame_count());
+        let load = if clear {
+            LoadOp::Clear(vk::ClearValue::default())
+        } else {
+            LoadOp::Load
+        };
+
+        let motion = self.import_object_motion(renderer);
+        renderer
+            .graph()
+            .add_pass(name)
+            .write_image(motion, ImageUsage::ColorAttachment)
+            .write_image(frame.depth, ImageUsage::DepthAttachment)
+            .execute(move |ctx| {
+                let attachment = Attachment {
+                    image: motion,
+                    load,
+                    store: true,
+                    resolve: None,
+                };
+                ctx.begin_rendering(&[attachment], Some(Attachment::load(frame.depth)));
+                ctx.set_viewport(frame.extent);
+                function(ctx);
+                ctx.end_rendering();
+            });
+        Ok(())
+    }
+
+    fn import_object_motion(&self, renderer: &mut Renderer) -> ImageId {
+        let targets = self.targets.as_ref().unwrap();
+        renderer.graph().import_image(
+            "object motion",
+            ImportedImage::from_allocated(&targets.object_motion, vk::SampleCountFlags::TYPE_1),
+        )
+    }
+
+    fn write_set(
+        device: &ash::Device,
+        set: vk::DescriptorSet,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) {
+        core::DescriptorWriter::new()
+            .write_image(
+                0,
+                view,
+                sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            )
+            .update_set(device, set);
+    }
+
+    /// Recreates the history when the scene or depth images changed, with the
+    /// swapchain, waiting for the device
+    fn prepare(&mut self, renderer: &mut Renderer) -> Result<(), Box<dyn Error>> {
+        let frame = renderer.frame().ok_or("TAA outside of a frame")?;
+        let graph = renderer.graph();
+        let scene_view = graph
+            .imported_image(frame.resolved)
+            .ok_or("The scene image isn't imported")?
+            .view;
+        let depth_view = graph
+            .imported_image(frame.depth)
+            .ok_or("Depth buffer is not imported")?
+            .view;
+        let source = (scene_view, depth_view, frame.extent);
+
+        if self.targets.as_ref().map(|val| val.source) == Some(source) {
+            return Ok(());
+        }
+
+        renderer.wait_idle();
+        if let Some(targets) = self.targets.take() {
+            Self::destroy_targets(renderer, targets);
+        }
+
+        let usage = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+        let image = |name, format| {
+            renderer.create_image(&core::ImageSpec::color(name, frame.extent, format, usage))
+        };
+        let targets = Targets {
+            history: [
+                image("taa history", SCENE_FORMAT)?,
+                image("taa history", SCENE_FORMAT)?,
+            ],
+            initialized: [false; 2],
+            object_motion: image("object motion", MOTION_FORMAT)?,
+            source,
+        };
+
+        let device = renderer.device_handle();
+        Self::write_set(device, self.scene_set, scene_view, self.linear);
+        Self::write_set(device, self.depth_set, depth_view, self.nearest);
+        for (set, image) in self.history_sets.iter().zip(&targets.history) {
+            Self::write_set(device, *set, image.view, self.linear);
+        }
+        Self::write_set(
+            device,
+            self.object_motion_set,
+            targets.object_motion.view,
+            self.nearest,
+        );
+
+        log::trace!("TAA history of {:?}", frame.extent);
+        self.targets = Some(targets);
+        self.history_valid = false;
+        self.object_motion_frame = None;
+        Ok(())
+    }
+
+    fn destroy_targets(renderer: &Renderer, targets: Targets) {
+        let [first, second] = targets.history;
+        renderer.destroy_image(first);
+        renderer.destroy_image(second);
+        renderer.destroy_image(targets.object_motion);
+    }
+
+    /// Adds the passes blending the scene, drawn from `view_proj` without the jitter,
+    /// into the history and sharpening it back into the scene. Does nothing when TAA
+    /// isn't selected.
+    pub fn render(
+        &mut self,
+        renderer: &mut Renderer,
+        view_proj: &Mat4,
+    ) -> Result<(), Box<dyn Error>> {
+        if !Self::is_selected(renderer) {
+            self.history_valid = false;
+            self.previous_view_proj = None;
+            return Ok(());
+        }
+
+        let frame = renderer.frame().ok_or("TAA outside of a frame")?;
+        let resolved = renderer.resolve_scene()?;
+        self.prepare(renderer)?;
+
+        let jitter = self.jitter(renderer);
+        let jittered = jitter_projection(view_proj, jitter);
+        let previous = self.previous_view_proj.unwrap_or(*view_proj);
+
+        let mut flags = 0;
+        if self.history_valid {
+            flags |= TAA_HISTORY;
+        }
+        if self.object_motion_frame == Some(renderer.frame_count()) {
+            flags |= TAA_OBJECT_MOTION;
+        }
+        if renderer.reverse_z() {
+            flags |= TAA_REVERSE_Z;
+        }
+        let constants = TaaConstants {
+            reprojection: (previous * jittered.inverse()).to_cols_array(),
+            jitter: jitter.to_array(),
+            texel_size: [
+                1.0 / frame.extent.width as f32,
+                1.0 / frame.extent.height as f32,
+            ],
+            feedback: FEEDBACK,
+            sharpness: renderer.render_settings().post.taa_sharpness.max(0.0),
+            flags,
+            _pad: 0,
+        };
+
+        // sampled even when nothing drew motion, so the set always sees a valid layout
+        let object_motion = self.import_object_motion(renderer);
+
+        let (read, write) = (self.current, 1 - self.current);
+        let history_read = self.import_history(renderer, read);
+        let history_write = self.import_history(renderer, write);
+
+        let (layout, pipeline) = (self.layout, self.resolve);
+        let sets = [
+            self.scene_set,
+            self.history_sets[read],
+            self.depth_set,
+            self.object_motion_set,
+        ];
+        let extent = frame.extent;
+        renderer
+            .graph()
+            .add_pass("taa resolve")
+            .read_image(resolved, ImageUsage::Sampled)
+            .read_image(history_read, ImageUsage::Sampled)
+            .read_image(frame.depth, ImageUsage::Sampled)
+            .read_image(object_motion, ImageUsage::Sampled)
+            .write_image(history_write, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                fullscreen(ctx, history_write, extent, LoadOp::DontCare, |ctx| {
+                    draw(ctx, pipeline, layout, &sets, &constants)
+                });
+            });
+
+        // the history itself stays unsharpened
+        let pipeline = self.sharpen;
+        let sets = [self.history_sets[write]];
+        renderer
+            .graph()
+            .add_pass("taa sharpen")
+            .read_image(history_write, ImageUsage::Sampled)
+            .write_image(resolved, ImageUsage::ColorAttachment)
+            .execute(move |ctx| {
+                fullscreen(ctx, resolved, extent, LoadOp::DontCare, |ctx| {
+                    draw(ctx, pipeline, layout, &sets, &constants)
+                });
+            });
+
+        self.current = write;
+        self.history_valid = true;
+        self.previous_view_proj = Some(*view_proj);
+        self.phase = (self.phase + 1) % JITTER_PHASES;
+        Ok(())
+    }
+
+    /// Imports a history image, they are always left ready to be sampled
+    fn import_history(&mut self, renderer: &mut Renderer, index: usize) -> ImageId {
+        let targets = self.targets.as_mut().unwrap();
+        let initial = if targets.initialized[index] {
+            ResourceState {
+                stage: vk::PipelineStageFlags2::ALL_COMMANDS,
+                access: vk::AccessFlags2::MEMORY_WRITE,
+                layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }
+        } else {
+            ResourceState::UNKNOWN
+        };
+        targets.initialized[index] = true;
+
+        let mut image =
+            ImportedImage::from_allocated(&targets.history[index], vk::SampleCountFlags::TYPE_1);
+        image.initial = initial;
+        image.final_layout = Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        renderer.graph().import_image("taa history", image)
+    }
+
+    /// Nothing may be in flight that still uses the history
+    pub fn destroy(mut self, renderer: &Renderer) {
+        if let Some(targets) = self.targets.take() {
+            Self::destroy_targets(renderer, targets);
+        }
+
+        let device = renderer.device_handle();
+        unsafe {
+            device.destroy_pipeline(self.resolve, None);
+            device.destroy_pipeline(self.sharpen, None);
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_sampler(self.linear, None);
+            device.destroy_sampler(self.nearest, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    #[test]
+    fn halton_fills_the_unit_interval() {
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 2), 0.25);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+        assert!((halton(2, 3) - 2.0 / 3.0).abs() < 1e-6);
+        assert!((halton(4, 3) - 4.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn jitter_stays_within_a_pixel_and_averages_out() {
+        let mut sum = Vec2::ZERO;
+        for phase in 0..JITTER_PHASES {
+            let offset = jitter_offset(phase);
+            assert!(offset.abs().max_element() <= 0.5, "{:?}", offset);
+            sum += offset;
+        }
+        assert!((sum / JITTER_PHASES as f32).abs().max_element() < 0.1);
+
+        // every phase is another offset, and the sequence repeats
+        for phase in 1..JITTER_PHASES {
+            assert_ne!(jitter_offset(phase), jitter_offset(0));
+        }
+        assert_eq!(jitter_offset(JITTER_PHASES), jitter_offset(0));
+    }
+
+    #[test]
+    fn jittered_projections_shift_every_depth_equally() {
+        let proj = Mat4::perspective_infinite_reverse_rh(1.2, 1.5, 0.1);
+        let jitter = Vec2::new(0.003, -0.002);
+        let jittered = jitter_projection(&proj, jitter);
+
+        for point in [
+            Vec3::new(0.5, 0.2, -1.0),
+            Vec3::new(-3.0, 4.0, -40.0),
+            Vec3::new(10.0, -2.0, -300.0),
+        ] {
+            let ndc = proj.project_point3(point);
+            let moved = jittered.project_point3(point);
+            assert!((moved.truncate() - ndc.truncate() - jitter).length() < 1e-5);
+            assert!((moved.z - ndc.z).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Dynamic resolution: picks the scale the scene is drawn at from the GPU time of the
+//! frames, see [`ResolutionSettings`]
+
+use ash::vk;
+
+use crate::config::ResolutionSettings;
+
+/// Scales are multiples of it so the attachments aren't recreated for every small change
+const SCALE_STEP: f32 = 0.05;
+
+/// Weight of the latest frame time in the smoothed one
+const SMOOTHING: f32 = 0.1;
+
+/// Frames the scale is kept after changing, the frame times have to follow the new scale
+/// before the next change
+const COOLDOWN: u32 = 30;
+
+/// Fraction of the target frame time under which the scale goes up, keeps it from going
+/// back and forth around the target
+const HEADROOM: f32 = 0.85;
+
+/// Scale of the scene of a surface
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ResolutionScaler {
+    scale: f32,
+    /// Smoothed GPU time of the frames, in milliseconds
+    frame_time: Option<f32>,
+    cooldown: u32,
+}
+
+impl ResolutionScaler {
+    pub fn new(settings: &ResolutionSettings) -> Self {
+        let mut scaler = Self {
+            scale: 1.0,
+            frame_time: None,
+            cooldown: 0,
+        };
+        scaler.scale = scaler.bounds(settings).1;
+        scaler
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Lowest and highest scale, quantized
+    fn bounds(&self, settings: &ResolutionSettings) -> (f32, f32) {
+        if !settings.dynamic {
+            return (1.0, 1.0);
+        }
+
+        let quantize = |scale: f32| (scale / SCALE_STEP).round() * SCALE_STEP;
+        let max = quantize(settings.max_scale.clamp(SCALE_STEP, 1.0));
+        let min = quantize(settings.min_scale.clamp(SCALE_STEP, 1.0)).min(max);
+        (min, max)
+    }
+
+    /// Takes in the GPU time of the last frame, in milliseconds when it was measured,
+    /// and returns the scale of the next one
+    pub fn update(&mut self, settings: &ResolutionSettings, gpu_time: Option<f32>) -> f32 {
+        let (min, max) = self.bounds(settings);
+        if !settings.dynamic {
+            self.frame_time = None;
+            self.scale = 1.0;
+            return self.scale;
+        }
+        self.scale = self.scale.clamp(min, max);
+
+        if let Some(time) = gpu_time {
+            let smoothed = match self.frame_time {
+                Some(val) => val + (time - val) * SMOOTHING,
+                None => time,
+            };
+            self.frame_time = Some(smoothed);
+        }
+
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return self.scale;
+        }
+        let Some(time) = self.frame_time.filter(|val| *val > 0.0) else {
+            return self.scale;
+        };
+
+        let target = settings.target_frame_time.max(0.1);
+        let over = time > target;
+        let under = time < target * HEADROOM;
+        if !over && !under {
+            return self.scale;
+        }
+
+        // the GPU time follows the pixel count, the square of the scale
+        let ideal = self.scale * (target / time).sqrt();
+        let steps = ((ideal - self.scale) / SCALE_STEP).round();
+        let steps = if over {
+            steps.min(-1.0)
+        } else {
+            steps.max(1.0)
+        };
+        let scale = (self.scale + steps * SCALE_STEP).clamp(min, max);
+
+        if scale != self.scale {
+            // the frames measured at the old scale don't tell anything about the new one
+            self.frame_time = Some(time * (scale / self.scale).powi(2));
+            self.scale = scale;
+            self.cooldown = COOLDOWN;
+        }
+        self.scale
+    }
+}
+
+/// Size the scene is drawn at for an output of `extent`
+pub(crate) fn scaled_extent(extent: vk::Extent2D, scale: f32) -> vk::Extent2D {
+    let scaled = |size: u32| ((size as f32 * scale).round() as u32).clamp(1, size.max(1));
+    vk::Extent2D {
+        width: scaled(extent.width),
+        height: scaled(extent.height),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> ResolutionSettings {
+        ResolutionSettings {
+            dynamic: true,
+            target_frame_time: 16.0,
+            ..ResolutionSettings::default()
+        }
+    }
+
+    /// Runs the scaler against a GPU whose frame time follows the pixel count
+    fn run(scaler: &mut ResolutionScaler, settings: &ResolutionSettings, full_time: f32) -> f32 {
+        for _ in 0..1000 {
+            let time = full_time * scaler.scale().powi(2);
+            scaler.update(settings, Some(time));
+        }
+        scaler.scale()
+    }
+
+    #[test]
+    fn fixed_resolution_without_dynamic_scaling() {
+        let settings = ResolutionSettings::default();
+        let mut scaler = ResolutionScaler::new(&settings);
+        assert_eq!(scaler.update(&settings, Some(100.0)), 1.0);
+        assert_eq!(run(&mut scaler, &settings, 100.0), 1.0);
+    }
+
+    #[test]
+    fn slow_frames_lower_the_scale_until_on_target() {
+        let settings = settings();
+        let mut scaler = ResolutionScaler::new(&settings);
+        assert_eq!(scaler.scale(), 1.0);
+
+        let scale = run(&mut scaler, &settings, 32.0);
+        let time = 32.0 * scale * scale;
+        assert!(scale < 1.0);
+        assert!(
+            (16.0 * HEADROOM * 0.8..=16.0).contains(&time),
+            "{} {}",
+            scale,
+            time
+        );
+    }
+
+    #[test]
+    fn fast_frames_raise_the_scale_back() {
+        let settings = settings();
+        let mut scaler = ResolutionScaler::new(&settings);
+        run(&mut scaler, &settings, 40.0);
+        assert!(scaler.scale() < 0.7);
+
+        assert_eq!(run(&mut scaler, &settings, 8.0), 1.0);
+    }
+
+    #[test]
+    fn scale_stays_within_bounds() {
+        let settings = ResolutionSettings {
+            min_scale: 0.6,
+            max_scale: 0.9,
+            ..settings()
+        };
+        let mut scaler = ResolutionScaler::new(&settings);
+        assert!((scaler.scale() - 0.9).abs() < 1e-6);
+        assert!((run(&mut scaler, &settings, 1000.0) - 0.6).abs() < 1e-6);
+        assert!((run(&mut scaler, &settings, 1.0) - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn scale_holds_without_measurements() {
+        let settings = settings();
+        let mut scaler = ResolutionScaler::new(&settings);
+        for _ in 0..100 {
+            assert_eq!(scaler.update(&settings, None), 1.0);
+        }
+    }
+
+    #[test]
+    fn scaled_extents_are_never_empty() {
+        let extent = vk::Extent2D {
+            width: 1920,
+            height: 1080,
+        };
+        assert_eq!(scaled_extent(extent, 1.0), extent);
+        assert_eq!(
+            scaled_extent(extent, 0.5),
+            vk::Extent2D {
+                width: 960,
+                height: 540
+            }
+        );
+        let tiny = vk::Extent2D {
+            width: 3,
+            height: 1,
+        };
+        assert_eq!(
+            scaled_extent(tiny, 0.05),
+            vk::Extent2D {
+                width: 1,
+                height: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,42 @@
+//! SPIR-V of the shaders in `renderer/shaders/`, compiled by the build script
+
+macro_rules! shader {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/shaders/", $name, ".spv"))
+    };
+}
+
+pub(crate) const BLOOM_DOWN_FRAG: &[u8] = shader!("bloom_down.frag");
+pub(crate) const BLOOM_PREFILTER_FRAG: &[u8] = shader!("bloom_prefilter.frag");
+pub(crate) const BLOOM_UP_FRAG: &[u8] = shader!("bloom_up.frag");
+pub(crate) const CULL_CHUNKS: &[u8] = shader!("cull_chunks.comp");
+pub(crate) const CULL_DEBUG_VERT: &[u8] = shader!("cull_debug.vert");
+pub(crate) const COLOR_FRAG: &[u8] = shader!("color.frag");
+pub(crate) const DEBUG_DRAW_VERT: &[u8] = shader!("debug_draw.vert");
+pub(crate) const FXAA_FRAG: &[u8] = shader!("fxaa.frag");
+pub(crate) const HIZ_REDUCE: &[u8] = shader!("hiz_reduce.comp");
+pub(crate) const POST_VERT: &[u8] = shader!("post.vert");
+pub(crate) const SHADOW_FRAG: &[u8] = shader!("shadow.frag");
+pub(crate) const SSAO_APPLY_FRAG: &[u8] = shader!("ssao_apply.frag");
+pub(crate) const SSAO_BLUR_FRAG: &[u8] = shader!("ssao_blur.frag");
+pub(crate) const SSAO_DEPTH_FRAG: &[u8] = shader!("ssao_depth.frag");
+pub(crate) const SSAO_DEPTH_MS_FRAG: &[u8] = shader!("ssao_depth_ms.frag");
+pub(crate) const SSAO_FRAG: &[u8] = shader!("ssao.frag");
+pub(crate) const SKY_VERT: &[u8] = shader!("sky.vert");
+pub(crate) const SKY_FRAG: &[u8] = shader!("sky.frag");
+pub(crate) const TAA_RESOLVE_FRAG: &[u8] = shader!("taa_resolve.frag");
+pub(crate) const TAA_SHARPEN_FRAG: &[u8] = shader!("taa_sharpen.frag");
+pub(crate) const TEXT_FRAG: &[u8] = shader!("text.frag");
+pub(crate) const TEXT_VERT: &[u8] = shader!("text.vert");
+pub(crate) const TONEMAP_FRAG: &[u8] = shader!("tonemap.frag");
+pub(crate) const UPSCALE_FRAG: &[u8] = shader!("upscale.frag");
+pub(crate) const VOXEL_VERT: &[u8] = shader!("voxel.vert");
+pub(crate) const VOXEL_FRAG: &[u8] = shader!("voxel.frag");
+
+/// SPIR-V words of a compiled shader, the bytes are not guaranteed to be aligned
+pub(crate) fn words(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect()
+}
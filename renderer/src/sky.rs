@@ -0,0 +1,301 @@
+//! Day/night cycle: the sun, moon and sky colors at a time of day, and the pass drawing
+//! the sky behind the world
+
+use std::{error::Error, f32::consts::TAU, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+
+use crate::{core, shaders, Renderer};
+
+/// The sun orbit leans towards +Z so noon shadows don't fall straight down
+const SUN_TILT: f32 = 0.2;
+
+/// Sky light left in the middle of the night, as a fraction of noon
+const MIN_SKY_LIGHT: f32 = 0.2;
+
+/// Elevations of the sun between which the day fades into the night
+const DUSK_ELEVATION: (f32, f32) = (-0.12, 0.18);
+/// Elevation of the sun under which the stars fully show
+const STARS_ELEVATION: f32 = -0.25;
+/// Sun elevation under which the horizon glows with the sunset colors
+const TWILIGHT_ELEVATION: f32 = 0.35;
+
+const DAY_ZENITH: Vec3 = Vec3::new(0.24, 0.45, 0.86);
+const DAY_HORIZON: Vec3 = Vec3::new(0.66, 0.78, 0.95);
+const NIGHT_ZENITH: Vec3 = Vec3::new(0.004, 0.006, 0.025);
+const NIGHT_HORIZON: Vec3 = Vec3::new(0.02, 0.03, 0.07);
+const SUNSET_HORIZON: Vec3 = Vec3::new(0.98, 0.48, 0.2);
+
+const NOON_LIGHT: Vec3 = Vec3::new(1.0, 0.98, 0.94);
+const SUNSET_LIGHT: Vec3 = Vec3::new(1.0, 0.62, 0.36);
+const MOON_LIGHT: Vec3 = Vec3::new(0.62, 0.7, 1.0);
+
+/// Sun, moon and sky at a time of day. Time is a fraction of a day wrapping at 1: the
+/// sun rises in +X at 0.25, is highest at noon (0.5) and sets in -X at 0.75.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkyState {
+    pub time_of_day: f32,
+    /// Towards the sun
+    pub sun_direction: Vec3,
+    /// Towards the moon, always opposite to the sun
+    pub moon_direction: Vec3,
+    /// Direction the light casting the shadows travels in, from the sun by day and the
+    /// moon by night, see [`ShadowMaps::set_light_direction`](crate::ShadowMaps)
+    pub light_direction: Vec3,
+    pub zenith_color: Vec3,
+    pub horizon_color: Vec3,
+    /// Color of the light reaching sky lit blocks
+    pub light_color: Vec3,
+    /// How much of the sky light of the blocks is left, 1 at noon
+    pub sky_light: f32,
+    /// 0 by day, 1 once the sun is well below the horizon
+    pub star_visibility: f32,
+}
+
+impl SkyState {
+    pub fn at(time_of_day: f32) -> Self {
+        let time_of_day = time_of_day.rem_euclid(1.0);
+        let angle = (time_of_day - 0.25) * TAU;
+        let sun_direction = Vec3::new(angle.cos(), angle.sin(), SUN_TILT).normalize();
+        let moon_direction = -sun_direction;
+
+        let elevation = sun_direction.y;
+        let day = smoothstep(DUSK_ELEVATION.0, DUSK_ELEVATION.1, elevation);
+        let twilight = 1.0 - smoothstep(0.0, TWILIGHT_ELEVATION, elevation.abs());
+
+        let zenith_color = NIGHT_ZENITH.lerp(DAY_ZENITH, day);
+        let horizon_color = NIGHT_HORIZON
+            .lerp(DAY_HORIZON, day)
+            .lerp(SUNSET_HORIZON, twilight * day.max(0.5));
+
+        let light_direction = if elevation >= 0.0 {
+            -sun_direction
+        } else {
+            -moon_direction
+        };
+        let light_color = MOON_LIGHT.lerp(NOON_LIGHT.lerp(SUNSET_LIGHT, twilight), day);
+
+        Self {
+            time_of_day,
+            sun_direction,
+            moon_direction,
+            light_direction,
+            zenith_color,
+            horizon_color,
+            light_color,
+            sky_light: MIN_SKY_LIGHT + (1.0 - MIN_SKY_LIGHT) * day,
+            star_visibility: 1.0 - smoothstep(STARS_ELEVATION, DUSK_ELEVATION.0, elevation),
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Layout of `Constants` in `sky.vert`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct SkyConstants {
+    inverse_view_proj: [f32; 16],
+    eye: [f32; 4],
+}
+
+/// Draws the sky gradient, the sun, the moon and the stars of the
+/// [`Renderer::sky_state`] behind everything else
+#[derive(Default)]
+pub struct SkyRenderer {
+    pipeline: Option<(vk::Pipeline, vk::PipelineLayout)>,
+    formats: Option<(vk::Format, vk::SampleCountFlags)>,
+}
+
+impl SkyRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the pass filling the frame with the sky seen from `eye`, call it before
+    /// drawing the world as the sky ignores and doesn't write depth
+    pub fn draw(
+        &mut self,
+        renderer: &mut Renderer,
+        view_proj: &Mat4,
+        eye: Vec3,
+    ) -> Result<(), Box<dyn Error>> {
+        let frame = renderer
+            .frame()
+            .ok_or("Drawing the sky outside of a frame")?;
+        let formats = (
+            renderer
+                .draw_format(frame.surface)
+                .ok_or("Unknown surface")?,
+            renderer.sample_count(),
+        );
+
+        if self.formats != Some(formats) {
+            if let Some((pipeline, layout)) = self.pipeline.take() {
+                renderer.wait_idle();
+                Self::destroy_pipeline(renderer.device_handle(), pipeline, layout);
+            }
+            self.pipeline = Some(Self::create_pipeline(
+                renderer.device_handle(),
+                renderer.frame_set_layout(),
+                formats,
+                renderer.depth_format(),
+            )?);
+            self.formats = Some(formats);
+        }
+
+        let (pipeline, layout) = self.pipeline.unwrap();
+        let set = renderer.frame_set();
+        let constants = SkyConstants {
+            inverse_view_proj: view_proj.inverse().to_cols_array(),
+            eye: eye.extend(1.0).to_array(),
+        };
+
+        renderer.draw("sky", move |ctx| unsafe {
+            ctx.device
+                .cmd_bind_pipeline(ctx.cmd, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            ctx.device.cmd_bind_descriptor_sets(
+                ctx.cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                layout,
+                0,
+                &[set],
+                &[],
+            );
+            ctx.device.cmd_push_constants(
+                ctx.cmd,
+                layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytemuck::bytes_of(&constants),
+            );
+            ctx.device.cmd_draw(ctx.cmd, 3, 1, 0, 0);
+            ctx.count_draws(1);
+        })
+    }
+
+    fn create_pipeline(
+        device: &ash::Device,
+        frame_set_layout: vk::DescriptorSetLayout,
+        formats: (vk::Format, vk::SampleCountFlags),
+        depth_format: vk::Format,
+    ) -> Result<(vk::Pipeline, vk::PipelineLayout), vk::Result> {
+        let layout = core::create_pipeline_layout(
+            device,
+            &[frame_set_layout],
+            size_of::<SkyConstants>() as u32,
+            vk::ShaderStageFlags::VERTEX,
+        )?;
+
+        let vertex = shaders::words(shaders::SKY_VERT);
+        let fragment = shaders::words(shaders::SKY_FRAG);
+
+        // a single triangle covers the screen, the depth attachment is only there
+        // because the frame passes render with it
+        let pipeline = core::GraphicsPipelineBuilder::new(layout)
+            .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+            .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+            .color_format(formats.0)
+            .depth(depth_format, None, false)
+            .samples(formats.1)
+            .build(device);
+
+        match pipeline {
+            Ok(pipeline) => Ok((pipeline, layout)),
+            Err(err) => {
+                unsafe { device.destroy_pipeline_layout(layout, None) };
+                Err(err)
+            }
+        }
+    }
+
+    fn destroy_pipeline(device: &ash::Device, pipeline: vk::Pipeline, layout: vk::PipelineLayout) {
+        unsafe {
+            device.destroy_pipeline(pipeline, None);
+            device.destroy_pipeline_layout(layout, None);
+        }
+    }
+
+    /// Nothing may be in flight that still uses the pipeline
+    pub fn destroy(mut self, renderer: &Renderer) {
+        if let Some((pipeline, layout)) = self.pipeline.take() {
+            Self::destroy_pipeline(renderer.device_handle(), pipeline, layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    #[test]
+    fn sun_is_highest_at_noon_and_lowest_at_midnight() {
+        let noon = SkyState::at(0.5);
+        let midnight = SkyState::at(0.0);
+
+        assert!(noon.sun_direction.y > 0.9);
+        assert!(midnight.sun_direction.y < -0.9);
+        assert!(noon.sun_direction.dot(noon.moon_direction) < -1.0 + EPSILON);
+    }
+
+    #[test]
+    fn sun_rises_in_the_east_and_sets_in_the_west() {
+        let sunrise = SkyState::at(0.25);
+        let sunset = SkyState::at(0.75);
+
+        assert!(sunrise.sun_direction.y.abs() < EPSILON);
+        assert!(sunrise.sun_direction.x > 0.9);
+        assert!(sunset.sun_direction.y.abs() < EPSILON);
+        assert!(sunset.sun_direction.x < -0.9);
+    }
+
+    #[test]
+    fn time_wraps_around_days() {
+        for time in [0.1, 0.4, 0.8] {
+            let state = SkyState::at(time);
+            for wrapped in [SkyState::at(time + 3.0), SkyState::at(time - 1.0)] {
+                assert!((wrapped.time_of_day - time).abs() < EPSILON);
+                assert!(wrapped
+                    .sun_direction
+                    .abs_diff_eq(state.sun_direction, EPSILON));
+                assert!((wrapped.sky_light - state.sky_light).abs() < EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn world_darkens_at_night() {
+        let noon = SkyState::at(0.5);
+        let midnight = SkyState::at(0.0);
+
+        assert_eq!(noon.sky_light, 1.0);
+        assert_eq!(midnight.sky_light, MIN_SKY_LIGHT);
+        assert_eq!(noon.star_visibility, 0.0);
+        assert_eq!(midnight.star_visibility, 1.0);
+        assert!(noon.zenith_color.length() > midnight.zenith_color.length());
+
+        // the light only ever fades out between noon and midnight
+        let mut last = noon.sky_light;
+        for step in 1..=100 {
+            let light = SkyState::at(0.5 + step as f32 * 0.005).sky_light;
+            assert!(light <= last + EPSILON);
+            last = light;
+        }
+    }
+
+    #[test]
+    fn light_always_comes_from_above() {
+        for step in 0..200 {
+            let state = SkyState::at(step as f32 / 200.0);
+            assert!(state.light_direction.y <= 0.0, "{:?}", state);
+            assert!((state.light_direction.length() - 1.0).abs() < EPSILON);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::error::Error;
+
+use ash::vk;
+
+use crate::{
+    core,
+    graph::{BufferId, ImageId},
+    resolution::ResolutionScaler,
+};
+
+pub(crate) const MAX_FRAMES_IN_FLIGHT: usize = 2;
+
+/// Timestamps written by every frame, at its start and at its end
+pub(crate) const FRAME_TIMESTAMPS: u32 = 2;
+
+/// Format the scene is drawn in, HDR so post-processing can pick out bright light
+pub(crate) const SCENE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Handle to a surface attached to the [`crate::Renderer`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SurfaceId(u32);
+
+impl SurfaceId {
+    /// The surface the renderer was created with, or the offscreen target when headless
+    pub const PRIMARY: SurfaceId = SurfaceId(0);
+
+    pub(crate) fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+}
+
+/// Frame being recorded for a surface, returned by [`crate::Renderer::begin_frame`]
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    pub surface: SurfaceId,
+    /// Size the scene is drawn at, smaller than the output with dynamic resolution
+    pub extent: vk::Extent2D,
+    /// Size of [`Frame::output`], the one of the surface
+    pub output_extent: vk::Extent2D,
+    /// HDR color attachment passes draw into, multisampled when MSAA is on
+    pub color: ImageId,
+    pub depth: ImageId,
+    /// Single sampled HDR scene, what post-processing reads
+    pub resolved: ImageId,
+    /// Image in the output format that gets presented or read back, the resolved scene
+    /// is copied into it unless a pass added with [`crate::Renderer::draw_output`]
+    /// writes it
+    pub output: ImageId,
+    /// Uniforms shared by the passes of the frame, see [`crate::Renderer::frame_set`]
+    pub uniforms: BufferId,
+}
+
+pub(crate) struct FrameData {
+    pub pool: vk::CommandPool,
+    pub buffer: vk::CommandBuffer,
+
+    pub swapchain_sem: vk::Semaphore,
+    pub render_sem: vk::Semaphore,
+    pub render_fen: vk::Fence,
+
+    /// GPU time of the frame, see [`FRAME_TIMESTAMPS`]
+    pub timestamps: vk::QueryPool,
+    /// Whether the last submission of the frame wrote the timestamps
+    pub timestamps_written: bool,
+    /// Renderer frame count of the last submission, its fence covers every frame up to it
+    pub submitted: u64,
+}
+
+impl Default for FrameData {
+    fn default() -> Self {
+        Self {
+            pool: vk::CommandPool::null(),
+            buffer: vk::CommandBuffer::null(),
+            swapchain_sem: vk::Semaphore::null(),
+            render_sem: vk::Semaphore::null(),
+            render_fen: vk::Fence::null(),
+            timestamps: vk::QueryPool::null(),
+            timestamps_written: false,
+            submitted: 0,
+        }
+    }
+}
+
+/// Everything the attachments of a target are created from,
+/// when any of it changes the attachments have to be recreated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct AttachmentSpec {
+    /// Size of the output image
+    pub extent: vk::Extent2D,
+    /// Size of the scene, depth and MSAA images
+    pub scene_extent: vk::Extent2D,
+    /// Format of the output image, the scene is always drawn in [`SCENE_FORMAT`]
+    pub color_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+}
+
+/// Per-surface frame resources: the frames in flight, the images the scene is drawn into
+/// and, unless headless, the swapchain it gets presented to
+// NOTE: swapchain must be declared before surface so it gets dropped first
+pub(crate) struct RenderTarget {
+    pub frame_number: usize,
+    pub frames: [FrameData; MAX_FRAMES_IN_FLIGHT],
+    pub attachments: AttachmentSpec,
+    /// Output image in the output format, it's what gets copied to the swapchain
+    pub draw_image: Option<core::AllocatedImage>,
+    /// Single sampled HDR image the scene is drawn in, the MSAA image resolves into it
+    pub scene_image: Option<core::AllocatedImage>,
+    pub depth_image: Option<core::AllocatedImage>,
+    /// Multisampled HDR image, only present when MSAA is enabled
+    pub msaa_image: Option<core::AllocatedImage>,
+    /// Layout the draw image was left in by the last submitted frame
+    pub draw_layout: vk::ImageLayout,
+    /// Swapchain image acquired for the frame being recorded
+    pub image_index: u32,
+    /// Size requested by the client, the swapchain may end up with a different one
+    pub extent: vk::Extent2D,
+    pub out_of_date: bool,
+    /// Scale of the scene for the next frames
+    pub scaler: ResolutionScaler,
+    /// GPU time of the last finished frame, in milliseconds
+    pub gpu_time: Option<f32>,
+    pub swapchain: Option<core::Swapchain>,
+    pub surface: Option<core::Surface>,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &core::Device,
+        surface: Option<core::Surface>,
+        swapchain: Option<core::Swapchain>,
+        extent: vk::Extent2D,
+        attachments: AttachmentSpec,
+        scaler: ResolutionScaler,
+    ) -> Result<Self, Box<dyn Error>> {
+        let frames = Self::create_frames_structs(device)?;
+
+        let mut target = Self {
+            frame_number: 0,
+            frames,
+            attachments,
+            draw_image: None,
+            scene_image: None,
+            depth_image: None,
+            msaa_image: None,
+            draw_layout: vk::ImageLayout::UNDEFINED,
+            image_index: 0,
+            extent,
+            out_of_date: false,
+            scaler,
+            gpu_time: None,
+            swapchain,
+            surface,
+        };
+
+        if let Err(err) = target.create_attachments(device, attachments) {
+            target.destroy(device);
+            return Err(err);
+        }
+
+        Ok(target)
+    }
+
+    fn create_frames_structs(
+        device: &core::Device,
+    ) -> Result<[FrameData; MAX_FRAMES_IN_FLIGHT], vk::Result> {
+        // Init frames data
+        let mut frames: [FrameData; MAX_FRAMES_IN_FLIGHT] =
+            [FrameData::default(), FrameData::default()];
+
+        for frame in &mut frames {
+            let pool =
+                device.create_command_pool(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER)?;
+            let buffer = device.allocate_command_buffer(pool, vk::CommandBufferLevel::PRIMARY)?;
+            let swapchain_sem = device.create_semaphore(vk::SemaphoreCreateFlags::default())?;
+            let render_sem = device.create_semaphore(vk::SemaphoreCreateFlags::default())?;
+            let render_fen = device.create_fence(vk::FenceCreateFlags::SIGNALED)?;
+            let timestamps = device.create_timestamp_pool(FRAME_TIMESTAMPS)?;
+
+            frame.pool = pool;
+            frame.buffer = buffer;
+            frame.render_fen = render_fen;
+            frame.render_sem = render_sem;
+            frame.swapchain_sem = swapchain_sem;
+            frame.timestamps = timestamps;
+        }
+
+        Ok(frames)
+    }
+
+    /// (Re)creates the output, scene, depth and MSAA images, the device must be idle
+    pub fn create_attachments(
+        &mut self,
+        device: &core::Device,
+        spec: AttachmentSpec,
+    ) -> Result<(), Box<dyn Error>> {
+        self.destroy_attachments(device);
+        self.attachments = spec;
+        self.draw_layout = vk::ImageLayout::UNDEFINED;
+
+        self.draw_image = Some(device.create_image(&core::ImageSpec::color(
+            "draw image",
+            spec.extent,
+            spec.color_format,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST,
+        ))?);
+
+        self.scene_image = Some(device.create_image(&core::ImageSpec::color(
+            "scene image",
+            spec.scene_extent,
+            SCENE_FORMAT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::TRANSFER_SRC,
+        ))?);
+
+        self.depth_image = Some(device.create_image(&core::ImageSpec::depth(
+            "depth image",
+            spec.scene_extent,
+            spec.depth_format,
+            spec.samples,
+        ))?);
+
+        if spec.samples != vk::SampleCountFlags::TYPE_1 {
+            let mut msaa = core::ImageSpec::color(
+                "msaa image",
+                spec.scene_extent,
+                SCENE_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+            );
+            msaa.samples = spec.samples;
+            self.msaa_image = Some(device.create_image(&msaa)?);
+        }
+
+        Ok(())
+    }
+
+    fn destroy_attachments(&mut self, device: &core::Device) {
+        let images = [
+            self.draw_image.take(),
+            self.scene_image.take(),
+            self.depth_image.take(),
+            self.msaa_image.take(),
+        ];
+
+        for image in images.into_iter().flatten() {
+            device.destroy_image(image);
+        }
+    }
+
+    pub fn current_frame(&self) -> &FrameData {
+        &self.frames[self.frame_number % MAX_FRAMES_IN_FLIGHT]
+    }
+
+    pub fn current_frame_mut(&mut self) -> &mut FrameData {
+        &mut self.frames[self.frame_number % MAX_FRAMES_IN_FLIGHT]
+    }
+
+    pub fn draw_image(&self) -> &core::AllocatedImage {
+        self.draw_image.as_ref().unwrap()
+    }
+
+    pub fn scene_image(&self) -> &core::AllocatedImage {
+        self.scene_image.as_ref().unwrap()
+    }
+
+    pub fn depth_image(&self) -> &core::AllocatedImage {
+        self.depth_image.as_ref().unwrap()
+    }
+
+    /// Destroys the resources owned through the device, the swapchain and the
+    /// surface are released when the target is dropped
+    pub fn destroy(&mut self, device: &core::Device) {
+        self.destroy_attachments(device);
+
+        for frame in &self.frames {
+            device.destroy_command_pool(frame.pool);
+            device.destroy_semaphore(frame.swapchain_sem);
+            device.destroy_semaphore(frame.render_sem);
+            device.destroy_fence(frame.render_fen);
+            device.destroy_query_pool(frame.timestamps);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Fonts drawn by [`Renderer::draw_text`](crate::Renderer::draw_text): Minecraft-style
+//! bitmap grids and TrueType fonts rasterized into a glyph atlas
+
+use std::{collections::HashMap, error::Error, path::Path};
+
+use glam::Vec2;
+
+use crate::chunks::load_png;
+
+/// Cells along each side of a bitmap font grid
+const GRID_CELLS: u32 = 16;
+
+/// Font units a bitmap cell is wide and tall, whatever the size of the texture
+const CELL_UNITS: f32 = 8.0;
+
+/// Advance of the space of a bitmap font, in font units
+const BITMAP_SPACE_ADVANCE: f32 = 4.0;
+
+/// Width of the atlas TrueType glyphs are packed in, it grows downwards
+const ATLAS_WIDTH: u32 = 512;
+
+/// Texels left empty around every packed glyph so filtering doesn't bleed
+const ATLAS_PADDING: u32 = 1;
+
+/// Drawn in place of characters the font doesn't have
+const FALLBACK: char = '?';
+
+/// Where a character is in the atlas and how it's placed on the line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Glyph {
+    /// Atlas texels covered, left, top, right and bottom
+    pub texels: [u32; 4],
+    /// Top left corner from the pen, y down from the top of the line, in font units
+    pub offset: Vec2,
+    /// Size of the quad, in font units
+    pub size: Vec2,
+    /// Distance the pen moves after the glyph, in font units
+    pub advance: f32,
+}
+
+/// Glyph atlas and metrics of a font. Sizes are in font units: a text style scale of 1
+/// draws a unit over one output pixel.
+#[derive(Clone, Debug)]
+pub struct Font {
+    width: u32,
+    height: u32,
+    /// Coverage of the atlas texels
+    coverage: Vec<u8>,
+    glyphs: HashMap<char, Glyph>,
+    line_height: f32,
+    /// Distance the shadow is drawn at, down and right
+    shadow_offset: f32,
+    /// Whether the atlas is sampled with linear filtering, bitmap fonts stay pixelated
+    smooth: bool,
+}
+
+impl Font {
+    /// Reads a bitmap font laid out like Minecraft's `ascii.png`: a grid of 16 by 16
+    /// square cells, the character of a cell is its index in Latin-1, row by row. Glyphs
+    /// are as wide as the rightmost column with any opaque texel and cells are 8 units
+    /// whatever the resolution of the texture.
+    pub fn from_ascii_grid(width: u32, height: u32, pixels: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let cell = width / GRID_CELLS;
+        if cell == 0 || width != cell * GRID_CELLS || height != width {
+            return Err(format!("{}x{} isn't a grid of 16x16 square cells", width, height).into());
+        }
+        if pixels.len() != (width * height * 4) as usize {
+            return Err("Font pixels don't match its size".into());
+        }
+
+        let coverage: Vec<u8> = pixels.chunks_exact(4).map(|rgba| rgba[3]).collect();
+        let units = CELL_UNITS / cell as f32;
+
+        let mut glyphs = HashMap::new();
+        for index in 0..GRID_CELLS * GRID_CELLS {
+            let (x, y) = ((index % GRID_CELLS) * cell, (index / GRID_CELLS) * cell);
+            let character = char::from(index as u8);
+
+            if character == ' ' {
+                glyphs.insert(
+                    character,
+                    Glyph {
+                        texels: [x, y, x, y],
+                        offset: Vec2::ZERO,
+                        size: Vec2::ZERO,
+                        advance: BITMAP_SPACE_ADVANCE,
+                    },
+                );
+                continue;
+            }
+
+            let Some(columns) = glyph_columns(&coverage, width, x, y, cell) else {
+                continue;
+            };
+            let size = Vec2::new(columns as f32 * units, CELL_UNITS);
+            glyphs.insert(
+                character,
+                Glyph {
+                    texels: [x, y, x + columns, y + cell],
+                    offset: Vec2::ZERO,
+                    size,
+                    // one unit of spacing between the glyphs
+                    advance: size.x + 1.0,
+                },
+            );
+        }
+
+        Ok(Self {
+            width,
+            height,
+            coverage,
+            glyphs,
+            line_height: CELL_UNITS + 1.0,
+            shadow_offset: 1.0,
+            smooth: false,
+        })
+    }
+
+    /// Loads a bitmap font grid from a PNG, see [`Font::from_ascii_grid`]
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let (width, height, pixels) = load_png(path.as_ref())?;
+        Self::from_ascii_grid(width, height, &pixels)
+    }
+
+    /// Rasterizes the printable ASCII and Latin-1 characters of a TrueType or OpenType
+    /// font at `size` pixels per em, a font unit is a pixel at that size
+    pub fn from_ttf(data: &[u8], size: f32) -> Result<Self, Box<dyn Error>> {
+        if size.is_nan() || size <= 0.0 {
+            return Err(format!("Invalid font size {}", size).into());
+        }
+        let font = fontdue::Font::from_bytes(data, fontdue::FontSettings::default())?;
+        let line = font
+            .horizontal_line_metrics(size)
+            .ok_or("The font has no horizontal metrics")?;
+
+        let mut rasterized = vec![];
+        for character in (' '..='~').chain('\u{a1}'..='\u{ff}') {
+            if font.lookup_glyph_index(character) == 0 {
+                continue;
+            }
+            let (metrics, bitmap) = font.rasterize(character, size);
+            rasterized.push((character, metrics, bitmap));
+        }
+
+        let sizes: Vec<_> = rasterized
+            .iter()
+            .map(|(_, metrics, _)| (metrics.width as u32, metrics.height as u32))
+            .collect();
+        let (height, positions) = pack_shelves(&sizes, ATLAS_WIDTH)?;
+
+        let mut coverage = vec![0; (ATLAS_WIDTH * height) as usize];
+        let mut glyphs = HashMap::new();
+        for ((character, metrics, bitmap), (x, y)) in rasterized.iter().zip(positions) {
+            let (width, rows) = (metrics.width as u32, metrics.height as u32);
+            for row in 0..rows {
+                let src = (row * width) as usize;
+                let dst = ((y + row) * ATLAS_WIDTH + x) as usize;
+                coverage[dst..dst + width as usize]
+                    .copy_from_slice(&bitmap[src..src + width as usize]);
+            }
+
+            // fontdue places the bitmap from the baseline, y up
+            let top = line.ascent - (metrics.ymin as f32 + rows as f32);
+            glyphs.insert(
+                *character,
+                Glyph {
+                    texels: [x, y, x + width, y + rows],
+                    offset: Vec2::new(metrics.xmin as f32, top),
+                    size: Vec2::new(width as f32, rows as f32),
+                    advance: metrics.advance_width,
+                },
+            );
+        }
+
+        Ok(Self {
+            width: ATLAS_WIDTH,
+            height,
+            coverage,
+            glyphs,
+            line_height: line.new_line_size.ceil(),
+            shadow_offset: (size / 12.0).round().max(1.0),
+            smooth: true,
+        })
+    }
+
+    /// Loads a TrueType or OpenType font file, see [`Font::from_ttf`]
+    pub fn load_ttf(path: impl AsRef<Path>, size: f32) -> Result<Self, Box<dyn Error>> {
+        Self::from_ttf(&std::fs::read(path)?, size)
+    }
+
+    /// Distance between two lines, in font units
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    /// Glyph of `character`, the fallback one when the font doesn't have it
+    pub(crate) fn glyph(&self, character: char) -> Option<&Glyph> {
+        self.glyphs
+            .get(&character)
+            .or_else(|| self.glyphs.get(&FALLBACK))
+    }
+
+    pub(crate) fn shadow_offset(&self) -> f32 {
+        self.shadow_offset
+    }
+
+    pub(crate) fn smooth(&self) -> bool {
+        self.smooth
+    }
+
+    pub(crate) fn atlas_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// One byte of coverage per atlas texel, row by row
+    pub(crate) fn coverage(&self) -> &[u8] {
+        &self.coverage
+    }
+}
+
+/// Columns of the cell at `x`, `y` up to the rightmost one with an opaque texel, `None`
+/// when the cell is empty
+fn glyph_columns(coverage: &[u8], width: u32, x: u32, y: u32, cell: u32) -> Option<u32> {
+    (0..cell).rev().find_map(|column| {
+        let opaque = (0..cell).any(|row| coverage[((y + row) * width + x + column) as usize] > 0);
+        opaque.then_some(column + 1)
+    })
+}
+
+/// Top left corner of a rectangle in the atlas
+type Position = (u32, u32);
+
+/// Packs rectangles in rows of the tallest ones first, returns the height of the atlas
+/// and the position of every rectangle
+fn pack_shelves(sizes: &[(u32, u32)], width: u32) -> Result<(u32, Vec<Position>), Box<dyn Error>> {
+    let mut order: Vec<_> = (0..sizes.len()).collect();
+    order.sort_by_key(|index| std::cmp::Reverse(sizes[*index].1));
+
+    let mut positions = vec![(0, 0); sizes.len()];
+    let (mut x, mut y, mut shelf) = (ATLAS_PADDING, ATLAS_PADDING, 0);
+    for index in order {
+        let (w, h) = sizes[index];
+        if w + 2 * ATLAS_PADDING > width {
+            return Err(format!("A {}x{} glyph doesn't fit in the atlas", w, h).into());
+        }
+        if x + w + ATLAS_PADDING > width {
+            x = ATLAS_PADDING;
+            y += shelf + ATLAS_PADDING;
+            shelf = 0;
+        }
+        positions[index] = (x, y);
+        x += w + ATLAS_PADDING;
+        shelf = shelf.max(h);
+    }
+
+    Ok(((y + shelf + ATLAS_PADDING).max(1), positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Grid of `cell` texels wide cells where the cell of `character` is filled up to
+    /// `columns` columns
+    fn grid(cell: u32, filled: &[(char, u32)]) -> Vec<u8> {
+        let width = cell * GRID_CELLS;
+        let mut pixels = vec![0; (width * width * 4) as usize];
+        for (character, columns) in filled {
+            let index = *character as u32;
+            let (x, y) = ((index % GRID_CELLS) * cell, (index / GRID_CELLS) * cell);
+            for row in 0..cell {
+                for column in 0..*columns {
+                    let texel = ((y + row) * width + x + column) as usize * 4;
+                    pixels[texel..texel + 4].copy_from_slice(&[255; 4]);
+                }
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn bitmap_glyphs_are_as_wide_as_their_texels() {
+        let pixels = grid(8, &[('A', 5), ('i', 1), ('W', 8)]);
+        let font = Font::from_ascii_grid(128, 128, &pixels).unwrap();
+
+        let a = font.glyph('A').unwrap();
+        assert_eq!(a.size, Vec2::new(5.0, 8.0));
+        assert_eq!(a.advance, 6.0);
+        assert_eq!(a.texels, [8, 32, 13, 40]);
+        assert_eq!(font.glyph('i').unwrap().advance, 2.0);
+        assert_eq!(font.glyph('W').unwrap().advance, 9.0);
+        assert_eq!(font.glyph(' ').unwrap().advance, BITMAP_SPACE_ADVANCE);
+        assert_eq!(font.line_height(), 9.0);
+    }
+
+    #[test]
+    fn high_resolution_grids_keep_the_same_units() {
+        let pixels = grid(16, &[('A', 10)]);
+        let font = Font::from_ascii_grid(256, 256, &pixels).unwrap();
+
+        let a = font.glyph('A').unwrap();
+        assert_eq!(a.size, Vec2::new(5.0, 8.0));
+        assert_eq!(a.texels, [16, 64, 26, 80]);
+    }
+
+    #[test]
+    fn missing_glyphs_fall_back() {
+        let pixels = grid(8, &[('?', 5)]);
+        let font = Font::from_ascii_grid(128, 128, &pixels).unwrap();
+        assert_eq!(font.glyph('Z'), font.glyph('?'));
+        assert!(font.glyph('?').is_some());
+
+        let pixels = grid(8, &[]);
+        let font = Font::from_ascii_grid(128, 128, &pixels).unwrap();
+        assert!(font.glyph('Z').is_none());
+    }
+
+    #[test]
+    fn grids_must_be_square() {
+        let pixels = grid(8, &[]);
+        assert!(Font::from_ascii_grid(128, 64, &pixels[..128 * 64 * 4]).is_err());
+        assert!(Font::from_ascii_grid(100, 100, &pixels[..100 * 100 * 4]).is_err());
+        assert!(Font::from_ascii_grid(128, 128, &pixels[4..]).is_err());
+    }
+
+    #[test]
+    fn packed_glyphs_never_overlap() {
+        let sizes: Vec<_> = (0..200).map(|i| (3 + i % 17, 5 + i % 11)).collect();
+        let (height, positions) = pack_shelves(&sizes, 128).unwrap();
+
+        let rects: Vec<_> = sizes
+            .iter()
+            .zip(&positions)
+            .map(|((w, h), (x, y))| (*x, *y, x + w, y + h))
+            .collect();
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a.2 < 128 && a.3 < height, "{:?} outside of the atlas", a);
+            for b in &rects[i + 1..] {
+                let apart = a.2 <= b.0 || b.2 <= a.0 || a.3 <= b.1 || b.3 <= a.1;
+                assert!(apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+
+        assert!(pack_shelves(&[(200, 4)], 128).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//! Lays strings out in glyph quads, with Minecraft formatting codes and shadows
+
+use bytemuck::{Pod, Zeroable};
+use glam::Vec2;
+
+use super::font::Font;
+
+/// Starts a formatting code, the character after it picks a color or resets it
+pub const FORMATTING_CODE: char = '§';
+
+/// sRGB colors of the formatting codes `0` to `f`
+const CODE_COLORS: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xaa],
+    [0x00, 0xaa, 0x00],
+    [0x00, 0xaa, 0xaa],
+    [0xaa, 0x00, 0x00],
+    [0xaa, 0x00, 0xaa],
+    [0xff, 0xaa, 0x00],
+    [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xff],
+    [0x55, 0xff, 0x55],
+    [0x55, 0xff, 0xff],
+    [0xff, 0x55, 0x55],
+    [0xff, 0x55, 0xff],
+    [0xff, 0xff, 0x55],
+    [0xff, 0xff, 0xff],
+];
+
+/// Shadows are the color of the text scaled by it
+const SHADOW_SHADE: f32 = 0.25;
+
+/// How [`Renderer::draw_text`](crate::Renderer::draw_text) draws a string
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    /// sRGB color and opacity, until a formatting code changes it
+    pub color: [f32; 4],
+    /// Output pixels per font unit
+    pub scale: f32,
+    /// Draws a darker copy of the text under it, offset down and right
+    pub shadow: bool,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 4],
+            scale: 1.0,
+            shadow: true,
+        }
+    }
+}
+
+/// Layout of `Glyph` in `text.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub(crate) struct GlyphInstance {
+    /// Covered output pixels, left, top, right and bottom
+    pub rect: [f32; 4],
+    /// Normalized atlas coordinates of the corners
+    pub uv: [f32; 4],
+    pub color: [f32; 4],
+}
+
+/// Visible characters of `text` with the color they are drawn in, formatting codes
+/// `§0` to `§f` switch to their color and `§r` goes back to `color`. The other codes,
+/// like the obfuscated and bold ones, are left out without any effect.
+fn colored_chars(text: &str, color: [f32; 4]) -> impl Iterator<Item = (char, [f32; 4])> + '_ {
+    let mut current = color;
+    let mut chars = text.chars();
+    std::iter::from_fn(move || loop {
+        let character = chars.next()?;
+        if character != FORMATTING_CODE {
+            return Some((character, current));
+        }
+
+        let Some(code) = chars.next() else {
+            continue;
+        };
+        if let Some(index) = code.to_digit(16) {
+            let [r, g, b] = CODE_COLORS[index as usize].map(|val| val as f32 / 255.0);
+            current = [r, g, b, color[3]];
+        } else if code.eq_ignore_ascii_case(&'r') {
+            current = color;
+        }
+    })
+}
+
+/// Appends the quads of `text` with its top left corner at `position`, in output pixels.
+/// Every shadow comes before the text so none of them covers a glyph.
+pub(crate) fn layout(
+    font: &Font,
+    position: Vec2,
+    text: &str,
+    style: &TextStyle,
+    glyphs: &mut Vec<GlyphInstance>,
+) {
+    let (width, height) = font.atlas_size();
+    let texel = Vec2::new(1.0 / width as f32, 1.0 / height as f32);
+
+    let mut add = |origin: Vec2, shade: f32| {
+        let mut pen = Vec2::ZERO;
+        for (character, color) in colored_chars(text, style.color) {
+            if character == '\n' {
+                pen = Vec2::new(0.0, pen.y + font.line_height());
+                continue;
+            }
+            let Some(glyph) = font.glyph(character) else {
+                continue;
+            };
+
+            if glyph.size.x > 0.0 && glyph.size.y > 0.0 {
+                let min = origin + (pen + glyph.offset) * style.scale;
+                let max = min + glyph.size * style.scale;
+                let [left, top, right, bottom] = glyph.texels.map(|val| val as f32);
+                glyphs.push(GlyphInstance {
+                    rect: [min.x, min.y, max.x, max.y],
+                    uv: [
+                        left * texel.x,
+                        top * texel.y,
+                        right * texel.x,
+                        bottom * texel.y,
+                    ],
+                    color: [
+                        color[0] * shade,
+                        color[1] * shade,
+                        color[2] * shade,
+                        color[3],
+                    ],
+                });
+            }
+            pen.x += glyph.advance;
+        }
+    };
+
+    if style.shadow {
+        add(position + font.shadow_offset() * style.scale, SHADOW_SHADE);
+    }
+    add(position, 1.0);
+}
+
+/// Width of the longest line of `text` and height of its lines, in output pixels
+pub(crate) fn measure(font: &Font, text: &str, scale: f32) -> Vec2 {
+    let (mut width, mut line, mut lines) = (0.0_f32, 0.0, 1);
+    for (character, _) in colored_chars(text, [1.0; 4]) {
+        if character == '\n' {
+            width = width.max(line);
+            line = 0.0;
+            lines += 1;
+            continue;
+        }
+        line += font.glyph(character).map_or(0.0, |glyph| glyph.advance);
+    }
+    Vec2::new(width.max(line), lines as f32 * font.line_height()) * scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bitmap font where every printable ASCII character is `columns` texels wide
+    fn font(columns: u32) -> Font {
+        let mut pixels = vec![0; 128 * 128 * 4];
+        for index in 33..127u32 {
+            let (x, y) = ((index % 16) * 8, (index / 16) * 8);
+            for row in 0..8 {
+                for column in 0..columns {
+                    let texel = ((y + row) * 128 + x + column) as usize * 4;
+                    pixels[texel + 3] = 255;
+                }
+            }
+        }
+        Font::from_ascii_grid(128, 128, &pixels).unwrap()
+    }
+
+    fn plain() -> TextStyle {
+        TextStyle {
+            shadow: false,
+            ..TextStyle::default()
+        }
+    }
+
+    #[test]
+    fn glyphs_advance_along_the_line() {
+        let font = font(5);
+        let mut glyphs = vec![];
+        layout(&font, Vec2::new(10.0, 20.0), "ab c", &plain(), &mut glyphs);
+
+        assert_eq!(glyphs.len(), 3);
+        assert_eq!(glyphs[0].rect, [10.0, 20.0, 15.0, 28.0]);
+        assert_eq!(glyphs[1].rect, [16.0, 20.0, 21.0, 28.0]);
+        // the space advances by 4
+        assert_eq!(glyphs[2].rect, [26.0, 20.0, 31.0, 28.0]);
+    }
+
+    #[test]
+    fn glyphs_sample_their_cell() {
+        let font = font(5);
+        let mut glyphs = vec![];
+        layout(&font, Vec2::ZERO, "A", &plain(), &mut glyphs);
+
+        let step = 1.0 / 128.0;
+        assert_eq!(
+            glyphs[0].uv,
+            [8.0 * step, 32.0 * step, 13.0 * step, 40.0 * step]
+        );
+    }
+
+    #[test]
+    fn newlines_start_over_a_line_below() {
+        let font = font(5);
+        let mut glyphs = vec![];
+        layout(&font, Vec2::ZERO, "a\nb", &plain(), &mut glyphs);
+        assert_eq!(glyphs[1].rect, [0.0, 9.0, 5.0, 17.0]);
+    }
+
+    #[test]
+    fn scale_multiplies_everything() {
+        let font = font(5);
+        let style = TextStyle {
+            scale: 2.0,
+            ..plain()
+        };
+        let mut glyphs = vec![];
+        layout(&font, Vec2::new(1.0, 1.0), "ab", &style, &mut glyphs);
+        assert_eq!(glyphs[0].rect, [1.0, 1.0, 11.0, 17.0]);
+        assert_eq!(glyphs[1].rect, [13.0, 1.0, 23.0, 17.0]);
+        assert_eq!(measure(&font, "ab", 2.0), Vec2::new(24.0, 18.0));
+    }
+
+    #[test]
+    fn formatting_codes_color_the_glyphs() {
+        let font = font(5);
+        let style = TextStyle {
+            color: [1.0, 1.0, 1.0, 0.5],
+            ..plain()
+        };
+        let mut glyphs = vec![];
+        layout(&font, Vec2::ZERO, "a§cb§lc§rd§", &style, &mut glyphs);
+
+        assert_eq!(glyphs.len(), 4);
+        assert_eq!(glyphs[0].color, [1.0, 1.0, 1.0, 0.5]);
+        let red = [1.0, 0x55 as f32 / 255.0, 0x55 as f32 / 255.0, 0.5];
+        assert_eq!(glyphs[1].color, red);
+        // bold isn't supported, the color stays
+        assert_eq!(glyphs[2].color, red);
+        assert_eq!(glyphs[3].color, [1.0, 1.0, 1.0, 0.5]);
+        // codes take no room
+        assert_eq!(glyphs[1].rect[0], 6.0);
+        assert_eq!(measure(&font, "§4abc§r", 1.0), measure(&font, "abc", 1.0));
+    }
+
+    #[test]
+    fn shadows_are_darker_and_drawn_first() {
+        let font = font(5);
+        let style = TextStyle {
+            scale: 2.0,
+            ..TextStyle::default()
+        };
+        let mut glyphs = vec![];
+        layout(&font, Vec2::ZERO, "ab", &style, &mut glyphs);
+
+        assert_eq!(glyphs.len(), 4);
+        assert_eq!(glyphs[0].rect, [2.0, 2.0, 12.0, 18.0]);
+        assert_eq!(glyphs[0].color, [0.25, 0.25, 0.25, 1.0]);
+        assert_eq!(glyphs[2].rect, [0.0, 0.0, 10.0, 16.0]);
+        assert_eq!(glyphs[2].color, [1.0; 4]);
+    }
+
+    #[test]
+    fn measures_the_longest_line() {
+        let font = font(5);
+        assert_eq!(measure(&font, "", 1.0), Vec2::new(0.0, 9.0));
+        assert_eq!(measure(&font, "abc\na", 1.0), Vec2::new(18.0, 18.0));
+        assert_eq!(measure(&font, "a\nabc\n", 1.0), Vec2::new(18.0, 27.0));
+    }
+}
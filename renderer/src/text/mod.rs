@@ -0,0 +1,328 @@
+//! Text drawn over the output with [`Renderer::draw_text`](crate::Renderer::draw_text):
+//! the glyphs of a frame are gathered and drawn by a single instanced draw at the end of
+//! it, every instance pulled from a buffer through its device address
+
+mod font;
+mod layout;
+
+use std::{collections::HashMap, error::Error, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::Vec2;
+use gpu_allocator::MemoryLocation;
+
+pub use font::Font;
+pub use layout::{TextStyle, FORMATTING_CODE};
+
+use crate::{core, shaders, Renderer};
+pub(crate) use layout::GlyphInstance;
+
+/// Glyphs the buffer is first created for, it doubles whenever a frame needs more
+const MIN_CAPACITY: usize = 1024;
+
+/// Layout of `Constants` in `text.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub(crate) struct TextConstants {
+    pub glyphs: vk::DeviceAddress,
+    pub screen_size: [f32; 2],
+    pub encoding: u32,
+    pub _pad: u32,
+}
+
+/// Font uploaded to the GPU
+struct FontAtlas {
+    font: Font,
+    image: core::AllocatedImage,
+    sampler: vk::Sampler,
+}
+
+/// Glyphs queued for the frame being recorded and what draws them
+pub(crate) struct TextRenderer {
+    set_layout: vk::DescriptorSetLayout,
+    set: vk::DescriptorSet,
+    layout: vk::PipelineLayout,
+    /// One pipeline per output format
+    pipelines: HashMap<vk::Format, vk::Pipeline>,
+    atlas: Option<FontAtlas>,
+    glyphs: Vec<GlyphInstance>,
+    buffer: Option<core::AllocatedBuffer>,
+}
+
+impl TextRenderer {
+    pub fn new(
+        device: &core::Device,
+        descriptors: &mut core::DescriptorAllocator,
+    ) -> Result<Self, Box<dyn Error>> {
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .build(device.handle(), vk::ShaderStageFlags::FRAGMENT)?;
+        let set = descriptors.allocate(device.handle(), set_layout)?;
+        let layout = core::create_pipeline_layout(
+            device.handle(),
+            &[set_layout],
+            size_of::<TextConstants>() as u32,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        )?;
+
+        Ok(Self {
+            set_layout,
+            set,
+            layout,
+            pipelines: HashMap::new(),
+            atlas: None,
+            glyphs: vec![],
+            buffer: None,
+        })
+    }
+
+    pub fn font(&self) -> Option<&Font> {
+        self.atlas.as_ref().map(|atlas| &atlas.font)
+    }
+
+    /// Replaces the font with `font` uploaded in `image`, nothing may be in flight
+    pub fn set_font(
+        &mut self,
+        device: &core::Device,
+        font: Font,
+        image: core::AllocatedImage,
+    ) -> Result<(), Box<dyn Error>> {
+        let filter = if font.smooth() {
+            vk::Filter::LINEAR
+        } else {
+            vk::Filter::NEAREST
+        };
+        let info = vk::SamplerCreateInfo::default()
+            .mag_filter(filter)
+            .min_filter(filter)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = match unsafe { device.handle().create_sampler(&info, None) } {
+            Ok(val) => val,
+            Err(err) => {
+                device.destroy_image(image);
+                return Err(err.into());
+            }
+        };
+
+        core::DescriptorWriter::new()
+            .write_image(
+                0,
+                image.view,
+                sampler,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            )
+            .update_set(device.handle(), self.set);
+
+        let atlas = FontAtlas {
+            font,
+            image,
+            sampler,
+        };
+        if let Some(old) = self.atlas.replace(atlas) {
+            Self::destroy_atlas(device, old);
+        }
+        Ok(())
+    }
+
+    /// Lays `text` out and queues its glyphs for the end of the frame
+    pub fn queue(
+        &mut self,
+        position: Vec2,
+        text: &str,
+        style: &TextStyle,
+    ) -> Result<(), Box<dyn Error>> {
+        let atlas = self.atlas.as_ref().ok_or("No font to draw text with")?;
+        layout::layout(&atlas.font, position, text, style, &mut self.glyphs);
+        Ok(())
+    }
+
+    /// Size `text` would take, in output pixels
+    pub fn measure(&self, text: &str, scale: f32) -> Option<Vec2> {
+        Some(layout::measure(self.font()?, text, scale))
+    }
+
+    pub fn glyphs(&self) -> &[GlyphInstance] {
+        &self.glyphs
+    }
+
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+    }
+
+    pub fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    pub fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    /// Pipeline drawing into an output of `format`, created the first time
+    pub fn pipeline(
+        &mut self,
+        device: &core::Device,
+        format: vk::Format,
+    ) -> Result<vk::Pipeline, vk::Result> {
+        if let Some(pipeline) = self.pipelines.get(&format) {
+            return Ok(*pipeline);
+        }
+
+        let vertex = shaders::words(shaders::TEXT_VERT);
+        let fragment = shaders::words(shaders::TEXT_FRAG);
+        let pipeline = core::GraphicsPipelineBuilder::new(self.layout)
+            .shader(vk::ShaderStageFlags::VERTEX, &vertex)
+            .shader(vk::ShaderStageFlags::FRAGMENT, &fragment)
+            .topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
+            .color_format(format)
+            .blend(core::Blend::Alpha)
+            .build(device.handle())?;
+
+        log::trace!("Text pipeline for {:?} created", format);
+        self.pipelines.insert(format, pipeline);
+        Ok(pipeline)
+    }
+
+    /// Buffer `count` glyphs are copied into and its address, grown when they don't fit
+    /// anymore, waiting for the device
+    pub fn buffer(
+        &mut self,
+        device: &core::Device,
+        count: usize,
+    ) -> Result<(vk::Buffer, vk::DeviceAddress), Box<dyn Error>> {
+        let size = (count * size_of::<GlyphInstance>()) as vk::DeviceSize;
+        if self
+            .buffer
+            .as_ref()
+            .is_some_and(|buffer| buffer.size >= size)
+        {
+            let buffer = self.buffer.as_ref().unwrap();
+            return Ok((buffer.handle, device.buffer_address(buffer.handle)));
+        }
+
+        if let Some(buffer) = self.buffer.take() {
+            device.wait_idle();
+            device.destroy_buffer(buffer);
+        }
+        let capacity = count.max(MIN_CAPACITY).next_power_of_two();
+        let buffer = device.create_buffer(
+            "glyphs",
+            (capacity * size_of::<GlyphInstance>()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            MemoryLocation::GpuOnly,
+        )?;
+
+        let result = (buffer.handle, device.buffer_address(buffer.handle));
+        self.buffer = Some(buffer);
+        Ok(result)
+    }
+
+    fn destroy_atlas(device: &core::Device, atlas: FontAtlas) {
+        unsafe { device.handle().destroy_sampler(atlas.sampler, None) };
+        device.destroy_image(atlas.image);
+    }
+
+    pub fn destroy(&mut self, device: &core::Device) {
+        if let Some(atlas) = self.atlas.take() {
+            Self::destroy_atlas(device, atlas);
+        }
+        if let Some(buffer) = self.buffer.take() {
+            device.destroy_buffer(buffer);
+        }
+        unsafe {
+            for (_, pipeline) in self.pipelines.drain() {
+                device.handle().destroy_pipeline(pipeline, None);
+            }
+            device.handle().destroy_pipeline_layout(self.layout, None);
+            device
+                .handle()
+                .destroy_descriptor_set_layout(self.set_layout, None);
+        }
+    }
+}
+
+/// Uploads the atlas of `font` to a sampled single channel image, waiting for the device
+/// to finish
+pub(crate) fn upload_atlas(
+    renderer: &Renderer,
+    font: &Font,
+) -> Result<core::AllocatedImage, Box<dyn Error>> {
+    let (width, height) = font.atlas_size();
+    let image = renderer.create_image(&core::ImageSpec::color(
+        "font atlas",
+        vk::Extent2D { width, height },
+        vk::Format::R8_UNORM,
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+    ))?;
+
+    let coverage = font.coverage();
+    let mut staging = match renderer.create_buffer(
+        "font atlas staging",
+        coverage.len() as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::CpuToGpu,
+    ) {
+        Ok(val) => val,
+        Err(err) => {
+            renderer.destroy_image(image);
+            return Err(err);
+        }
+    };
+    let Some(data) = staging.mapped_mut() else {
+        renderer.destroy_buffer(staging);
+        renderer.destroy_image(image);
+        return Err("Staging buffer is not host visible".into());
+    };
+    data[..coverage.len()].copy_from_slice(coverage);
+
+    let result = renderer.immediate_submit(|cmd| {
+        let device = renderer.device_handle();
+        core::transition_image(
+            device,
+            cmd,
+            image.handle,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .layer_count(1),
+            )
+            .image_extent(image.extent);
+        unsafe {
+            device.cmd_copy_buffer_to_image(
+                cmd,
+                staging.handle,
+                image.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            )
+        };
+
+        core::transition_image(
+            device,
+            cmd,
+            image.handle,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    });
+
+    renderer.destroy_buffer(staging);
+    if let Err(err) = result {
+        renderer.destroy_image(image);
+        return Err(err.into());
+    }
+    Ok(image)
+}
@@ -0,0 +1,158 @@
+//! Uniforms shared by every pass of a frame, bound with the set of
+//! [`Renderer::frame_set_layout`](crate::Renderer::frame_set_layout)
+
+use std::{error::Error, mem::size_of};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+
+use crate::{
+    core,
+    fog::{FogState, Medium},
+    graph::{BufferId, BufferUsage, RenderGraph},
+    sky::SkyState,
+};
+
+/// Layout of `FrameUniforms` in `frame.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct FrameParams {
+    sun_direction: [f32; 4],
+    moon_direction: [f32; 4],
+    zenith_color: [f32; 4],
+    horizon_color: [f32; 4],
+    light_color: [f32; 4],
+    time_of_day: f32,
+    sky_light: f32,
+    star_visibility: f32,
+    _pad: f32,
+    eye: [f32; 4],
+    fog_color: [f32; 4],
+    fog_start: f32,
+    fog_end: f32,
+    fog_density: f32,
+    medium: u32,
+}
+
+/// What the uniforms of a frame are computed from
+pub(crate) struct FrameInputs {
+    pub sky: SkyState,
+    pub fog: FogState,
+    pub eye: Vec3,
+    pub medium: Medium,
+}
+
+impl FrameParams {
+    fn new(inputs: &FrameInputs) -> Self {
+        let FrameInputs {
+            sky,
+            fog,
+            eye,
+            medium,
+        } = inputs;
+
+        Self {
+            sun_direction: sky.sun_direction.extend(0.0).to_array(),
+            moon_direction: sky.moon_direction.extend(0.0).to_array(),
+            zenith_color: sky.zenith_color.extend(1.0).to_array(),
+            horizon_color: sky.horizon_color.extend(1.0).to_array(),
+            light_color: sky.light_color.extend(1.0).to_array(),
+            time_of_day: sky.time_of_day,
+            sky_light: sky.sky_light,
+            star_visibility: sky.star_visibility,
+            _pad: 0.0,
+            eye: eye.extend(1.0).to_array(),
+            fog_color: fog.color.extend(1.0).to_array(),
+            fog_start: fog.start,
+            fog_end: fog.end,
+            fog_density: fog.density,
+            medium: *medium as u32,
+        }
+    }
+}
+
+pub(crate) struct FrameUniforms {
+    /// Taken once destroyed
+    buffer: Option<core::AllocatedBuffer>,
+    handle: vk::Buffer,
+    set_layout: vk::DescriptorSetLayout,
+    set: vk::DescriptorSet,
+}
+
+impl FrameUniforms {
+    pub fn new(
+        device: &core::Device,
+        descriptors: &mut core::DescriptorAllocator,
+    ) -> Result<Self, Box<dyn Error>> {
+        let set_layout = core::DescriptorLayoutBuilder::new()
+            .add_binding(0, vk::DescriptorType::UNIFORM_BUFFER)
+            .build(
+                device.handle(),
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            )?;
+
+        let buffer = device.create_buffer(
+            "frame uniforms",
+            size_of::<FrameParams>() as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+
+        let set = descriptors.allocate(device.handle(), set_layout)?;
+        core::DescriptorWriter::new()
+            .write_buffer(
+                0,
+                buffer.handle,
+                0,
+                vk::WHOLE_SIZE,
+                vk::DescriptorType::UNIFORM_BUFFER,
+            )
+            .update_set(device.handle(), set);
+
+        Ok(Self {
+            handle: buffer.handle,
+            buffer: Some(buffer),
+            set_layout,
+            set,
+        })
+    }
+
+    /// Imports the buffer in `graph` with a pass filling it for the frame
+    pub fn upload(&self, graph: &mut RenderGraph, inputs: &FrameInputs) -> BufferId {
+        let buffer = graph.import_buffer("frame uniforms", self.handle);
+        let params = FrameParams::new(inputs);
+
+        graph
+            .add_pass("upload frame uniforms")
+            .write_buffer(buffer, BufferUsage::TransferDst)
+            .execute(move |ctx| unsafe {
+                ctx.device.cmd_update_buffer(
+                    ctx.cmd,
+                    ctx.buffer(buffer),
+                    0,
+                    bytemuck::bytes_of(&params),
+                )
+            });
+        buffer
+    }
+
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    pub fn set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+
+    pub fn destroy(&mut self, device: &core::Device) {
+        if let Some(buffer) = self.buffer.take() {
+            device.destroy_buffer(buffer);
+        }
+        unsafe {
+            device
+                .handle()
+                .destroy_descriptor_set_layout(self.set_layout, None)
+        };
+    }
+}
@@ -0,0 +1,481 @@
+//! Golden image tests: every scene is rendered by a headless [`Renderer`] on whatever
+//! Vulkan device is present and compared against `tests/golden/<scene>.png`.
+//!
+//! - `GOLDEN_BLESS=1` rewrites the references from the current output
+//! - on failure the actual frame and a diff image are written to the test tmp dir
+//! - when no Vulkan ICD is available the tests are skipped, not failed
+
+use std::{
+    error::Error,
+    ffi::CString,
+    fs::File,
+    io::BufWriter,
+    mem::size_of,
+    path::{Path, PathBuf},
+};
+
+use renderer::glam::{ivec3, vec2, vec3, IVec3, Mat4, Vec3};
+use renderer::mesher::{self, BlockId, BlockTypes, SectionBlocks, SECTION_SIZE};
+use renderer::{
+    debug_draw, graph::PassContext, vk, BlockTextureSet, BlockTextures, CascadeCamera, ChunkCuller,
+    ChunkRenderer, DebugStyle, Face, Font, GeometryArena, MeshDraw, PackedVertex, RenderLayer,
+    Renderer, RendererConfig, SectionDraw, ShadowMaps, SurfaceId, TextStyle, TextureLayers,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+/// Maximum per-channel difference before a pixel counts as mismatched
+const CHANNEL_TOLERANCE: u8 = 2;
+
+/// Number of mismatched pixels allowed before the test fails
+const MAX_MISMATCHED_PIXELS: usize = 0;
+
+const FOV_Y: f32 = 1.2;
+const NEAR: f32 = 0.1;
+
+const STONE: BlockId = 1;
+const GLASS: BlockId = 2;
+
+struct Scene {
+    name: &'static str,
+    clear: [f32; 4],
+    rects: Vec<(vk::Rect2D, [f32; 4])>,
+}
+
+impl Scene {
+    fn record(&self, renderer: &mut Renderer) {
+        let rects = self.rects.clone();
+
+        let pass = move |ctx: &PassContext| {
+            for (rect, color) in &rects {
+                let attachment = vk::ClearAttachment {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    color_attachment: 0,
+                    clear_value: vk::ClearValue {
+                        color: vk::ClearColorValue { float32: *color },
+                    },
+                };
+                let clear_rect = vk::ClearRect {
+                    rect: *rect,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                };
+                unsafe {
+                    ctx.device
+                        .cmd_clear_attachments(ctx.cmd, &[attachment], &[clear_rect])
+                };
+            }
+        };
+
+        renderer.draw("rects", pass).unwrap();
+    }
+}
+
+fn rect(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+    vk::Rect2D {
+        offset: vk::Offset2D { x, y },
+        extent: vk::Extent2D { width, height },
+    }
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+fn output_dir() -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn create_renderer() -> Option<Renderer> {
+    let config = RendererConfig {
+        app_name: CString::new("golden").unwrap(),
+        validation: std::env::var_os("GOLDEN_VALIDATION").is_some(),
+        ..Default::default()
+    };
+
+    match Renderer::headless(WIDTH, HEIGHT, config) {
+        Ok(val) => Some(val),
+        Err(err) => {
+            eprintln!("skipping golden test, no usable Vulkan device: {}", err);
+            None
+        }
+    }
+}
+
+/// Renders a single frame with the passes added by `record` and reads it back
+fn render<F>(renderer: &mut Renderer, clear: [f32; 4], record: F) -> Vec<u8>
+where
+    F: FnOnce(&mut Renderer) -> Result<(), Box<dyn Error>>,
+{
+    renderer.set_clear_color(clear);
+    renderer.begin_frame(SurfaceId::PRIMARY).unwrap();
+    record(renderer).unwrap();
+    renderer.end_frame().unwrap();
+    renderer.read_pixels(SurfaceId::PRIMARY).unwrap()
+}
+
+/// Right handed camera at `eye` looking at `target`, y flipped for Vulkan
+fn camera(eye: Vec3, target: Vec3) -> (Mat4, Mat4) {
+    let view = Mat4::look_at_rh(eye, target, Vec3::Y);
+    let mut proj = Mat4::perspective_rh(FOV_Y, WIDTH as f32 / HEIGHT as f32, NEAR, 100.0);
+    proj.y_axis.y = -proj.y_axis.y;
+    (view, proj)
+}
+
+fn load_png(path: &Path) -> Option<(u32, u32, Vec<u8>)> {
+    let decoder = png::Decoder::new(File::open(path).ok()?);
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "{} must be an 8-bit RGBA png",
+        path.display()
+    );
+    buf.truncate(info.buffer_size());
+    Some((info.width, info.height, buf))
+}
+
+fn save_png(path: &Path, width: u32, height: u32, pixels: &[u8]) {
+    let file = BufWriter::new(File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(pixels).unwrap();
+}
+
+/// Returns the number of mismatched pixels and an image highlighting them in red
+fn diff(actual: &[u8], expected: &[u8]) -> (usize, Vec<u8>) {
+    let mut mismatched = 0;
+    let mut image = Vec::with_capacity(actual.len());
+
+    for (a, e) in actual.chunks_exact(4).zip(expected.chunks_exact(4)) {
+        let off = a
+            .iter()
+            .zip(e)
+            .any(|(a, e)| a.abs_diff(*e) > CHANNEL_TOLERANCE);
+        if off {
+            mismatched += 1;
+            image.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // dimmed grayscale of the expected pixel, so the mismatch stands out
+            let luma = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 12) as u8;
+            image.extend_from_slice(&[luma, luma, luma, 255]);
+        }
+    }
+
+    (mismatched, image)
+}
+
+fn check(scene: Scene) {
+    let Some(mut renderer) = create_renderer() else {
+        return;
+    };
+
+    let actual = render(&mut renderer, scene.clear, |renderer| {
+        scene.record(renderer);
+        Ok(())
+    });
+    compare(scene.name, &actual);
+}
+
+/// Compares a rendered frame with the reference of the scene `name`
+fn compare(name: &str, actual: &[u8]) {
+    let reference = golden_dir().join(format!("{}.png", name));
+
+    if std::env::var_os("GOLDEN_BLESS").is_some() {
+        save_png(&reference, WIDTH, HEIGHT, actual);
+        eprintln!("blessed {}", reference.display());
+        return;
+    }
+
+    let (width, height, expected) = load_png(&reference).unwrap_or_else(|| {
+        panic!(
+            "missing reference {}, run with GOLDEN_BLESS=1 to create it",
+            reference.display()
+        )
+    });
+    assert_eq!((width, height), (WIDTH, HEIGHT), "reference size mismatch");
+
+    let (mismatched, diff_image) = diff(actual, &expected);
+    if mismatched > MAX_MISMATCHED_PIXELS {
+        let out = output_dir();
+        let actual_path = out.join(format!("{}.actual.png", name));
+        let diff_path = out.join(format!("{}.diff.png", name));
+        save_png(&actual_path, WIDTH, HEIGHT, actual);
+        save_png(&diff_path, WIDTH, HEIGHT, &diff_image);
+        panic!(
+            "scene \"{}\": {} pixels differ from the reference, see {} and {}",
+            name,
+            mismatched,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+fn clear_black() {
+    check(Scene {
+        name: "clear_black",
+        clear: [0.0, 0.0, 0.0, 1.0],
+        rects: vec![],
+    });
+}
+
+#[test]
+fn clear_color() {
+    check(Scene {
+        name: "clear_color",
+        clear: [0.2, 0.4, 0.8, 1.0],
+        rects: vec![],
+    });
+}
+
+#[test]
+fn overlapping_rects() {
+    check(Scene {
+        name: "overlapping_rects",
+        clear: [0.0, 0.0, 0.0, 1.0],
+        rects: vec![
+            (rect(8, 8, 32, 32), [1.0, 0.0, 0.0, 1.0]),
+            (rect(24, 24, 32, 32), [0.0, 1.0, 0.0, 1.0]),
+            (rect(0, 56, 64, 8), [0.2, 0.4, 0.6, 1.0]),
+        ],
+    });
+}
+
+/// Stone, and glass drawn in the translucent pass
+struct TestBlocks {
+    stone: u16,
+    glass: u16,
+}
+
+impl BlockTypes for TestBlocks {
+    fn render_layer(&self, block: BlockId) -> Option<RenderLayer> {
+        match block {
+            STONE => Some(RenderLayer::Opaque),
+            GLASS => Some(RenderLayer::Translucent),
+            _ => None,
+        }
+    }
+
+    fn texture(&self, block: BlockId, _face: Face) -> u16 {
+        match block {
+            STONE => self.stone,
+            GLASS => self.glass,
+            _ => TextureLayers::MISSING,
+        }
+    }
+}
+
+/// 16x16 texture of `color` with a darker border, so the block edges show
+fn bordered_texture(color: [u8; 4]) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(16 * 16 * 4);
+    for y in 0..16 {
+        for x in 0..16 {
+            let border = x == 0 || y == 0 || x == 15 || y == 15;
+            let [r, g, b, a] = color;
+            pixels.extend(if border {
+                [r / 2, g / 2, b / 2, a]
+            } else {
+                color
+            });
+        }
+    }
+    pixels
+}
+
+/// A stone platform with a staircase and a glass block on it, lit by the sky
+fn test_section() -> SectionBlocks {
+    let mut section = SectionBlocks::new();
+    for y in -1..=SECTION_SIZE {
+        for z in -1..=SECTION_SIZE {
+            for x in -1..=SECTION_SIZE {
+                section.set_light(ivec3(x, y, z), 15, 0);
+            }
+        }
+    }
+
+    for z in 4..12 {
+        for x in 4..12 {
+            section.set_block(ivec3(x, 4, z), STONE);
+        }
+    }
+    for step in 0..3 {
+        for y in 5..6 + step {
+            for z in 5..7 {
+                section.set_block(ivec3(5 + step, y, z), STONE);
+            }
+        }
+    }
+    section.set_block(ivec3(9, 5, 9), GLASS);
+    section.set_block(ivec3(9, 5, 8), GLASS);
+    section
+}
+
+#[test]
+fn chunk_section() {
+    let Some(mut renderer) = create_renderer() else {
+        return;
+    };
+
+    let mut set = BlockTextureSet::new(16, 16);
+    let blocks = TestBlocks {
+        stone: set
+            .add("stone", 16, 16, &bordered_texture([128, 128, 128, 255]))
+            .unwrap(),
+        glass: set
+            .add("glass", 16, 16, &bordered_texture([96, 160, 255, 128]))
+            .unwrap(),
+    };
+    let mut textures = BlockTextures::new(&mut renderer, &set).unwrap();
+    let mut arena =
+        GeometryArena::new(&renderer, size_of::<PackedVertex>() as u32, 4096, 8192).unwrap();
+    let mut culler = ChunkCuller::new(&mut renderer, 16).unwrap();
+    let mut shadows = ShadowMaps::new(&mut renderer).unwrap();
+    let mut chunks = ChunkRenderer::new();
+
+    let meshes = mesher::mesh_greedy(&test_section(), &blocks, &renderer.render_settings());
+    let origin = IVec3::ZERO;
+    let eye = vec3(-4.0, 14.0, 20.0);
+    let (view, proj) = camera(eye, vec3(8.0, 5.0, 8.0));
+    let view_proj = proj * view;
+    let cascade_camera = CascadeCamera {
+        view,
+        fov_y: FOV_Y,
+        aspect: WIDTH as f32 / HEIGHT as f32,
+        near: NEAR,
+    };
+
+    renderer.set_camera(eye, renderer::Medium::Air);
+    let actual = render(&mut renderer, [0.5, 0.7, 1.0, 1.0], |renderer| {
+        let mut layers = [MeshDraw::default(); RenderLayer::COUNT];
+        for layer in RenderLayer::ALL {
+            let mesh = meshes.layer(layer);
+            if mesh.is_empty() {
+                continue;
+            }
+            let handle = arena.insert(renderer, mesh.vertex_bytes(), &mesh.indices)?;
+            if layer == RenderLayer::Translucent {
+                chunks.set_translucent(handle, origin, mesh.clone());
+            }
+            let range = arena.range(handle).unwrap();
+            layers[layer as usize] = MeshDraw {
+                first_index: range.first_index,
+                index_count: range.index_count,
+                vertex_offset: range.vertex_offset as i32,
+            };
+        }
+        culler.set_section(
+            0,
+            &SectionDraw {
+                aabb_min: origin.as_vec3(),
+                aabb_max: (origin + SECTION_SIZE).as_vec3(),
+                origin,
+                layers,
+            },
+        );
+
+        chunks.sort_translucent(renderer, &mut arena, eye)?;
+        arena.flush(renderer);
+        let culled = culler.cull(renderer, &view_proj)?;
+        shadows.set_light_direction(renderer.sky_state().light_direction);
+        shadows.render(renderer, &culler, &arena, &textures, &cascade_camera)?;
+        chunks.draw(
+            renderer,
+            &culler,
+            &culled,
+            &arena,
+            &mut textures,
+            &mut shadows,
+            &view_proj,
+            eye,
+        )
+    });
+
+    chunks.destroy(&renderer);
+    shadows.destroy(&renderer);
+    culler.destroy(&renderer);
+    arena.destroy(&renderer);
+    textures.destroy(&renderer);
+    compare("chunk_section", &actual);
+}
+
+/// Bitmap font grid whose glyphs are a 5x7 outline around the bits of their character
+/// code, so every glyph differs and the test doesn't depend on a font file
+fn test_font() -> Font {
+    const SIZE: u32 = 128;
+    let mut pixels = vec![0; (SIZE * SIZE * 4) as usize];
+    for code in 33..127u32 {
+        let (cell_x, cell_y) = ((code % 16) * 8, (code / 16) * 8);
+        for y in 0..7 {
+            for x in 0..5 {
+                let outline = x == 0 || y == 0 || x == 4 || y == 6;
+                if outline || (code >> ((y - 1) * 3 + x - 1)) & 1 == 1 {
+                    let texel = ((cell_y + y) * SIZE + cell_x + x) as usize * 4;
+                    pixels[texel..texel + 4].copy_from_slice(&[255; 4]);
+                }
+            }
+        }
+    }
+    Font::from_ascii_grid(SIZE, SIZE, &pixels).unwrap()
+}
+
+#[test]
+fn text() {
+    let Some(mut renderer) = create_renderer() else {
+        return;
+    };
+    renderer.set_font(test_font()).unwrap();
+
+    let actual = render(&mut renderer, [0.1, 0.1, 0.1, 1.0], |renderer| {
+        let style = TextStyle::default();
+        renderer.draw_text(vec2(2.0, 2.0), "Hi §cred§r\n§a12§93", &style)?;
+        let large = TextStyle {
+            scale: 2.0,
+            shadow: false,
+            color: [1.0, 1.0, 0.0, 0.5],
+        };
+        renderer.draw_text(vec2(4.0, 30.0), "Ab", &large)
+    });
+    compare("text", &actual);
+}
+
+#[test]
+fn debug_lines() {
+    let Some(mut renderer) = create_renderer() else {
+        return;
+    };
+    let (view, proj) = camera(vec3(3.0, 4.0, 6.0), Vec3::ZERO);
+    let view_proj = proj * view;
+
+    let actual = render(&mut renderer, [0.0, 0.0, 0.0, 1.0], |renderer| {
+        let red = DebugStyle {
+            color: [1.0, 0.2, 0.2, 1.0],
+            ..DebugStyle::default()
+        };
+        let green = DebugStyle {
+            color: [0.2, 1.0, 0.2, 0.5],
+            depth_test: false,
+        };
+        debug_draw::aabb(renderer, Vec3::splat(-1.0), Vec3::ONE, &red);
+        debug_draw::sphere(renderer, Vec3::ZERO, 1.5, &green);
+        debug_draw::arrow(
+            renderer,
+            Vec3::ZERO,
+            vec3(0.0, 2.5, 0.0),
+            &DebugStyle::default(),
+        );
+        debug_draw::line(renderer, vec3(-3.0, 0.0, 0.0), vec3(3.0, 0.0, 0.0), &red);
+        debug_draw::render(renderer, &view_proj)
+    });
+    compare("debug_lines", &actual);
+}
@@ -1,14 +1,35 @@
-use std::ffi::CString;
+use std::{collections::HashSet, ffi::CString, time::Instant};
 
-use renderer::Renderer;
-use winit::{application::ApplicationHandler, event::WindowEvent};
+use renderer::{Medium, Renderer, RendererConfig, SurfaceId};
+use winit::{
+    application::ApplicationHandler,
+    event::{ElementState, KeyEvent, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
 
+use crate::camera::{Camera, CameraControls};
+use crate::overlay::{DebugInfo, DebugOverlay};
+use crate::scene::Scene;
 use crate::window::Window;
+use crate::world::World;
+
+/// Blocks away the block under the crosshair is looked for, like the F3 screen
+const TARGET_REACH: f32 = 20.0;
+
+/// Cleared to before the world is drawn
+const SKY_COLOR: [f32; 4] = [0.47, 0.65, 1.0, 1.0];
 
-#[derive(Default)]
 pub struct App {
     window: Window,
     renderer: Option<Renderer>,
+    world: World,
+    /// Created with the renderer, `None` when the world can't be drawn
+    scene: Option<Scene>,
+    camera: Camera,
+    overlay: DebugOverlay,
+    /// Keys held down, the camera moves while they are
+    pressed: HashSet<KeyCode>,
+    last_frame: Option<Instant>,
 }
 
 impl App {
@@ -16,7 +37,102 @@ impl App {
         Self {
             window,
             renderer: None,
+            world: World::generate(),
+            scene: None,
+            camera: Camera::default(),
+            overlay: DebugOverlay::default(),
+            pressed: HashSet::new(),
+            last_frame: None,
+        }
+    }
+
+    /// WASD to move, space and shift to go up and down, arrows to look around
+    fn controls(&self) -> CameraControls {
+        let axis = |positive, negative| {
+            let held = |key| {
+                if self.pressed.contains(&key) {
+                    1.0
+                } else {
+                    0.0
+                }
+            };
+            held(positive) - held(negative)
+        };
+        CameraControls {
+            forward: axis(KeyCode::KeyW, KeyCode::KeyS),
+            right: axis(KeyCode::KeyD, KeyCode::KeyA),
+            up: axis(KeyCode::Space, KeyCode::ShiftLeft),
+            yaw: axis(KeyCode::ArrowRight, KeyCode::ArrowLeft),
+            pitch: axis(KeyCode::ArrowUp, KeyCode::ArrowDown),
+        }
+    }
+
+    fn key_input(&mut self, event: KeyEvent) {
+        let PhysicalKey::Code(code) = event.physical_key else {
+            return;
+        };
+        match event.state {
+            ElementState::Pressed => {
+                if code == KeyCode::F3 && !event.repeat {
+                    self.overlay.toggle();
+                }
+                self.pressed.insert(code);
+            }
+            ElementState::Released => {
+                self.pressed.remove(&code);
+            }
+        }
+    }
+
+    fn draw_frame(&mut self) {
+        let now = Instant::now();
+        let delta = self
+            .last_frame
+            .map_or(0.0, |last| (now - last).as_secs_f32());
+        self.last_frame = Some(now);
+        self.camera.update(&self.controls(), delta);
+
+        let Some(renderer) = &mut self.renderer else {
+            return;
+        };
+        renderer.set_camera(self.camera.position, Medium::Air);
+        // a minimized window has nothing to present to
+        let size = self.window.handle().inner_size();
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        let frame = match renderer.begin_frame(SurfaceId::PRIMARY) {
+            Ok(val) => val,
+            Err(err) => {
+                log::error!("Failed to begin the frame: {}", err);
+                return;
+            }
+        };
+        // the wait for the frame in flight is left out of the CPU time
+        let start = Instant::now();
+
+        if let Some(scene) = &mut self.scene {
+            if let Err(err) = scene.draw(renderer, &frame, &self.world, &self.camera) {
+                log::error!("Failed to draw the world: {}", err);
+            }
+        }
+
+        let info = DebugInfo {
+            camera: &self.camera,
+            loaded_sections: self.scene.as_ref().map_or(0, Scene::loaded_sections),
+            target: self
+                .world
+                .raycast(self.camera.position, self.camera.forward(), TARGET_REACH),
+        };
+        if let Err(err) = self.overlay.draw(renderer, &frame, &info) {
+            log::error!("Failed to draw the debug overlay: {}", err);
+        }
+
+        if let Err(err) = renderer.end_frame() {
+            log::error!("Failed to end the frame: {}", err);
         }
+        self.overlay.frame_done(start.elapsed());
     }
 }
 
@@ -30,9 +146,30 @@ impl ApplicationHandler for App {
             log::info!("Winit window created successfully");
 
             let app_name = CString::new(self.window.title.clone()).unwrap();
-            let renderer = Renderer::new(self.window.handle(), app_name, true);
-            self.renderer = Some(renderer);
+            let size = self.window.handle().inner_size();
+            let config = RendererConfig {
+                app_name,
+                validation: true,
+                ..Default::default()
+            };
+            let mut renderer = Renderer::new(self.window.handle(), size.width, size.height, config);
             log::info!("Renderer created succesfully");
+
+            renderer.set_clear_color(SKY_COLOR);
+            match Scene::new(&mut renderer, &self.world) {
+                Ok(scene) => self.scene = Some(scene),
+                Err(err) => log::error!("Failed to create the world renderer: {}", err),
+            }
+            self.overlay.init(&mut renderer);
+            self.renderer = Some(renderer);
+            self.window.handle().request_redraw();
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        if let (Some(renderer), Some(scene)) = (&self.renderer, self.scene.take()) {
+            renderer.wait_idle();
+            scene.destroy(renderer);
         }
     }
 
@@ -42,10 +179,20 @@ impl ApplicationHandler for App {
         _window_id: winit::window::WindowId,
         event: winit::event::WindowEvent,
     ) {
-        event_loop.exit(); // TODO: remove
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
-            WindowEvent::Resized(_) => (),
+            WindowEvent::Resized(size) => {
+                if let Some(renderer) = &mut self.renderer {
+                    renderer.resize_surface(SurfaceId::PRIMARY, size.width, size.height);
+                }
+            }
+            WindowEvent::KeyboardInput { event, .. } => self.key_input(event),
+            // the release of keys held down when the focus goes away is never seen
+            WindowEvent::Focused(false) => self.pressed.clear(),
+            WindowEvent::RedrawRequested => {
+                self.draw_frame();
+                self.window.handle().request_redraw();
+            }
             _ => (),
         }
     }
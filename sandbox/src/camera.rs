@@ -0,0 +1,199 @@
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+use renderer::glam::{IVec3, Vec3};
+use renderer::mesher::SECTION_SIZE;
+
+/// Where the camera starts, above the ground of a default world
+const SPAWN: Vec3 = Vec3::new(0.5, 80.0, 0.5);
+
+/// Blocks per second, like flying in spectator mode
+const FLY_SPEED: f32 = 10.0;
+
+/// Radians per second the camera turns at
+const TURN_SPEED: f32 = 2.0;
+
+/// Looking straight up or down would flip the view
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+/// Position and orientation the world is viewed from
+pub struct Camera {
+    pub position: Vec3,
+    /// Radians around the Y axis, 0 looks north towards -Z and it grows towards east
+    pub yaw: f32,
+    /// Radians above the horizon
+    pub pitch: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: SPAWN,
+            yaw: 0.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+/// Movement asked for by the player, every axis goes from -1 to 1
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CameraControls {
+    pub forward: f32,
+    pub right: f32,
+    pub up: f32,
+    /// Turn towards east
+    pub yaw: f32,
+    /// Look up
+    pub pitch: f32,
+}
+
+/// Horizontal direction the camera mostly looks at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Facing {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Facing {
+    pub fn name(self) -> &'static str {
+        match self {
+            Facing::North => "north",
+            Facing::East => "east",
+            Facing::South => "south",
+            Facing::West => "west",
+        }
+    }
+
+    /// Axis and direction it points along
+    pub fn axis(self) -> &'static str {
+        match self {
+            Facing::North => "-Z",
+            Facing::East => "+X",
+            Facing::South => "+Z",
+            Facing::West => "-X",
+        }
+    }
+}
+
+impl Camera {
+    pub fn forward(&self) -> Vec3 {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        Vec3::new(sin_yaw * cos_pitch, sin_pitch, -cos_yaw * cos_pitch)
+    }
+
+    /// Flies for `delta` seconds, moving forward and right stays level whatever the pitch
+    pub fn update(&mut self, controls: &CameraControls, delta: f32) {
+        self.yaw = (self.yaw + controls.yaw * TURN_SPEED * delta + PI).rem_euclid(TAU) - PI;
+        self.pitch =
+            (self.pitch + controls.pitch * TURN_SPEED * delta).clamp(-MAX_PITCH, MAX_PITCH);
+
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let forward = Vec3::new(sin_yaw, 0.0, -cos_yaw);
+        let right = Vec3::new(cos_yaw, 0.0, sin_yaw);
+        let movement = forward * controls.forward + right * controls.right + Vec3::Y * controls.up;
+        self.position += movement.normalize_or_zero() * FLY_SPEED * delta;
+    }
+
+    pub fn facing(&self) -> Facing {
+        let forward = self.forward();
+        if forward.x.abs() > forward.z.abs() {
+            if forward.x > 0.0 {
+                Facing::East
+            } else {
+                Facing::West
+            }
+        } else if forward.z > 0.0 {
+            Facing::South
+        } else {
+            Facing::North
+        }
+    }
+
+    /// Block the camera is in
+    pub fn block(&self) -> IVec3 {
+        self.position.floor().as_ivec3()
+    }
+
+    /// Section the camera is in, its X and Z are the ones of the chunk
+    pub fn section(&self) -> IVec3 {
+        self.block().div_euclid(IVec3::splat(SECTION_SIZE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+
+    use super::*;
+
+    #[test]
+    fn facing_follows_the_yaw() {
+        let facing = |yaw: f32| {
+            Camera {
+                yaw,
+                ..Camera::default()
+            }
+            .facing()
+        };
+        assert_eq!(facing(0.0), Facing::North);
+        assert_eq!(facing(FRAC_PI_2), Facing::East);
+        assert_eq!(facing(2.0 * FRAC_PI_2), Facing::South);
+        assert_eq!(facing(-FRAC_PI_2), Facing::West);
+        assert_eq!(facing(0.3), Facing::North);
+    }
+
+    #[test]
+    fn moves_along_the_yaw() {
+        let mut camera = Camera {
+            position: Vec3::ZERO,
+            yaw: FRAC_PI_2,
+            pitch: 1.0,
+        };
+        let forward = CameraControls {
+            forward: 1.0,
+            ..CameraControls::default()
+        };
+        camera.update(&forward, 0.5);
+        assert!(camera
+            .position
+            .abs_diff_eq(Vec3::new(FLY_SPEED * 0.5, 0.0, 0.0), 1e-4));
+
+        // diagonals are as fast as straight lines
+        let diagonal = CameraControls {
+            right: -1.0,
+            up: 1.0,
+            ..forward
+        };
+        camera.position = Vec3::ZERO;
+        camera.update(&diagonal, 1.0);
+        assert!((camera.position.length() - FLY_SPEED).abs() < 1e-4);
+        assert!(camera.position.z < 0.0 && camera.position.y > 0.0);
+    }
+
+    #[test]
+    fn turning_wraps_the_yaw_and_clamps_the_pitch() {
+        let mut camera = Camera::default();
+        let turn = CameraControls {
+            yaw: 1.0,
+            pitch: -1.0,
+            ..CameraControls::default()
+        };
+        camera.update(&turn, 2.0);
+        assert!((-PI..PI).contains(&camera.yaw));
+        assert!((camera.yaw - (2.0 * TURN_SPEED - TAU)).abs() < 1e-4);
+        assert_eq!(camera.pitch, -MAX_PITCH);
+        assert_eq!(camera.position, SPAWN);
+    }
+
+    #[test]
+    fn negative_positions_round_down() {
+        let camera = Camera {
+            position: Vec3::new(-0.5, 15.9, -16.0),
+            ..Camera::default()
+        };
+        assert_eq!(camera.block(), IVec3::new(-1, 15, -16));
+        assert_eq!(camera.section(), IVec3::new(-1, 0, -1));
+    }
+}
@@ -1,5 +1,9 @@
 mod app;
+mod camera;
+mod overlay;
+mod scene;
 mod window;
+mod world;
 
 use winit::event_loop::EventLoop;
 
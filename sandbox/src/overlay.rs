@@ -0,0 +1,265 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use renderer::glam::{vec2, IVec3};
+use renderer::mesher::SECTION_SIZE;
+use renderer::{Font, Frame, Renderer, TextStyle};
+
+use crate::camera::Camera;
+use crate::world::{self, RayHit};
+
+/// Overrides the font of the overlay, a Minecraft `ascii.png` or a TrueType font
+const FONT_VAR: &str = "MINECRUST_FONT";
+
+/// Where a Minecraft resource pack keeps its bitmap font
+const BITMAP_FONT: &str = "assets/minecraft/textures/font/ascii.png";
+
+/// DejaVu Sans Mono, used when there's no bitmap font, see `assets/fonts/LICENSE-DejaVu.txt`
+const DEFAULT_FONT: &[u8] = include_bytes!("../assets/fonts/DejaVuSansMono.ttf");
+
+/// Pixels per em TrueType fonts are rasterized at
+const TTF_SIZE: f32 = 16.0;
+
+/// Output pixels per texel of bitmap fonts, the GUI scale
+const BITMAP_SCALE: f32 = 2.0;
+
+/// Distance of the text from the edges of the window
+const MARGIN: f32 = 4.0;
+
+/// How often the frame rate and times are refreshed, like the F3 screen
+const REFRESH: Duration = Duration::from_secs(1);
+
+const MEBIBYTE: f64 = 1024.0 * 1024.0;
+
+/// What the overlay shows about the game, filled every frame, the rest comes from the
+/// renderer
+pub struct DebugInfo<'a> {
+    pub camera: &'a Camera,
+    /// Sections with a mesh on the GPU
+    pub loaded_sections: usize,
+    /// Block under the crosshair
+    pub target: Option<RayHit>,
+}
+
+/// Frame rate and CPU time averaged over [`REFRESH`]
+#[derive(Default)]
+struct FrameCounter {
+    start: Option<Instant>,
+    frames: u32,
+    cpu_time: Duration,
+    /// Frames per second and milliseconds of CPU time per frame of the last period
+    shown: Option<(u32, f32)>,
+}
+
+impl FrameCounter {
+    fn add(&mut self, cpu_time: Duration) {
+        let now = Instant::now();
+        let start = *self.start.get_or_insert(now);
+        self.frames += 1;
+        self.cpu_time += cpu_time;
+
+        let elapsed = now - start;
+        if elapsed >= REFRESH {
+            let fps = (self.frames as f32 / elapsed.as_secs_f32()).round() as u32;
+            let cpu_ms = self.cpu_time.as_secs_f32() * 1000.0 / self.frames as f32;
+            self.shown = Some((fps, cpu_ms));
+            self.start = Some(now);
+            self.frames = 0;
+            self.cpu_time = Duration::ZERO;
+        }
+    }
+}
+
+/// F3 screen: diagnostics drawn over the frame, toggled with [`DebugOverlay::toggle`]
+#[derive(Default)]
+pub struct DebugOverlay {
+    visible: bool,
+    /// Scale the text is drawn at, `None` when no font could be loaded
+    scale: Option<f32>,
+    counter: FrameCounter,
+}
+
+impl DebugOverlay {
+    /// Loads the font of the overlay into `renderer`, without one the overlay stays hidden
+    pub fn init(&mut self, renderer: &mut Renderer) {
+        let (font, scale) = match load_font() {
+            Ok(val) => val,
+            Err(err) => {
+                log::error!("Failed to load the debug overlay font: {}", err);
+                return;
+            }
+        };
+
+        match renderer.set_font(font) {
+            Ok(()) => self.scale = Some(scale),
+            Err(err) => log::error!("Failed to upload the debug overlay font: {}", err),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        // F3 doing nothing at all would look like a bug
+        if self.scale.is_none() {
+            log::warn!("The debug overlay can't be shown, its font failed to load or upload");
+            return;
+        }
+        self.visible = !self.visible;
+    }
+
+    /// Counts a frame that took `cpu_time` to record and submit
+    pub fn frame_done(&mut self, cpu_time: Duration) {
+        self.counter.add(cpu_time);
+    }
+
+    /// Queues the overlay text for `frame` when visible
+    pub fn draw(
+        &self,
+        renderer: &mut Renderer,
+        frame: &Frame,
+        info: &DebugInfo,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(scale) = self.scale.filter(|_| self.visible) else {
+            return Ok(());
+        };
+        let style = TextStyle {
+            scale,
+            ..TextStyle::default()
+        };
+
+        let left = self.left_lines(renderer, frame, info).join("\n");
+        renderer.draw_text(vec2(MARGIN, MARGIN), &left, &style)?;
+
+        // every line of the right column is aligned on the right edge on its own
+        let width = frame.output_extent.width as f32;
+        let mut y = MARGIN;
+        for line in right_lines(renderer, info) {
+            let size = renderer.measure_text(&line, scale).unwrap_or_default();
+            renderer.draw_text(vec2(width - MARGIN - size.x, y), &line, &style)?;
+            y += size.y;
+        }
+        Ok(())
+    }
+
+    fn left_lines(&self, renderer: &Renderer, frame: &Frame, info: &DebugInfo) -> Vec<String> {
+        let gpu = match renderer.gpu_frame_time(frame.surface) {
+            Some(val) => format!("{:.2} ms", val),
+            None => "n/a".to_owned(),
+        };
+        let fps = match self.counter.shown {
+            Some((fps, cpu)) => format!("§e{}§r fps  CPU {:.2} ms  GPU {}", fps, cpu, gpu),
+            None => format!("§e-§r fps  CPU -  GPU {}", gpu),
+        };
+
+        let camera = info.camera;
+        let position = camera.position;
+        let block = camera.block();
+        let in_section = block.rem_euclid(IVec3::splat(SECTION_SIZE));
+        let section = camera.section();
+        let facing = camera.facing();
+        let extent = frame.output_extent;
+        let scale = renderer.render_scale(frame.surface).unwrap_or(1.0);
+
+        vec![
+            format!("Minecrust {}", env!("CARGO_PKG_VERSION")),
+            fps,
+            format!("Draw calls: {}", renderer.draw_calls()),
+            format!(
+                "Resolution: {}x{} ({:.0}%)",
+                extent.width,
+                extent.height,
+                scale * 100.0
+            ),
+            String::new(),
+            format!(
+                "XYZ: {:.3} / {:.3} / {:.3}",
+                position.x, position.y, position.z
+            ),
+            format!("Block: {} {} {}", block.x, block.y, block.z),
+            format!(
+                "Chunk: {} {} {} in {} {} {}",
+                in_section.x, in_section.y, in_section.z, section.x, section.y, section.z
+            ),
+            format!(
+                "Facing: {} (Towards {}) ({:.1} / {:.1})",
+                facing.name(),
+                facing.axis(),
+                camera.yaw.to_degrees(),
+                camera.pitch.to_degrees()
+            ),
+            format!("Loaded sections: {}", info.loaded_sections),
+        ]
+    }
+}
+
+fn right_lines(renderer: &Renderer, info: &DebugInfo) -> Vec<String> {
+    let gpu = renderer.gpu_info();
+    let memory = renderer.memory_usage();
+
+    let mut lines = vec![
+        format!("GPU: {}", gpu.name),
+        format!("Driver: {}", gpu.driver),
+        format!("Vulkan {}", gpu.api_version),
+        format!(
+            "GPU memory: {:.0}/{:.0} MiB ({} allocations)",
+            memory.allocated as f64 / MEBIBYTE,
+            memory.reserved as f64 / MEBIBYTE,
+            memory.allocations
+        ),
+        String::new(),
+    ];
+    lines.push(match &info.target {
+        Some(target) => format!(
+            "Targeted block: {} {} {} ({})",
+            target.position.x,
+            target.position.y,
+            target.position.z,
+            world::block_name(target.block)
+        ),
+        None => "Targeted block: none".to_owned(),
+    });
+    lines
+}
+
+/// Font of [`FONT_VAR`], a bitmap font in the working directory or [`DEFAULT_FONT`],
+/// with the scale it is drawn at
+fn load_font() -> Result<(Font, f32), Box<dyn Error>> {
+    let load = |path: &Path| -> Result<(Font, f32), Box<dyn Error>> {
+        let is_png = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+        if is_png {
+            Ok((Font::load_png(path)?, BITMAP_SCALE))
+        } else {
+            Ok((Font::load_ttf(path, TTF_SIZE)?, 1.0))
+        }
+    };
+
+    let custom = std::env::var_os(FONT_VAR).map(PathBuf::from);
+    let candidates = custom.into_iter().chain([PathBuf::from(BITMAP_FONT)]);
+    for path in candidates {
+        if !path.exists() {
+            continue;
+        }
+        match load(&path) {
+            Ok(val) => {
+                log::info!("Debug overlay font loaded from {}", path.display());
+                return Ok(val);
+            }
+            Err(err) => log::warn!("Failed to load font {}: {}", path.display(), err),
+        }
+    }
+    Ok((Font::from_ttf(DEFAULT_FONT, TTF_SIZE)?, 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_font_loads() {
+        let font = Font::from_ttf(DEFAULT_FONT, TTF_SIZE).unwrap();
+        assert!(font.line_height() >= TTF_SIZE);
+    }
+}
@@ -0,0 +1,215 @@
+use std::{error::Error, mem::size_of};
+
+use renderer::glam::{IVec3, Mat4, Vec3};
+use renderer::mesher::{self, SECTION_SIZE};
+use renderer::{
+    BlockTextureSet, BlockTextures, CascadeCamera, ChunkCuller, ChunkRenderer, Frame,
+    GeometryArena, MeshDraw, PackedVertex, RenderLayer, Renderer, SectionDraw, ShadowMaps,
+};
+
+use crate::camera::Camera;
+use crate::world::{Blocks, World, BLOCKS};
+
+/// Texels along each side of the generated block textures
+const TEXTURE_SIZE: u32 = 16;
+
+/// Vertices and indices the geometry arena has room for
+const VERTEX_CAPACITY: u32 = 1 << 20;
+const INDEX_CAPACITY: u32 = 3 << 19;
+
+/// Sections meshed and uploaded per frame, the world appears over the first frames
+/// instead of stalling the first one
+const UPLOADS_PER_FRAME: usize = 8;
+
+/// Vertical field of view in radians, about 70 degrees like the default
+const FOV_Y: f32 = 1.22;
+const NEAR: f32 = 0.1;
+const FAR: f32 = 1000.0;
+
+/// GPU side of the [`World`]: its sections meshed into a geometry arena, culled and
+/// drawn with their shadows
+pub struct Scene {
+    blocks: Blocks,
+    textures: BlockTextures,
+    arena: GeometryArena,
+    culler: ChunkCuller,
+    shadows: ShadowMaps,
+    chunks: ChunkRenderer,
+    /// Sections waiting to be meshed and uploaded
+    queued: Vec<IVec3>,
+    /// Sections with a mesh in the arena, each one has the culler slot of its index
+    loaded: Vec<IVec3>,
+}
+
+impl Scene {
+    pub fn new(renderer: &mut Renderer, world: &World) -> Result<Self, Box<dyn Error>> {
+        let mut set = BlockTextureSet::new(TEXTURE_SIZE, TEXTURE_SIZE);
+        for (name, color) in BLOCKS {
+            set.add(name, TEXTURE_SIZE, TEXTURE_SIZE, &bordered_texture(color))?;
+        }
+        let blocks = Blocks::new(set.layers());
+        let textures = BlockTextures::new(renderer, &set)?;
+
+        let arena = GeometryArena::new(
+            renderer,
+            size_of::<PackedVertex>() as u32,
+            VERTEX_CAPACITY,
+            INDEX_CAPACITY,
+        )?;
+        let culler = ChunkCuller::new(renderer, world.section_count() as u32)?;
+        let shadows = ShadowMaps::new(renderer)?;
+
+        // the sections around the origin come first
+        let mut queued: Vec<_> = world.section_positions().collect();
+        queued.sort_by_key(|pos| std::cmp::Reverse(pos.x.abs() + pos.z.abs()));
+
+        Ok(Self {
+            blocks,
+            textures,
+            arena,
+            culler,
+            shadows,
+            chunks: ChunkRenderer::new(),
+            queued,
+            loaded: vec![],
+        })
+    }
+
+    /// Sections with a mesh on the GPU
+    pub fn loaded_sections(&self) -> usize {
+        self.loaded.len()
+    }
+
+    /// Adds the passes drawing the world seen from `camera` to `frame`
+    pub fn draw(
+        &mut self,
+        renderer: &mut Renderer,
+        frame: &Frame,
+        world: &World,
+        camera: &Camera,
+    ) -> Result<(), Box<dyn Error>> {
+        for _ in 0..UPLOADS_PER_FRAME {
+            let Some(pos) = self.queued.pop() else {
+                break;
+            };
+            self.upload(renderer, world, pos)?;
+        }
+
+        let eye = camera.position;
+        let view = Mat4::look_to_rh(eye, camera.forward(), Vec3::Y);
+        let aspect = frame.extent.width as f32 / frame.extent.height as f32;
+        // Vulkan clip space has y pointing down
+        let mut proj = Mat4::perspective_rh(FOV_Y, aspect, NEAR, FAR);
+        proj.y_axis.y = -proj.y_axis.y;
+        let view_proj = proj * view;
+        let cascade_camera = CascadeCamera {
+            view,
+            fov_y: FOV_Y,
+            aspect,
+            near: NEAR,
+        };
+
+        self.chunks
+            .sort_translucent(renderer, &mut self.arena, eye)?;
+        self.arena.flush(renderer);
+        let culled = self.culler.cull(renderer, &view_proj)?;
+
+        self.shadows
+            .set_light_direction(renderer.sky_state().light_direction);
+        self.shadows.render(
+            renderer,
+            &self.culler,
+            &self.arena,
+            &self.textures,
+            &cascade_camera,
+        )?;
+        self.chunks.draw(
+            renderer,
+            &self.culler,
+            &culled,
+            &self.arena,
+            &mut self.textures,
+            &mut self.shadows,
+            &view_proj,
+            eye,
+        )?;
+        self.culler.build_hiz(renderer)
+    }
+
+    /// Meshes the section at `pos` and uploads its layers, sections without faces
+    /// are left out
+    fn upload(
+        &mut self,
+        renderer: &mut Renderer,
+        world: &World,
+        pos: IVec3,
+    ) -> Result<(), Box<dyn Error>> {
+        let Some(blocks) = world.section(pos) else {
+            return Ok(());
+        };
+        let meshes = mesher::mesh_greedy(blocks, &self.blocks, &renderer.render_settings());
+        if meshes.is_empty() {
+            return Ok(());
+        }
+
+        let origin = pos * SECTION_SIZE;
+        let mut layers = [MeshDraw::default(); RenderLayer::COUNT];
+        for layer in RenderLayer::ALL {
+            let mesh = meshes.layer(layer);
+            if mesh.is_empty() {
+                continue;
+            }
+            let handle = self
+                .arena
+                .insert(renderer, mesh.vertex_bytes(), &mesh.indices)?;
+            if layer == RenderLayer::Translucent {
+                self.chunks.set_translucent(handle, origin, mesh.clone());
+            }
+            let range = self.arena.range(handle).unwrap();
+            layers[layer as usize] = MeshDraw {
+                first_index: range.first_index,
+                index_count: range.index_count,
+                vertex_offset: range.vertex_offset as i32,
+            };
+        }
+
+        let slot = self.loaded.len() as u32;
+        self.culler.set_section(
+            slot,
+            &SectionDraw {
+                aabb_min: origin.as_vec3(),
+                aabb_max: (origin + SECTION_SIZE).as_vec3(),
+                origin,
+                layers,
+            },
+        );
+        self.loaded.push(pos);
+        Ok(())
+    }
+
+    /// Nothing may be in flight that still uses the scene
+    pub fn destroy(self, renderer: &Renderer) {
+        self.chunks.destroy(renderer);
+        self.shadows.destroy(renderer);
+        self.culler.destroy(renderer);
+        self.arena.destroy(renderer);
+        self.textures.destroy(renderer);
+    }
+}
+
+/// Texture of `color` with a darker border, so the edges of the blocks show
+fn bordered_texture(color: [u8; 4]) -> Vec<u8> {
+    let [r, g, b, a] = color;
+    let mut pixels = Vec::with_capacity((TEXTURE_SIZE * TEXTURE_SIZE * 4) as usize);
+    for y in 0..TEXTURE_SIZE {
+        for x in 0..TEXTURE_SIZE {
+            let border = x == 0 || y == 0 || x == TEXTURE_SIZE - 1 || y == TEXTURE_SIZE - 1;
+            pixels.extend(if border {
+                [r / 4 * 3, g / 4 * 3, b / 4 * 3, a]
+            } else {
+                color
+            });
+        }
+    }
+    pixels
+}
@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+
+use renderer::glam::{ivec3, IVec3, Vec3};
+use renderer::mesher::{BlockId, BlockTypes, SectionBlocks, AIR, SECTION_SIZE};
+use renderer::{Face, RenderLayer, TextureLayers};
+
+pub const STONE: BlockId = 1;
+pub const DIRT: BlockId = 2;
+pub const GRASS: BlockId = 3;
+
+/// Name and color of every block after air, indexed by id - 1. The textures are
+/// generated from the color, the sandbox ships no block textures.
+pub const BLOCKS: [(&str, [u8; 4]); 3] = [
+    ("stone", [125, 125, 125, 255]),
+    ("dirt", [134, 96, 67, 255]),
+    ("grass_block", [95, 159, 53, 255]),
+];
+
+/// Sections generated on each side of the origin along X and Z
+const RADIUS: i32 = 4;
+
+/// Sections along Y the terrain is generated in, they hold every surface height
+const SECTIONS_Y: [i32; 2] = [3, 4];
+
+/// Height the hills go up and down around
+const SEA_LEVEL: f32 = 64.0;
+
+/// Blocks of dirt between the grass and the stone
+const DIRT_DEPTH: i32 = 3;
+
+/// Block a ray starting at the eye stops on, see [`World::raycast`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RayHit {
+    pub position: IVec3,
+    pub block: BlockId,
+}
+
+pub fn block_name(block: BlockId) -> &'static str {
+    match block {
+        AIR => "air",
+        _ => BLOCKS
+            .get(block as usize - 1)
+            .map_or("unknown", |(name, _)| name),
+    }
+}
+
+/// Block registry of the sandbox, every block is opaque with the same texture on all
+/// its faces
+pub struct Blocks {
+    /// Texture layer of every block after air
+    textures: Vec<u16>,
+}
+
+impl Blocks {
+    pub fn new(layers: &TextureLayers) -> Self {
+        let textures = BLOCKS
+            .iter()
+            .map(|(name, _)| layers.get_or_missing(name))
+            .collect();
+        Self { textures }
+    }
+}
+
+impl BlockTypes for Blocks {
+    fn render_layer(&self, block: BlockId) -> Option<RenderLayer> {
+        match block {
+            AIR => None,
+            _ => Some(RenderLayer::Opaque),
+        }
+    }
+
+    fn texture(&self, block: BlockId, _face: Face) -> u16 {
+        self.textures
+            .get(block as usize - 1)
+            .copied()
+            .unwrap_or(TextureLayers::MISSING)
+    }
+}
+
+/// Hilly terrain generated around the origin, lit by the sky everywhere
+pub struct World {
+    sections: HashMap<IVec3, SectionBlocks>,
+}
+
+impl World {
+    pub fn generate() -> Self {
+        let mut sections = HashMap::new();
+        for z in -RADIUS..=RADIUS {
+            for x in -RADIUS..=RADIUS {
+                for y in SECTIONS_Y {
+                    let section = ivec3(x, y, z);
+                    sections.insert(section, generate_section(section));
+                }
+            }
+        }
+        Self { sections }
+    }
+
+    /// Coordinates of the generated sections, in blocks divided by [`SECTION_SIZE`]
+    pub fn section_positions(&self) -> impl Iterator<Item = IVec3> + '_ {
+        self.sections.keys().copied()
+    }
+
+    pub fn section(&self, pos: IVec3) -> Option<&SectionBlocks> {
+        self.sections.get(&pos)
+    }
+
+    pub fn section_count(&self) -> usize {
+        self.sections.len()
+    }
+
+    /// Block at `pos`, air outside of the generated sections
+    pub fn block(&self, pos: IVec3) -> BlockId {
+        let size = IVec3::splat(SECTION_SIZE);
+        match self.sections.get(&pos.div_euclid(size)) {
+            Some(section) => section.block(pos.rem_euclid(size)),
+            None => AIR,
+        }
+    }
+
+    /// First block that isn't air along the ray from `origin` towards `direction`, up to
+    /// `reach` blocks away. Blocks are walked one at a time in the order the ray enters
+    /// them.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, reach: f32) -> Option<RayHit> {
+        let direction = direction.normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return None;
+        }
+
+        let mut position = origin.floor().as_ivec3();
+        let step = direction.signum().as_ivec3();
+        // distance along the ray to cross a whole block, and to the next block boundary,
+        // axes the ray doesn't move along are never crossed
+        let delta = direction.recip().abs();
+        let mut boundary = Vec3::INFINITY;
+        for axis in 0..3 {
+            if direction[axis] != 0.0 {
+                let offset = origin[axis] - origin[axis].floor();
+                let next = if step[axis] > 0 { 1.0 - offset } else { offset };
+                boundary[axis] = next * delta[axis];
+            }
+        }
+
+        let mut distance = 0.0;
+        while distance <= reach {
+            let block = self.block(position);
+            if block != AIR {
+                return Some(RayHit { position, block });
+            }
+
+            let axis = if boundary.x <= boundary.y && boundary.x <= boundary.z {
+                0
+            } else if boundary.y <= boundary.z {
+                1
+            } else {
+                2
+            };
+            distance = boundary[axis];
+            position[axis] += step[axis];
+            boundary[axis] += delta[axis];
+        }
+        None
+    }
+}
+
+/// Height of the grass at the column `x`, `z`
+fn surface_height(x: i32, z: i32) -> i32 {
+    let (x, z) = (x as f32, z as f32);
+    let hills = (x * 0.07).sin() * 4.0 + (z * 0.05).cos() * 3.0 + ((x + z) * 0.13).sin();
+    (SEA_LEVEL + hills).floor() as i32
+}
+
+fn generated_block(pos: IVec3) -> BlockId {
+    let height = surface_height(pos.x, pos.z);
+    if pos.y > height {
+        AIR
+    } else if pos.y == height {
+        GRASS
+    } else if pos.y > height - DIRT_DEPTH - 1 {
+        DIRT
+    } else {
+        STONE
+    }
+}
+
+/// Blocks of `section` and of the layer around it, the terrain below the generated
+/// sections counts as stone so their bottom faces stay hidden
+fn generate_section(section: IVec3) -> SectionBlocks {
+    let origin = section * SECTION_SIZE;
+    let lowest = SECTIONS_Y[0] * SECTION_SIZE;
+
+    let mut blocks = SectionBlocks::new();
+    for y in -1..=SECTION_SIZE {
+        for z in -1..=SECTION_SIZE {
+            for x in -1..=SECTION_SIZE {
+                let pos = ivec3(x, y, z);
+                let world = origin + pos;
+                let block = if world.y < lowest {
+                    STONE
+                } else {
+                    generated_block(world)
+                };
+                blocks.set_block(pos, block);
+                blocks.set_light(pos, 15, 0);
+            }
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terrain_fits_in_the_generated_sections() {
+        let lowest = SECTIONS_Y[0] * SECTION_SIZE;
+        let highest = (SECTIONS_Y[SECTIONS_Y.len() - 1] + 1) * SECTION_SIZE;
+        let range = -RADIUS * SECTION_SIZE..(RADIUS + 1) * SECTION_SIZE;
+        for z in range.clone() {
+            for x in range.clone() {
+                let height = surface_height(x, z);
+                assert!((lowest + DIRT_DEPTH..highest).contains(&height));
+            }
+        }
+    }
+
+    #[test]
+    fn blocks_are_looked_up_across_sections() {
+        let world = World::generate();
+        let height = surface_height(-17, 5);
+        assert_eq!(world.block(ivec3(-17, height, 5)), GRASS);
+        assert_eq!(world.block(ivec3(-17, height - 1, 5)), DIRT);
+        assert_eq!(world.block(ivec3(-17, height - DIRT_DEPTH - 1, 5)), STONE);
+        assert_eq!(world.block(ivec3(-17, height + 1, 5)), AIR);
+        assert_eq!(world.block(ivec3(1000, 60, 0)), AIR);
+        assert_eq!(block_name(GRASS), "grass_block");
+    }
+
+    #[test]
+    fn raycast_stops_on_the_first_block() {
+        let world = World::generate();
+        let height = surface_height(3, -2);
+
+        let above = Vec3::new(3.5, height as f32 + 5.5, -1.5);
+        let hit = world.raycast(above, Vec3::NEG_Y, 10.0);
+        assert_eq!(
+            hit,
+            Some(RayHit {
+                position: ivec3(3, height, -2),
+                block: GRASS
+            })
+        );
+        assert_eq!(world.raycast(above, Vec3::NEG_Y, 4.0), None);
+        assert_eq!(world.raycast(above, Vec3::Y, 100.0), None);
+
+        // a ray starting inside a block hits it right away
+        let inside = Vec3::new(3.5, height as f32 - 0.5, -1.5);
+        let hit = world.raycast(inside, Vec3::X, 10.0).unwrap();
+        assert_eq!(hit.position, ivec3(3, height - 1, -2));
+    }
+
+    #[test]
+    fn raycast_walks_diagonals_through_every_block() {
+        let mut section = SectionBlocks::new();
+        section.set_block(ivec3(3, 2, 1), STONE);
+        section.set_block(ivec3(6, 4, 2), DIRT);
+        let mut world = World {
+            sections: HashMap::from([(IVec3::ZERO, section)]),
+        };
+
+        let origin = Vec3::new(0.5, 0.5, 0.5);
+        let direction = Vec3::new(3.0, 2.0, 1.0);
+        let hit = world.raycast(origin, direction, 20.0).unwrap();
+        assert_eq!((hit.position, hit.block), (ivec3(3, 2, 1), STONE));
+
+        world
+            .sections
+            .get_mut(&IVec3::ZERO)
+            .unwrap()
+            .set_block(ivec3(3, 2, 1), AIR);
+        let hit = world.raycast(origin, direction, 20.0).unwrap();
+        assert_eq!((hit.position, hit.block), (ivec3(6, 4, 2), DIRT));
+
+        // the same line walked backwards
+        let hit = world.raycast(Vec3::new(9.5, 6.5, 3.5), -direction, 20.0);
+        assert_eq!(hit.map(|hit| hit.position), Some(ivec3(6, 4, 2)));
+    }
+}